@@ -1,8 +1,10 @@
 use std::path::PathBuf;
 
-use clap::{Args, Subcommand};
+use clap::{builder, Args, Subcommand};
 
-use crate::core::alignment::DEFAULT_ALIGNMENT_OUTPUT_DIR;
+use crate::core::alignment::{
+    qc::DEFAULT_ALIGNMENT_QC_OUTPUT_DIR, trim::DEFAULT_EDGE_OCCUPANCY, DEFAULT_ALIGNMENT_OUTPUT_DIR,
+};
 
 use super::common::{CommonInitArgs, CommonRunnerArgs};
 
@@ -14,6 +16,9 @@ pub(crate) enum AlignmentSubcommand {
     /// Perform locus alignment
     #[command(name = "align", about = "Align multiple sequences")]
     Run(AlignmentArgs),
+    /// Trim and filter alignments
+    #[command(name = "qc", about = "Trim and filter alignments")]
+    Qc(AlignmentQcArgs),
 }
 
 #[derive(Args)]
@@ -38,6 +43,114 @@ pub struct AlignmentInitArgs {
     /// Input directory containing the assemblies
     #[arg(short, long, help = "Input directory containing the assemblies")]
     pub dir: PathBuf,
+    /// Multiple sequence aligner
+    /// Options: mafft, muscle (MUSCLE 5), clustalo (Clustal Omega)
+    #[arg(
+        long,
+        default_value = "mafft",
+        help = "Multiple sequence aligner",
+        value_parser = builder::PossibleValuesParser::new(["mafft", "muscle", "clustalo"])
+    )]
+    pub aligner: String,
+    /// Translate the sequences, align the amino acids,
+    /// and back-translate them to codons
+    #[arg(long, help = "Align protein-coding loci by codons")]
+    pub codon: bool,
+    /// Reading frame of all loci.
+    /// Detected for each locus if not set.
+    #[arg(
+        long,
+        requires = "codon",
+        help = "Reading frame of the loci for codon alignment",
+        value_parser = clap::value_parser!(u8).range(1..=3)
+    )]
+    pub reading_frame: Option<u8>,
     #[command(flatten)]
     pub common: CommonInitArgs,
 }
+
+#[derive(Args)]
+pub struct AlignmentQcArgs {
+    /// Path to an alignment QC config file.
+    /// Trimming and filtering thresholds are read from it
+    /// and the threshold arguments are ignored.
+    #[arg(
+        short,
+        long,
+        help = "Path to an alignment QC config file to repeat a previous run"
+    )]
+    pub config: Option<PathBuf>,
+    /// Input directory containing the alignments
+    #[arg(
+        short,
+        long,
+        default_value = DEFAULT_ALIGNMENT_OUTPUT_DIR,
+        help = "Input directory containing the alignments"
+    )]
+    pub dir: PathBuf,
+    /// Input format of the alignment files
+    #[arg(
+        short = 'f',
+        long,
+        default_value = "auto",
+        help = "Input format of the alignment files",
+        value_parser = builder::PossibleValuesParser::new(["auto", "fasta", "phylip", "nexus"])
+    )]
+    pub input_format: String,
+    /// Sequence data type of the alignments
+    #[arg(
+        long,
+        default_value = "dna",
+        help = "Sequence data type of the alignments",
+        value_parser = builder::PossibleValuesParser::new(["dna", "aa"])
+    )]
+    pub datatype: String,
+    /// Output directory to store the trimmed alignments
+    #[arg(
+        short,
+        long,
+        default_value = DEFAULT_ALIGNMENT_QC_OUTPUT_DIR,
+        help = "Output directory to store the trimmed alignments"
+    )]
+    pub output: PathBuf,
+    /// Edges are trimmed until a column has
+    /// data in at least this proportion of sequences
+    #[arg(
+        long,
+        default_value_t = DEFAULT_EDGE_OCCUPANCY,
+        help = "Minimum proportion of sequences with data at the alignment edges"
+    )]
+    pub edge_occupancy: f64,
+    #[arg(long, help = "Skip edge trimming")]
+    pub no_edge_trim: bool,
+    /// Remove columns with more gaps and missing data
+    #[arg(long, help = "Maximum proportion of gaps and missing data in a column")]
+    pub max_gap: Option<f64>,
+    /// Select the gap threshold from the alignment,
+    /// similar to trimAl gappyout
+    #[arg(
+        long,
+        conflicts_with = "max_gap",
+        help = "Select the gap threshold of each alignment automatically"
+    )]
+    pub automated: bool,
+    /// Remove columns with higher Shannon entropy
+    /// DNA columns range from 0 to 2 bits
+    #[arg(long, help = "Maximum Shannon entropy (bits) of a column")]
+    pub max_entropy: Option<f64>,
+    #[command(flatten)]
+    pub filter: LocusFilterArgs,
+    #[command(flatten)]
+    pub common: CommonRunnerArgs,
+}
+
+#[derive(Args)]
+pub struct LocusFilterArgs {
+    /// Minimum proportion of all taxa present
+    /// in a locus after trimming
+    #[arg(long, help = "Drop loci with fewer than this proportion of all taxa")]
+    pub min_taxon_completeness: Option<f64>,
+    /// Minimum number of parsimony-informative sites
+    #[arg(long, help = "Drop loci with fewer parsimony-informative sites")]
+    pub min_pis: Option<usize>,
+}
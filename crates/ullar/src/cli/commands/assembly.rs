@@ -1,6 +1,6 @@
 use std::path::PathBuf;
 
-use clap::{Args, Subcommand};
+use clap::{builder, Args, Subcommand};
 
 use crate::core::{assembly::DEFAULT_ASSEMBLY_OUTPUT_DIR, clean::DEFAULT_CLEAN_READ_OUTPUT_DIR};
 
@@ -23,6 +23,19 @@ pub struct AssemblyInitArgs {
     pub dir: PathBuf,
     #[command(flatten)]
     pub reads: GenomicReadsInitArgs,
+    /// De novo assembler
+    /// Options: spades, megahit
+    #[arg(
+        long,
+        default_value = "spades",
+        help = "De novo assembler",
+        value_parser = builder::PossibleValuesParser::new(["spades", "megahit"])
+    )]
+    pub assembler: String,
+    /// Keep samples that failed read cleaning QC.
+    /// The samples are flagged in the config.
+    #[arg(long, help = "Keep samples that failed read cleaning QC")]
+    pub keep_failed_qc: bool,
     #[command(flatten)]
     pub common: CommonInitArgs,
 }
@@ -42,6 +55,7 @@ pub struct AssemblyArgs {
     #[arg(long, help = "Remove SPAdes intermediate files")]
     pub keep_intermediates: bool,
     /// Rename contigs file to sample name
+    /// Only applied when intermediates are removed
     #[arg(long, help = "Rename contigs file to sample name")]
     pub rename_contigs: bool,
 }
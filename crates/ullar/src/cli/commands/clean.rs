@@ -2,7 +2,9 @@ use std::path::PathBuf;
 
 use clap::{Args, Subcommand};
 
-use crate::core::clean::{DEFAULT_CLEAN_READ_OUTPUT_DIR, DEFAULT_RAW_READS_DIR};
+use crate::core::clean::{
+    configs::DEFAULT_MIN_CLEAN_READS, DEFAULT_CLEAN_READ_OUTPUT_DIR, DEFAULT_RAW_READS_DIR,
+};
 
 use super::common::{CommonInitArgs, CommonRunnerArgs, GenomicReadsInitArgs};
 
@@ -24,9 +26,29 @@ pub struct ReadCleaningInitArgs {
     #[command(flatten)]
     pub reads: GenomicReadsInitArgs,
     #[command(flatten)]
+    pub qc: ReadQcArgs,
+    #[command(flatten)]
     pub common: CommonInitArgs,
 }
 
+/// Thresholds for samples to pass read cleaning QC
+#[derive(Args)]
+pub struct ReadQcArgs {
+    /// Minimum number of reads after filtering
+    #[arg(
+        long,
+        default_value_t = DEFAULT_MIN_CLEAN_READS,
+        help = "Minimum number of reads after filtering"
+    )]
+    pub min_reads: usize,
+    /// Minimum percentage of bases with quality >= Q30 after filtering
+    #[arg(long, help = "Minimum Q30 bases percentage after filtering")]
+    pub min_q30: Option<f64>,
+    /// Maximum duplication percentage
+    #[arg(long, help = "Maximum duplication percentage")]
+    pub max_duplication: Option<f64>,
+}
+
 #[derive(Args)]
 pub struct ReadCleaningArgs {
     /// Path to the raw read config file
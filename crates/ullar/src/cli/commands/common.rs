@@ -25,6 +25,14 @@ pub struct CommonRunnerArgs {
     /// Force overwrite of existing files
     #[arg(long, help = "Force overwrite of existing files")]
     pub overwrite: bool,
+    /// Keep the existing output directory
+    /// and skip items completed in the previous runs
+    #[arg(
+        long,
+        conflicts_with = "overwrite",
+        help = "Resume a previous run. Skip samples or loci that were completed"
+    )]
+    pub resume: bool,
 }
 
 #[derive(Args)]
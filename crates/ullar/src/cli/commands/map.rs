@@ -3,7 +3,10 @@ use std::path::PathBuf;
 use clap::{builder, Args, Subcommand};
 
 use crate::{
-    core::map::{configs::DEFAULT_REF_MAPPING_CONFIG, DEFAULT_CONTIG_MAPPING_OUTPUT_DIR},
+    core::map::{
+        configs::DEFAULT_REF_MAPPING_CONFIG, DEFAULT_CONTIG_MAPPING_OUTPUT_DIR,
+        DEFAULT_READ_MAPPING_OUTPUT_DIR,
+    },
     helper::regex::CONTIG_SAMPLE_REGEX,
 };
 
@@ -13,7 +16,7 @@ use super::common::{CommonInitArgs, CommonRunnerArgs};
 pub(crate) enum MapSubcommand {
     /// Create a new map config file
     #[command(name = "init", about = "Create a new map config file")]
-    Init(MapInitArgs),
+    Init(Box<MapInitArgs>),
     /// Perform contig mapping
     #[command(name = "contig", about = "Map contigs to reference sequences")]
     Contig(MapContigArgs),
@@ -54,7 +57,7 @@ pub struct MapInitArgs {
         short,
         long,
         default_value = "fasta",
-        conflicts_with = "query-dir",
+        conflicts_with = "dir",
         num_args(0..),
         help = "Input query path using stdin.",
     )]
@@ -107,6 +110,24 @@ pub struct MapInitArgs {
         value_parser = builder::PossibleValuesParser::new(["probes", "loci", "genome"])
     )]
     pub reference_type: String,
+    /// Target regions for the genome reference type
+    #[arg(
+        long,
+        required_if_eq("reference_type", "genome"),
+        help = "BED or GFF file of target regions in the genome reference"
+    )]
+    pub regions: Option<PathBuf>,
+    /// Contig aligner
+    /// Exonerate extracts in-frame exons using
+    /// protein or CDS reference sequences.
+    /// Native maps contigs to probes without Lastz.
+    #[arg(
+        long,
+        default_value = "lastz",
+        help = "Aligner to map contigs",
+        value_parser = builder::PossibleValuesParser::new(["lastz", "exonerate", "native"])
+    )]
+    pub aligner: String,
     #[arg(long, help = "Input reference file contains a single reference")]
     pub single_ref: bool,
     /// Config file name
@@ -117,9 +138,61 @@ pub struct MapInitArgs {
     )]
     pub config_name: String,
     #[command(flatten)]
+    pub contig_filter: ContigFilterArgs,
+    /// Exclude paralogous loci
+    #[arg(
+        long,
+        help = "Exclude loci flagged as paralogous in more than this percent of samples"
+    )]
+    pub max_paralog_percent: Option<f64>,
+    /// Flank length for probe references
+    #[arg(
+        long,
+        help = "Write only the region matched by the probes plus flanks of this length (bp)"
+    )]
+    pub flank: Option<usize>,
+    /// Write Lastz or native alignments to BAM files
+    #[arg(long, help = "Write contig alignments to sorted BAM files")]
+    pub bam: bool,
+    #[arg(
+        long,
+        requires = "bam",
+        help = "Minimum percent identity of alignments written to BAM files"
+    )]
+    pub min_identity: Option<f64>,
+    /// Output format of the mapped loci sequences.
+    /// Mapped loci are unaligned, so only FASTA is supported.
+    #[arg(
+        long,
+        default_value = "fasta-int",
+        help = "Output format of the mapped loci",
+        value_parser = builder::PossibleValuesParser::new(["fasta", "fasta-int"])
+    )]
+    pub output_format: String,
+    #[arg(long, help = "Compress the mapped loci using gzip")]
+    pub gzip: bool,
+    #[command(flatten)]
     pub common: CommonInitArgs,
 }
 
+/// Filter contigs before mapping.
+/// Skipped if no threshold is set.
+#[derive(Args)]
+pub struct ContigFilterArgs {
+    /// Minimum contig length
+    #[arg(long, help = "Drop contigs shorter than this length")]
+    pub min_contig_length: Option<usize>,
+    /// Minimum k-mer coverage parsed from the contig headers
+    #[arg(long, help = "Drop contigs with k-mer coverage below this value")]
+    pub min_contig_coverage: Option<f64>,
+    /// Maximum fold difference from the sample median coverage
+    #[arg(
+        long,
+        help = "Drop contigs with coverage this many folds above or below the sample median"
+    )]
+    pub max_coverage_fold: Option<f64>,
+}
+
 #[derive(Args)]
 pub struct MapReadArgs {
     /// Path to the map config file
@@ -129,7 +202,7 @@ pub struct MapReadArgs {
     #[arg( 
         short,
         long,
-        default_value = DEFAULT_CONTIG_MAPPING_OUTPUT_DIR,
+        default_value = DEFAULT_READ_MAPPING_OUTPUT_DIR,
         help = "Output directory to store the alignments"
     )]
     pub output: PathBuf,
@@ -7,6 +7,7 @@ pub mod common;
 pub mod deps;
 pub mod map;
 pub mod new;
+pub mod run;
 pub mod tree;
 pub mod utils;
 
@@ -17,6 +18,7 @@ use clean::ReadCleaningSubcommand;
 use deps::DepsSubcommand;
 use map::MapSubcommand;
 use new::NewArgs;
+use run::WorkflowArgs;
 use tree::TreeInferenceSubcommand;
 use utils::UtilSubCommand;
 
@@ -66,6 +68,9 @@ pub(crate) enum UllarSubcommand {
     /// Phylogenetic tree estimation
     #[command(subcommand, name = "tree", about = "Estimate phylogenetic tree")]
     Tree(TreeInferenceSubcommand),
+    /// Run the whole pipeline from raw reads to trees
+    #[command(name = "run", about = "Run the pipeline from raw reads to trees")]
+    Run(Box<WorkflowArgs>),
     /// For checking dependencies
     #[command(subcommand, name = "deps", about = "Check and manage dependencies")]
     Deps(DepsSubcommand),
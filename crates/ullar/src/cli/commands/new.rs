@@ -2,17 +2,21 @@ use std::path::PathBuf;
 
 use clap::Args;
 
-use super::common::GenomicReadsInitArgs;
+use crate::core::clean::DEFAULT_RAW_READS_DIR;
 
-// use crate::helper::configs::DEFAULT_CONFIG_DIR;
+use super::common::GenomicReadsInitArgs;
 
 #[derive(Args)]
 pub struct NewArgs {
     /// Name of the project
+    /// Also used as the project directory
+    #[arg(help = "Name of the project. Used as the project directory.")]
+    pub name: PathBuf,
+    /// Raw read location relative to the project directory
     #[arg(
         short,
         long,
-        default_value = "raw_reads",
+        default_value = DEFAULT_RAW_READS_DIR,
         help = "Select a directory for the raw read location."
     )]
     pub dir: PathBuf,
@@ -0,0 +1,116 @@
+use std::path::PathBuf;
+
+use clap::{builder, Args};
+
+use crate::core::clean::DEFAULT_RAW_READS_DIR;
+
+use super::{
+    alignment::LocusFilterArgs,
+    clean::ReadQcArgs,
+    common::{CommonRunnerArgs, GenomicReadsInitArgs},
+    map::ContigFilterArgs,
+    tree::{AsterSettingArgs, IqTreeSettingArgs},
+};
+
+#[derive(Args)]
+pub struct WorkflowArgs {
+    /// Input directory containing the raw reads
+    #[arg(
+        short,
+        long,
+        default_value = DEFAULT_RAW_READS_DIR,
+        help = "Input directory containing the raw reads"
+    )]
+    pub dir: PathBuf,
+    /// Path to the reference sequence
+    /// used to map the contigs
+    #[arg(short, long, help = "Path to the reference sequence")]
+    pub reference: PathBuf,
+    #[arg(
+        long,
+        default_value = "^(uce|locus)-\\d+",
+        help = "Regular expression to extract reference name",
+        require_equals = true
+    )]
+    pub re_reference: String,
+    /// Input reference type
+    /// Options: probes, loci
+    /// Default: probe (e.g., UCE probes)
+    #[arg(
+        long,
+        default_value = "probes",
+        help = "Input reference type",
+        value_parser = builder::PossibleValuesParser::new(["probes", "loci"])
+    )]
+    pub reference_type: String,
+    #[arg(long, help = "Input reference file contains a single reference")]
+    pub single_ref: bool,
+    /// Phylogenetic tree inference method options.
+    /// Default to run all methods.
+    #[arg(
+        num_args(..=4),
+        long,
+        help = "Phylogenetic tree inference method",
+        value_parser = builder::PossibleValuesParser::new(["ml-species", "ml-genes", "gscf", "msc"])
+    )]
+    pub specify_analyses: Option<Vec<String>>,
+    /// De novo assembler
+    /// Options: spades, megahit
+    #[arg(
+        long,
+        default_value = "spades",
+        help = "De novo assembler",
+        value_parser = builder::PossibleValuesParser::new(["spades", "megahit"])
+    )]
+    pub assembler: String,
+    /// Contig aligner
+    /// Options: lastz, exonerate, native
+    #[arg(
+        long,
+        default_value = "lastz",
+        help = "Aligner to map contigs",
+        value_parser = builder::PossibleValuesParser::new(["lastz", "exonerate", "native"])
+    )]
+    pub contig_aligner: String,
+    /// Multiple sequence aligner
+    /// Options: mafft, muscle (MUSCLE 5), clustalo (Clustal Omega)
+    #[arg(
+        long,
+        default_value = "mafft",
+        help = "Multiple sequence aligner",
+        value_parser = builder::PossibleValuesParser::new(["mafft", "muscle", "clustalo"])
+    )]
+    pub aligner: String,
+    /// Keep samples that failed read cleaning QC
+    #[arg(long, help = "Keep samples that failed read cleaning QC")]
+    pub keep_failed_qc: bool,
+    #[command(flatten)]
+    pub reads: GenomicReadsInitArgs,
+    #[command(flatten)]
+    pub qc: ReadQcArgs,
+    #[command(flatten)]
+    pub contig_filter: ContigFilterArgs,
+    /// Exclude paralogous loci
+    #[arg(
+        long,
+        help = "Exclude loci flagged as paralogous in more than this percent of samples"
+    )]
+    pub max_paralog_percent: Option<f64>,
+    #[arg(
+        long,
+        help = "Write only the region matched by the probes plus flanks of this length (bp)"
+    )]
+    pub flank: Option<usize>,
+    /// Trim the alignments before filtering.
+    /// Only the locus filters are applied if not set.
+    #[arg(long, help = "Trim alignment edges before tree inference")]
+    pub trim_alignments: bool,
+    #[command(flatten)]
+    pub locus_filter: LocusFilterArgs,
+    #[command(flatten)]
+    pub iqtree: IqTreeSettingArgs,
+    #[command(flatten)]
+    pub aster: AsterSettingArgs,
+    #[command(flatten)]
+    pub common: CommonRunnerArgs,
+}
@@ -1,7 +1,7 @@
 //! Command-line interface for the binary ULLAR.
 use clap::Parser;
 use commands::{
-    alignment::{AlignmentArgs, AlignmentInitArgs, AlignmentSubcommand},
+    alignment::{AlignmentArgs, AlignmentInitArgs, AlignmentQcArgs, AlignmentSubcommand},
     assembly::{AssemblyArgs, AssemblyInitArgs, AssemblySubcommand},
     clean::{ReadCleaningInitArgs, ReadCleaningSubcommand},
     deps::DepsSubcommand,
@@ -15,13 +15,15 @@ use std::{path::PathBuf, time::Instant};
 
 use crate::{
     core::{
-        alignment::{init::AlignmentInit, SequenceAlignment},
+        alignment::{init::AlignmentInit, qc::AlignmentQc, SequenceAlignment},
         assembly::{init::AssemblyInit, Assembly},
         clean::{init::ReadCleaningInit, ReadCleaning},
         deps::DependencyCheck,
         map::{init::InitMappingConfig, ContigMapping, ReadMapping},
+        project::NewProject,
         tree::{init::TreeInferenceInit, TreeEstimation},
         utils::{checksum::Sha256Executor, rename::FileDirRename, scan::ReadScanner},
+        workflow::Workflow,
     },
     helper::{self, common::PrettyHeader},
 };
@@ -68,12 +70,13 @@ impl Cli {
 
     fn match_subcommand(&self) {
         match &self.command.sub_cmd {
-            UllarSubcommand::New(_) => unimplemented!("New project is not yet implemented"),
+            UllarSubcommand::New(args) => NewProject::from_arg(args).create(),
             UllarSubcommand::Clean(subcommand) => CleanArgParser::new(subcommand).parse(),
             UllarSubcommand::Assemble(subcommand) => AssemblyArgParser::new(subcommand).parse(),
             UllarSubcommand::Map(subcommand) => MapArgParser::new(subcommand).parse(),
             UllarSubcommand::Alignment(subcommand) => AlignmentArgParser::new(subcommand).parse(),
             UllarSubcommand::Tree(subcommand) => TreeArgParser::new(subcommand).parse(),
+            UllarSubcommand::Run(args) => Workflow::from_arg(args).run(),
             UllarSubcommand::Deps(subcommand) => self.parse_dependencies(subcommand),
             UllarSubcommand::Utils(util_args) => self.parse_utils(util_args),
         }
@@ -129,7 +132,7 @@ impl<'a> CleanArgParser<'a> {
             ReadCleaningSubcommand::Init(init_args) => self.init(init_args),
             ReadCleaningSubcommand::Clean(clean_args) => {
                 let cleaner = ReadCleaning::from_arg(clean_args);
-                cleaner.clean();
+                cleaner.clean().expect("Failed to clean reads");
             }
         }
     }
@@ -161,7 +164,9 @@ impl<'a> AssemblyArgParser<'a> {
     }
 
     fn assemble(&self, args: &AssemblyArgs) {
-        Assembly::from_arg(args).assemble();
+        Assembly::from_arg(args)
+            .assemble()
+            .expect("Failed to assemble reads");
     }
 }
 
@@ -177,8 +182,16 @@ impl<'a> MapArgParser<'a> {
     fn parse(&self) {
         match self.subcommand {
             MapSubcommand::Init(args) => InitMappingConfig::from_arg(args).init(),
-            MapSubcommand::Contig(args) => ContigMapping::from_arg(args).map(),
-            MapSubcommand::Read(args) => ReadMapping::from_arg(args).map(),
+            MapSubcommand::Contig(args) => {
+                ContigMapping::from_arg(args)
+                    .map()
+                    .expect("Failed to map contigs");
+            }
+            MapSubcommand::Read(args) => {
+                ReadMapping::from_arg(args)
+                    .map()
+                    .expect("Failed to map reads");
+            }
         }
     }
 }
@@ -196,6 +209,7 @@ impl<'a> AlignmentArgParser<'a> {
         match self.subcommand {
             AlignmentSubcommand::Init(init_args) => self.init(init_args),
             AlignmentSubcommand::Run(run_args) => self.run(run_args),
+            AlignmentSubcommand::Qc(qc_args) => self.qc(qc_args),
         }
     }
 
@@ -204,7 +218,13 @@ impl<'a> AlignmentArgParser<'a> {
     }
 
     fn run(&self, args: &AlignmentArgs) {
-        SequenceAlignment::from_arg(args).align();
+        SequenceAlignment::from_arg(args)
+            .align()
+            .expect("Failed to align sequences");
+    }
+
+    fn qc(&self, args: &AlignmentQcArgs) {
+        AlignmentQc::from_arg(args).trim();
     }
 }
 
@@ -220,7 +240,9 @@ impl<'a> TreeArgParser<'a> {
         match self.subcommand {
             TreeInferenceSubcommand::Init(args) => TreeInferenceInit::from_arg(args).init(),
             TreeInferenceSubcommand::Run(args) => {
-                TreeEstimation::from_arg(args).infer();
+                TreeEstimation::from_arg(args)
+                    .infer()
+                    .expect("Failed to run tree inference");
             }
         }
     }
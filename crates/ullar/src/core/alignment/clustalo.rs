@@ -0,0 +1,51 @@
+//! Align multiple sequences using Clustal Omega.
+use std::path::Path;
+use std::process::Command;
+
+use crate::core::deps::clustalo::CLUSTALO_EXE;
+use crate::core::deps::DepMetadata;
+use crate::parse_override_args;
+
+/// Default Clustal Omega parameters.
+/// Loci are aligned in parallel, so each
+/// Clustal Omega run uses a single thread.
+///
+/// Use --iter in --override-args to
+/// enable iterative refinement.
+pub const DEFAULT_CLUSTALO_PARAMS: &str = "--threads=1";
+
+/// Build the Clustal Omega command of a locus.
+/// Existing alignments are overwritten.
+pub struct ClustalOmegaRunner<'a> {
+    /// Path to the unaligned sequences
+    pub input_path: &'a Path,
+    pub output_path: &'a Path,
+    /// Override arguments replace DEFAULT_CLUSTALO_PARAMS
+    pub dep_metadata: &'a DepMetadata,
+}
+
+impl<'a> ClustalOmegaRunner<'a> {
+    pub fn new(input_path: &'a Path, output_path: &'a Path, dep_metadata: &'a DepMetadata) -> Self {
+        Self {
+            input_path,
+            output_path,
+            dep_metadata,
+        }
+    }
+
+    pub fn command(&self) -> Command {
+        let executable = self.dep_metadata.get_executable(CLUSTALO_EXE);
+        let mut cmd = Command::new(&executable);
+        cmd.arg("-i")
+            .arg(self.input_path)
+            .arg("-o")
+            .arg(self.output_path)
+            .arg("--outfmt=fasta")
+            .arg("--force");
+        match &self.dep_metadata.override_args {
+            Some(params) => parse_override_args!(cmd, params),
+            None => parse_override_args!(cmd, DEFAULT_CLUSTALO_PARAMS),
+        };
+        cmd
+    }
+}
@@ -0,0 +1,399 @@
+//! Codon-aware alignment of protein-coding loci.
+//!
+//! Sequences are translated, aligned as amino acids
+//! with the configured aligner, and back-translated to codons.
+//! Bases before the reading frame and incomplete codons
+//! at the end of a sequence are removed.
+use std::{
+    error::Error,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use segul::{
+    helper::{
+        sequence::SeqParser,
+        types::{DataType, Header, InputFmt, OutputFmt, SeqMatrix},
+    },
+    writer::sequences::SeqWriter,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{core::deps::DepMetadata, helper::files::FileMetadata, types::alignments::Aligner};
+
+use super::{mafft::DEFAULT_MAFFT_PARAMS, AlignerRunner};
+
+pub const CODON_PARTITION_FILE: &str = "codon_partition.csv";
+pub const FRAME_SHIFT_REPORT: &str = "frame_shift_report.csv";
+const TRANSLATION_DIR: &str = "codon_translations";
+const PROTEIN_ALIGNMENT_DIR: &str = "codon_protein_alignments";
+
+/// Amino acids of the standard genetic code.
+/// Codons are ordered by TCAG at each position.
+const STANDARD_CODE: &[u8] = b"FFLLSSSSYY**CC*WLLLLPPPPHHQQRRRRIIIMTTTTNNKKSSRRVVVVAAAADDEEGGGG";
+const CODON_BASES: &[u8] = b"TCAG";
+const STOP: u8 = b'*';
+
+/// Codon alignment settings
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CodonSettings {
+    /// Reading frame (1-3) of all loci.
+    /// Detected for each locus if not set.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reading_frame: Option<usize>,
+}
+
+impl CodonSettings {
+    pub fn new(reading_frame: Option<usize>) -> Self {
+        Self { reading_frame }
+    }
+
+    /// Check that the reading frame is between 1 and 3
+    pub fn validate(&self) -> Result<(), Box<dyn Error>> {
+        match self.reading_frame {
+            Some(frame) if !(1..=3).contains(&frame) => {
+                Err(format!("Invalid reading frame {}. Must be 1, 2, or 3", frame).into())
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Length of a codon-aligned locus.
+/// Every locus starts at the first codon position.
+/// Used to partition the concatenated alignment by codon position.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CodonPartition {
+    pub locus: String,
+    pub length: usize,
+}
+
+impl CodonPartition {
+    /// Read the partition written by a codon alignment run
+    pub fn from_csv(path: &Path) -> Result<Vec<CodonPartition>, Box<dyn Error>> {
+        let mut reader = csv::Reader::from_path(path)?;
+        let mut partitions = Vec::new();
+        for record in reader.deserialize() {
+            partitions.push(record?);
+        }
+        Ok(partitions)
+    }
+
+    /// Write the partition to a csv file
+    pub fn to_csv(partitions: &[CodonPartition], path: &Path) -> Result<(), Box<dyn Error>> {
+        let mut writer = csv::Writer::from_path(path)?;
+        for partition in partitions {
+            writer.serialize(partition)?;
+        }
+        writer.flush()?;
+        Ok(())
+    }
+}
+
+/// A sequence with internal stop codons
+/// in the codon alignment, likely from a frame shift
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct FrameShift {
+    pub locus: String,
+    pub sample: String,
+    pub stop_codons: usize,
+    /// Codon number of the first stop codon
+    /// in the unaligned sequence
+    pub first_stop: usize,
+}
+
+/// Codon partition and frame shifts of the codon alignments
+#[derive(Debug, Default)]
+pub struct CodonReport {
+    pub partitions: Vec<CodonPartition>,
+    pub frame_shifts: Vec<FrameShift>,
+}
+
+impl CodonReport {
+    /// Check the codon alignments written by the aligner
+    pub fn from_alignments(alignments: &[PathBuf]) -> Self {
+        let mut report = Self::default();
+        alignments.iter().for_each(|path| {
+            let locus = path
+                .file_stem()
+                .map(|s| s.to_string_lossy().to_string())
+                .unwrap_or_default();
+            let (matrix, header) = SeqParser::new(path, &DataType::Dna).parse(&InputFmt::Fasta);
+            report.partitions.push(CodonPartition {
+                locus: locus.clone(),
+                length: header.nchar,
+            });
+            matrix.iter().for_each(|(sample, sequence)| {
+                let ungapped = remove_gaps(sequence);
+                let stops = internal_stops(&translate(&ungapped, 1));
+                if let Some(first) = stops.first() {
+                    report.frame_shifts.push(FrameShift {
+                        locus: locus.clone(),
+                        sample: sample.to_string(),
+                        stop_codons: stops.len(),
+                        first_stop: first + 1,
+                    });
+                }
+            });
+        });
+        report.partitions.sort_by(|a, b| a.locus.cmp(&b.locus));
+        report
+            .frame_shifts
+            .sort_by(|a, b| a.locus.cmp(&b.locus).then(a.sample.cmp(&b.sample)));
+        report
+    }
+
+    /// Write the partition and frame shift report.
+    /// Returns the path to the partition file.
+    pub fn write(&self, output_dir: &Path) -> Result<PathBuf, Box<dyn Error>> {
+        let partition_path = output_dir.join(CODON_PARTITION_FILE);
+        CodonPartition::to_csv(&self.partitions, &partition_path)?;
+        let mut writer = csv::Writer::from_path(output_dir.join(FRAME_SHIFT_REPORT))?;
+        for frame_shift in &self.frame_shifts {
+            writer.serialize(frame_shift)?;
+        }
+        writer.flush()?;
+        Ok(partition_path)
+    }
+}
+
+/// Align a locus by its amino acid translation
+pub struct CodonAligner<'a> {
+    pub aligner: Aligner,
+    pub input_file: &'a FileMetadata,
+    /// Output directory. The codon alignment will be
+    ///     named the same as the input file
+    pub output_dir: &'a Path,
+    pub dep_metadata: &'a DepMetadata,
+    pub settings: &'a CodonSettings,
+}
+
+impl<'a> CodonAligner<'a> {
+    pub fn new(
+        aligner: Aligner,
+        input_file: &'a FileMetadata,
+        output_dir: &'a Path,
+        dep_metadata: &'a DepMetadata,
+        settings: &'a CodonSettings,
+    ) -> Self {
+        Self {
+            aligner,
+            input_file,
+            output_dir,
+            dep_metadata,
+            settings,
+        }
+    }
+
+    /// Translate, align, and back-translate the locus.
+    /// Return the output path if successful
+    pub fn run(&self) -> Result<PathBuf, Box<dyn Error>> {
+        let input_path = self.input_file.parent_dir.join(&self.input_file.file_name);
+        let (matrix, _) = SeqParser::new(&input_path, &DataType::Dna).parse(&InputFmt::Fasta);
+        let sequences: SeqMatrix = matrix
+            .iter()
+            .map(|(name, seq)| (name.to_string(), remove_gaps(seq)))
+            .collect();
+        let frame = match self.settings.reading_frame {
+            Some(frame) => frame,
+            None => detect_frame(&sequences),
+        };
+        let proteins: SeqMatrix = sequences
+            .iter()
+            .map(|(name, seq)| {
+                // Stop codons are written as unknown amino acids
+                // because not all aligners accept them.
+                let protein = translate(seq, frame).replace(STOP as char, "X");
+                (name.to_string(), protein)
+            })
+            .collect();
+        let translation_path = self.write_translation(&proteins)?;
+        let translation = FileMetadata::from_path(&translation_path);
+        let protein_dir = self.output_dir.join(PROTEIN_ALIGNMENT_DIR);
+        let protein_dep = protein_dep_metadata(self.aligner, self.dep_metadata);
+        let protein_path =
+            AlignerRunner::new(self.aligner, &translation, &protein_dir, &protein_dep).run()?;
+        let (aligned, _) = SeqParser::new(&protein_path, &DataType::Aa).parse(&InputFmt::Fasta);
+        let mut codons = SeqMatrix::new();
+        for (name, protein) in aligned.iter() {
+            let sequence = sequences
+                .get(name)
+                .ok_or_else(|| format!("Sequence {} not found after aligning", name))?;
+            codons.insert(name.to_string(), back_translate(protein, sequence, frame));
+        }
+        let output_path = AlignerRunner::new(
+            self.aligner,
+            self.input_file,
+            self.output_dir,
+            self.dep_metadata,
+        )
+        .create_output_path()?;
+        let mut header = Header::default();
+        header.from_seq_matrix(&codons, true);
+        SeqWriter::new(&output_path, &codons, &header).write_sequence(&OutputFmt::Fasta)?;
+        Ok(output_path)
+    }
+
+    fn write_translation(&self, proteins: &SeqMatrix) -> Result<PathBuf, Box<dyn Error>> {
+        let translation_dir = self.output_dir.join(TRANSLATION_DIR);
+        fs::create_dir_all(&translation_dir)?;
+        let output_path = translation_dir.join(&self.input_file.file_name);
+        let mut header = Header::default();
+        header.from_seq_matrix(proteins, false);
+        SeqWriter::new(&output_path, proteins, &header).write_sequence(&OutputFmt::Fasta)?;
+        Ok(output_path)
+    }
+}
+
+/// Drop MAFFT direction adjustment when aligning amino acids.
+/// Proteins have no reverse complement, and MAFFT prefixes
+/// the names of adjusted sequences with `_R_`.
+fn protein_dep_metadata(aligner: Aligner, dep_metadata: &DepMetadata) -> DepMetadata {
+    let mut dep_metadata = dep_metadata.clone();
+    if aligner == Aligner::Mafft {
+        let params = dep_metadata
+            .override_args
+            .as_deref()
+            .unwrap_or(DEFAULT_MAFFT_PARAMS);
+        let params: Vec<&str> = params
+            .split_whitespace()
+            .filter(|param| !param.starts_with("--adjustdirection"))
+            .collect();
+        dep_metadata.override_args = Some(params.join(" "));
+    }
+    dep_metadata
+}
+
+/// Select the frame with the fewest internal stop codons.
+/// Ties are resolved to the lowest frame.
+pub fn detect_frame(sequences: &SeqMatrix) -> usize {
+    (1..=3)
+        .min_by_key(|frame| {
+            sequences
+                .values()
+                .map(|s| internal_stops(&translate(s, *frame)).len())
+                .sum::<usize>()
+        })
+        .unwrap_or(1)
+}
+
+/// Translate an ungapped sequence using the standard genetic code.
+/// Codons with ambiguous bases are translated as X.
+pub fn translate(sequence: &str, frame: usize) -> String {
+    sequence
+        .as_bytes()
+        .get(frame.saturating_sub(1)..)
+        .unwrap_or_default()
+        .chunks_exact(3)
+        .map(|codon| translate_codon(codon) as char)
+        .collect()
+}
+
+/// Replace each amino acid with its codon
+/// and each gap with a codon gap
+fn back_translate(protein: &str, sequence: &str, frame: usize) -> String {
+    let mut codons = sequence
+        .as_bytes()
+        .get(frame.saturating_sub(1)..)
+        .unwrap_or_default()
+        .chunks_exact(3);
+    let mut back_translated = String::with_capacity(protein.len() * 3);
+    protein.bytes().for_each(|aa| {
+        if aa == b'-' {
+            back_translated.push_str("---");
+        } else {
+            match codons.next() {
+                Some(codon) => back_translated.push_str(&String::from_utf8_lossy(codon)),
+                None => back_translated.push_str("NNN"),
+            }
+        }
+    });
+    back_translated
+}
+
+fn translate_codon(codon: &[u8]) -> u8 {
+    let mut index = 0;
+    for base in codon {
+        let base = match base.to_ascii_uppercase() {
+            b'U' => b'T',
+            base => base,
+        };
+        match CODON_BASES.iter().position(|b| *b == base) {
+            Some(i) => index = index * 4 + i,
+            None => return b'X',
+        }
+    }
+    STANDARD_CODE[index]
+}
+
+// Positions of stop codons, excluding a stop codon at the end
+fn internal_stops(protein: &str) -> Vec<usize> {
+    let protein = protein.strip_suffix(STOP as char).unwrap_or(protein);
+    protein
+        .bytes()
+        .enumerate()
+        .filter(|(_, aa)| *aa == STOP)
+        .map(|(i, _)| i)
+        .collect()
+}
+
+fn remove_gaps(sequence: &str) -> String {
+    sequence
+        .chars()
+        .filter(|c| *c != '-' && *c != '?')
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_translate() {
+        assert_eq!(translate("ATGGCCTAA", 1), "MA*");
+        assert_eq!(translate("CATGGCNTAAG", 2), "MX*");
+        assert_eq!(internal_stops("MA*"), Vec::<usize>::new());
+        assert_eq!(internal_stops("M*A*"), vec![1]);
+    }
+
+    #[test]
+    fn test_protein_dep_metadata() {
+        let dep = DepMetadata::default();
+        let protein_dep = protein_dep_metadata(Aligner::Mafft, &dep);
+        assert_eq!(
+            protein_dep.override_args.as_deref(),
+            Some("--maxiterate 1000")
+        );
+        let dep = DepMetadata {
+            override_args: Some(String::from("--auto --adjustdirectionaccurately")),
+            ..Default::default()
+        };
+        let protein_dep = protein_dep_metadata(Aligner::Mafft, &dep);
+        assert_eq!(protein_dep.override_args.as_deref(), Some("--auto"));
+        let protein_dep = protein_dep_metadata(Aligner::Muscle, &dep);
+        assert_eq!(protein_dep.override_args, dep.override_args);
+    }
+
+    #[test]
+    fn test_detect_frame() {
+        let sequences: SeqMatrix = [("sample_1", "TATGATTAGGCTA"), ("sample_2", "TATGATTAGGCTG")]
+            .iter()
+            .map(|(name, seq)| (name.to_string(), seq.to_string()))
+            .collect();
+        assert_eq!(detect_frame(&sequences), 2);
+    }
+
+    #[test]
+    fn test_back_translate() {
+        assert_eq!(back_translate("M-A", "GATGGCCT", 2), "ATG---GCC");
+        assert_eq!(back_translate("MAK", "ATGGCC", 1), "ATGGCCNNN");
+    }
+
+    #[test]
+    fn test_validate_reading_frame() {
+        assert!(CodonSettings::new(None).validate().is_ok());
+        assert!(CodonSettings::new(Some(3)).validate().is_ok());
+        assert!(CodonSettings::new(Some(0)).validate().is_err());
+        assert!(CodonSettings::new(Some(4)).validate().is_err());
+    }
+}
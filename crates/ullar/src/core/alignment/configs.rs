@@ -10,17 +10,24 @@ use segul::helper::{finder::SeqFileFinder, types::InputFmt};
 use serde::{Deserialize, Serialize};
 
 use crate::{
-    core::deps::{mafft::MafftMetadata, segul::get_segul_metadata, DepMetadata},
+    core::deps::{
+        clustalo::ClustalOmegaMetadata, mafft::MafftMetadata, muscle::MuscleMetadata,
+        segul::get_segul_metadata, DepMetadata,
+    },
     helper::{
         alignments::{FilteredSequenceInput, SequenceInput},
         common::UllarConfig,
-        configs::generate_config_output_path,
+        configs::{generate_config_output_path, PreviousStep},
         files::FileMetadata,
     },
+    types::alignments::Aligner,
 };
 
+use super::{codon::CodonSettings, filter::LocusFilter, trim::TrimParams};
+
 pub const DEFAULT_ALIGNMENT_CONFIG: &str = "sequence_alignment";
 pub const ALIGNER_DEPENDENCY: &str = "aligner";
+pub const DEFAULT_ALIGNMENT_QC_CONFIG: &str = "alignment_qc";
 
 #[derive(Debug, Default, Serialize, Deserialize)]
 pub struct AlignmentConfig {
@@ -28,6 +35,13 @@ pub struct AlignmentConfig {
     pub app: UllarConfig,
     pub input: SequenceInput,
     pub dependencies: BTreeMap<String, DepMetadata>,
+    /// Pipeline step that produced the input
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub previous_step: Option<PreviousStep>,
+    /// Align protein-coding loci by codons.
+    /// Nucleotides are aligned directly if not set.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub codon: Option<CodonSettings>,
     pub sequences: Vec<FileMetadata>,
 }
 
@@ -37,6 +51,8 @@ impl AlignmentConfig {
             app: UllarConfig::init(),
             input: SequenceInput::default(),
             dependencies: BTreeMap::new(),
+            previous_step: None,
+            codon: None,
             sequences,
         }
     }
@@ -50,7 +66,7 @@ impl AlignmentConfig {
     pub fn from_toml(config_path: &Path) -> Result<Self, Box<dyn Error>> {
         let content = fs::read_to_string(config_path)?;
         let ext = config_path.extension().unwrap_or_default();
-        if ext == "yaml" || ext == "yml" {
+        let config: Self = if ext == "yaml" || ext == "yml" {
             let config = serde_yaml::from_str(&content)?;
             let toml = toml::to_string_pretty(&config)?;
             let config_path = config_path.with_extension("toml");
@@ -59,14 +75,22 @@ impl AlignmentConfig {
                 "Converted YAML config to TOML format: {}",
                 config_path.display()
             );
-            return Ok(config);
+            config
+        } else {
+            toml::from_str(&content)?
+        };
+        if let Some(codon) = &config.codon {
+            codon.validate()?;
         }
-        let config = toml::from_str(&content)?;
         Ok(config)
     }
 
-    pub fn to_toml(&mut self, override_args: Option<&str>) -> Result<PathBuf, Box<dyn Error>> {
-        self.get_dependency(override_args);
+    pub fn to_toml(
+        &mut self,
+        aligner: Aligner,
+        override_args: Option<&str>,
+    ) -> Result<PathBuf, Box<dyn Error>> {
+        self.get_dependency(aligner, override_args);
         let output_path = generate_config_output_path(DEFAULT_ALIGNMENT_CONFIG);
         let config = toml::to_string_pretty(self)?;
         fs::write(&output_path, config)?;
@@ -76,7 +100,7 @@ impl AlignmentConfig {
     /// Get raw loci files
     #[deprecated(since = "0.5.0", note = "Use `to_toml` instead")]
     pub fn to_yaml(&mut self, override_args: Option<&str>) -> Result<PathBuf, Box<dyn Error>> {
-        self.get_dependency(override_args);
+        self.get_dependency(Aligner::Mafft, override_args);
         let output_path = generate_config_output_path(DEFAULT_ALIGNMENT_CONFIG);
         let writer = File::create(&output_path)?;
         serde_yaml::to_writer(&writer, self)?;
@@ -105,18 +129,62 @@ impl AlignmentConfig {
             .collect()
     }
 
-    fn get_dependency(&mut self, override_args: Option<&str>) {
-        let mafft = MafftMetadata::new().override_args(override_args).get();
-
-        match mafft {
-            Some(metadata) => self
-                .dependencies
-                .insert(ALIGNER_DEPENDENCY.to_string(), metadata),
-            None => panic!("MAFFT dependency not found. Please install MAFFT."),
+    fn get_dependency(&mut self, aligner: Aligner, override_args: Option<&str>) {
+        let dep = match aligner {
+            Aligner::Mafft => MafftMetadata::new()
+                .override_args(override_args)
+                .get()
+                .unwrap_or_else(|| panic!("MAFFT dependency not found. Please install MAFFT.")),
+            Aligner::Muscle => MuscleMetadata::new()
+                .override_args(override_args)
+                .get()
+                .unwrap_or_else(|| panic!("MUSCLE dependency not found. Please install MUSCLE 5.")),
+            Aligner::ClustalOmega => ClustalOmegaMetadata::new()
+                .override_args(override_args)
+                .get()
+                .unwrap_or_else(|| {
+                    panic!("Clustal Omega dependency not found. Please install Clustal Omega.")
+                }),
         };
+        self.dependencies
+            .insert(ALIGNER_DEPENDENCY.to_string(), dep);
 
         let segul = get_segul_metadata();
         self.dependencies
             .insert(segul.app_name.to_lowercase(), segul);
     }
 }
+
+/// Alignment QC thresholds.
+/// Written before trimming so the run can be repeated.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct AlignmentQcConfig {
+    #[serde(flatten)]
+    pub app: UllarConfig,
+    pub trimming: TrimParams,
+    #[serde(default)]
+    pub filtering: LocusFilter,
+}
+
+impl AlignmentQcConfig {
+    pub fn new(trimming: TrimParams, filtering: LocusFilter) -> Self {
+        Self {
+            app: UllarConfig::init(),
+            trimming,
+            filtering,
+        }
+    }
+
+    pub fn from_toml(config_path: &Path) -> Result<Self, Box<dyn Error>> {
+        let content = fs::read_to_string(config_path)?;
+        let config = toml::from_str(&content)?;
+        Ok(config)
+    }
+
+    pub fn to_toml(&self) -> Result<PathBuf, Box<dyn Error>> {
+        let output_path = generate_config_output_path(DEFAULT_ALIGNMENT_QC_CONFIG);
+        let config = toml::to_string_pretty(self)?;
+        fs::write(&output_path, config)?;
+        Ok(output_path)
+    }
+}
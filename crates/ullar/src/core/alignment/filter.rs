@@ -0,0 +1,221 @@
+//! Filter loci by taxon completeness
+//! and parsimony-informative sites.
+use std::{
+    collections::HashSet,
+    error::Error,
+    fmt::Display,
+    path::{Path, PathBuf},
+};
+
+use segul::helper::types::{DataType, SeqMatrix};
+use serde::{Deserialize, Serialize};
+
+use crate::{cli::commands::alignment::LocusFilterArgs, helper::alignments::residues};
+
+pub const DROPPED_LOCI_FILE: &str = "dropped_loci.csv";
+/// Alignments with fewer sequences are always dropped
+pub const MIN_SEQUENCES: usize = 2;
+
+/// Locus filtering thresholds.
+/// Each filter is skipped if its threshold is not set.
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+pub struct LocusFilter {
+    /// Minimum proportion of all taxa
+    /// present in the locus
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub min_taxon_completeness: Option<f64>,
+    /// Minimum number of parsimony-informative sites
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub min_parsimony_informative: Option<usize>,
+}
+
+impl LocusFilter {
+    pub fn new(
+        min_taxon_completeness: Option<f64>,
+        min_parsimony_informative: Option<usize>,
+    ) -> Self {
+        Self {
+            min_taxon_completeness,
+            min_parsimony_informative,
+        }
+    }
+
+    pub fn from_arg(args: &LocusFilterArgs) -> Self {
+        Self::new(args.min_taxon_completeness, args.min_pis)
+    }
+
+    /// Check the alignment against the thresholds.
+    /// Returns the first failed filter, if any.
+    pub fn check(
+        &self,
+        locus: &str,
+        matrix: &SeqMatrix,
+        datatype: &DataType,
+        total_taxa: usize,
+    ) -> Option<DroppedLocus> {
+        let taxon_counts = matrix.len();
+        if taxon_counts < MIN_SEQUENCES {
+            return Some(DroppedLocus::new(
+                locus,
+                DropReason::TooFewSequences,
+                taxon_counts as f64,
+                MIN_SEQUENCES as f64,
+            ));
+        }
+        if let Some(min_completeness) = self.min_taxon_completeness {
+            let completeness = if total_taxa == 0 {
+                0.0
+            } else {
+                taxon_counts as f64 / total_taxa as f64
+            };
+            if completeness < min_completeness {
+                return Some(DroppedLocus::new(
+                    locus,
+                    DropReason::TaxonCompleteness,
+                    completeness,
+                    min_completeness,
+                ));
+            }
+        }
+        if let Some(min_sites) = self.min_parsimony_informative {
+            let sites = parsimony_informative_sites(matrix, datatype);
+            if sites < min_sites {
+                return Some(DroppedLocus::new(
+                    locus,
+                    DropReason::ParsimonyInformative,
+                    sites as f64,
+                    min_sites as f64,
+                ));
+            }
+        }
+        None
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DropReason {
+    TooFewSequences,
+    TaxonCompleteness,
+    ParsimonyInformative,
+}
+
+impl Display for DropReason {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DropReason::TooFewSequences => write!(f, "too_few_sequences"),
+            DropReason::TaxonCompleteness => write!(f, "taxon_completeness"),
+            DropReason::ParsimonyInformative => write!(f, "parsimony_informative"),
+        }
+    }
+}
+
+/// A dropped locus and the failed threshold
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DroppedLocus {
+    pub locus: String,
+    pub reason: DropReason,
+    /// Value of the locus for the failed filter
+    pub value: f64,
+    pub threshold: f64,
+}
+
+impl DroppedLocus {
+    pub fn new(locus: &str, reason: DropReason, value: f64, threshold: f64) -> Self {
+        Self {
+            locus: locus.to_string(),
+            reason,
+            value,
+            threshold,
+        }
+    }
+
+    /// Read the dropped loci written by a previous alignment QC run
+    pub fn from_csv(path: &Path) -> Result<Vec<DroppedLocus>, Box<dyn Error>> {
+        let mut reader = csv::Reader::from_path(path)?;
+        let mut dropped = Vec::new();
+        for record in reader.deserialize() {
+            dropped.push(record?);
+        }
+        Ok(dropped)
+    }
+}
+
+/// Remove loci listed in the dropped loci file
+/// of the input directory, in case they are still
+/// in the directory from an earlier run.
+pub fn remove_dropped_loci(input_dir: &Path, files: &mut Vec<PathBuf>) {
+    let dropped_path = input_dir.join(DROPPED_LOCI_FILE);
+    if !dropped_path.is_file() {
+        return;
+    }
+    let dropped: HashSet<String> = DroppedLocus::from_csv(&dropped_path)
+        .unwrap_or_default()
+        .into_iter()
+        .map(|d| d.locus)
+        .collect();
+    files.retain(|f| {
+        !f.file_stem()
+            .is_some_and(|stem| dropped.contains(stem.to_string_lossy().as_ref()))
+    });
+}
+
+/// Count sites with at least two nucleotides or amino acids
+/// that each occur in at least two sequences.
+/// Gaps, missing data, and ambiguous characters are ignored.
+pub fn parsimony_informative_sites(matrix: &SeqMatrix, datatype: &DataType) -> usize {
+    let sequences: Vec<&[u8]> = matrix.values().map(|s| s.as_bytes()).collect();
+    let site_counts = sequences.iter().map(|s| s.len()).max().unwrap_or(0);
+    let residues = residues(datatype);
+    (0..site_counts)
+        .filter(|i| {
+            let mut counts = vec![0usize; residues.len()];
+            sequences.iter().for_each(|s| {
+                if let Some(c) = s.get(*i) {
+                    let c = c.to_ascii_uppercase();
+                    if let Some(n) = residues.iter().position(|n| *n == c) {
+                        counts[n] += 1;
+                    }
+                }
+            });
+            counts.iter().filter(|c| **c >= 2).count() >= 2
+        })
+        .count()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn matrix(sequences: &[(&str, &str)]) -> SeqMatrix {
+        sequences
+            .iter()
+            .map(|(name, seq)| (name.to_string(), seq.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn test_locus_filter() {
+        let alignment = matrix(&[
+            ("sample_1", "ACGTA"),
+            ("sample_2", "ACGTT"),
+            ("sample_3", "AGGAT"),
+            ("sample_4", "AGGA-"),
+        ]);
+        assert_eq!(parsimony_informative_sites(&alignment, &DataType::Dna), 2);
+        let filter = LocusFilter::new(Some(0.8), Some(2));
+        assert!(filter
+            .check("uce-1", &alignment, &DataType::Dna, 5)
+            .is_none());
+        let dropped = filter
+            .check("uce-1", &alignment, &DataType::Dna, 6)
+            .unwrap();
+        assert_eq!(dropped.reason, DropReason::TaxonCompleteness);
+        let filter = LocusFilter::new(None, Some(3));
+        let dropped = filter
+            .check("uce-1", &alignment, &DataType::Dna, 4)
+            .unwrap();
+        assert_eq!(dropped.reason, DropReason::ParsimonyInformative);
+        assert_eq!(dropped.value, 2.0);
+    }
+}
@@ -9,13 +9,19 @@ use segul::helper::types::InputFmt;
 use crate::cli::commands::alignment::AlignmentInitArgs;
 use crate::cli::commands::common::CommonInitArgs;
 use crate::core::alignment::SequenceAlignment;
+use crate::core::project::manifest::{ProjectManifest, TaskStatus};
 use crate::helper::common::{self, PrettyHeader};
+use crate::types::{alignments::Aligner, Task};
 
-use super::configs::AlignmentConfig;
+use super::{codon::CodonSettings, configs::AlignmentConfig};
 
 pub struct AlignmentInit<'a> {
     pub input_dir: &'a Path,
     pub input_fmt: InputFmt,
+    pub aligner: Aligner,
+    /// Codon alignment settings.
+    /// Nucleotides are aligned directly if not set.
+    pub codon: Option<CodonSettings>,
     pub common: &'a CommonInitArgs,
 }
 
@@ -23,8 +29,12 @@ impl<'a> AlignmentInit<'a> {
     pub fn new(args: &'a AlignmentInitArgs) -> Self {
         Self {
             input_dir: &args.dir,
-            // Mafft only supports FASTA format
+            // Only FASTA format is supported by all aligners
             input_fmt: InputFmt::Fasta,
+            aligner: args.aligner.parse().expect("Invalid aligner"),
+            codon: args
+                .codon
+                .then(|| CodonSettings::new(args.reading_frame.map(usize::from))),
             common: &args.common,
         }
     }
@@ -46,6 +56,11 @@ impl<'a> AlignmentInit<'a> {
                     "✔".green()
                 ));
                 self.log_final_output(&path, &config);
+                ProjectManifest::record(
+                    Task::SequenceAlignment,
+                    TaskStatus::Initialized,
+                    Some(&path),
+                );
                 if self.common.autorun {
                     let footer = PrettyHeader::new();
                     footer.get_section_footer();
@@ -64,25 +79,28 @@ impl<'a> AlignmentInit<'a> {
         log::info!("{}", header.cyan());
         log::info!("");
         let runner = SequenceAlignment::from_config_path(config_path);
-        runner.align();
+        runner.align().expect("Failed to align sequences");
     }
 
     fn write_config(&self) -> Result<(PathBuf, AlignmentConfig), Box<dyn Error>> {
         let mut config = AlignmentConfig::default();
         config.init(self.input_dir, &self.input_fmt);
+        config.codon = self.codon.clone();
         if config.sequences.is_empty() {
             return Err(
                 "No sequence found in the input directory. Please, check input is FASTA".into(),
             );
         }
-        let output_path = config.to_toml(self.common.override_args.as_deref())?;
+        let output_path = config.to_toml(self.aligner, self.common.override_args.as_deref())?;
         Ok((output_path, config))
     }
 
     fn log_input(&self) {
         log::info!("{}", "Input".cyan());
         log::info!("{:18}: {}", "Directory", self.input_dir.display());
-        log::info!("{:18}: {}\n", "Task", "Initialize alignment config");
+        log::info!("{:18}: {}", "Task", "Initialize alignment config");
+        log::info!("{:18}: {}", "Aligner", self.aligner);
+        log::info!("{:18}: {}\n", "Codon alignment", self.codon.is_some());
     }
 
     fn log_final_output(&self, config_path: &Path, config: &AlignmentConfig) {
@@ -1,32 +1,51 @@
+pub mod clustalo;
+pub mod codon;
 pub mod configs;
+pub mod filter;
 pub mod init;
 pub mod mafft;
+pub mod muscle;
+pub mod qc;
 pub mod reports;
+pub mod summary;
+pub mod trim;
 
 use std::{
     error::Error,
+    fs,
     path::{Path, PathBuf},
+    process::Output,
     sync::mpsc,
 };
 
+use clustalo::{ClustalOmegaRunner, DEFAULT_CLUSTALO_PARAMS};
+use codon::{CodonAligner, CodonReport, CodonSettings};
 use colored::Colorize;
 use configs::{AlignmentConfig, ALIGNER_DEPENDENCY};
 use mafft::{MafftRunner, DEFAULT_MAFFT_PARAMS};
+use muscle::{MuscleRunner, DEFAULT_MUSCLE_PARAMS};
 use rayon::prelude::*;
 use reports::MafftReport;
+use summary::AlignmentSummary;
 
 use crate::{
     cli::commands::alignment::AlignmentArgs,
     helper::{
         common,
         files::{FileMetadata, PathCheck},
+        ledger::{self, LedgerEntry, LedgerStatus, StepLedger},
     },
-    types::{runner::RunnerOptions, Task},
+    types::{alignments::Aligner, runner::RunnerOptions, Task},
 };
 
-use crate::core::deps::mafft::MafftMetadata;
+use crate::core::deps::{
+    clustalo::ClustalOmegaMetadata, mafft::MafftMetadata, muscle::MuscleMetadata,
+};
 
-use super::deps::DepMetadata;
+use super::{
+    deps::DepMetadata,
+    project::manifest::{ProjectManifest, TaskStatus},
+};
 
 pub const DEFAULT_ALIGNMENT_OUTPUT_DIR: &str = "out_sequence_alignment";
 
@@ -81,20 +100,64 @@ impl<'a> SequenceAlignment<'a> {
     /// 4. Check configuration
     /// 6. If dry-run, print the summary and exit
     /// 7. Align the sequences
-    pub fn align(&self) {
+    /// 8. Write the per-locus and dataset summary
+    ///
+    /// Returns the report of the finished alignments.
+    pub fn align(&self) -> Result<MafftReport, Box<dyn Error>> {
         let spinner = common::init_spinner();
         spinner.set_message("Parsing config file");
-        let config = self.parse_config().expect("Failed to parse config");
+        let config = self.parse_config()?;
         spinner.finish_with_message(format!("{} Finished parsing config file\n", "✔".green()));
-        let aligner = config.dependencies.get(ALIGNER_DEPENDENCY);
-        let updated_dep = MafftMetadata::new().update(aligner);
-        self.log_input(&config, &updated_dep);
+        let dep = config.dependencies.get(ALIGNER_DEPENDENCY);
+        let aligner = Aligner::from_dependency(dep);
+        let updated_dep = match aligner {
+            Aligner::Mafft => MafftMetadata::new().update(dep),
+            Aligner::Muscle => MuscleMetadata::new().update(dep),
+            Aligner::ClustalOmega => ClustalOmegaMetadata::new().update(dep),
+        };
+        self.log_input(&config, aligner, &updated_dep);
         PathCheck::new(self.output_dir)
             .is_dir()
             .with_force_overwrite(self.runner.overwrite)
+            .with_resume(self.runner.resume)
             .prompt_exists(self.runner.dry_run);
-        let reports = self.par_align(&config.sequences, &updated_dep);
-        self.log_final_output(&reports);
+        ProjectManifest::record(self.task, TaskStatus::Running, Some(self.config_path));
+        let reports = self.par_align(
+            &config.sequences,
+            aligner,
+            &updated_dep,
+            config.codon.as_ref(),
+        );
+        let status =
+            TaskStatus::from_success(reports.alignments.file_counts == config.sequences.len());
+        ProjectManifest::record(self.task, status, Some(self.config_path));
+        let summary = self.summarize(&reports);
+        let summary_paths = match summary.write() {
+            Ok(paths) => Some(paths),
+            Err(e) => {
+                log::error!("Failed to write alignment summary: {}", e);
+                None
+            }
+        };
+        self.log_final_output(&reports, &summary, summary_paths.as_ref());
+        if config.codon.is_some() {
+            self.write_codon_report(&reports);
+        }
+        Ok(reports)
+    }
+
+    fn get_output_paths(&self, reports: &MafftReport) -> Vec<PathBuf> {
+        reports
+            .alignments
+            .files
+            .iter()
+            .map(|f| f.parent_dir.join(&f.file_name))
+            .collect()
+    }
+
+    fn summarize(&self, reports: &MafftReport) -> AlignmentSummary<'a> {
+        let files = self.get_output_paths(reports);
+        AlignmentSummary::from_files(self.output_dir, &files, &reports.format, &reports.datatype)
     }
 
     fn parse_config(&self) -> Result<AlignmentConfig, Box<dyn Error>> {
@@ -102,13 +165,54 @@ impl<'a> SequenceAlignment<'a> {
         Ok(config)
     }
 
-    fn par_align(&self, sequences: &[FileMetadata], mafft: &DepMetadata) -> MafftReport {
+    fn par_align(
+        &self,
+        sequences: &[FileMetadata],
+        aligner: Aligner,
+        dep: &DepMetadata,
+        codon: Option<&CodonSettings>,
+    ) -> MafftReport {
         let progress_bar = common::init_progress_bar(sequences.len() as u64);
         log::info!("{}", "Aligning sequences".cyan());
         progress_bar.set_message("Alignments");
+        let ledger = StepLedger::new(self.output_dir);
+        // Codon alignments are not reused for nucleotide alignments
+        // and the other way around.
+        let params_hash = match codon {
+            Some(settings) => ledger::hash_string(&format!(
+                "{}{}",
+                ledger::hash_params(dep),
+                ledger::hash_params(settings)
+            )),
+            None => ledger::hash_params(dep),
+        };
         let (tx, rx) = mpsc::channel();
         sequences.par_iter().for_each_with(tx, |tx, file| {
-            let output = self.align_mafft(file, mafft);
+            let runner = AlignerRunner::new(aligner, file, self.output_dir, dep);
+            let input_hash = ledger::hash_files(&[file]);
+            if self.runner.resume && ledger.is_completed(&file.file_name, &input_hash, &params_hash)
+            {
+                if let Ok(path) = runner.create_output_path() {
+                    if path.is_file() {
+                        tx.send(path).expect("Failed to send output path");
+                        progress_bar.inc(1);
+                        return;
+                    }
+                }
+            }
+            let output = match codon {
+                Some(settings) => {
+                    CodonAligner::new(aligner, file, self.output_dir, dep, settings).run()
+                }
+                None => runner.run(),
+            };
+            let status = LedgerStatus::from_success(output.is_ok());
+            ledger.record(&LedgerEntry::new(
+                &file.file_name,
+                &input_hash,
+                &params_hash,
+                status,
+            ));
             match output {
                 Ok(path) => tx.send(path).expect("Failed to send output path"),
                 Err(e) => log::error!("Failed to align {}: {}", file.file_name.red(), e),
@@ -124,16 +228,7 @@ impl<'a> SequenceAlignment<'a> {
         report
     }
 
-    fn align_mafft(
-        &self,
-        input: &FileMetadata,
-        mafft: &DepMetadata,
-    ) -> Result<PathBuf, Box<dyn Error>> {
-        let mafft = MafftRunner::new(input, self.output_dir, mafft);
-        mafft.run()
-    }
-
-    fn log_input(&self, config: &AlignmentConfig, dep: &DepMetadata) {
+    fn log_input(&self, config: &AlignmentConfig, aligner: Aligner, dep: &DepMetadata) {
         log::info!("{}", "Input".cyan());
         log::info!("{:18}: {}", "Config path", self.config_path.display());
         log::info!("{:18}: {}", "Sample counts", config.input.sample_counts);
@@ -141,16 +236,40 @@ impl<'a> SequenceAlignment<'a> {
         log::info!("{:18}: {}", "File skipped", config.input.file_skipped);
         log::info!("{:18}: {}", "Final file count", config.input.file_counts);
         log::info!("{:18}: {}", "Task", self.task);
-        log::info!("{:18}: {} v{}", "Aligner", "MAFFT", dep.version);
-        let params = dep
-            .override_args
-            .as_ref()
-            .unwrap_or(&DEFAULT_MAFFT_PARAMS.to_string())
-            .to_string();
-        log::info!("{:18}: {}\n", "Parameters", params);
+        log::info!("{:18}: {} v{}", "Aligner", dep.app_name, dep.version);
+        let default_params = match aligner {
+            Aligner::Mafft => DEFAULT_MAFFT_PARAMS,
+            Aligner::Muscle => DEFAULT_MUSCLE_PARAMS,
+            Aligner::ClustalOmega => DEFAULT_CLUSTALO_PARAMS,
+        };
+        let params = dep.override_args.as_deref().unwrap_or(default_params);
+        log::info!("{:18}: {}", "Parameters", params);
+        match config.codon.as_ref().map(|c| c.reading_frame) {
+            Some(Some(frame)) => log::info!("{:18}: frame {}\n", "Codon alignment", frame),
+            Some(None) => log::info!("{:18}: {}\n", "Codon alignment", "detect frame"),
+            None => log::info!("{:18}: {}\n", "Codon alignment", "None"),
+        }
     }
 
-    fn log_final_output(&self, reports: &MafftReport) {
+    fn write_codon_report(&self, reports: &MafftReport) {
+        let files = self.get_output_paths(reports);
+        let report = CodonReport::from_alignments(&files);
+        match report.write(self.output_dir) {
+            Ok(path) => {
+                log::info!("{:18}: {}", "Codon partition", path.display());
+                log::info!("{:18}: {}", "Frame shifts", report.frame_shifts.len());
+            }
+            Err(e) => log::error!("Failed to write codon report: {}", e),
+        }
+    }
+
+    fn log_final_output(
+        &self,
+        reports: &MafftReport,
+        summary: &AlignmentSummary,
+        summary_paths: Option<&(PathBuf, PathBuf)>,
+    ) {
+        summary.log_dataset();
         log::info!("{}", "Output".cyan());
         log::info!("{:18}: {}", "Directory", self.output_dir.display());
         log::info!("{:18}: {}", "File counts", reports.alignments.file_counts);
@@ -159,5 +278,78 @@ impl<'a> SequenceAlignment<'a> {
             "Sample counts",
             reports.alignments.sample_counts
         );
+        if let Some((locus_path, dataset_path)) = summary_paths {
+            log::info!("{:18}: {}", "Locus summary", locus_path.display());
+            log::info!("{:18}: {}", "Dataset summary", dataset_path.display());
+        }
+    }
+}
+
+/// Align a locus with the selected aligner.
+/// Each aligner module only builds its command.
+struct AlignerRunner<'a> {
+    aligner: Aligner,
+    input_file: &'a FileMetadata,
+    /// Output directory. The alignment will be
+    ///     named the same as the input file
+    output_dir: &'a Path,
+    dep: &'a DepMetadata,
+}
+
+impl<'a> AlignerRunner<'a> {
+    fn new(
+        aligner: Aligner,
+        input_file: &'a FileMetadata,
+        output_dir: &'a Path,
+        dep: &'a DepMetadata,
+    ) -> Self {
+        Self {
+            aligner,
+            input_file,
+            output_dir,
+            dep,
+        }
+    }
+
+    /// Return the output path if successful
+    fn run(&self) -> Result<PathBuf, Box<dyn Error>> {
+        let input_path = self.get_input_path()?;
+        let output_path = self.create_output_path()?;
+        let mut cmd = match self.aligner {
+            Aligner::Mafft => MafftRunner::new(&input_path, self.dep).command(),
+            Aligner::Muscle => MuscleRunner::new(&input_path, &output_path, self.dep).command(),
+            Aligner::ClustalOmega => {
+                ClustalOmegaRunner::new(&input_path, &output_path, self.dep).command()
+            }
+        };
+        let output = cmd.output()?;
+        self.check_success(&output)?;
+        if self.aligner == Aligner::Mafft && !output.stdout.is_empty() {
+            fs::write(&output_path, &output.stdout)?;
+        }
+        Ok(output_path)
+    }
+
+    /// Output path of the alignment.
+    /// Creates the output directory if it does not exist.
+    fn create_output_path(&self) -> Result<PathBuf, Box<dyn Error>> {
+        fs::create_dir_all(self.output_dir)?;
+        let output_dir = self.output_dir.canonicalize()?;
+        Ok(output_dir.join(&self.input_file.file_name))
+    }
+
+    fn get_input_path(&self) -> Result<PathBuf, Box<dyn Error>> {
+        let input_path = self.input_file.parent_dir.join(&self.input_file.file_name);
+        Ok(input_path.canonicalize()?)
+    }
+
+    fn check_success(&self, output: &Output) -> Result<(), Box<dyn Error>> {
+        if output.status.success() {
+            Ok(())
+        } else {
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            log::error!("{}", stderr);
+            Err("Alignment failed".into())
+        }
     }
 }
@@ -0,0 +1,45 @@
+//! Align multiple sequences using MUSCLE 5.
+use std::path::Path;
+use std::process::Command;
+
+use crate::core::deps::muscle::MUSCLE_EXE;
+use crate::core::deps::DepMetadata;
+use crate::parse_override_args;
+
+/// Default MUSCLE parameters.
+/// Loci are aligned in parallel, so each
+/// MUSCLE run uses a single thread.
+pub const DEFAULT_MUSCLE_PARAMS: &str = "-threads 1";
+
+/// Build the MUSCLE 5 command of a locus
+pub struct MuscleRunner<'a> {
+    /// Path to the unaligned sequences
+    pub input_path: &'a Path,
+    pub output_path: &'a Path,
+    /// Override arguments replace DEFAULT_MUSCLE_PARAMS
+    pub dep_metadata: &'a DepMetadata,
+}
+
+impl<'a> MuscleRunner<'a> {
+    pub fn new(input_path: &'a Path, output_path: &'a Path, dep_metadata: &'a DepMetadata) -> Self {
+        Self {
+            input_path,
+            output_path,
+            dep_metadata,
+        }
+    }
+
+    pub fn command(&self) -> Command {
+        let executable = self.dep_metadata.get_executable(MUSCLE_EXE);
+        let mut cmd = Command::new(&executable);
+        cmd.arg("-align")
+            .arg(self.input_path)
+            .arg("-output")
+            .arg(self.output_path);
+        match &self.dep_metadata.override_args {
+            Some(params) => parse_override_args!(cmd, params),
+            None => parse_override_args!(cmd, DEFAULT_MUSCLE_PARAMS),
+        };
+        cmd
+    }
+}
@@ -0,0 +1,449 @@
+//! Alignment quality control.
+//!
+//! Trim alignments, drop loci that fail the filters,
+//! and write a before and after summary of each locus.
+//! Codon alignments are trimmed by whole codons
+//! and their codon partition is carried over.
+use std::{
+    collections::HashMap,
+    error::Error,
+    fs,
+    path::{Path, PathBuf},
+    sync::mpsc,
+};
+
+use colored::Colorize;
+use rayon::prelude::*;
+use segul::{
+    helper::{
+        finder::{IDs, SeqFileFinder},
+        sequence::SeqParser,
+        types::{DataType, Header, InputFmt, OutputFmt},
+    },
+    writer::sequences::SeqWriter,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    cli::commands::alignment::AlignmentQcArgs,
+    helper::{
+        common::{self, percent},
+        files::{FileMetadata, PathCheck},
+        ledger::{self, LedgerEntry, LedgerStatus, StepLedger},
+    },
+    types::{runner::RunnerOptions, Task},
+};
+
+use super::{
+    super::project::manifest::{ProjectManifest, TaskStatus},
+    codon::{CodonPartition, CODON_PARTITION_FILE},
+    configs::AlignmentQcConfig,
+    filter::{DroppedLocus, LocusFilter, DROPPED_LOCI_FILE},
+    trim::{AlignmentTrimmer, TrimParams},
+};
+
+pub const DEFAULT_ALIGNMENT_QC_OUTPUT_DIR: &str = "out_alignment_qc";
+pub const TRIMMING_SUMMARY_FILE: &str = "alignment_trimming_summary.csv";
+
+/// Before and after trimming summary of a locus
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TrimmingSummary {
+    pub locus: String,
+    pub taxa_before: usize,
+    pub taxa_after: usize,
+    pub length_before: usize,
+    pub length_after: usize,
+    pub edge_columns: usize,
+    pub gappy_columns: usize,
+    pub entropy_columns: usize,
+    pub codon_columns: usize,
+    pub percent_length_retained: f64,
+    /// False if the locus is dropped by the filters
+    pub kept: bool,
+}
+
+pub struct AlignmentQc<'a> {
+    /// Input directory containing the alignments
+    pub input_dir: &'a Path,
+    pub input_format: InputFmt,
+    pub datatype: DataType,
+    pub output_dir: &'a Path,
+    pub params: TrimParams,
+    pub filter: LocusFilter,
+    /// Config the thresholds are read from.
+    /// A new config is written if not set.
+    pub config_path: Option<&'a Path>,
+    pub runner: RunnerOptions,
+    task: Task,
+}
+
+impl<'a> AlignmentQc<'a> {
+    pub fn new(
+        input_dir: &'a Path,
+        output_dir: &'a Path,
+        params: TrimParams,
+        filter: LocusFilter,
+    ) -> Self {
+        Self {
+            input_dir,
+            input_format: InputFmt::Auto,
+            datatype: DataType::Dna,
+            output_dir,
+            params,
+            filter,
+            config_path: None,
+            runner: RunnerOptions::default(),
+            task: Task::AlignmentQc,
+        }
+    }
+
+    pub fn from_arg(args: &'a AlignmentQcArgs) -> Self {
+        let (params, filter) = match &args.config {
+            Some(path) => {
+                let config = AlignmentQcConfig::from_toml(path)
+                    .expect("Failed to parse alignment QC config");
+                (config.trimming, config.filtering)
+            }
+            None => (
+                TrimParams {
+                    edge_occupancy: (!args.no_edge_trim).then_some(args.edge_occupancy),
+                    max_gap: args.max_gap,
+                    automated: args.automated,
+                    max_entropy: args.max_entropy,
+                },
+                LocusFilter::from_arg(&args.filter),
+            ),
+        };
+        Self {
+            input_dir: &args.dir,
+            input_format: args
+                .input_format
+                .parse::<InputFmt>()
+                .expect("Invalid input format"),
+            datatype: args
+                .datatype
+                .parse::<DataType>()
+                .expect("Invalid data type"),
+            output_dir: &args.output,
+            params,
+            filter,
+            config_path: args.config.as_deref(),
+            runner: RunnerOptions::from_arg(&args.common),
+            task: Task::AlignmentQc,
+        }
+    }
+
+    /// Trim and filter the alignments and write the summary.
+    /// Only loci that pass the filters are written.
+    /// Returns the summary of each locus.
+    pub fn trim(&self) -> Vec<TrimmingSummary> {
+        let files = SeqFileFinder::new(self.input_dir).find(&self.input_format);
+        self.log_input(files.len());
+        if files.is_empty() {
+            log::error!(
+                "{} No alignment found in the input directory",
+                "Error:".red()
+            );
+            return Vec::new();
+        }
+        PathCheck::new(self.output_dir)
+            .is_dir()
+            .with_force_overwrite(self.runner.overwrite)
+            .with_resume(self.runner.resume)
+            .prompt_exists(self.runner.dry_run);
+        let previous = if self.runner.resume {
+            PreviousRun::from_dir(self.output_dir)
+        } else {
+            PreviousRun::default()
+        };
+        let config_path = self.get_config_path();
+        ProjectManifest::record(self.task, TaskStatus::Running, config_path.as_deref());
+        let total_taxa = IDs::new(&files, &self.input_format, &self.datatype)
+            .id_unique()
+            .len();
+        let codon_partition = self.input_dir.join(CODON_PARTITION_FILE);
+        let codons = codon_partition.is_file();
+        let (summary, dropped) = self.par_trim(&files, total_taxa, codons, &previous);
+        let status = TaskStatus::from_success(summary.len() == files.len());
+        ProjectManifest::record(self.task, status, config_path.as_deref());
+        if let Err(e) = self.write_dropped_loci(&dropped) {
+            log::error!("Failed to write dropped loci: {}", e);
+        }
+        if codons {
+            if let Err(e) = self.write_codon_partition(&codon_partition, &summary) {
+                log::error!("Failed to write codon partition: {}", e);
+            }
+        }
+        match self.write_summary(&summary) {
+            Ok(path) => self.log_output(&summary, &dropped, &path),
+            Err(e) => log::error!("Failed to write trimming summary: {}", e),
+        }
+        summary
+    }
+
+    // Write the thresholds to a config
+    // unless they are read from one.
+    fn get_config_path(&self) -> Option<PathBuf> {
+        if let Some(path) = self.config_path {
+            return Some(path.to_path_buf());
+        }
+        let config = AlignmentQcConfig::new(self.params.clone(), self.filter.clone());
+        match config.to_toml() {
+            Ok(path) => Some(path),
+            Err(e) => {
+                log::warn!("Failed to write alignment QC config: {}", e);
+                None
+            }
+        }
+    }
+
+    fn par_trim(
+        &self,
+        files: &[PathBuf],
+        total_taxa: usize,
+        codons: bool,
+        previous: &PreviousRun,
+    ) -> (Vec<TrimmingSummary>, Vec<DroppedLocus>) {
+        fs::create_dir_all(self.output_dir).expect("Failed to create output directory");
+        let progress_bar = common::init_progress_bar(files.len() as u64);
+        log::info!("{}", "Trimming alignments".cyan());
+        progress_bar.set_message("Alignments");
+        let ledger = StepLedger::new(self.output_dir);
+        let params_hash = self.hash_params(total_taxa, codons);
+        let (tx, rx) = mpsc::channel();
+        files.par_iter().for_each_with(tx, |tx, file| {
+            let locus = file
+                .file_stem()
+                .map(|s| s.to_string_lossy().to_string())
+                .unwrap_or_default();
+            let input_hash = ledger::hash_files(&[&FileMetadata::from_path(file)]);
+            if self.runner.resume && ledger.is_completed(&locus, &input_hash, &params_hash) {
+                let result = previous
+                    .get(&locus)
+                    .filter(|(summary, _)| !summary.kept || self.output_path(&locus).is_file());
+                if let Some(result) = result {
+                    tx.send(result).expect("Failed to send summary");
+                    progress_bar.inc(1);
+                    return;
+                }
+            }
+            let output = self.trim_alignment(file, &locus, total_taxa, codons);
+            let status = LedgerStatus::from_success(output.is_ok());
+            ledger.record(&LedgerEntry::new(&locus, &input_hash, &params_hash, status));
+            match output {
+                Ok(result) => tx.send(result).expect("Failed to send summary"),
+                Err(e) => log::error!("Failed to trim {}: {}", file.display(), e),
+            }
+            progress_bar.inc(1);
+        });
+        let (mut summary, dropped): (Vec<TrimmingSummary>, Vec<Option<DroppedLocus>>) =
+            rx.iter().unzip();
+        let mut dropped: Vec<DroppedLocus> = dropped.into_iter().flatten().collect();
+        summary.sort_by(|a, b| a.locus.cmp(&b.locus));
+        dropped.sort_by(|a, b| a.locus.cmp(&b.locus));
+        progress_bar.finish_with_message(format!("{} Finished trimming\n", "✔".green()));
+        (summary, dropped)
+    }
+
+    fn trim_alignment(
+        &self,
+        file: &Path,
+        locus: &str,
+        total_taxa: usize,
+        codons: bool,
+    ) -> Result<(TrimmingSummary, Option<DroppedLocus>), Box<dyn Error>> {
+        let (matrix, header) = SeqParser::new(file, &self.datatype).parse(&self.input_format);
+        let (trimmed, stats) = AlignmentTrimmer::new(&self.params)
+            .with_datatype(&self.datatype)
+            .with_codons(codons)
+            .trim(&matrix);
+        let length_after = trimmed.values().next().map_or(0, |s| s.len());
+        let dropped = self
+            .filter
+            .check(locus, &trimmed, &self.datatype, total_taxa);
+        let kept = dropped.is_none();
+        if kept {
+            let output_path = self.output_path(locus);
+            let mut output_header = Header::default();
+            output_header.from_seq_matrix(&trimmed, true);
+            SeqWriter::new(&output_path, &trimmed, &output_header)
+                .write_sequence(&OutputFmt::Fasta)?;
+        }
+        let summary = TrimmingSummary {
+            locus: locus.to_string(),
+            taxa_before: header.ntax,
+            taxa_after: trimmed.len(),
+            length_before: header.nchar,
+            length_after,
+            edge_columns: stats.edge_columns,
+            gappy_columns: stats.gappy_columns,
+            entropy_columns: stats.entropy_columns,
+            codon_columns: stats.codon_columns,
+            percent_length_retained: percent(length_after, header.nchar),
+            kept,
+        };
+        Ok((summary, dropped))
+    }
+
+    fn output_path(&self, locus: &str) -> PathBuf {
+        self.output_dir.join(format!("{}.fas", locus))
+    }
+
+    // Trimmed loci are only reused with the same thresholds
+    // and the same total taxa used by the completeness filter
+    fn hash_params(&self, total_taxa: usize, codons: bool) -> String {
+        ledger::hash_string(&format!(
+            "{}{}{}{}{}",
+            ledger::hash_params(&self.params),
+            ledger::hash_params(&self.filter),
+            matches!(self.datatype, DataType::Aa),
+            codons,
+            total_taxa
+        ))
+    }
+
+    fn write_summary(&self, summary: &[TrimmingSummary]) -> Result<PathBuf, Box<dyn Error>> {
+        let output_path = self.output_dir.join(TRIMMING_SUMMARY_FILE);
+        let mut writer = csv::Writer::from_path(&output_path)?;
+        for locus in summary {
+            writer.serialize(locus)?;
+        }
+        writer.flush()?;
+        Ok(output_path)
+    }
+
+    // Carry the codon partition of the input alignments
+    // over to the trimmed alignments that are kept
+    fn write_codon_partition(
+        &self,
+        input_partition: &Path,
+        summary: &[TrimmingSummary],
+    ) -> Result<PathBuf, Box<dyn Error>> {
+        let partitions: Vec<CodonPartition> = CodonPartition::from_csv(input_partition)?
+            .into_iter()
+            .filter_map(|codon| {
+                summary
+                    .iter()
+                    .find(|s| s.kept && s.locus == codon.locus)
+                    .map(|s| CodonPartition {
+                        locus: codon.locus,
+                        length: s.length_after,
+                    })
+            })
+            .collect();
+        let output_path = self.output_dir.join(CODON_PARTITION_FILE);
+        CodonPartition::to_csv(&partitions, &output_path)?;
+        Ok(output_path)
+    }
+
+    fn write_dropped_loci(&self, dropped: &[DroppedLocus]) -> Result<PathBuf, Box<dyn Error>> {
+        let output_path = self.output_dir.join(DROPPED_LOCI_FILE);
+        let mut writer = csv::Writer::from_path(&output_path)?;
+        for locus in dropped {
+            writer.serialize(locus)?;
+        }
+        writer.flush()?;
+        Ok(output_path)
+    }
+
+    fn log_input(&self, file_counts: usize) {
+        log::info!("{}", "Input".cyan());
+        log::info!("{:18}: {}", "Directory", self.input_dir.display());
+        log::info!("{:18}: {}", "File counts", file_counts);
+        log::info!("{:18}: {}", "Task", self.task);
+        if self.input_dir.join(CODON_PARTITION_FILE).is_file() {
+            log::info!("{:18}: {}", "Codon partition", "Trim by whole codons");
+        }
+        match self.params.edge_occupancy {
+            Some(occupancy) => log::info!("{:18}: {}", "Edge occupancy", occupancy),
+            None => log::info!("{:18}: {}", "Edge occupancy", "None"),
+        }
+        match (self.params.max_gap, self.params.automated) {
+            (Some(max_gap), _) => log::info!("{:18}: {}", "Max gap", max_gap),
+            (None, true) => log::info!("{:18}: {}", "Max gap", "Automated"),
+            (None, false) => log::info!("{:18}: {}", "Max gap", "None"),
+        }
+        match self.params.max_entropy {
+            Some(entropy) => log::info!("{:18}: {}", "Max entropy", entropy),
+            None => log::info!("{:18}: {}", "Max entropy", "None"),
+        }
+        match self.filter.min_taxon_completeness {
+            Some(completeness) => log::info!("{:18}: {}", "Min completeness", completeness),
+            None => log::info!("{:18}: {}", "Min completeness", "None"),
+        }
+        match self.filter.min_parsimony_informative {
+            Some(sites) => log::info!("{:18}: {}\n", "Min PIS", sites),
+            None => log::info!("{:18}: {}\n", "Min PIS", "None"),
+        }
+    }
+
+    fn log_output(
+        &self,
+        summary: &[TrimmingSummary],
+        dropped: &[DroppedLocus],
+        summary_path: &Path,
+    ) {
+        let kept = summary.iter().filter(|s| s.kept).count();
+        let length_before: usize = summary.iter().map(|s| s.length_before).sum();
+        let length_after: usize = summary
+            .iter()
+            .filter(|s| s.kept)
+            .map(|s| s.length_after)
+            .sum();
+        log::info!("{}", "Output".cyan());
+        log::info!("{:18}: {}", "Directory", self.output_dir.display());
+        log::info!("{:18}: {}", "Summary", summary_path.display());
+        log::info!("{:18}: {}", "Alignments kept", kept);
+        log::info!("{:18}: {}", "Alignments removed", dropped.len());
+        log::info!(
+            "{:18}: {:.2}%",
+            "Length retained",
+            percent(length_after, length_before)
+        );
+    }
+}
+
+/// Summary and dropped loci of the previous run.
+/// Reused for loci completed in the ledger when resuming.
+#[derive(Default)]
+struct PreviousRun {
+    summary: HashMap<String, TrimmingSummary>,
+    dropped: HashMap<String, DroppedLocus>,
+}
+
+impl PreviousRun {
+    fn from_dir(output_dir: &Path) -> Self {
+        let summary_path = output_dir.join(TRIMMING_SUMMARY_FILE);
+        let dropped_path = output_dir.join(DROPPED_LOCI_FILE);
+        let summary = Self::read_summary(&summary_path)
+            .unwrap_or_default()
+            .into_iter()
+            .map(|s| (s.locus.clone(), s))
+            .collect();
+        let dropped = DroppedLocus::from_csv(&dropped_path)
+            .unwrap_or_default()
+            .into_iter()
+            .map(|d| (d.locus.clone(), d))
+            .collect();
+        Self { summary, dropped }
+    }
+
+    fn get(&self, locus: &str) -> Option<(TrimmingSummary, Option<DroppedLocus>)> {
+        let summary = self.summary.get(locus)?;
+        let dropped = self.dropped.get(locus);
+        // Both files must agree on whether the locus was kept
+        if summary.kept != dropped.is_none() {
+            return None;
+        }
+        Some((summary.clone(), dropped.cloned()))
+    }
+
+    fn read_summary(path: &Path) -> Result<Vec<TrimmingSummary>, Box<dyn Error>> {
+        let mut reader = csv::Reader::from_path(path)?;
+        let mut summary = Vec::new();
+        for record in reader.deserialize() {
+            summary.push(record?);
+        }
+        Ok(summary)
+    }
+}
@@ -0,0 +1,293 @@
+//! Compute summary statistics of each aligned locus
+//! and of the whole dataset.
+use std::{
+    error::Error,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use colored::Colorize;
+use rayon::prelude::*;
+use segul::helper::{
+    finder::IDs,
+    sequence::SeqParser,
+    types::{DataType, InputFmt, SeqMatrix},
+};
+use serde::{Deserialize, Serialize};
+
+use crate::helper::{
+    alignments::{missing_chars, residues},
+    common::percent,
+};
+
+use super::filter::parsimony_informative_sites;
+
+pub const ALIGNMENT_SUMMARY: &str = "alignment_summary.csv";
+pub const DATASET_SUMMARY: &str = "alignment_dataset_summary.csv";
+
+/// Summary statistics of an aligned locus
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct LocusStats {
+    pub locus: String,
+    pub taxon_counts: usize,
+    pub alignment_length: usize,
+    /// Percent of gaps and missing data in the matrix
+    pub missing_percent: f64,
+    /// GC percentage of unambiguous bases.
+    /// Not computed for amino acids.
+    pub gc_percent: Option<f64>,
+    pub variable_sites: usize,
+    pub parsimony_informative_sites: usize,
+    /// Mean percent of identical residues between sequence pairs.
+    /// Only sites where both sequences have an unambiguous
+    /// nucleotide or amino acid are compared.
+    pub pairwise_identity: f64,
+    #[serde(skip)]
+    missing_counts: usize,
+    #[serde(skip)]
+    gc_counts: usize,
+    #[serde(skip)]
+    base_counts: usize,
+    #[serde(skip)]
+    identical_pairs: usize,
+    #[serde(skip)]
+    compared_pairs: usize,
+}
+
+impl LocusStats {
+    /// Compute statistics from an alignment.
+    /// Sequences shorter than the alignment are
+    /// treated as missing data at the end.
+    pub fn from_matrix(locus: &str, matrix: &SeqMatrix, datatype: &DataType) -> Self {
+        let sequences: Vec<&[u8]> = matrix.values().map(|s| s.as_bytes()).collect();
+        let alignment_length = sequences.iter().map(|s| s.len()).max().unwrap_or(0);
+        let missing_chars = missing_chars(datatype);
+        let residues = residues(datatype);
+        let is_dna = !matches!(datatype, DataType::Aa);
+        let mut stats = Self {
+            locus: locus.to_string(),
+            taxon_counts: sequences.len(),
+            alignment_length,
+            parsimony_informative_sites: parsimony_informative_sites(matrix, datatype),
+            ..Self::default()
+        };
+        for i in 0..alignment_length {
+            let mut counts = vec![0usize; residues.len()];
+            sequences.iter().for_each(|s| match s.get(i) {
+                Some(c) if missing_chars.contains(c) => stats.missing_counts += 1,
+                None => stats.missing_counts += 1,
+                Some(c) => {
+                    let c = c.to_ascii_uppercase();
+                    if let Some(n) = residues.iter().position(|n| *n == c) {
+                        counts[n] += 1;
+                    }
+                }
+            });
+            let total: usize = counts.iter().sum();
+            stats.base_counts += total;
+            if is_dna {
+                stats.gc_counts += counts[1] + counts[2];
+            }
+            if counts.iter().filter(|c| **c > 0).count() > 1 {
+                stats.variable_sites += 1;
+            }
+            stats.identical_pairs += counts.iter().copied().map(pairs).sum::<usize>();
+            stats.compared_pairs += pairs(total);
+        }
+        stats.missing_percent = percent(
+            stats.missing_counts,
+            stats.taxon_counts * stats.alignment_length,
+        );
+        stats.gc_percent = is_dna.then(|| percent(stats.gc_counts, stats.base_counts));
+        stats.pairwise_identity = percent(stats.identical_pairs, stats.compared_pairs);
+        stats
+    }
+}
+
+/// Aggregate statistics of all loci
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct DatasetStats {
+    pub locus_counts: usize,
+    /// Number of unique taxa across loci
+    pub taxon_counts: usize,
+    /// Length of the concatenated alignment
+    pub total_sites: usize,
+    pub min_length: usize,
+    pub max_length: usize,
+    pub mean_length: f64,
+    pub mean_taxa_per_locus: f64,
+    pub missing_percent: f64,
+    pub gc_percent: Option<f64>,
+    pub variable_sites: usize,
+    pub parsimony_informative_sites: usize,
+    pub pairwise_identity: f64,
+}
+
+impl DatasetStats {
+    pub fn from_loci(stats: &[LocusStats], taxon_counts: usize) -> Self {
+        let locus_counts = stats.len();
+        let total_sites: usize = stats.iter().map(|s| s.alignment_length).sum();
+        let total_taxa: usize = stats.iter().map(|s| s.taxon_counts).sum();
+        let sum = |f: fn(&LocusStats) -> usize| stats.iter().map(f).sum::<usize>();
+        let matrix_size: usize = stats
+            .iter()
+            .map(|s| s.taxon_counts * s.alignment_length)
+            .sum();
+        Self {
+            locus_counts,
+            taxon_counts,
+            total_sites,
+            min_length: stats.iter().map(|s| s.alignment_length).min().unwrap_or(0),
+            max_length: stats.iter().map(|s| s.alignment_length).max().unwrap_or(0),
+            mean_length: mean(total_sites, locus_counts),
+            mean_taxa_per_locus: mean(total_taxa, locus_counts),
+            missing_percent: percent(sum(|s| s.missing_counts), matrix_size),
+            gc_percent: stats
+                .iter()
+                .any(|s| s.gc_percent.is_some())
+                .then(|| percent(sum(|s| s.gc_counts), sum(|s| s.base_counts))),
+            variable_sites: sum(|s| s.variable_sites),
+            parsimony_informative_sites: sum(|s| s.parsimony_informative_sites),
+            pairwise_identity: percent(sum(|s| s.identical_pairs), sum(|s| s.compared_pairs)),
+        }
+    }
+}
+
+/// Per-locus and dataset summary of the alignment step
+pub struct AlignmentSummary<'a> {
+    /// Alignment output directory
+    pub output_dir: &'a Path,
+    pub loci: Vec<LocusStats>,
+    pub dataset: DatasetStats,
+}
+
+impl<'a> AlignmentSummary<'a> {
+    /// Compute statistics of each alignment file
+    pub fn from_files(
+        output_dir: &'a Path,
+        files: &[PathBuf],
+        format: &InputFmt,
+        datatype: &DataType,
+    ) -> Self {
+        let mut loci: Vec<LocusStats> = files
+            .par_iter()
+            .map(|file| {
+                let locus = file
+                    .file_stem()
+                    .map(|s| s.to_string_lossy().to_string())
+                    .unwrap_or_default();
+                let (matrix, _) = SeqParser::new(file, datatype).parse(format);
+                LocusStats::from_matrix(&locus, &matrix, datatype)
+            })
+            .collect();
+        loci.sort_by(|a, b| a.locus.cmp(&b.locus));
+        let taxon_counts = IDs::new(files, format, datatype).id_unique().len();
+        let dataset = DatasetStats::from_loci(&loci, taxon_counts);
+        Self {
+            output_dir,
+            loci,
+            dataset,
+        }
+    }
+
+    /// Write the per-locus and dataset summaries.
+    /// Returns the paths to both files.
+    pub fn write(&self) -> Result<(PathBuf, PathBuf), Box<dyn Error>> {
+        fs::create_dir_all(self.output_dir)?;
+        let locus_path = self.output_dir.join(ALIGNMENT_SUMMARY);
+        let mut writer = csv::Writer::from_path(&locus_path)?;
+        for stats in &self.loci {
+            writer.serialize(stats)?;
+        }
+        writer.flush()?;
+        let dataset_path = self.output_dir.join(DATASET_SUMMARY);
+        let mut writer = csv::Writer::from_path(&dataset_path)?;
+        writer.serialize(&self.dataset)?;
+        writer.flush()?;
+        Ok((locus_path, dataset_path))
+    }
+
+    pub fn log_dataset(&self) {
+        let stats = &self.dataset;
+        log::info!("{}", "Alignment summary".cyan());
+        log::info!("{:18}: {}", "Loci", stats.locus_counts);
+        log::info!("{:18}: {}", "Taxa", stats.taxon_counts);
+        log::info!("{:18}: {}", "Total sites", stats.total_sites);
+        log::info!("{:18}: {:.2}", "Mean length", stats.mean_length);
+        log::info!("{:18}: {:.2}%", "Missing data", stats.missing_percent);
+        if let Some(gc_percent) = stats.gc_percent {
+            log::info!("{:18}: {:.2}%", "GC content", gc_percent);
+        }
+        log::info!("{:18}: {}", "Variable sites", stats.variable_sites);
+        log::info!(
+            "{:18}: {}",
+            "Parsimony inf.",
+            stats.parsimony_informative_sites
+        );
+        log::info!(
+            "{:18}: {:.2}%\n",
+            "Pairwise identity",
+            stats.pairwise_identity
+        );
+    }
+}
+
+fn pairs(count: usize) -> usize {
+    count * count.saturating_sub(1) / 2
+}
+
+fn mean(sum: usize, count: usize) -> f64 {
+    if count == 0 {
+        return 0.0;
+    }
+    sum as f64 / count as f64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn matrix(sequences: &[(&str, &str)]) -> SeqMatrix {
+        sequences
+            .iter()
+            .map(|(name, seq)| (name.to_string(), seq.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn test_locus_stats() {
+        let alignment = matrix(&[
+            ("sample_1", "ACGT"),
+            ("sample_2", "ACGA"),
+            ("sample_3", "AG-A"),
+            ("sample_4", "AGN"),
+        ]);
+        let stats = LocusStats::from_matrix("uce-1", &alignment, &DataType::Dna);
+        assert_eq!(stats.taxon_counts, 4);
+        assert_eq!(stats.alignment_length, 4);
+        assert_eq!(stats.missing_percent, 18.75);
+        assert!((stats.gc_percent.unwrap() - 46.15).abs() < 0.01);
+        assert_eq!(stats.variable_sites, 2);
+        assert_eq!(stats.parsimony_informative_sites, 1);
+        // 10 identical of 16 compared pairs
+        assert_eq!(stats.pairwise_identity, 62.5);
+
+        let dataset = DatasetStats::from_loci(&[stats.clone(), stats], 5);
+        assert_eq!(dataset.locus_counts, 2);
+        assert_eq!(dataset.total_sites, 8);
+        assert_eq!(dataset.missing_percent, 18.75);
+        assert_eq!(dataset.variable_sites, 4);
+        assert_eq!(dataset.pairwise_identity, 62.5);
+    }
+
+    #[test]
+    fn test_protein_stats() {
+        let alignment = matrix(&[("sample_1", "MNK-"), ("sample_2", "MNRX")]);
+        let stats = LocusStats::from_matrix("exon-1", &alignment, &DataType::Aa);
+        assert_eq!(stats.missing_percent, 25.0);
+        assert_eq!(stats.gc_percent, None);
+        assert_eq!(stats.variable_sites, 1);
+        // 2 identical of 3 compared pairs
+        assert!((stats.pairwise_identity - 66.67).abs() < 0.01);
+    }
+}
@@ -0,0 +1,348 @@
+//! Trim multiple sequence alignments.
+//!
+//! Columns are removed in three steps: ragged edges,
+//! gappy columns, and high-entropy columns. Sequences
+//! left without data after trimming are removed.
+//! Codon alignments are trimmed by whole codons.
+use std::collections::BTreeMap;
+
+use segul::helper::types::{DataType, SeqMatrix};
+use serde::{Deserialize, Serialize};
+
+use crate::helper::alignments::{missing_chars, residues};
+
+pub const DEFAULT_EDGE_OCCUPANCY: f64 = 0.5;
+
+/// Alignment trimming parameters.
+/// Each step is skipped if its threshold is not set.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TrimParams {
+    /// Minimum proportion of sequences with data
+    /// to keep the first and last columns.
+    /// Columns outside them are removed.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub edge_occupancy: Option<f64>,
+    /// Maximum proportion of gaps and missing data
+    /// in a column to keep it
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_gap: Option<f64>,
+    /// Select the gap threshold from the distribution
+    /// of column gaps, similar to trimAl gappyout.
+    /// Ignored if max_gap is set.
+    #[serde(default)]
+    pub automated: bool,
+    /// Maximum Shannon entropy (bits) of the
+    /// nucleotides or amino acids in a column to keep it
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_entropy: Option<f64>,
+}
+
+impl Default for TrimParams {
+    fn default() -> Self {
+        Self {
+            edge_occupancy: Some(DEFAULT_EDGE_OCCUPANCY),
+            max_gap: None,
+            automated: false,
+            max_entropy: None,
+        }
+    }
+}
+
+impl TrimParams {
+    /// Skip all trimming steps.
+    /// Used to only filter the alignments.
+    pub fn disabled() -> Self {
+        Self {
+            edge_occupancy: None,
+            max_gap: None,
+            automated: false,
+            max_entropy: None,
+        }
+    }
+}
+
+/// Number of columns and sequences removed by each step
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct TrimStats {
+    pub edge_columns: usize,
+    pub gappy_columns: usize,
+    pub entropy_columns: usize,
+    /// Columns removed to keep whole codons
+    pub codon_columns: usize,
+    pub removed_sequences: usize,
+}
+
+pub struct AlignmentTrimmer<'a> {
+    pub params: &'a TrimParams,
+    /// Selects the missing data characters
+    pub datatype: &'a DataType,
+    /// Remove whole codons so the alignment
+    /// stays in the first codon position
+    pub codons: bool,
+}
+
+impl<'a> AlignmentTrimmer<'a> {
+    pub fn new(params: &'a TrimParams) -> Self {
+        Self {
+            params,
+            datatype: &DataType::Dna,
+            codons: false,
+        }
+    }
+
+    pub fn with_datatype(mut self, datatype: &'a DataType) -> Self {
+        self.datatype = datatype;
+        self
+    }
+
+    pub fn with_codons(mut self, codons: bool) -> Self {
+        self.codons = codons;
+        self
+    }
+
+    /// Trim the alignment.
+    /// Sequences shorter than the alignment are
+    /// treated as missing data at the end.
+    pub fn trim(&self, matrix: &SeqMatrix) -> (SeqMatrix, TrimStats) {
+        let mut stats = TrimStats::default();
+        let sequences: Vec<&[u8]> = matrix.values().map(|s| s.as_bytes()).collect();
+        let site_counts = sequences.iter().map(|s| s.len()).max().unwrap_or(0);
+        let taxon_counts = sequences.len();
+        let missing_chars = missing_chars(self.datatype);
+        let gaps: Vec<usize> = (0..site_counts)
+            .map(|i| {
+                sequences
+                    .iter()
+                    .filter(|s| s.get(i).is_none_or(|c| missing_chars.contains(c)))
+                    .count()
+            })
+            .collect();
+        let mut keep = vec![true; site_counts];
+        if let Some(min_occupancy) = self.params.edge_occupancy {
+            stats.edge_columns = trim_edges(&mut keep, &gaps, taxon_counts, min_occupancy);
+        }
+        let max_gaps = match (self.params.max_gap, self.params.automated) {
+            (Some(max_gap), _) => Some((max_gap * taxon_counts as f64).floor() as usize),
+            (None, true) => {
+                let kept_gaps: Vec<usize> = (0..site_counts)
+                    .filter(|i| keep[*i])
+                    .map(|i| gaps[i])
+                    .collect();
+                Some(automated_gap_cutoff(&kept_gaps))
+            }
+            (None, false) => None,
+        };
+        if let Some(max_gaps) = max_gaps {
+            stats.gappy_columns = remove_columns(&mut keep, |i| gaps[i] > max_gaps);
+        }
+        if let Some(max_entropy) = self.params.max_entropy {
+            let residues = residues(self.datatype);
+            stats.entropy_columns = remove_columns(&mut keep, |i| {
+                column_entropy(&sequences, residues, i) > max_entropy
+            });
+        }
+        if self.codons {
+            stats.codon_columns = remove_partial_codons(&mut keep);
+        }
+
+        let mut trimmed = SeqMatrix::new();
+        matrix.iter().for_each(|(name, sequence)| {
+            let sequence = sequence.as_bytes();
+            let trimmed_seq: String = keep
+                .iter()
+                .enumerate()
+                .filter(|(_, keep)| **keep)
+                .map(|(i, _)| sequence.get(i).copied().unwrap_or(b'-') as char)
+                .collect();
+            if trimmed_seq.bytes().all(|c| missing_chars.contains(&c)) {
+                stats.removed_sequences += 1;
+            } else {
+                trimmed.insert(name.to_string(), trimmed_seq);
+            }
+        });
+        (trimmed, stats)
+    }
+}
+
+// Returns the number of columns removed
+fn trim_edges(keep: &mut [bool], gaps: &[usize], taxon_counts: usize, min_occupancy: f64) -> usize {
+    let is_occupied = |gaps: &usize| {
+        let occupancy = (taxon_counts - gaps) as f64 / taxon_counts as f64;
+        occupancy >= min_occupancy
+    };
+    let start = gaps.iter().position(is_occupied).unwrap_or(gaps.len());
+    let end = gaps.iter().rposition(is_occupied).map_or(start, |i| i + 1);
+    remove_columns(keep, |i| i < start || i >= end)
+}
+
+// Returns the number of columns removed
+fn remove_columns<F>(keep: &mut [bool], is_removed: F) -> usize
+where
+    F: Fn(usize) -> bool,
+{
+    let mut removed = 0;
+    keep.iter_mut().enumerate().for_each(|(i, keep)| {
+        if *keep && is_removed(i) {
+            *keep = false;
+            removed += 1;
+        }
+    });
+    removed
+}
+
+// Remove the remaining columns of codons with removed columns
+// and of the incomplete codon at the end.
+// Returns the number of columns removed
+fn remove_partial_codons(keep: &mut [bool]) -> usize {
+    let is_partial: Vec<bool> = keep
+        .chunks(3)
+        .map(|codon| codon.len() < 3 || codon.contains(&false))
+        .collect();
+    remove_columns(keep, |i| is_partial[i / 3])
+}
+
+/// Select the maximum gaps per column to keep.
+/// Columns are accumulated from the least to the most gappy.
+/// The cutoff is where the slope of the retained columns
+/// drops the most, so the long tail of gappy columns is removed.
+/// Keeps all columns if there are fewer than three gap counts.
+fn automated_gap_cutoff(gaps: &[usize]) -> usize {
+    let mut counts: BTreeMap<usize, usize> = BTreeMap::new();
+    gaps.iter()
+        .for_each(|g| *counts.entry(*g).or_default() += 1);
+    let mut retained = 0;
+    let points: Vec<(f64, f64)> = counts
+        .iter()
+        .map(|(gaps, columns)| {
+            retained += columns;
+            (*gaps as f64, retained as f64)
+        })
+        .collect();
+    let max_gaps = counts.keys().last().copied().unwrap_or(0);
+    let mut cutoff = max_gaps;
+    let mut max_change = 0.0;
+    points.windows(3).for_each(|p| {
+        let before = (p[1].1 - p[0].1) / (p[1].0 - p[0].0);
+        let after = (p[2].1 - p[1].1) / (p[2].0 - p[1].0);
+        if before - after > max_change {
+            max_change = before - after;
+            cutoff = p[1].0 as usize;
+        }
+    });
+    cutoff
+}
+
+/// Shannon entropy of the residues in a column.
+/// Ambiguous characters, gaps, and missing data are ignored.
+fn column_entropy(sequences: &[&[u8]], residues: &[u8], index: usize) -> f64 {
+    let mut counts = vec![0usize; residues.len()];
+    sequences.iter().for_each(|s| {
+        if let Some(c) = s.get(index) {
+            let c = c.to_ascii_uppercase();
+            if let Some(i) = residues.iter().position(|n| *n == c) {
+                counts[i] += 1;
+            }
+        }
+    });
+    let total: usize = counts.iter().sum();
+    if total == 0 {
+        return 0.0;
+    }
+    counts
+        .iter()
+        .filter(|c| **c > 0)
+        .map(|c| {
+            let p = *c as f64 / total as f64;
+            -p * p.log2()
+        })
+        .sum()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn matrix(sequences: &[(&str, &str)]) -> SeqMatrix {
+        sequences
+            .iter()
+            .map(|(name, seq)| (name.to_string(), seq.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn test_trim_alignment() {
+        let alignment = matrix(&[
+            ("sample_1", "--ACGTACGTA"),
+            ("sample_2", "-AACGTACG--"),
+            ("sample_3", "--ACGAACGT-"),
+            ("sample_4", "?-ACGTAC---"),
+            ("sample_5", "-----------"),
+        ]);
+        let params = TrimParams {
+            edge_occupancy: Some(0.5),
+            max_gap: Some(0.3),
+            automated: false,
+            max_entropy: Some(0.8),
+        };
+        let (trimmed, stats) = AlignmentTrimmer::new(&params).trim(&alignment);
+        assert_eq!(stats.edge_columns, 4);
+        assert_eq!(stats.gappy_columns, 1);
+        assert_eq!(stats.entropy_columns, 1);
+        assert_eq!(stats.removed_sequences, 1);
+        assert_eq!(trimmed.len(), 4);
+        assert_eq!(trimmed["sample_1"], "ACGAC");
+        assert_eq!(trimmed["sample_4"], "ACGAC");
+    }
+
+    #[test]
+    fn test_trim_protein() {
+        let alignment = matrix(&[
+            ("sample_1", "NMK"),
+            ("sample_2", "NM-"),
+            ("sample_3", "XM-"),
+        ]);
+        // N is an amino acid, not missing data
+        let params = TrimParams {
+            edge_occupancy: Some(0.6),
+            max_gap: None,
+            automated: false,
+            max_entropy: None,
+        };
+        let (trimmed, stats) = AlignmentTrimmer::new(&params)
+            .with_datatype(&DataType::Aa)
+            .trim(&alignment);
+        assert_eq!(stats.edge_columns, 1);
+        assert_eq!(trimmed["sample_1"], "NM");
+    }
+
+    #[test]
+    fn test_trim_codons() {
+        let alignment = matrix(&[
+            ("sample_1", "--GGCCAAGTT"),
+            ("sample_2", "-TGGCCAAG--"),
+            ("sample_3", "ATGGCCAAG-T"),
+        ]);
+        let params = TrimParams {
+            edge_occupancy: Some(0.5),
+            max_gap: None,
+            automated: false,
+            max_entropy: None,
+        };
+        let (trimmed, stats) = AlignmentTrimmer::new(&params)
+            .with_codons(true)
+            .trim(&alignment);
+        assert_eq!(stats.edge_columns, 1);
+        assert_eq!(stats.codon_columns, 4);
+        assert_eq!(trimmed["sample_1"], "GCCAAG");
+        assert_eq!(trimmed["sample_2"], "GCCAAG");
+    }
+
+    #[test]
+    fn test_automated_gap_cutoff() {
+        let mut gaps = vec![0; 90];
+        gaps.extend([1; 5]);
+        gaps.extend([8, 9]);
+        assert_eq!(automated_gap_cutoff(&gaps), 1);
+        assert_eq!(automated_gap_cutoff(&[0, 0, 1]), 1);
+    }
+}
@@ -5,14 +5,20 @@ use std::{
     sync::mpsc::channel,
 };
 
+use colored::Colorize;
 use rayon::prelude::*;
 use serde::{Deserialize, Serialize};
 
+use super::spades::SpadesRetryConfig;
 use crate::{
-    core::clean::reports::CleanReadReport,
-    core::deps::{spades::SpadesMetadata, DepMetadata},
-    helper::{common::UllarConfig, configs::generate_config_output_path, fastq::FastqInput},
-    types::reads::FastqReads,
+    core::clean::{reports::CleanReadReport, summary::FastpSummary},
+    core::deps::{megahit::MegahitMetadata, spades::SpadesMetadata, DepMetadata},
+    helper::{
+        common::UllarConfig,
+        configs::{generate_config_output_path, PreviousStep},
+        fastq::FastqInput,
+    },
+    types::{assembly::Assembler, reads::FastqReads},
 };
 
 pub const DEFAULT_ASSEMBLY_CONFIG: &str = "denovo_assembly";
@@ -25,15 +31,36 @@ pub struct AssemblyConfig {
     pub app: UllarConfig,
     pub input: FastqInput,
     pub dependencies: BTreeMap<String, DepMetadata>,
+    /// Retry ladder for failed SPAdes runs
+    #[serde(default)]
+    pub spades_retry: SpadesRetryConfig,
+    /// Pipeline step that produced the input
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub previous_step: Option<PreviousStep>,
+    /// Samples that failed read cleaning QC
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub qc_flagged: Vec<QcFlag>,
     pub samples: Vec<FastqReads>,
 }
 
+/// Sample flagged by read cleaning QC
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct QcFlag {
+    pub sample_name: String,
+    pub reason: String,
+    /// Whether the sample was removed from the config
+    pub excluded: bool,
+}
+
 impl AssemblyConfig {
     pub fn new(input: FastqInput, samples: Vec<FastqReads>) -> Self {
         Self {
             app: UllarConfig::init(),
             input,
             dependencies: BTreeMap::new(),
+            spades_retry: SpadesRetryConfig::default(),
+            previous_step: None,
+            qc_flagged: Vec::new(),
             samples,
         }
     }
@@ -60,9 +87,10 @@ impl AssemblyConfig {
 
     pub fn to_toml(
         &mut self,
+        assembler: Assembler,
         override_args: Option<&str>,
     ) -> Result<PathBuf, Box<dyn std::error::Error>> {
-        self.get_dependency(override_args);
+        self.get_dependency(assembler, override_args);
         self.get_sample_counts();
         self.get_file_counts();
         let output_dir = generate_config_output_path(DEFAULT_ASSEMBLY_CONFIG);
@@ -76,7 +104,7 @@ impl AssemblyConfig {
         &mut self,
         override_args: Option<&str>,
     ) -> Result<PathBuf, Box<dyn std::error::Error>> {
-        self.get_dependency(override_args);
+        self.get_dependency(Assembler::Spades, override_args);
         let output_dir = generate_config_output_path(DEFAULT_ASSEMBLY_CONFIG);
         let writer = fs::File::create(&output_dir)?;
         serde_yaml::to_writer(&writer, self)?;
@@ -86,22 +114,71 @@ impl AssemblyConfig {
     pub fn from_clean_read_config(
         &mut self,
         reports: &[CleanReadReport],
+        qc_summaries: &[FastpSummary],
+        keep_failed_qc: bool,
+        assembler: Assembler,
     ) -> Result<PathBuf, Box<dyn std::error::Error>> {
-        self.get_dependency(None);
+        self.get_dependency(assembler, None);
+        self.samples = self.parse_fastp_report(reports);
+        self.apply_read_qc(qc_summaries, keep_failed_qc);
         self.get_sample_counts();
         self.get_file_counts();
-        self.samples = self.parse_fastp_report(reports);
         let output_path = generate_config_output_path(DEFAULT_ASSEMBLY_CONFIG);
         let toml = toml::to_string_pretty(self)?;
         fs::write(&output_path, toml)?;
         Ok(output_path)
     }
 
-    fn get_dependency(&mut self, override_args: Option<&str>) {
-        let dep = SpadesMetadata::new().override_args(override_args).get();
-        let spades = dep.unwrap_or_else(|| panic!("Failed to get Spades dependency"));
+    /// Flag samples that failed read cleaning QC.
+    /// Flagged samples are removed from the config
+    /// unless `keep_failed` is true.
+    pub fn apply_read_qc(&mut self, qc_summaries: &[FastpSummary], keep_failed: bool) {
+        let failed: BTreeMap<&str, &str> = qc_summaries
+            .iter()
+            .filter(|s| !s.is_passed())
+            .map(|s| (s.sample_name.as_str(), s.qc_reason.as_str()))
+            .collect();
+        self.qc_flagged = self
+            .samples
+            .iter()
+            .filter_map(|sample| {
+                failed
+                    .get(sample.sample_name.as_str())
+                    .map(|reason| QcFlag {
+                        sample_name: sample.sample_name.to_string(),
+                        reason: reason.to_string(),
+                        excluded: !keep_failed,
+                    })
+            })
+            .collect();
+        self.qc_flagged.iter().for_each(|flag| {
+            log::warn!(
+                "{} {} failed read cleaning QC ({}). {}",
+                "Warning:".yellow(),
+                flag.sample_name,
+                flag.reason,
+                if flag.excluded { "Excluded" } else { "Kept" }
+            );
+        });
+        if !keep_failed {
+            self.samples
+                .retain(|sample| !failed.contains_key(sample.sample_name.as_str()));
+        }
+    }
+
+    fn get_dependency(&mut self, assembler: Assembler, override_args: Option<&str>) {
+        let dep = match assembler {
+            Assembler::Spades => SpadesMetadata::new()
+                .override_args(override_args)
+                .get()
+                .unwrap_or_else(|| panic!("Failed to get Spades dependency")),
+            Assembler::Megahit => MegahitMetadata::new()
+                .override_args(override_args)
+                .get()
+                .unwrap_or_else(|| panic!("Failed to get MEGAHIT dependency")),
+        };
         self.dependencies
-            .insert(ASSEMBLER_DEPENDENCY.to_string(), spades);
+            .insert(ASSEMBLER_DEPENDENCY.to_string(), dep);
     }
 
     fn parse_fastp_report(&self, reports: &[CleanReadReport]) -> Vec<FastqReads> {
@@ -133,3 +210,42 @@ impl AssemblyConfig {
         self.input.file_counts = read1 + read2;
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::clean::summary::QcStatus;
+
+    fn sample(name: &str) -> FastqReads {
+        let mut sample = FastqReads::new();
+        sample.sample_name = name.to_string();
+        sample
+    }
+
+    #[test]
+    fn test_apply_read_qc() {
+        let samples = vec![sample("sample_1"), sample("sample_2")];
+        let summaries = vec![
+            FastpSummary {
+                sample_name: "sample_1".to_string(),
+                ..Default::default()
+            },
+            FastpSummary {
+                sample_name: "sample_2".to_string(),
+                qc_status: QcStatus::Fail,
+                qc_reason: "reads after filtering 2000 < 10000".to_string(),
+                ..Default::default()
+            },
+        ];
+        let mut config = AssemblyConfig::new(FastqInput::default(), samples.clone());
+        config.apply_read_qc(&summaries, false);
+        assert_eq!(config.samples.len(), 1);
+        assert_eq!(config.qc_flagged.len(), 1);
+        assert_eq!(config.qc_flagged[0].sample_name, "sample_2");
+        assert!(config.qc_flagged[0].excluded);
+        let mut config = AssemblyConfig::new(FastqInput::default(), samples);
+        config.apply_read_qc(&summaries, true);
+        assert_eq!(config.samples.len(), 2);
+        assert!(!config.qc_flagged[0].excluded);
+    }
+}
@@ -15,15 +15,20 @@ use crate::{
         assembly::AssemblyInitArgs,
         common::{CommonInitArgs, GenomicReadsInitArgs},
     },
-    core::assembly::Assembly,
+    core::{
+        assembly::Assembly,
+        clean::summary::{ReadCleaningSummary, READ_CLEANING_SUMMARY},
+        project::manifest::{ProjectManifest, TaskStatus},
+    },
     helper::{
         common::{self, PrettyHeader},
         fastq::{FastqInput, ReadAssignmentStrategy},
         files::FileFinder,
     },
     types::{
+        assembly::Assembler,
         reads::{FastqReads, ReadAssignment, SampleNameFormat},
-        SupportedFormats,
+        SupportedFormats, Task,
     },
 };
 
@@ -33,6 +38,8 @@ pub struct AssemblyInit<'a> {
     input_dir: &'a Path,
     common: &'a CommonInitArgs,
     reads: &'a GenomicReadsInitArgs,
+    keep_failed_qc: bool,
+    assembler: Assembler,
     sample_name_format: SampleNameFormat,
 }
 
@@ -42,6 +49,11 @@ impl<'a> AssemblyInit<'a> {
             input_dir: &args.dir,
             common: &args.common,
             reads: &args.reads,
+            keep_failed_qc: args.keep_failed_qc,
+            assembler: args
+                .assembler
+                .parse::<Assembler>()
+                .expect("Invalid assembler"),
             sample_name_format: args
                 .reads
                 .sample_name
@@ -89,12 +101,17 @@ impl<'a> AssemblyInit<'a> {
 
         let config = self.write_config(samples, file_count);
         match config {
-            Ok(config_path) => {
+            Ok((config_path, config)) => {
                 spin.finish_with_message(format!(
                     "{} Finished creating a config file\n",
                     "✔".green()
                 ));
-                self.log_output(&config_path, sample_count, file_count);
+                self.log_output(&config_path, &config);
+                ProjectManifest::record(
+                    Task::Assembly,
+                    TaskStatus::Initialized,
+                    Some(&config_path),
+                );
                 if self.common.autorun {
                     let footer = PrettyHeader::new();
                     footer.get_section_footer();
@@ -113,7 +130,7 @@ impl<'a> AssemblyInit<'a> {
         log::info!("{}", header.cyan());
         log::info!("");
         let runner = Assembly::from_config_path(config_path);
-        runner.assemble();
+        runner.assemble().expect("Failed to assemble reads");
     }
 
     fn log_empty_input(&self) {
@@ -146,21 +163,41 @@ impl<'a> AssemblyInit<'a> {
         &self,
         records: Vec<FastqReads>,
         file_counts: usize,
-    ) -> Result<PathBuf, Box<dyn Error>> {
+    ) -> Result<(PathBuf, AssemblyConfig), Box<dyn Error>> {
         let strategy = ReadAssignmentStrategy::from_arg(self.reads);
         let input_summary = FastqInput::new(self.input_dir, records.len(), file_counts, strategy);
         let mut config = AssemblyConfig::new(input_summary, records.to_vec());
-        let output_path = config.to_toml(self.common.override_args.as_deref())?;
-        Ok(output_path)
+        self.apply_read_qc(&mut config);
+        let output_path = config.to_toml(self.assembler, self.common.override_args.as_deref())?;
+        Ok((output_path, config))
+    }
+
+    // Only available when the input directory
+    // is the output of ullar read cleaning.
+    fn apply_read_qc(&self, config: &mut AssemblyConfig) {
+        let summary_path = self.input_dir.join(READ_CLEANING_SUMMARY);
+        if !summary_path.is_file() {
+            return;
+        }
+        match ReadCleaningSummary::from_csv(&summary_path) {
+            Ok(summaries) => config.apply_read_qc(&summaries, self.keep_failed_qc),
+            Err(e) => log::warn!(
+                "{} Failed to read {}: {}",
+                "Warning:".yellow(),
+                summary_path.display(),
+                e
+            ),
+        }
     }
 
     fn log_input(&self) {
         log::info!("{}", "Input".cyan());
         log::info!("{:18}: {}", "Directory", self.input_dir.display());
-        log::info!("{:18}: {}\n", "Sample name format", self.reads.sample_name);
+        log::info!("{:18}: {}", "Sample name format", self.reads.sample_name);
+        log::info!("{:18}: {}\n", "Assembler", self.assembler);
     }
 
-    fn log_output(&self, output_path: &Path, record_counts: usize, file_counts: usize) {
+    fn log_output(&self, output_path: &Path, config: &AssemblyConfig) {
         let config_filename = output_path
             .file_name()
             .expect("Failed to get config file name")
@@ -175,7 +212,10 @@ impl<'a> AssemblyInit<'a> {
                 .display()
         );
         log::info!("{:18}: {}", "Config file", config_filename);
-        log::info!("{:18}: {}", "Sample counts", record_counts);
-        log::info!("{:18}: {}", "File counts", file_counts);
+        log::info!("{:18}: {}", "Sample counts", config.input.sample_counts);
+        log::info!("{:18}: {}", "File counts", config.input.file_counts);
+        if !config.qc_flagged.is_empty() {
+            log::info!("{:18}: {}", "QC flagged", config.qc_flagged.len());
+        }
     }
 }
@@ -0,0 +1,251 @@
+use std::{
+    error::Error,
+    path::{Path, PathBuf},
+    process::{Command, Output},
+};
+
+use colored::Colorize;
+use indicatif::ProgressBar;
+
+use crate::{
+    check_read1_exists,
+    core::deps::{megahit::MEGAHIT_EXE, DepMetadata},
+    create_output_dir,
+    helper::{
+        command::has_override_arg,
+        common::{self, PrettyHeader},
+        scheduler::JobRequirement,
+    },
+    parse_override_args,
+    types::{assembly::Assembler, reads::FastqReads},
+};
+
+use super::reports::AssemblyReports;
+
+/// MEGAHIT output directory inside the sample output directory.
+/// MEGAHIT refuses to write to an existing directory.
+const MEGAHIT_OUTPUT_DIR: &str = "megahit";
+
+/// Resource requirement of a MEGAHIT job.
+/// MEGAHIT uses much less memory than SPAdes.
+pub const MEGAHIT_REQUIREMENT: JobRequirement = JobRequirement::new(4, 32, 4);
+
+pub struct MegahitRunner<'a> {
+    sample: &'a FastqReads,
+    pub sample_output_dir: PathBuf,
+    pub dependency: &'a DepMetadata,
+    pub keep_intermediates: bool,
+    pub rename_contigs: bool,
+    /// Threads for MEGAHIT. Use MEGAHIT default if None.
+    pub threads: Option<usize>,
+    /// Memory limit for MEGAHIT in GiB.
+    /// Use MEGAHIT default if None.
+    pub memory_limit: Option<u64>,
+}
+
+impl<'a> MegahitRunner<'a> {
+    pub fn new(
+        sample: &'a FastqReads,
+        output_dir: &Path,
+        dependency: &'a DepMetadata,
+    ) -> MegahitRunner<'a> {
+        MegahitRunner {
+            sample,
+            sample_output_dir: output_dir.join(&sample.sample_name),
+            dependency,
+            keep_intermediates: false,
+            rename_contigs: false,
+            threads: None,
+            memory_limit: None,
+        }
+    }
+
+    pub fn threads(mut self, threads: usize) -> Self {
+        self.threads = Some(threads);
+        self
+    }
+
+    pub fn memory_limit(mut self, memory_gib: u64) -> Self {
+        self.memory_limit = Some(memory_gib);
+        self
+    }
+
+    pub fn keep_intermediates(mut self, keep_intermediates: bool) -> Self {
+        self.keep_intermediates = keep_intermediates;
+        self
+    }
+
+    pub fn rename_contigs(mut self, rename_contigs: bool) -> Self {
+        self.rename_contigs = rename_contigs;
+        self
+    }
+
+    pub fn run(&mut self) -> Result<AssemblyReports, Box<dyn Error>> {
+        let decorator = self.print_header();
+        let read1 = self.sample.get_read1();
+        check_read1_exists!(self, read1);
+        let read2 = self.sample.get_read2();
+        let singleton = self.sample.get_singleton();
+        create_output_dir!(self);
+        let megahit_dir = self.sample_output_dir.join(MEGAHIT_OUTPUT_DIR);
+        if megahit_dir.exists() {
+            std::fs::remove_dir_all(&megahit_dir)?;
+        }
+        let spinner = common::init_spinner();
+        spinner.set_message("Assembling reads");
+        let megahit = Megahit::new(&read1, read2.as_deref(), singleton.as_deref(), &megahit_dir)
+            .resources(self.threads, self.memory_limit);
+        let output = megahit.execute(self.dependency);
+        let reports = match output {
+            Ok(output) => self.check_megahit_success(&output, &megahit_dir, &spinner),
+            Err(e) => {
+                spinner.finish_with_message(format!("{} Failed to assemble reads\n", "✘".red()));
+                Err(e)
+            }
+        };
+        if let Ok(reports) = &reports {
+            self.print_output_summary(reports);
+        }
+        decorator.get_section_footer();
+        reports
+    }
+
+    /// Report of a sample assembled in a previous run.
+    /// Used to skip the sample when resuming.
+    pub fn completed_report(&self) -> AssemblyReports {
+        let mut reports = AssemblyReports::new(
+            &self.sample.sample_name,
+            &self.sample_output_dir,
+            Assembler::Megahit,
+        );
+        let renamed_contigs = reports.get_renamed_contigs();
+        if renamed_contigs.is_file() {
+            reports.contigs = renamed_contigs;
+        }
+        reports
+    }
+
+    fn check_megahit_success(
+        &self,
+        output: &Output,
+        megahit_dir: &Path,
+        spinner: &ProgressBar,
+    ) -> Result<AssemblyReports, Box<dyn Error>> {
+        if !output.status.success() {
+            spinner.finish_with_message(format!("{} Failed to assemble reads\n", "✘".red()));
+            log::error!("{}", String::from_utf8_lossy(&output.stderr));
+            log::info!("{}", String::from_utf8_lossy(&output.stdout));
+            return Err("Failed to assemble reads".into());
+        }
+        let mut reports = AssemblyReports::new(
+            &self.sample.sample_name,
+            &self.sample_output_dir,
+            Assembler::Megahit,
+        );
+        reports.normalize_megahit(megahit_dir)?;
+        if !self.keep_intermediates {
+            spinner.set_message("Removing intermediates");
+            reports.remove_intermediates()?;
+            log::info!(
+                "\n\n{} {}\n",
+                "Intermediate MEGAHIT files were removed.",
+                "✔".green()
+            );
+            if self.rename_contigs {
+                reports.rename_contigs();
+            }
+        }
+        spinner.finish_with_message(format!("{} Finished assembling reads\n", "✔".green()));
+        Ok(reports)
+    }
+
+    fn print_header(&self) -> PrettyHeader {
+        let mut decorator = PrettyHeader::new();
+        let header = decorator.get_section_header(&self.sample.sample_name);
+        log::info!("{}", header);
+        decorator
+    }
+
+    fn print_output_summary(&self, reports: &AssemblyReports) {
+        log::info!("{}", "Output".cyan());
+        log::info!("{:18}: {}", "Directory", reports.output_dir.display());
+        log::info!("{:18}: {}", "Contigs", reports.contigs.display());
+        log::info!("{:18}: {}", "Log", reports.log.display());
+    }
+}
+
+pub struct Megahit<'a> {
+    pub read1: &'a Path,
+    pub read2: Option<&'a Path>,
+    pub singleton: Option<&'a Path>,
+    pub output_dir: PathBuf,
+    pub threads: Option<usize>,
+    pub memory_limit: Option<u64>,
+}
+
+impl<'a> Megahit<'a> {
+    pub fn new(
+        read1: &'a Path,
+        read2: Option<&'a Path>,
+        singleton: Option<&'a Path>,
+        output_dir: &Path,
+    ) -> Megahit<'a> {
+        Megahit {
+            read1,
+            read2,
+            singleton,
+            output_dir: output_dir.to_path_buf(),
+            threads: None,
+            memory_limit: None,
+        }
+    }
+
+    pub fn resources(mut self, threads: Option<usize>, memory_limit: Option<u64>) -> Self {
+        self.threads = threads;
+        self.memory_limit = memory_limit;
+        self
+    }
+
+    pub fn execute(&self, dep: &DepMetadata) -> Result<Output, Box<dyn Error>> {
+        let executable = dep.get_executable(MEGAHIT_EXE);
+        let mut cmd = Command::new(executable);
+        match self.read2 {
+            Some(read2) => {
+                cmd.arg("-1").arg(self.read1).arg("-2").arg(read2);
+                if let Some(singleton) = self.singleton {
+                    cmd.arg("-r").arg(singleton);
+                }
+            }
+            None => {
+                // MEGAHIT accepts a comma-separated list of single-end reads
+                let mut reads = self.read1.as_os_str().to_owned();
+                if let Some(singleton) = self.singleton {
+                    reads.push(",");
+                    reads.push(singleton);
+                }
+                cmd.arg("-r").arg(reads);
+            }
+        }
+
+        cmd.arg("-o").arg(&self.output_dir);
+        // User specified resources take precedence
+        let override_args = dep.override_args.as_deref().unwrap_or_default();
+        if let Some(threads) = self.threads {
+            if !has_override_arg(override_args, &["-t", "--num-cpu-threads"]) {
+                cmd.arg("-t").arg(threads.to_string());
+            }
+        }
+        // MEGAHIT reads values >= 1 as bytes
+        if let Some(memory) = self.memory_limit {
+            if !has_override_arg(override_args, &["-m", "--memory"]) {
+                cmd.arg("-m").arg((memory * 1024 * 1024 * 1024).to_string());
+            }
+        }
+
+        if let Some(params) = &dep.override_args {
+            parse_override_args!(cmd, params);
+        }
+
+        Ok(cmd.output()?)
+    }
+}
@@ -1,13 +1,16 @@
 use std::{
     error::Error,
     path::{Path, PathBuf},
+    sync::{mpsc, Mutex},
     time::Instant,
 };
 
 use colored::Colorize;
 use comfy_table::Table;
 use configs::{AssemblyConfig, ASSEMBLER_DEPENDENCY, DEFAULT_ASSEMBLY_CONFIG};
-use spades::SpadeRunner;
+use megahit::{MegahitRunner, MEGAHIT_REQUIREMENT};
+use rayon::prelude::*;
+use spades::{SpadeRunner, SPADES_REQUIREMENT};
 
 use crate::{
     cli::commands::assembly::AssemblyArgs,
@@ -16,19 +19,25 @@ use crate::{
         configs::{CONFIG_EXTENSION_TOML, DEFAULT_CONFIG_DIR},
         fastq::FastqConfigCheck,
         files::PathCheck,
+        ledger::{self, LedgerEntry, LedgerStatus, StepLedger},
+        scheduler::JobScheduler,
         tracker::ProcessingTracker,
     },
-    types::{reads::FastqReads, runner::RunnerOptions, Task},
+    types::{assembly::Assembler, reads::FastqReads, runner::RunnerOptions, Task},
 };
 
-use self::reports::SpadeReports;
+use self::reports::AssemblyReports;
+use self::summary::AssemblySummary;
 
-use crate::core::deps::{spades::SpadesMetadata, DepMetadata};
+use crate::core::deps::{megahit::MegahitMetadata, spades::SpadesMetadata, DepMetadata};
+use crate::core::project::manifest::{ProjectManifest, TaskStatus};
 
 pub mod configs;
 pub mod init;
+pub mod megahit;
 pub mod reports;
 pub mod spades;
+pub mod summary;
 
 pub const DEFAULT_ASSEMBLY_OUTPUT_DIR: &str = "out_denovo_assembly";
 
@@ -37,7 +46,7 @@ pub struct Assembly<'a> {
     pub config_path: PathBuf,
     /// Output directory to store the assemblies
     pub output_dir: &'a Path,
-    /// Remove SPAdes intermediate files
+    /// Remove assembler intermediate files
     /// by default
     pub keep_intermediates: bool,
     /// Rename contigs file to sample name
@@ -96,17 +105,23 @@ impl<'a> Assembly<'a> {
         }
     }
 
-    /// Assemble cleaned read files using SPAdes
-    pub fn assemble(&self) {
+    /// Assemble cleaned read files using the assembler in the config.
+    /// Returns the reports of the successfully assembled samples.
+    pub fn assemble(&self) -> Result<Vec<AssemblyReports>, Box<dyn Error>> {
         let spinner = common::init_spinner();
         spinner.set_message("Parsing config file\n");
-        let config = self.parse_config().expect("Failed to parse config");
-        let assembler = config.dependencies.get(ASSEMBLER_DEPENDENCY);
-        let updated_dep = SpadesMetadata::new().update(assembler);
+        let config = self.parse_config()?;
+        let dep = config.dependencies.get(ASSEMBLER_DEPENDENCY);
+        let assembler = Assembler::from_dependency(dep);
+        let updated_dep = match assembler {
+            Assembler::Spades => SpadesMetadata::new().update(dep),
+            Assembler::Megahit => MegahitMetadata::new().update(dep),
+        };
         self.log_input(&config, &updated_dep);
         PathCheck::new(self.output_dir)
             .is_dir()
             .with_force_overwrite(self.runner.overwrite)
+            .with_resume(self.runner.resume)
             .prompt_exists(self.runner.dry_run);
         let mut check = FastqConfigCheck::new(config.input.sample_counts);
         if self.runner.skip_config_check {
@@ -120,17 +135,24 @@ impl<'a> Assembly<'a> {
         if self.runner.dry_run {
             check.log_status();
             self.log_unprocessed();
-            return;
+            return Ok(Vec::new());
         }
 
         if !check.is_config_ok() && !self.runner.skip_config_check {
             check.log_status();
             log::error!("\n{}\n", "Config check failed".red());
-            return;
+            return Ok(Vec::new());
         }
 
-        let _ = self.assemble_reads(&config.samples, &updated_dep);
-        self.log_output();
+        self.update_manifest(TaskStatus::Running);
+        let reports = self.assemble_reads(&config, &updated_dep, assembler);
+        self.update_manifest(TaskStatus::from_success(
+            reports.len() == config.samples.len(),
+        ));
+        let summary = AssemblySummary::from_reports(self.output_dir, &reports);
+        summary.log_table();
+        self.log_output(&summary);
+        Ok(reports)
     }
 
     fn parse_config(&self) -> Result<AssemblyConfig, Box<dyn Error>> {
@@ -138,39 +160,121 @@ impl<'a> Assembly<'a> {
         Ok(config)
     }
 
-    fn assemble_reads(&self, samples: &[FastqReads], dep: &DepMetadata) -> Vec<SpadeReports> {
-        let time = Instant::now();
-        let mut tracker = ProcessingTracker::new(samples.len());
-        let mut reports = Vec::new();
-
-        samples.iter().enumerate().for_each(|(idx, sample)| {
-            let results = SpadeRunner::new(sample, self.output_dir, dep)
-                .keep_intermediates(self.keep_intermediates)
-                .rename_contigs(self.rename_contigs)
-                .run();
-
-            match results {
-                Ok(report) => {
-                    reports.push(report);
-                    tracker.success_counts += 1;
+    fn assemble_reads(
+        &self,
+        config: &AssemblyConfig,
+        dep: &DepMetadata,
+        assembler: Assembler,
+    ) -> Vec<AssemblyReports> {
+        let samples = &config.samples;
+        let requirement = match assembler {
+            Assembler::Spades => &SPADES_REQUIREMENT,
+            Assembler::Megahit => &MEGAHIT_REQUIREMENT,
+        };
+        let scheduler = JobScheduler::from_system(requirement, samples.len());
+        scheduler.log_summary();
+        let tracker = Mutex::new(
+            ProcessingTracker::new(samples.len()).with_concurrent_jobs(scheduler.concurrent_jobs),
+        );
+        let ledger = StepLedger::new(self.output_dir);
+        // Assemblies are only reused with the same SPAdes retry ladder
+        let params_hash = match assembler {
+            Assembler::Spades => ledger::hash_string(&format!(
+                "{}{}",
+                ledger::hash_params(dep),
+                ledger::hash_params(&config.spades_retry)
+            )),
+            Assembler::Megahit => ledger::hash_params(dep),
+        };
+        let (tx, rx) = mpsc::channel();
+
+        scheduler.build_pool().install(|| {
+            samples.par_iter().for_each_with(tx, |tx, sample| {
+                let mut runner = self.init_runner(sample, dep, config, assembler, &scheduler);
+                let input_hash = ledger::hash_fastq(sample);
+                if self.runner.resume
+                    && ledger.is_completed(&sample.sample_name, &input_hash, &params_hash)
+                {
+                    let report = runner.completed_report();
+                    if report.contigs.is_file() {
+                        log::info!(
+                            "{:18}: {} (completed in a previous run)",
+                            "Skipping",
+                            sample.sample_name
+                        );
+                        tx.send(report).expect("Failed to send report");
+                        tracker.lock().expect("Failed to lock tracker").skip();
+                        return;
+                    }
                 }
-                Err(e) => {
-                    log::error!("Failed to assemble sample: {}", sample.sample_name);
-                    log::error!("Error: {}", e);
-                    tracker.failure_counts += 1;
+                tracker.lock().expect("Failed to lock tracker").start();
+                let time = Instant::now();
+                let results = runner.run();
+                let status = LedgerStatus::from_success(results.is_ok());
+                ledger.record(&LedgerEntry::new(
+                    &sample.sample_name,
+                    &input_hash,
+                    &params_hash,
+                    status,
+                ));
+
+                let mut tracker = tracker.lock().expect("Failed to lock tracker");
+                match results {
+                    Ok(report) => {
+                        tx.send(report).expect("Failed to send report");
+                        tracker.success_counts += 1;
+                    }
+                    Err(e) => {
+                        log::error!("Failed to assemble sample: {}", sample.sample_name);
+                        log::error!("Error: {}", e);
+                        tracker.failure_counts += 1;
+                    }
                 }
-            }
 
-            tracker.update(time.elapsed().as_secs_f64());
-            if idx < samples.len() - 1 {
-                tracker.print_summary();
-            }
+                tracker.update(time.elapsed().as_secs_f64());
+                if !tracker.is_finished() {
+                    tracker.print_summary();
+                }
+            });
         });
 
-        tracker.finalize();
+        tracker.lock().expect("Failed to lock tracker").finalize();
+        let mut reports: Vec<AssemblyReports> = rx.iter().collect();
+        reports.sort_by(|a, b| a.sample_name.cmp(&b.sample_name));
         reports
     }
 
+    fn init_runner<'b>(
+        &self,
+        sample: &'b FastqReads,
+        dep: &'b DepMetadata,
+        config: &AssemblyConfig,
+        assembler: Assembler,
+        scheduler: &JobScheduler,
+    ) -> AssemblerRunner<'b> {
+        match assembler {
+            Assembler::Spades => AssemblerRunner::Spades(
+                SpadeRunner::new(sample, self.output_dir, dep)
+                    .keep_intermediates(self.keep_intermediates)
+                    .rename_contigs(self.rename_contigs)
+                    .threads(scheduler.threads_per_job)
+                    .memory_limit(scheduler.memory_per_job_gib())
+                    .retry(&config.spades_retry),
+            ),
+            Assembler::Megahit => AssemblerRunner::Megahit(
+                MegahitRunner::new(sample, self.output_dir, dep)
+                    .keep_intermediates(self.keep_intermediates)
+                    .rename_contigs(self.rename_contigs)
+                    .threads(scheduler.threads_per_job)
+                    .memory_limit(scheduler.memory_per_job_gib()),
+            ),
+        }
+    }
+
+    fn update_manifest(&self, status: TaskStatus) {
+        ProjectManifest::record(self.task, status, Some(&self.config_path));
+    }
+
     fn log_unprocessed(&self) {
         let msg1 = "Samples were not processed";
         let msg2 = format!("To process samples use: {}", "ullar assemble --process");
@@ -193,9 +297,35 @@ impl<'a> Assembly<'a> {
         log::info!("{:18}: {} v{}\n", "Assembler", dep.app_name, dep.version);
     }
 
-    fn log_output(&self) {
+    fn log_output(&self, summary: &AssemblySummary) {
         log::info!("{}", "Output summary".cyan());
         let output_dir = self.output_dir.join("assemblies");
         log::info!("{:18}: {}", "Output directory", output_dir.display());
+        match summary.write() {
+            Ok(path) => log::info!("{:18}: {}", "Assembly summary", path.display()),
+            Err(e) => log::error!("Failed to write assembly summary: {}", e),
+        }
+    }
+}
+
+/// Dispatch a sample to the selected assembler
+enum AssemblerRunner<'a> {
+    Spades(SpadeRunner<'a>),
+    Megahit(MegahitRunner<'a>),
+}
+
+impl AssemblerRunner<'_> {
+    fn run(&mut self) -> Result<AssemblyReports, Box<dyn Error>> {
+        match self {
+            AssemblerRunner::Spades(runner) => runner.run(),
+            AssemblerRunner::Megahit(runner) => runner.run(),
+        }
+    }
+
+    fn completed_report(&self) -> AssemblyReports {
+        match self {
+            AssemblerRunner::Spades(runner) => runner.completed_report(),
+            AssemblerRunner::Megahit(runner) => runner.completed_report(),
+        }
     }
 }
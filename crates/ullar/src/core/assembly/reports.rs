@@ -5,49 +5,105 @@ use std::{
     path::{Path, PathBuf},
 };
 
+use serde::{Deserialize, Serialize};
 use walkdir::WalkDir;
 
-const SPADES_CONTIGS: &str = "contigs.fasta";
+use crate::types::assembly::Assembler;
+
+use super::spades::SpadesErrorKind;
+
+/// Contigs file name of all assemblers.
+/// MEGAHIT contigs are renamed to match SPAdes.
+const ASSEMBLY_CONTIGS: &str = "contigs.fasta";
 const SPADES_SCAFFOLDS: &str = "scaffolds.fasta";
 const SPADES_LOG: &str = "spades.log";
+const MEGAHIT_CONTIGS: &str = "final.contigs.fa";
+const MEGAHIT_LOG: &str = "log";
+const MEGAHIT_RENAMED_LOG: &str = "megahit.log";
 pub const CONTIG_SUFFIX: &str = "-contigs";
 const CONTIG_EXTENSION: &str = "fasta";
+pub const ASSEMBLY_ATTEMPTS: &str = "assembly_attempts.csv";
+
+/// Assembler run of a sample
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AssemblyAttempt {
+    pub attempt: usize,
+    /// Parameters changed from the first attempt
+    pub strategy: String,
+    pub success: bool,
+    /// Error category of a failed attempt
+    pub error: Option<SpadesErrorKind>,
+}
+
+/// Write the assembler attempts to the sample output directory
+pub fn write_attempts(
+    output_dir: &Path,
+    attempts: &[AssemblyAttempt],
+) -> Result<PathBuf, Box<dyn Error>> {
+    let output_path = output_dir.join(ASSEMBLY_ATTEMPTS);
+    let mut writer = csv::Writer::from_path(&output_path)?;
+    for attempt in attempts {
+        writer.serialize(attempt)?;
+    }
+    writer.flush()?;
+    Ok(output_path)
+}
 
-pub struct SpadeReports {
+pub struct AssemblyReports {
     pub sample_name: String,
+    pub assembler: Assembler,
     pub output_dir: PathBuf,
     pub contigs: PathBuf,
-    pub scaffolds: PathBuf,
+    /// Only available for SPAdes
+    pub scaffolds: Option<PathBuf>,
     pub log: PathBuf,
+    /// Assembler runs until the assembly succeeded
+    pub attempts: Vec<AssemblyAttempt>,
 }
 
-impl SpadeReports {
-    pub fn new(sample_name: &str, output_dir: &Path) -> SpadeReports {
-        SpadeReports {
+impl AssemblyReports {
+    pub fn new(sample_name: &str, output_dir: &Path, assembler: Assembler) -> AssemblyReports {
+        let (scaffolds, log) = match assembler {
+            Assembler::Spades => (Some(output_dir.join(SPADES_SCAFFOLDS)), SPADES_LOG),
+            Assembler::Megahit => (None, MEGAHIT_RENAMED_LOG),
+        };
+        AssemblyReports {
             sample_name: sample_name.to_string(),
+            assembler,
             output_dir: output_dir.to_path_buf(),
-            contigs: output_dir.join(SPADES_CONTIGS),
-            scaffolds: output_dir.join(SPADES_SCAFFOLDS),
-            log: output_dir.join(SPADES_LOG),
+            contigs: output_dir.join(ASSEMBLY_CONTIGS),
+            scaffolds,
+            log: output_dir.join(log),
+            attempts: Vec::new(),
         }
     }
 
+    /// Move MEGAHIT contigs to the sample output directory
+    /// using the same contigs file name as SPAdes.
+    /// The log is copied to keep the MEGAHIT directory
+    /// intact when intermediates are kept.
+    pub fn normalize_megahit(&self, megahit_dir: &Path) -> Result<(), Box<dyn Error>> {
+        std::fs::rename(megahit_dir.join(MEGAHIT_CONTIGS), &self.contigs)?;
+        let log = megahit_dir.join(MEGAHIT_LOG);
+        if log.is_file() {
+            std::fs::copy(&log, &self.log)?;
+        }
+        Ok(())
+    }
+
     pub fn remove_intermediates(&self) -> Result<(), Box<dyn Error>> {
         WalkDir::new(&self.output_dir)
             .into_iter()
             .filter_map(|e| e.ok())
-            .filter(|e| !self.is_essential_spades_file(e.path()))
+            .filter(|e| !self.is_essential_file(e.path()))
             .for_each(|e| self.remove(e.path()));
         Ok(())
     }
 
     // We rename contigs.fasta to sample_name-contigs.fasta
     pub fn rename_contigs(&mut self) {
-        let contigs = self.output_dir.join(SPADES_CONTIGS);
-        let new_contigs = self
-            .output_dir
-            .join(format!("{}{}", self.sample_name, CONTIG_SUFFIX))
-            .with_extension(CONTIG_EXTENSION);
+        let contigs = self.output_dir.join(ASSEMBLY_CONTIGS);
+        let new_contigs = self.get_renamed_contigs();
 
         let rename = std::fs::rename(&contigs, &new_contigs);
         match rename {
@@ -66,14 +122,24 @@ impl SpadeReports {
         }
     }
 
-    fn is_essential_spades_file(&self, file: &Path) -> bool {
+    pub fn get_renamed_contigs(&self) -> PathBuf {
+        self.output_dir
+            .join(format!("{}{}", self.sample_name, CONTIG_SUFFIX))
+            .with_extension(CONTIG_EXTENSION)
+    }
+
+    fn is_essential_file(&self, file: &Path) -> bool {
         // We don't want to remove the output directory
         if file.is_dir() {
             file.ends_with(&self.output_dir)
         } else {
             file.ends_with(&self.contigs)
-                || file.ends_with(&self.scaffolds)
+                || self
+                    .scaffolds
+                    .as_ref()
+                    .is_some_and(|scaffolds| file.ends_with(scaffolds))
                 || file.ends_with(&self.log)
+                || file.ends_with(ASSEMBLY_ATTEMPTS)
         }
     }
 
@@ -90,3 +156,25 @@ impl SpadeReports {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_normalize_megahit() {
+        let output_dir = tempdir::TempDir::new("megahit").unwrap();
+        let megahit_dir = output_dir.path().join("megahit");
+        std::fs::create_dir_all(megahit_dir.join("intermediate_contigs")).unwrap();
+        std::fs::write(megahit_dir.join(MEGAHIT_CONTIGS), ">k141_1\nACGT\n").unwrap();
+        std::fs::write(megahit_dir.join(MEGAHIT_LOG), "log").unwrap();
+        let reports = AssemblyReports::new("sample_1", output_dir.path(), Assembler::Megahit);
+        reports.normalize_megahit(&megahit_dir).unwrap();
+        reports.remove_intermediates().unwrap();
+        assert!(reports.contigs.ends_with("contigs.fasta"));
+        assert!(reports.contigs.is_file());
+        assert!(reports.log.is_file());
+        assert!(reports.scaffolds.is_none());
+        assert!(!megahit_dir.exists());
+    }
+}
@@ -1,32 +1,249 @@
 use std::{
     error::Error,
+    fmt::Display,
     path::{Path, PathBuf},
     process::{Command, Output},
+    str::FromStr,
 };
 
 use colored::Colorize;
 use indicatif::ProgressBar;
+use serde::{Deserialize, Serialize};
 use sysinfo::System;
 
 use crate::{
     check_read1_exists,
     core::deps::{spades::SPADES_EXE, DepMetadata},
     create_output_dir,
-    helper::common::{self, PrettyHeader},
+    helper::{
+        command::has_override_arg,
+        common::{self, PrettyHeader, SystemInfo},
+        scheduler::JobRequirement,
+    },
     parse_override_args,
-    types::reads::FastqReads,
+    types::{assembly::Assembler, reads::FastqReads},
 };
 
-use super::reports::SpadeReports;
+use super::reports::{write_attempts, AssemblyAttempt, AssemblyReports};
 
 pub const SPADES_DEFAULT_PARAMS: &str = "--isolate";
 
+/// Resource requirement of a SPAdes job.
+/// Memory usage depends on the data, we use
+/// a conservative estimate for target capture data.
+pub const SPADES_REQUIREMENT: JobRequirement = JobRequirement::new(8, 32, 16);
+
+const GIB: u64 = 1024 * 1024 * 1024;
+
+/// SPAdes error category of a failed run
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SpadesErrorKind {
+    OutOfMemory,
+    ReadError,
+    Kmer,
+    Unknown,
+}
+
+/// SPAdes messages of invalid or missing read files.
+/// Matched as phrases, so words like "thread"
+/// or "already" are not read errors.
+const READ_ERRORS: &[&str] = &[
+    "file is empty",
+    "empty file",
+    "file not found",
+    "fastq",
+    "corrupt",
+    "no reads",
+    "reads file",
+    "read file",
+    "invalid read",
+];
+
+impl SpadesErrorKind {
+    /// Categorize the error from the SPAdes output and log.
+    /// Only error lines are checked because normal SPAdes
+    /// logs also mention k-mers and reads.
+    pub fn from_output(output: &Output, log_path: &Path) -> Self {
+        // Killed by a signal, most likely the OOM killer
+        if output.status.code().is_none() {
+            return SpadesErrorKind::OutOfMemory;
+        }
+        let log = std::fs::read_to_string(log_path).unwrap_or_default();
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        let errors: Vec<String> = [stderr.as_ref(), stdout.as_ref(), log.as_str()]
+            .iter()
+            .flat_map(|text| text.lines())
+            .map(|line| line.to_lowercase())
+            .filter(|line| line.contains("error") || line.contains("exception"))
+            .collect();
+        Self::from_error_lines(&errors)
+    }
+
+    /// Invalid or empty reads fail
+    /// with any SPAdes parameters
+    pub fn is_retryable(&self) -> bool {
+        *self != SpadesErrorKind::ReadError
+    }
+
+    fn from_error_lines(lines: &[String]) -> Self {
+        let matches = |patterns: &[&str]| {
+            lines
+                .iter()
+                .any(|line| patterns.iter().any(|p| line.contains(p)))
+        };
+        if matches(&[
+            "memory",
+            "bad_alloc",
+            "cannot allocate",
+            "return value: -9",
+            "return value: 137",
+        ]) {
+            SpadesErrorKind::OutOfMemory
+        } else if matches(&["k-mer", "kmer", "k value", "k must"]) {
+            SpadesErrorKind::Kmer
+        } else if matches(READ_ERRORS) {
+            SpadesErrorKind::ReadError
+        } else {
+            SpadesErrorKind::Unknown
+        }
+    }
+}
+
+impl Display for SpadesErrorKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SpadesErrorKind::OutOfMemory => write!(f, "out_of_memory"),
+            SpadesErrorKind::ReadError => write!(f, "read_error"),
+            SpadesErrorKind::Kmer => write!(f, "kmer"),
+            SpadesErrorKind::Unknown => write!(f, "unknown"),
+        }
+    }
+}
+
+impl FromStr for SpadesErrorKind {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "out_of_memory" => Ok(SpadesErrorKind::OutOfMemory),
+            "read_error" => Ok(SpadesErrorKind::ReadError),
+            "kmer" => Ok(SpadesErrorKind::Kmer),
+            "unknown" => Ok(SpadesErrorKind::Unknown),
+            _ => Err(format!("Unknown SPAdes error kind: {}", s)),
+        }
+    }
+}
+
+/// Retry ladder for failed SPAdes runs.
+/// Retries run in order until the assembly succeeds:
+/// only assembler, each k-mer set, then a lower memory limit.
+/// Each retry keeps the changes of the previous retries.
+/// Missing fields use the default ladder.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SpadesRetryConfig {
+    pub enabled: bool,
+    /// Skip read error correction
+    pub only_assembler: bool,
+    /// Comma-separated k-mer sizes to try
+    pub kmer_sizes: Vec<String>,
+    /// Use a lower memory limit based on the available memory
+    pub lower_memory: bool,
+}
+
+impl Default for SpadesRetryConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            only_assembler: true,
+            kmer_sizes: vec![String::from("21,33,55"), String::from("21,33")],
+            lower_memory: true,
+        }
+    }
+}
+
+impl SpadesRetryConfig {
+    /// SPAdes parameters of each attempt,
+    /// starting with the default parameters.
+    pub fn ladder(&self, memory_limit: Option<u64>) -> Vec<SpadesParams> {
+        let mut params = SpadesParams {
+            memory_limit,
+            ..Default::default()
+        };
+        let mut ladder = vec![params.clone()];
+        if !self.enabled {
+            return ladder;
+        }
+        if self.only_assembler {
+            params.only_assembler = true;
+            ladder.push(params.clone());
+        }
+        for kmers in &self.kmer_sizes {
+            params.kmer_sizes = Some(kmers.to_string());
+            ladder.push(params.clone());
+        }
+        if self.lower_memory {
+            params.memory_limit = Some(Self::lower_memory_limit(memory_limit));
+            ladder.push(params);
+        }
+        ladder
+    }
+
+    // Half of the current limit or the available memory,
+    // whichever is lower. At least 1 GiB.
+    fn lower_memory_limit(memory_limit: Option<u64>) -> u64 {
+        let mut sysinfo = SystemInfo::new();
+        sysinfo.get();
+        let available = (sysinfo.available_memory / GIB).max(1);
+        let current = memory_limit.unwrap_or(available).min(available);
+        (current / 2).max(1)
+    }
+}
+
+/// SPAdes parameters changed by the retry ladder
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SpadesParams {
+    pub only_assembler: bool,
+    pub kmer_sizes: Option<String>,
+    /// Memory limit in GiB
+    pub memory_limit: Option<u64>,
+}
+
+impl Display for SpadesParams {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let mut params = Vec::new();
+        if self.only_assembler {
+            params.push(String::from("--only-assembler"));
+        }
+        if let Some(kmers) = &self.kmer_sizes {
+            params.push(format!("-k {}", kmers));
+        }
+        if let Some(memory) = self.memory_limit {
+            params.push(format!("-m {}", memory));
+        }
+        if params.is_empty() {
+            write!(f, "default")
+        } else {
+            write!(f, "{}", params.join(" "))
+        }
+    }
+}
+
 pub struct SpadeRunner<'a> {
     sample: &'a FastqReads,
     pub sample_output_dir: PathBuf,
     pub dependency: &'a DepMetadata,
     pub keep_intermediates: bool,
     pub rename_contigs: bool,
+    /// Threads for SPAdes. Use all threads if None.
+    pub threads: Option<usize>,
+    /// Memory limit for SPAdes in GiB.
+    /// Use SPAdes default if None.
+    pub memory_limit: Option<u64>,
+    /// Retry ladder for failed runs
+    pub retry: SpadesRetryConfig,
 }
 
 impl<'a> SpadeRunner<'a> {
@@ -41,9 +258,27 @@ impl<'a> SpadeRunner<'a> {
             dependency,
             keep_intermediates: false,
             rename_contigs: false,
+            threads: None,
+            memory_limit: None,
+            retry: SpadesRetryConfig::default(),
         }
     }
 
+    pub fn threads(mut self, threads: usize) -> Self {
+        self.threads = Some(threads);
+        self
+    }
+
+    pub fn retry(mut self, retry: &SpadesRetryConfig) -> Self {
+        self.retry = retry.clone();
+        self
+    }
+
+    pub fn memory_limit(mut self, memory_gib: u64) -> Self {
+        self.memory_limit = Some(memory_gib);
+        self
+    }
+
     pub fn keep_intermediates(mut self, keep_intermediates: bool) -> Self {
         self.keep_intermediates = keep_intermediates;
         self
@@ -54,7 +289,7 @@ impl<'a> SpadeRunner<'a> {
         self
     }
 
-    pub fn run(&mut self) -> Result<SpadeReports, Box<dyn Error>> {
+    pub fn run(&mut self) -> Result<AssemblyReports, Box<dyn Error>> {
         let decorator = self.print_header();
         let read1 = self.sample.get_read1();
         check_read1_exists!(self, read1);
@@ -63,73 +298,155 @@ impl<'a> SpadeRunner<'a> {
         self.print_input_summary(&read1, read2.as_deref(), singleton.as_deref());
         create_output_dir!(self);
         let spinner = common::init_spinner();
-        spinner.set_message("Assembling reads");
-        let spades = Spades::new(
-            &read1,
-            read2.as_deref(),
-            singleton.as_deref(),
+        let reports = self.assemble(&read1, read2.as_deref(), singleton.as_deref(), &spinner);
+        if let Ok(report) = &reports {
+            self.print_output_summary(report);
+        }
+        decorator.get_section_footer();
+        reports
+    }
+
+    /// Report of a sample assembled in a previous run.
+    /// Used to skip the sample when resuming.
+    pub fn completed_report(&self) -> AssemblyReports {
+        let mut reports = AssemblyReports::new(
+            &self.sample.sample_name,
             &self.sample_output_dir,
+            Assembler::Spades,
         );
-        let output = spades.execute(self.dependency);
-        match output {
-            Ok(output) => self.create_report(&output, &spinner, &decorator),
-            Err(e) => {
-                spinner.finish_with_message(format!("{} Failed to assemble reads\n", "✘".red()));
-                Err(e)
-            }
+        let renamed_contigs = reports.get_renamed_contigs();
+        if renamed_contigs.is_file() {
+            reports.contigs = renamed_contigs;
         }
+        reports
     }
 
-    fn create_report(
+    // Run SPAdes following the retry ladder
+    // until the assembly succeeds.
+    fn assemble(
         &self,
-        output: &Output,
+        read1: &Path,
+        read2: Option<&Path>,
+        singleton: Option<&Path>,
         spinner: &ProgressBar,
-        decorator: &PrettyHeader,
-    ) -> Result<SpadeReports, Box<dyn Error>> {
-        let reports = self.check_spades_success(output, spinner);
-        match reports {
-            Ok(report) => {
-                self.print_output_summary(&report);
-                decorator.get_section_footer();
-                Ok(report)
+    ) -> Result<AssemblyReports, Box<dyn Error>> {
+        let ladder = self.retry.ladder(self.memory_limit);
+        let mut attempts = Vec::new();
+        for (i, params) in ladder.iter().enumerate() {
+            if i > 0 {
+                log::warn!(
+                    "{} Retrying {} with {}",
+                    "Warning:".yellow(),
+                    self.sample.sample_name,
+                    params
+                );
+                self.reset_output_dir()?;
             }
-            Err(e) => {
-                decorator.get_section_footer();
-                Err(e)
+            spinner.set_message(format!(
+                "Assembling reads (attempt {} of {})",
+                i + 1,
+                ladder.len()
+            ));
+            let spades = Spades::new(read1, read2, singleton, &self.sample_output_dir)
+                .resources(self.threads, params.memory_limit)
+                .params(params);
+            let output = match spades.execute(self.dependency) {
+                Ok(output) => output,
+                Err(e) => {
+                    spinner
+                        .finish_with_message(format!("{} Failed to assemble reads\n", "✘".red()));
+                    return Err(e);
+                }
+            };
+            let mut reports = AssemblyReports::new(
+                &self.sample.sample_name,
+                &self.sample_output_dir,
+                Assembler::Spades,
+            );
+            let error = if output.status.success() {
+                None
+            } else {
+                Some(SpadesErrorKind::from_output(&output, &reports.log))
+            };
+            attempts.push(AssemblyAttempt {
+                attempt: i + 1,
+                strategy: params.to_string(),
+                success: error.is_none(),
+                error,
+            });
+            match error {
+                None => {
+                    reports.attempts = attempts;
+                    self.finalize_report(&mut reports, spinner)?;
+                    return Ok(reports);
+                }
+                Some(kind) => {
+                    self.log_failed_attempt(&output, kind);
+                    if !kind.is_retryable() {
+                        break;
+                    }
+                }
             }
         }
+
+        spinner.finish_with_message(format!("{} Failed to assemble reads\n", "✘".red()));
+        if let Err(e) = write_attempts(&self.sample_output_dir, &attempts) {
+            log::error!("Failed to write assembly attempts: {}", e);
+        }
+        let last_error = attempts
+            .last()
+            .and_then(|a| a.error)
+            .unwrap_or(SpadesErrorKind::Unknown);
+        Err(format!(
+            "Failed to assemble reads after {} attempts. Last error: {}",
+            attempts.len(),
+            last_error
+        )
+        .into())
     }
 
-    fn check_spades_success(
+    fn finalize_report(
         &self,
-        output: &Output,
+        reports: &mut AssemblyReports,
         spinner: &ProgressBar,
-    ) -> Result<SpadeReports, Box<dyn Error>> {
-        if output.status.success() {
-            let mut reports = SpadeReports::new(&self.sample.sample_name, &self.sample_output_dir);
-            if !self.keep_intermediates {
-                spinner.set_message("Removing intermediates");
-                reports.remove_intermediates()?;
-                log::info!(
-                    "\n\n{} {}\n",
-                    "Intermediate SPAdes files were removed.",
-                    "✔".green()
-                );
-                if self.rename_contigs {
-                    reports.rename_contigs();
-                }
+    ) -> Result<(), Box<dyn Error>> {
+        write_attempts(&self.sample_output_dir, &reports.attempts)?;
+        if !self.keep_intermediates {
+            spinner.set_message("Removing intermediates");
+            reports.remove_intermediates()?;
+            log::info!(
+                "\n\n{} {}\n",
+                "Intermediate SPAdes files were removed.",
+                "✔".green()
+            );
+            if self.rename_contigs {
+                reports.rename_contigs();
             }
+        }
 
-            spinner.finish_with_message(format!("{} Finished cleaning reads\n", "✔".green()));
+        spinner.finish_with_message(format!("{} Finished assembling reads\n", "✔".green()));
+        Ok(())
+    }
 
-            Ok(reports)
-        } else {
-            let error = String::from_utf8_lossy(&output.stderr);
-            let stdout = String::from_utf8_lossy(&output.stdout);
-            log::error!("{}", error);
-            log::info!("{}", stdout);
-            Err("Failed to clean reads".into())
+    fn log_failed_attempt(&self, output: &Output, kind: SpadesErrorKind) {
+        let error = String::from_utf8_lossy(&output.stderr);
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        log::error!("{}", error);
+        log::info!("{}", stdout);
+        log::error!(
+            "SPAdes failed for {} ({} error)",
+            self.sample.sample_name,
+            kind
+        );
+    }
+
+    // SPAdes may reuse files from a failed run
+    fn reset_output_dir(&self) -> Result<(), Box<dyn Error>> {
+        if self.sample_output_dir.exists() {
+            std::fs::remove_dir_all(&self.sample_output_dir)?;
         }
+        std::fs::create_dir_all(&self.sample_output_dir)?;
+        Ok(())
     }
 
     fn print_header(&self) -> PrettyHeader {
@@ -167,15 +484,13 @@ impl<'a> SpadeRunner<'a> {
         log::info!("");
     }
 
-    fn print_output_summary(&self, reports: &SpadeReports) {
+    fn print_output_summary(&self, reports: &AssemblyReports) {
         log::info!("{}", "Output".cyan());
         log::info!("{:18}: {}", "Directory", reports.output_dir.display());
         log::info!("{:18}: {}", "Contigs", self.get_file_name(&reports.contigs));
-        log::info!(
-            "{:18}: {}",
-            "Scaffolds",
-            self.get_file_name(&reports.scaffolds)
-        );
+        if let Some(scaffolds) = &reports.scaffolds {
+            log::info!("{:18}: {}", "Scaffolds", self.get_file_name(scaffolds));
+        }
         log::info!("{:18}: {}", "Log", self.get_file_name(&reports.log));
     }
 
@@ -192,6 +507,10 @@ pub struct Spades<'a> {
     pub read2: Option<&'a Path>,
     pub singleton: Option<&'a Path>,
     pub output_dir: PathBuf,
+    pub threads: Option<usize>,
+    pub memory_limit: Option<u64>,
+    pub only_assembler: bool,
+    pub kmer_sizes: Option<String>,
 }
 
 impl<'a> Spades<'a> {
@@ -206,9 +525,25 @@ impl<'a> Spades<'a> {
             read2,
             singleton,
             output_dir: output_dir.to_path_buf(),
+            threads: None,
+            memory_limit: None,
+            only_assembler: false,
+            kmer_sizes: None,
         }
     }
 
+    pub fn resources(mut self, threads: Option<usize>, memory_limit: Option<u64>) -> Self {
+        self.threads = threads;
+        self.memory_limit = memory_limit;
+        self
+    }
+
+    pub fn params(mut self, params: &SpadesParams) -> Self {
+        self.only_assembler = params.only_assembler;
+        self.kmer_sizes = params.kmer_sizes.clone();
+        self
+    }
+
     pub fn execute(&self, dep: &DepMetadata) -> Result<Output, Box<dyn Error>> {
         let executable = dep.get_executable(SPADES_EXE);
         let mut cmd = Command::new(executable);
@@ -221,7 +556,25 @@ impl<'a> Spades<'a> {
         }
 
         cmd.arg("-o").arg(&self.output_dir);
-        cmd.arg("-t").arg(Spades::get_thread_count());
+        // User specified resources take precedence
+        let override_args = dep.override_args.as_deref().unwrap_or_default();
+        if !has_override_arg(override_args, &["-t", "--threads"]) {
+            let threads = self.threads.unwrap_or_else(Spades::get_thread_count);
+            cmd.arg("-t").arg(threads.to_string());
+        }
+        if let Some(memory) = self.memory_limit {
+            if !has_override_arg(override_args, &["-m", "--memory"]) {
+                cmd.arg("-m").arg(memory.to_string());
+            }
+        }
+        if self.only_assembler && !has_override_arg(override_args, &["--only-assembler"]) {
+            cmd.arg("--only-assembler");
+        }
+        if let Some(kmers) = &self.kmer_sizes {
+            if !has_override_arg(override_args, &["-k"]) {
+                cmd.arg("-k").arg(kmers);
+            }
+        }
 
         match &dep.override_args {
             Some(params) => {
@@ -235,8 +588,73 @@ impl<'a> Spades<'a> {
         Ok(cmd.output()?)
     }
 
-    fn get_thread_count() -> String {
+    fn get_thread_count() -> usize {
         let sysinfo = System::new_all();
-        sysinfo.cpus().len().to_string()
+        sysinfo.cpus().len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_spades_retry_ladder() {
+        let retry = SpadesRetryConfig {
+            lower_memory: false,
+            ..Default::default()
+        };
+        let ladder = retry.ladder(Some(16));
+        assert_eq!(ladder.len(), 4);
+        assert_eq!(ladder[0].to_string(), "-m 16");
+        assert_eq!(ladder[1].to_string(), "--only-assembler -m 16");
+        assert_eq!(ladder[3].to_string(), "--only-assembler -k 21,33 -m 16");
+        let retry = SpadesRetryConfig {
+            enabled: false,
+            ..Default::default()
+        };
+        assert_eq!(retry.ladder(None).len(), 1);
+        let retry: SpadesRetryConfig = toml::from_str("lower_memory = false").unwrap();
+        assert!(retry.enabled);
+        assert_eq!(retry.kmer_sizes, SpadesRetryConfig::default().kmer_sizes);
+    }
+
+    #[test]
+    fn test_spades_error_kind() {
+        let lines = |text: &str| vec![text.to_lowercase()];
+        assert_eq!(
+            SpadesErrorKind::from_error_lines(&lines(
+                "== Error ==  system call for: spades-hammer finished abnormally, OS return value: -9"
+            )),
+            SpadesErrorKind::OutOfMemory
+        );
+        assert_eq!(
+            SpadesErrorKind::from_error_lines(&lines("== Error ==  k-mer sizes should be odd")),
+            SpadesErrorKind::Kmer
+        );
+        assert_eq!(
+            SpadesErrorKind::from_error_lines(&lines("== Error ==  file is empty: reads_1.fq.gz")),
+            SpadesErrorKind::ReadError
+        );
+        assert!(!SpadesErrorKind::ReadError.is_retryable());
+        assert!(SpadesErrorKind::OutOfMemory.is_retryable());
+        assert_eq!(
+            SpadesErrorKind::from_error_lines(&lines("== Error ==  unexpected")),
+            SpadesErrorKind::Unknown
+        );
+        assert_eq!(
+            SpadesErrorKind::from_error_lines(&lines("== Error ==  thread pool error")),
+            SpadesErrorKind::Unknown
+        );
+        assert_eq!(
+            SpadesErrorKind::from_error_lines(&lines(
+                "== Error ==  output directory already exists"
+            )),
+            SpadesErrorKind::Unknown
+        );
+        assert_eq!(
+            SpadesErrorKind::from_error_lines(&lines("== Error ==  file not found: reads_2.fq")),
+            SpadesErrorKind::ReadError
+        );
     }
 }
@@ -0,0 +1,193 @@
+//! Compute assembly statistics from the contigs of each sample
+
+use std::{
+    error::Error,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use bio::io::fasta;
+use colored::Colorize;
+use comfy_table::Table;
+use serde::{Deserialize, Serialize};
+
+use super::reports::AssemblyReports;
+
+pub const ASSEMBLY_SUMMARY: &str = "assembly_summary.csv";
+
+/// Assembly statistics of a sample
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct AssemblyStats {
+    pub sample_name: String,
+    pub contig_counts: usize,
+    pub total_length: usize,
+    pub n50: usize,
+    pub l50: usize,
+    pub longest_contig: usize,
+    /// GC percentage of unambiguous bases
+    pub gc_percent: f64,
+    pub contigs_500bp: usize,
+    pub contigs_1kb: usize,
+    pub contigs_5kb: usize,
+    pub contigs_10kb: usize,
+}
+
+impl AssemblyStats {
+    /// Compute statistics from a contig FASTA file
+    pub fn from_fasta(sample_name: &str, path: &Path) -> Result<Self, Box<dyn Error>> {
+        let reader = fasta::Reader::from_file(path)?;
+        let mut lengths = Vec::new();
+        let mut gc_counts = 0;
+        let mut base_counts = 0;
+        for record in reader.records() {
+            let record = record?;
+            let seq = record.seq();
+            lengths.push(seq.len());
+            seq.iter().for_each(|base| match base.to_ascii_uppercase() {
+                b'G' | b'C' => {
+                    gc_counts += 1;
+                    base_counts += 1;
+                }
+                b'A' | b'T' => base_counts += 1,
+                _ => (),
+            });
+        }
+        let mut stats = Self::from_lengths(sample_name, &mut lengths);
+        if base_counts > 0 {
+            stats.gc_percent = gc_counts as f64 / base_counts as f64 * 100.0;
+        }
+        Ok(stats)
+    }
+
+    fn from_lengths(sample_name: &str, lengths: &mut [usize]) -> Self {
+        lengths.sort_unstable_by(|a, b| b.cmp(a));
+        let total_length: usize = lengths.iter().sum();
+        let (n50, l50) = Self::compute_n50(lengths, total_length);
+        let count_min_length = |min: usize| lengths.iter().filter(|&&len| len >= min).count();
+        Self {
+            sample_name: sample_name.to_string(),
+            contig_counts: lengths.len(),
+            total_length,
+            n50,
+            l50,
+            longest_contig: lengths.first().copied().unwrap_or_default(),
+            gc_percent: 0.0,
+            contigs_500bp: count_min_length(500),
+            contigs_1kb: count_min_length(1_000),
+            contigs_5kb: count_min_length(5_000),
+            contigs_10kb: count_min_length(10_000),
+        }
+    }
+
+    // Lengths must be sorted in descending order
+    fn compute_n50(lengths: &[usize], total_length: usize) -> (usize, usize) {
+        let mut cumulative = 0;
+        for (i, len) in lengths.iter().enumerate() {
+            cumulative += len;
+            if cumulative * 2 >= total_length {
+                return (*len, i + 1);
+            }
+        }
+        (0, 0)
+    }
+}
+
+/// Cross-sample summary of the assembly step
+pub struct AssemblySummary<'a> {
+    /// Assembly output directory
+    pub output_dir: &'a Path,
+    pub stats: Vec<AssemblyStats>,
+}
+
+impl<'a> AssemblySummary<'a> {
+    /// Compute statistics of each assembled sample.
+    /// Samples with missing or invalid contigs are skipped.
+    pub fn from_reports(output_dir: &'a Path, reports: &[AssemblyReports]) -> Self {
+        let stats = reports
+            .iter()
+            .filter_map(|report| {
+                match AssemblyStats::from_fasta(&report.sample_name, &report.contigs) {
+                    Ok(stats) => Some(stats),
+                    Err(e) => {
+                        log::warn!(
+                            "{} Failed to compute assembly statistics for {}: {}",
+                            "Warning:".yellow(),
+                            report.contigs.display(),
+                            e
+                        );
+                        None
+                    }
+                }
+            })
+            .collect();
+        Self { output_dir, stats }
+    }
+
+    pub fn write(&self) -> Result<PathBuf, Box<dyn Error>> {
+        fs::create_dir_all(self.output_dir)?;
+        let output_path = self.output_dir.join(ASSEMBLY_SUMMARY);
+        let mut writer = csv::Writer::from_path(&output_path)?;
+        for stats in &self.stats {
+            writer.serialize(stats)?;
+        }
+        writer.flush()?;
+        Ok(output_path)
+    }
+
+    pub fn log_table(&self) {
+        if self.stats.is_empty() {
+            return;
+        }
+        let mut table = Table::new();
+        table.set_header(vec![
+            "Sample",
+            "Contigs",
+            "Total length",
+            "N50",
+            "L50",
+            "Longest",
+            "GC (%)",
+            ">= 1 kb",
+        ]);
+        for stats in &self.stats {
+            table.add_row(vec![
+                stats.sample_name.to_string(),
+                stats.contig_counts.to_string(),
+                stats.total_length.to_string(),
+                stats.n50.to_string(),
+                stats.l50.to_string(),
+                stats.longest_contig.to_string(),
+                format!("{:.2}", stats.gc_percent),
+                stats.contigs_1kb.to_string(),
+            ]);
+        }
+        log::info!("\n{}", table);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_assembly_stats() {
+        let path = Path::new("tests/data/contigs/sample_contigs.fasta");
+        let stats = AssemblyStats::from_fasta("sample_1", path).unwrap();
+        assert_eq!(stats.contig_counts, 5);
+        assert_eq!(stats.total_length, 30);
+        assert_eq!(stats.n50, 8);
+        assert_eq!(stats.l50, 2);
+        assert_eq!(stats.longest_contig, 10);
+        assert!((stats.gc_percent - 57.14).abs() < 0.01);
+        assert_eq!(stats.contigs_500bp, 0);
+    }
+
+    #[test]
+    fn test_empty_assembly_stats() {
+        let path = Path::new("tests/data/contigs/genus_species_contigs.fasta");
+        let stats = AssemblyStats::from_fasta("sample_1", path).unwrap();
+        assert_eq!(stats.contig_counts, 0);
+        assert_eq!(stats.n50, 0);
+        assert_eq!(stats.gc_percent, 0.0);
+    }
+}
@@ -6,6 +6,7 @@ use std::{error::Error, path::Path};
 use anyhow::Context;
 use serde::{Deserialize, Serialize};
 
+use crate::cli::commands::clean::ReadQcArgs;
 use crate::core::deps::fastp::FastpMetadata;
 use crate::core::deps::DepMetadata;
 use crate::helper::common::UllarConfig;
@@ -13,8 +14,11 @@ use crate::helper::configs::generate_config_output_path;
 use crate::helper::fastq::FastqInput;
 use crate::types::reads::FastqReads;
 
+use super::summary::FastpSummary;
+
 pub const DEFAULT_READ_CLEANING_CONFIG: &str = "read_cleaning";
 pub const QC_DEPENDENCY: &str = "qc";
+pub const DEFAULT_MIN_CLEAN_READS: usize = 10_000;
 
 pub enum FileMatchingStrategy {
     Regex,
@@ -27,6 +31,9 @@ pub struct CleanReadConfig {
     pub app: UllarConfig,
     pub input: FastqInput,
     pub dependencies: BTreeMap<String, DepMetadata>,
+    /// Thresholds to flag samples after cleaning
+    #[serde(default)]
+    pub qc_thresholds: QcThresholds,
     pub samples: Vec<FastqReads>,
 }
 
@@ -36,6 +43,7 @@ impl CleanReadConfig {
             app: UllarConfig::init(),
             input,
             dependencies: BTreeMap::new(),
+            qc_thresholds: QcThresholds::default(),
             samples,
         }
     }
@@ -87,6 +95,72 @@ impl CleanReadConfig {
     }
 }
 
+/// Read cleaning QC thresholds.
+/// Samples that fail any threshold are flagged
+/// and excluded from the assembly config.
+/// Only the minimum reads is set by default,
+/// matching the CLI default.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct QcThresholds {
+    /// Minimum number of reads after filtering
+    pub min_reads: Option<usize>,
+    /// Minimum percentage of Q30 bases after filtering
+    pub min_q30_percent: Option<f64>,
+    /// Maximum duplication percentage
+    pub max_duplication_percent: Option<f64>,
+}
+
+impl Default for QcThresholds {
+    fn default() -> Self {
+        Self {
+            min_reads: Some(DEFAULT_MIN_CLEAN_READS),
+            min_q30_percent: None,
+            max_duplication_percent: None,
+        }
+    }
+}
+
+impl QcThresholds {
+    pub fn from_arg(args: &ReadQcArgs) -> Self {
+        Self {
+            min_reads: Some(args.min_reads),
+            min_q30_percent: args.min_q30,
+            max_duplication_percent: args.max_duplication,
+        }
+    }
+
+    /// Check the sample QC metrics.
+    /// Returns the reasons the sample fails.
+    pub fn check(&self, summary: &FastpSummary) -> Vec<String> {
+        let mut reasons = Vec::new();
+        if let Some(min_reads) = self.min_reads {
+            if summary.reads_after < min_reads {
+                reasons.push(format!(
+                    "reads after filtering {} < {}",
+                    summary.reads_after, min_reads
+                ));
+            }
+        }
+        if let Some(min_q30) = self.min_q30_percent {
+            if summary.q30_percent_after < min_q30 {
+                reasons.push(format!(
+                    "Q30 {:.2}% < {:.2}%",
+                    summary.q30_percent_after, min_q30
+                ));
+            }
+        }
+        if let Some(max_duplication) = self.max_duplication_percent {
+            if summary.duplication_percent > max_duplication {
+                reasons.push(format!(
+                    "duplication {:.2}% > {:.2}%",
+                    summary.duplication_percent, max_duplication
+                ));
+            }
+        }
+        reasons
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -108,5 +182,33 @@ mod tests {
             config.samples[0].read_1.as_ref().unwrap().file_name,
             "Bunomys_chrysocomus_ABCD1234_READ1.fq.gz"
         );
+        assert_eq!(config.qc_thresholds, QcThresholds::default());
+    }
+
+    #[test]
+    fn test_qc_thresholds() {
+        let summary = FastpSummary {
+            reads_after: 2000,
+            q30_percent_after: 92.0,
+            duplication_percent: 40.0,
+            ..Default::default()
+        };
+        let thresholds = QcThresholds {
+            min_reads: None,
+            ..Default::default()
+        };
+        assert!(thresholds.check(&summary).is_empty());
+        let thresholds = QcThresholds::default();
+        assert_eq!(
+            thresholds.check(&summary),
+            vec!["reads after filtering 2000 < 10000"]
+        );
+        let thresholds = QcThresholds {
+            min_reads: Some(1000),
+            min_q30_percent: Some(90.0),
+            max_duplication_percent: Some(30.0),
+        };
+        let reasons = thresholds.check(&summary);
+        assert_eq!(reasons, vec!["duplication 40.00% > 30.00%"]);
     }
 }
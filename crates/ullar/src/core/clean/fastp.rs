@@ -13,12 +13,20 @@ use crate::{
     check_read1_exists,
     core::deps::{fastp::FASTP_EXE, DepMetadata},
     create_output_dir,
-    helper::common::{self, PrettyHeader},
+    helper::{
+        command::has_override_arg,
+        common::{self, PrettyHeader},
+        scheduler::JobRequirement,
+    },
     parse_override_args,
     types::reads::FastqReads,
 };
 
-use super::reports::CleanReadReport;
+use super::reports::{CleanReadReport, FASTP_HTML, FASTP_JSON};
+
+/// Resource requirement of a fastp job.
+/// fastp does not scale beyond 16 worker threads.
+pub const FASTP_REQUIREMENT: JobRequirement = JobRequirement::new(4, 16, 2);
 
 /// Run fastp for quality control
 pub struct FastpRunner<'a> {
@@ -29,6 +37,9 @@ pub struct FastpRunner<'a> {
     /// User specified fastp parameters
     /// Input as space separated string
     pub dependency: &'a DepMetadata,
+    /// Worker threads for fastp.
+    /// Use fastp default if None.
+    pub threads: Option<usize>,
 }
 
 impl<'a> FastpRunner<'a> {
@@ -38,9 +49,15 @@ impl<'a> FastpRunner<'a> {
             sample,
             sample_output_dir: output_dir.join(&sample.sample_name),
             dependency,
+            threads: None,
         }
     }
 
+    pub fn threads(mut self, threads: usize) -> Self {
+        self.threads = Some(threads);
+        self
+    }
+
     /// Run fastp
     pub fn run(&mut self) -> Result<CleanReadReport, Box<dyn Error>> {
         let decorator = self.print_header();
@@ -52,7 +69,7 @@ impl<'a> FastpRunner<'a> {
         let spinner = common::init_spinner();
         spinner.set_message("Cleaning reads");
         let mut fastp = Fastp::new(&self.sample_output_dir);
-        let output = fastp.execute(&read1, read2.as_deref(), self.dependency);
+        let output = fastp.execute(&read1, read2.as_deref(), self.dependency, self.threads);
 
         match output {
             Ok(output) => self.create_report(&output, fastp, &spinner, &decorator),
@@ -63,6 +80,17 @@ impl<'a> FastpRunner<'a> {
         }
     }
 
+    /// Report of a sample cleaned in a previous run.
+    /// Used to skip the sample when resuming.
+    pub fn completed_report(&self) -> CleanReadReport {
+        let mut fastp = Fastp::new(&self.sample_output_dir);
+        if let Some(read1) = &self.sample.read_1 {
+            fastp.read1_filename = read1.file_name.to_string();
+        }
+        fastp.read2_filename = self.sample.read_2.as_ref().map(|r| r.file_name.to_string());
+        CleanReadReport::from_existing(fastp, &self.sample.sample_name)
+    }
+
     fn create_report(
         &self,
         output: &Output,
@@ -92,7 +120,7 @@ impl<'a> FastpRunner<'a> {
     ) -> Result<CleanReadReport, Box<dyn Error>> {
         if output.status.success() {
             spinner.set_message(format!("Creating report for {}", self.sample.sample_name));
-            let report = CleanReadReport::new(fastp_data, &self.sample.sample_name);
+            let mut report = CleanReadReport::new(fastp_data, &self.sample.sample_name);
             report.create(output)?;
             report.finalize();
             spinner.finish_with_message(format!("{} Finished cleaning reads\n", "✔".green()));
@@ -164,6 +192,7 @@ impl Fastp {
         input_read1: &Path,
         input_read2: Option<&Path>,
         dep: &DepMetadata,
+        threads: Option<usize>,
     ) -> Result<Output, Box<dyn Error>> {
         self.get_read1_filename(input_read1);
         let output_read1 = self.output_dir.join(self.read1_filename.as_str());
@@ -181,11 +210,22 @@ impl Fastp {
             cmd.arg("-O").arg(&output_read2);
         }
         cmd.arg("-o").arg(&output_read1);
+        cmd.arg("-h").arg(self.output_dir.join(FASTP_HTML));
+        cmd.arg("-j").arg(self.output_dir.join(FASTP_JSON));
 
         if let Some(params) = &dep.override_args {
             self.build_custom_params(&mut cmd, params);
         }
 
+        // User specified threads take precedence
+        let has_threads = dep
+            .override_args
+            .as_ref()
+            .is_some_and(|args| has_override_arg(args, &["-w", "--thread"]));
+        if let Some(threads) = threads.filter(|_| !has_threads) {
+            cmd.arg("-w").arg(threads.to_string());
+        }
+
         Ok(cmd.output()?)
     }
 
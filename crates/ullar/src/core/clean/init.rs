@@ -5,21 +5,23 @@ use std::{error::Error, path::Path};
 
 use colored::Colorize;
 
-use crate::cli::commands::clean::ReadCleaningInitArgs;
+use crate::cli::commands::clean::{ReadCleaningInitArgs, ReadQcArgs};
 use crate::cli::commands::common::{CommonInitArgs, GenomicReadsInitArgs};
 use crate::core::clean::ReadCleaning;
+use crate::core::project::manifest::{ProjectManifest, TaskStatus};
 use crate::helper::common;
 use crate::helper::fastq::{FastqInput, ReadAssignmentStrategy};
 use crate::helper::files::FileFinder;
 use crate::types::reads::{FastqReads, ReadAssignment, SampleNameFormat};
-use crate::types::SupportedFormats;
+use crate::types::{SupportedFormats, Task};
 
-use super::configs::CleanReadConfig;
+use super::configs::{CleanReadConfig, QcThresholds};
 
 pub struct ReadCleaningInit<'a> {
     input_dir: &'a Path,
     common: &'a CommonInitArgs,
     reads: &'a GenomicReadsInitArgs,
+    qc: &'a ReadQcArgs,
     sample_name_format: SampleNameFormat,
 }
 
@@ -29,6 +31,7 @@ impl<'a> ReadCleaningInit<'a> {
             input_dir: args.dir.as_path(),
             common: &args.common,
             reads: &args.reads,
+            qc: &args.qc,
             sample_name_format: args
                 .reads
                 .sample_name
@@ -77,6 +80,7 @@ impl<'a> ReadCleaningInit<'a> {
                     "✔".green()
                 ));
                 self.log_output(&path, record_count, file_count);
+                ProjectManifest::record(Task::CleanReads, TaskStatus::Initialized, Some(&path));
                 if self.common.autorun {
                     let footer = common::PrettyHeader::new();
                     footer.get_section_footer();
@@ -100,7 +104,7 @@ impl<'a> ReadCleaningInit<'a> {
         log::info!("{}", header.cyan());
         log::info!("");
         let runner = ReadCleaning::from_config_path(config_path);
-        runner.clean();
+        runner.clean().expect("Failed to clean reads");
     }
 
     fn match_sample_name_format(&mut self) {
@@ -121,6 +125,7 @@ impl<'a> ReadCleaningInit<'a> {
         let strategy = ReadAssignmentStrategy::from_arg(self.reads);
         let input_summary = FastqInput::new(self.input_dir, records.len(), file_counts, strategy);
         let mut config = CleanReadConfig::new(input_summary, records.to_vec());
+        config.qc_thresholds = QcThresholds::from_arg(self.qc);
         let output_path = config.to_toml(self.common.override_args.as_deref())?;
         Ok(output_path)
     }
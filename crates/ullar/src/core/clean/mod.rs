@@ -3,23 +3,31 @@ pub mod configs;
 pub mod fastp;
 pub mod init;
 pub mod reports;
+pub mod summary;
 
 use std::fs;
 use std::path::{Path, PathBuf};
+use std::sync::{mpsc, Mutex};
+use std::time::Instant;
 
 use anyhow::Context;
 use colored::Colorize;
 use comfy_table::Table;
 use configs::{CleanReadConfig, DEFAULT_READ_CLEANING_CONFIG, QC_DEPENDENCY};
+use rayon::prelude::*;
 
 use self::reports::CleanReadReport;
+use self::summary::ReadCleaningSummary;
 use crate::cli::commands::clean::ReadCleaningArgs;
 use crate::core::deps::fastp::FastpMetadata;
 use crate::core::deps::DepMetadata;
+use crate::core::project::manifest::{ProjectManifest, TaskStatus};
 use crate::helper::common;
 use crate::helper::configs::{CONFIG_EXTENSION_TOML, DEFAULT_CONFIG_DIR};
 use crate::helper::fastq::FastqConfigCheck;
 use crate::helper::files::PathCheck;
+use crate::helper::ledger::{self, LedgerEntry, LedgerStatus, StepLedger};
+use crate::helper::scheduler::JobScheduler;
 use crate::helper::tracker::ProcessingTracker;
 use crate::types::reads::FastqReads;
 use crate::types::runner::RunnerOptions;
@@ -77,19 +85,19 @@ impl<'a> ReadCleaning<'a> {
         }
     }
 
-    /// Clean raw read files using Fastp
-    pub fn clean(&self) {
+    /// Clean raw read files using Fastp.
+    /// Returns the reports of the successfully cleaned samples.
+    pub fn clean(&self) -> Result<Vec<CleanReadReport>, Box<dyn std::error::Error>> {
         let spinner = common::init_spinner();
         spinner.set_message("Parsing and checking the config file\n");
-        let config = self
-            .parse_config()
-            .expect("Failed to parse config. Try to create a new config.");
+        let config = self.parse_config()?;
         let qc = config.dependencies.get(QC_DEPENDENCY);
         let updated_dep = FastpMetadata::new().update(qc);
         self.log_input(&config, &updated_dep);
         PathCheck::new(self.output_dir)
             .is_dir()
             .with_force_overwrite(self.runner.overwrite)
+            .with_resume(self.runner.resume)
             .prompt_exists(self.runner.dry_run);
         let mut check = FastqConfigCheck::new(config.input.sample_counts);
         if self.runner.skip_config_check {
@@ -103,46 +111,90 @@ impl<'a> ReadCleaning<'a> {
         if self.runner.dry_run {
             check.log_status();
             self.log_unprocessed();
-            return;
+            return Ok(Vec::new());
         }
 
         if !check.is_config_ok() && !self.runner.skip_config_check {
             check.log_status();
             log::error!("\n{}\n", "Config check failed".red());
-            return;
+            return Ok(Vec::new());
         }
 
+        self.update_manifest(TaskStatus::Running);
         let reports = self.clean_reads(&config.samples, &updated_dep);
-        self.log_final_output(&reports);
+        self.update_manifest(TaskStatus::from_success(
+            reports.len() == config.samples.len(),
+        ));
+        let mut summary = ReadCleaningSummary::from_reports(self.output_dir, &reports);
+        summary.check_qc(&config.qc_thresholds);
+        summary.log_table();
+        summary.log_failed();
+        self.log_final_output(&reports, &summary);
+        Ok(reports)
     }
 
     fn clean_reads(&self, samples: &[FastqReads], fastp: &DepMetadata) -> Vec<CleanReadReport> {
-        let mut tracker = ProcessingTracker::new(samples.len());
-        let time = std::time::Instant::now();
-        let mut reports = Vec::new();
-        samples.iter().enumerate().for_each(|(i, sample)| {
-            let mut runner = fastp::FastpRunner::new(sample, self.output_dir, fastp);
-            let results = runner.run();
-
-            match results {
-                Ok(report) => {
-                    reports.push(report);
-                    tracker.success_counts += 1;
+        let scheduler = JobScheduler::from_system(&fastp::FASTP_REQUIREMENT, samples.len());
+        scheduler.log_summary();
+        let tracker = Mutex::new(
+            ProcessingTracker::new(samples.len()).with_concurrent_jobs(scheduler.concurrent_jobs),
+        );
+        let ledger = StepLedger::new(self.output_dir);
+        let params_hash = ledger::hash_params(fastp);
+        let (tx, rx) = mpsc::channel();
+        scheduler.build_pool().install(|| {
+            samples.par_iter().for_each_with(tx, |tx, sample| {
+                let mut runner = fastp::FastpRunner::new(sample, self.output_dir, fastp)
+                    .threads(scheduler.threads_per_job);
+                let input_hash = ledger::hash_fastq(sample);
+                if self.runner.resume
+                    && runner.sample_output_dir.is_dir()
+                    && ledger.is_completed(&sample.sample_name, &input_hash, &params_hash)
+                {
+                    log::info!(
+                        "{:18}: {} (completed in a previous run)",
+                        "Skipping",
+                        sample.sample_name
+                    );
+                    tx.send(runner.completed_report())
+                        .expect("Failed to send report");
+                    tracker.lock().expect("Failed to lock tracker").skip();
+                    return;
                 }
-                Err(e) => {
-                    log::error!("Failed to clean reads for sample: {}", sample.sample_name);
-                    log::error!("{}", e);
-                    tracker.failure_counts += 1;
+                tracker.lock().expect("Failed to lock tracker").start();
+                let time = Instant::now();
+                let results = runner.run();
+                let status = LedgerStatus::from_success(results.is_ok());
+                ledger.record(&LedgerEntry::new(
+                    &sample.sample_name,
+                    &input_hash,
+                    &params_hash,
+                    status,
+                ));
+
+                let mut tracker = tracker.lock().expect("Failed to lock tracker");
+                match results {
+                    Ok(report) => {
+                        tx.send(report).expect("Failed to send report");
+                        tracker.success_counts += 1;
+                    }
+                    Err(e) => {
+                        log::error!("Failed to clean reads for sample: {}", sample.sample_name);
+                        log::error!("{}", e);
+                        tracker.failure_counts += 1;
+                    }
                 }
-            }
-            tracker.update(time.elapsed().as_secs_f64());
+                tracker.update(time.elapsed().as_secs_f64());
 
-            if i < samples.len() - 1 {
-                tracker.print_summary();
-            }
+                if !tracker.is_finished() {
+                    tracker.print_summary();
+                }
+            });
         });
 
-        tracker.finalize();
+        tracker.lock().expect("Failed to lock tracker").finalize();
+        let mut reports: Vec<CleanReadReport> = rx.iter().collect();
+        reports.sort_by(|a, b| a.sample_name.cmp(&b.sample_name));
         reports
     }
 
@@ -158,6 +210,10 @@ impl<'a> ReadCleaning<'a> {
         Ok(config)
     }
 
+    fn update_manifest(&self, status: TaskStatus) {
+        ProjectManifest::record(self.task, status, Some(&self.config_path));
+    }
+
     fn log_unprocessed(&self) {
         let msg1 = "Samples were not processed";
         let msg2 = format!("To process samples use: {}", "ullar clean --process");
@@ -181,9 +237,14 @@ impl<'a> ReadCleaning<'a> {
         log::info!("{:18}: {} v{}\n", "QC", dep.app_name, dep.version);
     }
 
-    fn log_final_output(&self, reports: &[CleanReadReport]) {
+    fn log_final_output(&self, reports: &[CleanReadReport], summary: &ReadCleaningSummary) {
         log::info!("{}", "\nOutput".cyan());
         log::info!("{:18}: {}", "Directory", self.output_dir.display());
         log::info!("{:18}: {}", "Total processed", reports.len());
+        log::info!("{:18}: {}", "Failed QC", summary.failed_samples().len());
+        match summary.write() {
+            Ok(path) => log::info!("{:18}: {}", "QC summary", path.display()),
+            Err(e) => log::error!("Failed to write read cleaning summary: {}", e),
+        }
     }
 }
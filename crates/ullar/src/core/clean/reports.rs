@@ -6,8 +6,8 @@ use walkdir::WalkDir;
 
 use super::fastp::Fastp;
 
-const FASTP_HTML: &str = "fastp.html";
-const FASTP_JSON: &str = "fastp.json";
+pub const FASTP_HTML: &str = "fastp.html";
+pub const FASTP_JSON: &str = "fastp.json";
 const FASTP_LOG: &str = "fastp.log";
 const FASTP_REPORT_DIR: &str = "reports";
 
@@ -21,17 +21,30 @@ pub struct CleanReadReport {
 }
 
 impl CleanReadReport {
+    /// Reports are written to the sample output directory
+    /// to avoid conflicts when samples are cleaned concurrently.
     pub fn new(fastp_data: Fastp, sample_name: &str) -> Self {
+        let output_dir = fastp_data.output_dir.to_path_buf();
         CleanReadReport {
             fastp_data,
             sample_name: sample_name.to_string(),
-            html: PathBuf::from(FASTP_HTML),
-            json: PathBuf::from(FASTP_JSON),
-            log: PathBuf::from(FASTP_LOG),
+            html: output_dir.join(FASTP_HTML),
+            json: output_dir.join(FASTP_JSON),
+            log: output_dir.join(FASTP_LOG),
         }
     }
 
-    pub fn create(&self, output: &Output) -> Result<(), Box<dyn Error>> {
+    /// Report of a sample cleaned in a previous run.
+    /// The html and json reports are already organized.
+    pub fn from_existing(fastp_data: Fastp, sample_name: &str) -> Self {
+        let mut report = Self::new(fastp_data, sample_name);
+        let report_dir = report.fastp_data.output_dir.join(FASTP_REPORT_DIR);
+        report.html = report_dir.join(FASTP_HTML);
+        report.json = report_dir.join(FASTP_JSON);
+        report
+    }
+
+    pub fn create(&mut self, output: &Output) -> Result<(), Box<dyn Error>> {
         self.write_log(output)?;
         self.organize()?;
         Ok(())
@@ -59,11 +72,15 @@ impl CleanReadReport {
         Ok(())
     }
 
-    fn organize(&self) -> Result<(), Box<dyn Error>> {
+    fn organize(&mut self) -> Result<(), Box<dyn Error>> {
         let report_dir = self.fastp_data.output_dir.join(FASTP_REPORT_DIR);
         std::fs::create_dir_all(&report_dir)?;
-        std::fs::rename(&self.html, report_dir.join(FASTP_HTML))?;
-        std::fs::rename(&self.json, report_dir.join(FASTP_JSON))?;
+        let html = report_dir.join(FASTP_HTML);
+        let json = report_dir.join(FASTP_JSON);
+        std::fs::rename(&self.html, &html)?;
+        std::fs::rename(&self.json, &json)?;
+        self.html = html;
+        self.json = json;
 
         Ok(())
     }
@@ -0,0 +1,296 @@
+//! Summarize fastp JSON reports across samples
+
+use std::{
+    error::Error,
+    fmt::Display,
+    fs,
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+
+use colored::Colorize;
+use comfy_table::Table;
+use serde::{Deserialize, Serialize};
+
+use crate::helper::common::percent;
+
+use super::{configs::QcThresholds, reports::CleanReadReport};
+
+pub const READ_CLEANING_SUMMARY: &str = "read_cleaning_summary.csv";
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum QcStatus {
+    #[default]
+    Pass,
+    Fail,
+}
+
+impl QcStatus {
+    pub fn from_success(is_success: bool) -> Self {
+        if is_success {
+            QcStatus::Pass
+        } else {
+            QcStatus::Fail
+        }
+    }
+}
+
+impl Display for QcStatus {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            QcStatus::Pass => write!(f, "pass"),
+            QcStatus::Fail => write!(f, "fail"),
+        }
+    }
+}
+
+impl FromStr for QcStatus {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "pass" => Ok(QcStatus::Pass),
+            "fail" => Ok(QcStatus::Fail),
+            _ => Err(format!("Unknown QC status: {}", s)),
+        }
+    }
+}
+
+/// Read cleaning QC metrics of a sample
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct FastpSummary {
+    pub sample_name: String,
+    pub reads_before: usize,
+    pub reads_after: usize,
+    pub percent_reads_passed: f64,
+    pub bases_before: usize,
+    pub bases_after: usize,
+    pub q20_percent_before: f64,
+    pub q20_percent_after: f64,
+    pub q30_percent_before: f64,
+    pub q30_percent_after: f64,
+    pub duplication_percent: f64,
+    pub adapter_trimmed_reads: usize,
+    pub adapter_trimmed_bases: usize,
+    /// Only available for paired-end reads
+    pub insert_size_peak: Option<usize>,
+    #[serde(default)]
+    pub qc_status: QcStatus,
+    /// Reasons the sample failed QC separated by semicolons
+    #[serde(default)]
+    pub qc_reason: String,
+}
+
+impl FastpSummary {
+    /// Parse the fastp JSON report of a sample
+    pub fn from_json(sample_name: &str, path: &Path) -> Result<Self, Box<dyn Error>> {
+        let content = fs::read_to_string(path)?;
+        let json: FastpJson = serde_json::from_str(&content)?;
+        let before = &json.summary.before_filtering;
+        let after = &json.summary.after_filtering;
+        let adapter = json.adapter_cutting.unwrap_or_default();
+        Ok(Self {
+            sample_name: sample_name.to_string(),
+            reads_before: before.total_reads,
+            reads_after: after.total_reads,
+            percent_reads_passed: percent(after.total_reads, before.total_reads),
+            bases_before: before.total_bases,
+            bases_after: after.total_bases,
+            q20_percent_before: before.q20_rate * 100.0,
+            q20_percent_after: after.q20_rate * 100.0,
+            q30_percent_before: before.q30_rate * 100.0,
+            q30_percent_after: after.q30_rate * 100.0,
+            duplication_percent: json.duplication.map_or(0.0, |d| d.rate * 100.0),
+            adapter_trimmed_reads: adapter.adapter_trimmed_reads,
+            adapter_trimmed_bases: adapter.adapter_trimmed_bases,
+            insert_size_peak: json.insert_size.map(|i| i.peak),
+            qc_status: QcStatus::Pass,
+            qc_reason: String::new(),
+        })
+    }
+
+    pub fn check_qc(&mut self, thresholds: &QcThresholds) {
+        let reasons = thresholds.check(self);
+        self.qc_status = QcStatus::from_success(reasons.is_empty());
+        self.qc_reason = reasons.join("; ");
+    }
+
+    pub fn is_passed(&self) -> bool {
+        self.qc_status == QcStatus::Pass
+    }
+}
+
+/// Cross-sample summary of the read cleaning step
+pub struct ReadCleaningSummary<'a> {
+    /// Read cleaning output directory
+    pub output_dir: &'a Path,
+    pub summaries: Vec<FastpSummary>,
+}
+
+impl<'a> ReadCleaningSummary<'a> {
+    /// Summarize the fastp JSON report of each sample.
+    /// Samples with missing or invalid reports are skipped.
+    pub fn from_reports(output_dir: &'a Path, reports: &[CleanReadReport]) -> Self {
+        let summaries = reports
+            .iter()
+            .filter_map(
+                |report| match FastpSummary::from_json(&report.sample_name, &report.json) {
+                    Ok(summary) => Some(summary),
+                    Err(e) => {
+                        log::warn!(
+                            "{} Failed to parse fastp report {}: {}",
+                            "Warning:".yellow(),
+                            report.json.display(),
+                            e
+                        );
+                        None
+                    }
+                },
+            )
+            .collect();
+        Self {
+            output_dir,
+            summaries,
+        }
+    }
+
+    /// Read the summary written by a previous read cleaning run
+    pub fn from_csv(path: &Path) -> Result<Vec<FastpSummary>, Box<dyn Error>> {
+        let mut reader = csv::Reader::from_path(path)?;
+        let mut summaries = Vec::new();
+        for record in reader.deserialize() {
+            summaries.push(record?);
+        }
+        Ok(summaries)
+    }
+
+    /// Flag samples that do not meet the QC thresholds
+    pub fn check_qc(&mut self, thresholds: &QcThresholds) {
+        self.summaries
+            .iter_mut()
+            .for_each(|summary| summary.check_qc(thresholds));
+    }
+
+    pub fn failed_samples(&self) -> Vec<&FastpSummary> {
+        self.summaries.iter().filter(|s| !s.is_passed()).collect()
+    }
+
+    pub fn log_failed(&self) {
+        self.failed_samples().iter().for_each(|summary| {
+            log::warn!(
+                "{} {} failed QC: {}",
+                "Warning:".yellow(),
+                summary.sample_name,
+                summary.qc_reason
+            );
+        });
+    }
+
+    pub fn write(&self) -> Result<PathBuf, Box<dyn Error>> {
+        fs::create_dir_all(self.output_dir)?;
+        let output_path = self.output_dir.join(READ_CLEANING_SUMMARY);
+        let mut writer = csv::Writer::from_path(&output_path)?;
+        for summary in &self.summaries {
+            writer.serialize(summary)?;
+        }
+        writer.flush()?;
+        Ok(output_path)
+    }
+
+    pub fn log_table(&self) {
+        if self.summaries.is_empty() {
+            return;
+        }
+        let mut table = Table::new();
+        table.set_header(vec![
+            "Sample",
+            "Reads before",
+            "Reads after",
+            "Passed (%)",
+            "Q20 after (%)",
+            "Q30 after (%)",
+            "Duplication (%)",
+            "Adapter-trimmed bases",
+            "Insert size peak",
+            "QC",
+        ]);
+        for summary in &self.summaries {
+            table.add_row(vec![
+                summary.sample_name.to_string(),
+                summary.reads_before.to_string(),
+                summary.reads_after.to_string(),
+                format!("{:.2}", summary.percent_reads_passed),
+                format!("{:.2}", summary.q20_percent_after),
+                format!("{:.2}", summary.q30_percent_after),
+                format!("{:.2}", summary.duplication_percent),
+                summary.adapter_trimmed_bases.to_string(),
+                summary
+                    .insert_size_peak
+                    .map_or(String::from("-"), |peak| peak.to_string()),
+                summary.qc_status.to_string(),
+            ]);
+        }
+        log::info!("\n{}", table);
+    }
+}
+
+// Only the fields used in the summary are parsed.
+#[derive(Deserialize)]
+struct FastpJson {
+    summary: FastpJsonSummary,
+    duplication: Option<FastpDuplication>,
+    insert_size: Option<FastpInsertSize>,
+    adapter_cutting: Option<FastpAdapterCutting>,
+}
+
+#[derive(Deserialize)]
+struct FastpJsonSummary {
+    before_filtering: FastpReadStats,
+    after_filtering: FastpReadStats,
+}
+
+#[derive(Deserialize)]
+struct FastpReadStats {
+    total_reads: usize,
+    total_bases: usize,
+    q20_rate: f64,
+    q30_rate: f64,
+}
+
+#[derive(Deserialize)]
+struct FastpDuplication {
+    rate: f64,
+}
+
+#[derive(Deserialize)]
+struct FastpInsertSize {
+    peak: usize,
+}
+
+#[derive(Default, Deserialize)]
+struct FastpAdapterCutting {
+    #[serde(default)]
+    adapter_trimmed_reads: usize,
+    #[serde(default)]
+    adapter_trimmed_bases: usize,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_fastp_summary() {
+        let path = Path::new("tests/data/fastp/fastp.json");
+        let summary = FastpSummary::from_json("sample_1", path).unwrap();
+        assert_eq!(summary.reads_before, 200000);
+        assert_eq!(summary.reads_after, 180000);
+        assert_eq!(summary.percent_reads_passed, 90.0);
+        assert_eq!(summary.q30_percent_after, 95.0);
+        assert_eq!(summary.duplication_percent, 12.5);
+        assert_eq!(summary.adapter_trimmed_bases, 1250000);
+        assert_eq!(summary.insert_size_peak, Some(212));
+        assert!(summary.is_passed());
+    }
+}
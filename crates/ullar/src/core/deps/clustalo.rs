@@ -0,0 +1,70 @@
+use std::process::Command;
+
+use colored::Colorize;
+
+use crate::version;
+
+use super::{check_dependency_match, dependency_not_found, re_capture_version, DepMetadata};
+
+/// Clustal Omega executable.
+pub const CLUSTALO_EXE: &str = "clustalo";
+pub const CLUSTALO_NAME: &str = "Clustal Omega";
+
+/// Metadata for Clustal Omega.
+#[derive(Debug, Default)]
+pub struct ClustalOmegaMetadata<'a> {
+    version: Option<String>,
+    override_args: Option<&'a str>,
+}
+
+impl<'a> ClustalOmegaMetadata<'a> {
+    pub fn new() -> Self {
+        Self {
+            version: version!(CLUSTALO_EXE),
+            override_args: None,
+        }
+    }
+
+    pub fn override_args(mut self, override_args: Option<&'a str>) -> Self {
+        self.override_args = override_args;
+        self
+    }
+
+    pub fn get(&self) -> Option<DepMetadata> {
+        match &self.version {
+            Some(version) => self.metadata(version),
+            None => None,
+        }
+    }
+
+    pub fn update(&self, config_meta: Option<&DepMetadata>) -> DepMetadata {
+        let mut update = self.get().unwrap_or_else(|| {
+            panic!(
+                "{} Clustal Omega is not found. 
+                Please ensure Clustal Omega is installed and accessible in your PATH",
+                "Error:".red()
+            )
+        });
+
+        match config_meta {
+            Some(dep) => {
+                check_dependency_match(&update, &dep.version);
+                if dep.override_args.is_some() {
+                    update.override_args = dep.override_args.clone();
+                }
+                update
+            }
+            None => {
+                dependency_not_found(CLUSTALO_NAME);
+                update
+            }
+        }
+    }
+
+    fn metadata(&self, version_data: &str) -> Option<DepMetadata> {
+        let version = re_capture_version(version_data);
+        let metadata = DepMetadata::new(CLUSTALO_NAME, &version, Some(CLUSTALO_EXE))
+            .override_args(self.override_args);
+        Some(metadata)
+    }
+}
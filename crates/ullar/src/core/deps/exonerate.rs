@@ -0,0 +1,70 @@
+use std::process::Command;
+
+use colored::Colorize;
+
+use crate::version;
+
+use super::{check_dependency_match, dependency_not_found, re_capture_version, DepMetadata};
+
+/// Exonerate executable.
+pub const EXONERATE_EXE: &str = "exonerate";
+pub const EXONERATE_NAME: &str = "Exonerate";
+
+/// Metadata for Exonerate.
+#[derive(Debug, Default)]
+pub struct ExonerateMetadata<'a> {
+    version: Option<String>,
+    override_args: Option<&'a str>,
+}
+
+impl<'a> ExonerateMetadata<'a> {
+    pub fn new() -> Self {
+        Self {
+            version: version!(EXONERATE_EXE),
+            override_args: None,
+        }
+    }
+
+    pub fn override_args(mut self, override_args: Option<&'a str>) -> Self {
+        self.override_args = override_args;
+        self
+    }
+
+    pub fn get(&self) -> Option<DepMetadata> {
+        match &self.version {
+            Some(version) => self.metadata(version),
+            None => None,
+        }
+    }
+
+    pub fn update(&self, config_meta: Option<&DepMetadata>) -> DepMetadata {
+        let mut update = self.get().unwrap_or_else(|| {
+            panic!(
+                "{} Exonerate is not found. 
+                Please ensure Exonerate is installed and accessible in your PATH",
+                "Error:".red()
+            )
+        });
+
+        match config_meta {
+            Some(dep) => {
+                check_dependency_match(&update, &dep.version);
+                if dep.override_args.is_some() {
+                    update.override_args = dep.override_args.clone();
+                }
+                update
+            }
+            None => {
+                dependency_not_found(EXONERATE_NAME);
+                update
+            }
+        }
+    }
+
+    fn metadata(&self, version_data: &str) -> Option<DepMetadata> {
+        let version = re_capture_version(version_data);
+        let metadata = DepMetadata::new(EXONERATE_NAME, &version, Some(EXONERATE_EXE))
+            .override_args(self.override_args);
+        Some(metadata)
+    }
+}
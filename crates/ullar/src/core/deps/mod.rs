@@ -1,11 +1,15 @@
 //! Utilities for managing dependencies.
 use aster::AsterMetadata;
+use clustalo::ClustalOmegaMetadata;
 use colored::Colorize;
 use comfy_table::{Cell, Color, Table};
+use exonerate::ExonerateMetadata;
 use fastp::FastpMetadata;
 use iqtree::IqtreeMetadata;
 use lastz::LastzMetadata;
 use mafft::MafftMetadata;
+use megahit::MegahitMetadata;
+use muscle::MuscleMetadata;
 use segul::get_segul_metadata;
 use serde::{Deserialize, Serialize};
 use spades::SpadesMetadata;
@@ -13,11 +17,15 @@ use spades::SpadesMetadata;
 use crate::core::deps::minimap::get_minimap_version;
 
 pub mod aster;
+pub mod clustalo;
+pub mod exonerate;
 pub mod fastp;
 pub mod iqtree;
 pub mod lastz;
 pub mod mafft;
+pub mod megahit;
 pub mod minimap;
+pub mod muscle;
 pub mod segul;
 pub mod spades;
 
@@ -28,8 +36,12 @@ const STATUS_EMBEDDED: &str = "📦 EMBEDDED";
 pub enum Dependency {
     Fastp,
     Spades,
+    Megahit,
     Lastz,
+    Exonerate,
     Mafft,
+    Muscle,
+    ClustalOmega,
     Iqtree,
 }
 
@@ -82,7 +94,7 @@ pub fn dependency_not_found(dep: &str) {
 
 /// Data structure to store dependency metadata
 /// Shared by all dependencies
-#[derive(Debug, Serialize, Deserialize, Default)]
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
 pub struct DepMetadata {
     pub app_name: String,
     pub version: String,
@@ -135,8 +147,12 @@ impl DepMetadata {
 pub struct DependencyCheck {
     fastp: Option<DepMetadata>,
     spades: Option<DepMetadata>,
+    megahit: Option<DepMetadata>,
     lastz: Option<DepMetadata>,
+    exonerate: Option<DepMetadata>,
     mafft: Option<DepMetadata>,
+    muscle: Option<DepMetadata>,
+    clustalo: Option<DepMetadata>,
     iqtree: Option<DepMetadata>,
     segul: Option<DepMetadata>,
     minimap: Option<DepMetadata>,
@@ -148,8 +164,12 @@ impl DependencyCheck {
         Self {
             fastp: FastpMetadata::new().get(),
             spades: SpadesMetadata::new().get(),
+            megahit: MegahitMetadata::new().get(),
             lastz: LastzMetadata::new().get(),
+            exonerate: ExonerateMetadata::new().get(),
             mafft: MafftMetadata::new().get(),
+            muscle: MuscleMetadata::new().get(),
+            clustalo: ClustalOmegaMetadata::new().get(),
             iqtree: IqtreeMetadata::new().get(),
             aster: {
                 let mut aster = AsterMetadata::new();
@@ -165,8 +185,14 @@ impl DependencyCheck {
         Self {
             fastp: FastpMetadata::new().override_args(override_args).get(),
             spades: SpadesMetadata::new().override_args(override_args).get(),
+            megahit: MegahitMetadata::new().override_args(override_args).get(),
             lastz: LastzMetadata::new().override_args(override_args).get(),
+            exonerate: ExonerateMetadata::new().override_args(override_args).get(),
             mafft: MafftMetadata::new().override_args(override_args).get(),
+            muscle: MuscleMetadata::new().override_args(override_args).get(),
+            clustalo: ClustalOmegaMetadata::new()
+                .override_args(override_args)
+                .get(),
             iqtree: IqtreeMetadata::new().get(),
             aster: {
                 let mut aster = AsterMetadata::new();
@@ -224,6 +250,16 @@ impl DependencyCheck {
                 table.add_row(cells);
             }
         }
+        match &self.megahit {
+            Some(metadata) => {
+                let cells = self.get_cell(feature, "MEGAHIT", &metadata.version, Some(true));
+                table.add_row(cells);
+            }
+            None => {
+                let cells = self.get_cell(feature, "MEGAHIT", "Unknown", Some(false));
+                table.add_row(cells);
+            }
+        }
     }
 
     fn log_contig_mapping(&mut self, table: &mut Table) {
@@ -238,6 +274,16 @@ impl DependencyCheck {
                 table.add_row(cells);
             }
         }
+        match &self.exonerate {
+            Some(metadata) => {
+                let cells = self.get_cell(feature, "Exonerate", &metadata.version, Some(true));
+                table.add_row(cells);
+            }
+            None => {
+                let cells = self.get_cell(feature, "Exonerate", "Unknown", Some(false));
+                table.add_row(cells);
+            }
+        }
     }
 
     fn log_sequence_alignment(&mut self, table: &mut Table) {
@@ -252,6 +298,26 @@ impl DependencyCheck {
                 table.add_row(cells);
             }
         }
+        match &self.muscle {
+            Some(metadata) => {
+                let cells = self.get_cell(feature, "MUSCLE", &metadata.version, Some(true));
+                table.add_row(cells);
+            }
+            None => {
+                let cells = self.get_cell(feature, "MUSCLE", "Unknown", Some(false));
+                table.add_row(cells);
+            }
+        }
+        match &self.clustalo {
+            Some(metadata) => {
+                let cells = self.get_cell(feature, "Clustal Omega", &metadata.version, Some(true));
+                table.add_row(cells);
+            }
+            None => {
+                let cells = self.get_cell(feature, "Clustal Omega", "Unknown", Some(false));
+                table.add_row(cells);
+            }
+        }
     }
 
     fn log_ml_inference(&mut self, table: &mut Table) {
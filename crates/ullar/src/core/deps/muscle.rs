@@ -0,0 +1,131 @@
+use std::process::Command;
+
+use colored::Colorize;
+
+use super::{check_dependency_match, dependency_not_found, DepMetadata};
+
+/// MUSCLE executable.
+pub const MUSCLE_EXE: &str = "muscle";
+pub const MUSCLE_NAME: &str = "MUSCLE";
+/// MUSCLE 5 changed the command line interface.
+/// Older versions are not supported.
+const MUSCLE_MIN_MAJOR_VERSION: u32 = 5;
+
+/// Metadata for MUSCLE 5.
+#[derive(Debug, Default)]
+pub struct MuscleMetadata<'a> {
+    version: Option<String>,
+    override_args: Option<&'a str>,
+}
+
+impl<'a> MuscleMetadata<'a> {
+    pub fn new() -> Self {
+        Self {
+            version: get_muscle_version(),
+            override_args: None,
+        }
+    }
+
+    pub fn override_args(mut self, override_args: Option<&'a str>) -> Self {
+        self.override_args = override_args;
+        self
+    }
+
+    pub fn get(&self) -> Option<DepMetadata> {
+        match &self.version {
+            Some(version) => self.metadata(version),
+            None => None,
+        }
+    }
+
+    pub fn update(&self, config_meta: Option<&DepMetadata>) -> DepMetadata {
+        let mut update = self.get().unwrap_or_else(|| {
+            panic!(
+                "{} MUSCLE is not found. 
+                Please ensure MUSCLE is installed and accessible in your PATH",
+                "Error:".red()
+            )
+        });
+        if !is_supported_version(&update.version) {
+            panic!(
+                "{} MUSCLE version {} is not supported. Please install MUSCLE 5 or newer",
+                "Error:".red(),
+                update.version
+            );
+        }
+
+        match config_meta {
+            Some(dep) => {
+                check_dependency_match(&update, &dep.version);
+                if dep.override_args.is_some() {
+                    update.override_args = dep.override_args.clone();
+                }
+                update
+            }
+            None => {
+                dependency_not_found(MUSCLE_NAME);
+                update
+            }
+        }
+    }
+
+    fn metadata(&self, version_data: &str) -> Option<DepMetadata> {
+        let version = capture_version(version_data);
+        let metadata = DepMetadata::new(MUSCLE_NAME, &version, Some(MUSCLE_EXE))
+            .override_args(self.override_args);
+        Some(metadata)
+    }
+}
+
+// MUSCLE uses a single dash for the version flag
+fn get_muscle_version() -> Option<String> {
+    let output = Command::new(MUSCLE_EXE).arg("-version").output();
+    match output {
+        Err(_) => None,
+        Ok(output) => {
+            let version = String::from_utf8_lossy(&output.stdout);
+            if version.is_empty() {
+                Some(String::from_utf8_lossy(&output.stderr).to_string())
+            } else {
+                Some(version.to_string())
+            }
+        }
+    }
+}
+
+// MUSCLE 5 reports the version as major.minor,
+// e.g. "muscle 5.1.linux64 [12f0e2]"
+fn capture_version(version_data: &str) -> String {
+    let re = regex::Regex::new(r"\d+\.\d+").expect("Failed to compile regex");
+    match re.captures(version_data) {
+        None => "Unknown".to_string(),
+        Some(captures) => captures
+            .get(0)
+            .expect("Failed to get version")
+            .as_str()
+            .to_string(),
+    }
+}
+
+fn is_supported_version(version: &str) -> bool {
+    version
+        .split('.')
+        .next()
+        .and_then(|major| major.parse::<u32>().ok())
+        .is_some_and(|major| major >= MUSCLE_MIN_MAJOR_VERSION)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_muscle_version() {
+        let version = capture_version("muscle 5.1.linux64 [12f0e2]  61.8Gb RAM, 8 cores");
+        assert_eq!(version, "5.1");
+        assert!(is_supported_version(&version));
+        let version = capture_version("MUSCLE v3.8.1551 by Robert C. Edgar");
+        assert_eq!(version, "3.8");
+        assert!(!is_supported_version(&version));
+    }
+}
@@ -0,0 +1,499 @@
+//! Write coordinate-sorted BAM files.
+//!
+//! Records are sorted in chunks. Chunks larger than
+//! the buffer are spilled to temporary BAM files
+//! and merged when the final BAM file is written.
+use std::{
+    cmp::Reverse,
+    collections::BinaryHeap,
+    error::Error,
+    fs::{self, File},
+    num::NonZeroUsize,
+    path::{Path, PathBuf},
+};
+
+use bio::io::fasta;
+use noodles::{
+    bam,
+    core::Position,
+    sam::{
+        self,
+        alignment::{
+            io::Write,
+            record::{
+                cigar::{op::Kind, Op},
+                data::field::Tag,
+                Flags,
+            },
+            record_buf::{data::field::Value, Cigar, Data, Sequence},
+            RecordBuf,
+        },
+        header::record::value::{
+            map::{
+                self,
+                header::{sort_order, tag as header_tag},
+                read_group::tag as read_group_tag,
+                ReadGroup, ReferenceSequence,
+            },
+            Map,
+        },
+    },
+};
+use serde::{Deserialize, Serialize};
+
+use super::reports::BamMappingReport;
+
+pub const BAM_EXTENSION: &str = "bam";
+pub const DEFAULT_BAM_OUTPUT_DIR: &str = "bam";
+/// Number of records held in memory before
+/// they are sorted and spilled to a chunk file
+const MAX_BUFFERED_RECORDS: usize = 200_000;
+const CHUNK_EXT: &str = "tmp";
+
+/// Filter of alignments written to BAM files
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct BamFilter {
+    /// Minimum percent identity of alignments to keep.
+    /// Lastz does not estimate mapping quality,
+    /// so alignments are filtered by identity instead.
+    pub min_identity: f64,
+}
+
+impl BamFilter {
+    pub fn new(min_identity: f64) -> Self {
+        Self { min_identity }
+    }
+
+    pub fn is_pass(&self, alignment: &PairwiseAlignment) -> bool {
+        alignment.identity() >= self.min_identity
+    }
+}
+
+/// Pairwise alignment of a query to a reference sequence.
+/// Used to write alignments of aligners without SAM output.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct PairwiseAlignment {
+    pub query_name: String,
+    pub reference_name: String,
+    /// Zero-based start of the alignment in the reference
+    pub reference_start: usize,
+    /// Zero-based start of the alignment
+    /// on the aligned strand of the query
+    pub query_start: usize,
+    /// End of the alignment on the aligned strand of the query
+    pub query_end: usize,
+    pub query_size: usize,
+    pub is_reverse: bool,
+    pub score: usize,
+    /// Aligned reference sequence with gaps
+    pub reference_text: String,
+    /// Aligned query sequence with gaps
+    pub query_text: String,
+}
+
+impl PairwiseAlignment {
+    /// Percent of identical bases in columns
+    /// where both sequences have a base
+    pub fn identity(&self) -> f64 {
+        let (matches, aligned) = self
+            .reference_text
+            .bytes()
+            .zip(self.query_text.bytes())
+            .filter(|(r, q)| *r != b'-' && *q != b'-')
+            .fold((0, 0), |(matches, aligned), (r, q)| {
+                (matches + r.eq_ignore_ascii_case(&q) as usize, aligned + 1)
+            });
+        if aligned == 0 {
+            return 0.0;
+        }
+        matches as f64 / aligned as f64 * 100.0
+    }
+
+    /// Convert the alignment to a BAM record.
+    /// Unaligned ends of the query are hard clipped.
+    /// Mapping quality is not available and left unset.
+    pub fn to_record(
+        &self,
+        header: &sam::Header,
+        read_group: &str,
+    ) -> Result<RecordBuf, Box<dyn Error>> {
+        let reference_id = header
+            .reference_sequences()
+            .get_index_of(self.reference_name.as_bytes())
+            .ok_or_else(|| format!("Reference {} not found", self.reference_name))?;
+        let sequence: Vec<u8> = self.query_text.bytes().filter(|b| *b != b'-').collect();
+        let mut flags = Flags::empty();
+        if self.is_reverse {
+            flags |= Flags::REVERSE_COMPLEMENTED;
+        }
+        let record = RecordBuf::builder()
+            .set_name(self.query_name.as_str())
+            .set_flags(flags)
+            .set_reference_sequence_id(reference_id)
+            .set_alignment_start(Position::try_from(self.reference_start + 1)?)
+            .set_cigar(self.cigar().into_iter().collect::<Cigar>())
+            .set_sequence(Sequence::from(sequence))
+            .set_data(Data::from_iter([
+                (Tag::READ_GROUP, Value::from(read_group)),
+                (Tag::ALIGNMENT_SCORE, Value::from(self.score as i32)),
+            ]))
+            .build();
+        Ok(record)
+    }
+
+    fn cigar(&self) -> Vec<Op> {
+        let mut cigar: Vec<Op> = Vec::new();
+        if self.query_start > 0 {
+            cigar.push(Op::new(Kind::HardClip, self.query_start));
+        }
+        self.reference_text
+            .bytes()
+            .zip(self.query_text.bytes())
+            .filter_map(|(r, q)| match (r, q) {
+                (b'-', b'-') => None,
+                (b'-', _) => Some(Kind::Insertion),
+                (_, b'-') => Some(Kind::Deletion),
+                _ => Some(Kind::Match),
+            })
+            .for_each(|kind| match cigar.last_mut() {
+                Some(op) if op.kind() == kind => *op = Op::new(kind, op.len() + 1),
+                _ => cigar.push(Op::new(kind, 1)),
+            });
+        let tail_clip = self.query_size.saturating_sub(self.query_end);
+        if tail_clip > 0 {
+            cigar.push(Op::new(Kind::HardClip, tail_clip));
+        }
+        cigar
+    }
+}
+
+/// Parse reference names and lengths from a FASTA file.
+/// Returns them in the same order as the file.
+pub fn parse_references(reference_path: &Path) -> Result<Vec<(String, usize)>, Box<dyn Error>> {
+    let reader = fasta::Reader::from_file(reference_path)?;
+    let mut references = Vec::new();
+    for record in reader.records() {
+        let record = record?;
+        references.push((record.id().to_string(), record.seq().len()));
+    }
+    Ok(references)
+}
+
+/// Build a SAM header for coordinate-sorted records.
+/// Each sample uses its name as the read group ID.
+pub fn build_header(
+    references: &[(String, usize)],
+    read_group: &str,
+) -> Result<sam::Header, Box<dyn Error>> {
+    let header = Map::<map::Header>::builder()
+        .insert(header_tag::SORT_ORDER, sort_order::COORDINATE)
+        .build()?;
+    let read_group_map = Map::<ReadGroup>::builder()
+        .insert(read_group_tag::SAMPLE, read_group)
+        .build()?;
+    let mut builder = sam::Header::builder()
+        .set_header(header)
+        .add_read_group(read_group, read_group_map);
+    for (name, length) in references {
+        let length = NonZeroUsize::new(*length)
+            .ok_or_else(|| format!("Reference sequence {} is empty", name))?;
+        builder =
+            builder.add_reference_sequence(name.as_str(), Map::<ReferenceSequence>::new(length));
+    }
+    Ok(builder.build())
+}
+
+/// Write the alignments of a sample to a coordinate-sorted BAM file
+/// in the BAM directory of the output directory.
+/// The sample name is used as the read group.
+pub fn write_sample_bam(
+    output_dir: &Path,
+    references: &[(String, usize)],
+    alignments: &[PairwiseAlignment],
+    sample_name: &str,
+    filter: &BamFilter,
+) -> Result<BamMappingReport, Box<dyn Error>> {
+    let header = build_header(references, sample_name)?;
+    let bam_dir = output_dir.join(DEFAULT_BAM_OUTPUT_DIR);
+    fs::create_dir_all(&bam_dir)?;
+    let output_path = bam_dir.join(sample_name).with_extension(BAM_EXTENSION);
+    let mut writer = SortedBamWriter::new(header, &output_path);
+    let filtered = writer.push_alignments(alignments, sample_name, filter)?;
+    writer.write()?;
+    let mut report = BamMappingReport::new(sample_name, output_path);
+    report.alignments = writer.len();
+    report.filtered_alignments = filtered;
+    Ok(report)
+}
+
+/// Sort key of a record.
+/// Unmapped records are placed at the end.
+type SortKey = (usize, Option<Position>);
+
+pub struct SortedBamWriter {
+    pub header: sam::Header,
+    pub output_path: PathBuf,
+    records: Vec<RecordBuf>,
+    chunks: Vec<PathBuf>,
+    max_records: usize,
+    record_counts: usize,
+}
+
+impl SortedBamWriter {
+    pub fn new(header: sam::Header, output_path: &Path) -> Self {
+        Self {
+            header,
+            output_path: output_path.to_path_buf(),
+            records: Vec::new(),
+            chunks: Vec::new(),
+            max_records: MAX_BUFFERED_RECORDS,
+            record_counts: 0,
+        }
+    }
+
+    /// Set the number of records held in memory
+    /// before they are spilled to disk
+    pub fn with_max_records(mut self, max_records: usize) -> Self {
+        self.max_records = max_records.max(1);
+        self
+    }
+
+    pub fn push(&mut self, record: RecordBuf) -> Result<(), Box<dyn Error>> {
+        self.records.push(record);
+        self.record_counts += 1;
+        if self.records.len() >= self.max_records {
+            self.spill()?;
+        }
+        Ok(())
+    }
+
+    pub fn len(&self) -> usize {
+        self.record_counts
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.record_counts == 0
+    }
+
+    /// Add alignments that pass the filter.
+    /// Returns the number of alignments that failed the filter.
+    pub fn push_alignments(
+        &mut self,
+        alignments: &[PairwiseAlignment],
+        read_group: &str,
+        filter: &BamFilter,
+    ) -> Result<usize, Box<dyn Error>> {
+        let mut filtered = 0;
+        for alignment in alignments {
+            if !filter.is_pass(alignment) {
+                filtered += 1;
+                continue;
+            }
+            let record = alignment.to_record(&self.header, read_group)?;
+            self.push(record)?;
+        }
+        Ok(filtered)
+    }
+
+    /// Sort the records by reference and position,
+    /// then write them to the BAM file.
+    /// Spilled chunks are merged and removed.
+    pub fn write(&mut self) -> Result<(), Box<dyn Error>> {
+        if self.chunks.is_empty() {
+            self.sort_records();
+            let records = std::mem::take(&mut self.records);
+            return self.write_records(&self.output_path, &records);
+        }
+        if !self.records.is_empty() {
+            self.spill()?;
+        }
+        self.merge_chunks()?;
+        self.remove_chunks();
+        Ok(())
+    }
+
+    fn sort_records(&mut self) {
+        self.records.sort_by_key(sort_key);
+    }
+
+    fn spill(&mut self) -> Result<(), Box<dyn Error>> {
+        self.sort_records();
+        let chunk_path =
+            self.output_path
+                .with_extension(format!("{}.{}", self.chunks.len(), CHUNK_EXT));
+        let records = std::mem::take(&mut self.records);
+        self.chunks.push(chunk_path.clone());
+        self.write_records(&chunk_path, &records)
+    }
+
+    fn write_records(&self, path: &Path, records: &[RecordBuf]) -> Result<(), Box<dyn Error>> {
+        let file = File::create(path)?;
+        let mut writer = bam::io::Writer::new(file);
+        writer.write_header(&self.header)?;
+        for record in records {
+            writer.write_alignment_record(&self.header, record)?;
+        }
+        writer.try_finish()?;
+        Ok(())
+    }
+
+    // K-way merge of the sorted chunks.
+    // Ties keep the chunk order, so the sort is stable.
+    fn merge_chunks(&self) -> Result<(), Box<dyn Error>> {
+        let mut readers = Vec::with_capacity(self.chunks.len());
+        let mut heads = Vec::with_capacity(self.chunks.len());
+        let mut heap: BinaryHeap<Reverse<(SortKey, usize)>> = BinaryHeap::new();
+        for (i, chunk) in self.chunks.iter().enumerate() {
+            let mut reader = bam::io::Reader::new(File::open(chunk)?);
+            reader.read_header()?;
+            let mut record = RecordBuf::default();
+            if reader.read_record_buf(&self.header, &mut record)? > 0 {
+                heap.push(Reverse((sort_key(&record), i)));
+            }
+            readers.push(reader);
+            heads.push(record);
+        }
+        let file = File::create(&self.output_path)?;
+        let mut writer = bam::io::Writer::new(file);
+        writer.write_header(&self.header)?;
+        while let Some(Reverse((_, i))) = heap.pop() {
+            writer.write_alignment_record(&self.header, &heads[i])?;
+            if readers[i].read_record_buf(&self.header, &mut heads[i])? > 0 {
+                heap.push(Reverse((sort_key(&heads[i]), i)));
+            }
+        }
+        writer.try_finish()?;
+        Ok(())
+    }
+
+    fn remove_chunks(&mut self) {
+        self.chunks.drain(..).for_each(|chunk| {
+            if let Err(e) = fs::remove_file(&chunk) {
+                log::warn!("Failed to remove {}: {}", chunk.display(), e);
+            }
+        });
+    }
+}
+
+// Remove spilled chunks if writing fails or panics
+impl Drop for SortedBamWriter {
+    fn drop(&mut self) {
+        self.remove_chunks();
+    }
+}
+
+fn sort_key(record: &RecordBuf) -> SortKey {
+    (
+        record.reference_sequence_id().unwrap_or(usize::MAX),
+        record.alignment_start(),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn record(reference_id: usize, start: usize) -> RecordBuf {
+        RecordBuf::builder()
+            .set_name("read")
+            .set_flags(Flags::empty())
+            .set_reference_sequence_id(reference_id)
+            .set_alignment_start(Position::new(start).unwrap())
+            .set_cigar([Op::new(Kind::Match, 4)].into_iter().collect())
+            .set_sequence(Sequence::from(b"ACGT".to_vec()))
+            .build()
+    }
+
+    #[test]
+    fn test_sorted_bam_writer() {
+        let output_dir = tempdir::TempDir::new("bam").unwrap();
+        let references = vec![(String::from("uce-1"), 100), (String::from("uce-2"), 100)];
+        let header = build_header(&references, "sample_1").unwrap();
+        let output_path = output_dir.path().join("sample_1.bam");
+        // Spill every two records to test merging chunks
+        let mut writer = SortedBamWriter::new(header, &output_path).with_max_records(2);
+        writer.push(record(1, 5)).unwrap();
+        writer.push(record(0, 20)).unwrap();
+        writer.push(record(0, 10)).unwrap();
+        writer.write().unwrap();
+        assert_eq!(writer.len(), 3);
+        assert_eq!(fs::read_dir(output_dir.path()).unwrap().count(), 1);
+
+        let mut reader = bam::io::reader::Builder
+            .build_from_path(&output_path)
+            .unwrap();
+        let header = reader.read_header().unwrap();
+        assert_eq!(header.reference_sequences().len(), 2);
+        assert_eq!(header.read_groups().len(), 1);
+        let starts: Vec<(usize, usize)> = reader
+            .record_bufs(&header)
+            .map(|r| {
+                let r = r.unwrap();
+                (
+                    r.reference_sequence_id().unwrap(),
+                    r.alignment_start().unwrap().get(),
+                )
+            })
+            .collect();
+        assert_eq!(starts, vec![(0, 10), (0, 20), (1, 5)]);
+    }
+
+    fn alignment(reference_text: &str, query_text: &str) -> PairwiseAlignment {
+        PairwiseAlignment {
+            query_name: String::from("contig_1"),
+            reference_name: String::from("uce-2"),
+            reference_start: 10,
+            query_start: 2,
+            query_end: 9,
+            query_size: 12,
+            is_reverse: true,
+            score: 500,
+            reference_text: reference_text.to_string(),
+            query_text: query_text.to_string(),
+        }
+    }
+
+    #[test]
+    fn test_pairwise_alignment() {
+        let aligned = alignment("ACG-TACG", "ACGGT-CA");
+        assert!((aligned.identity() - 83.33).abs() < 0.01);
+        let cigar: Vec<(Kind, usize)> = aligned
+            .cigar()
+            .iter()
+            .map(|op| (op.kind(), op.len()))
+            .collect();
+        assert_eq!(
+            cigar,
+            vec![
+                (Kind::HardClip, 2),
+                (Kind::Match, 3),
+                (Kind::Insertion, 1),
+                (Kind::Match, 1),
+                (Kind::Deletion, 1),
+                (Kind::Match, 2),
+                (Kind::HardClip, 3),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_push_alignments() {
+        let references = vec![(String::from("uce-1"), 100), (String::from("uce-2"), 100)];
+        let header = build_header(&references, "sample_1").unwrap();
+        let mut writer = SortedBamWriter::new(header, Path::new("sample_1.bam"));
+        let alignments = vec![
+            alignment("ACGTACGT", "ACGTACGT"),
+            alignment("ACGTACGT", "ACGTTTTT"),
+        ];
+        let filtered = writer
+            .push_alignments(&alignments, "sample_1", &BamFilter::new(90.0))
+            .unwrap();
+        assert_eq!(filtered, 1);
+        assert_eq!(writer.len(), 1);
+        let record = &writer.records[0];
+        assert_eq!(record.reference_sequence_id(), Some(1));
+        assert_eq!(record.alignment_start(), Position::new(11));
+        assert!(record.flags().is_reverse_complemented());
+        assert!(record.data().get(&Tag::READ_GROUP).is_some());
+    }
+}
@@ -10,23 +10,47 @@ use std::{
 use rayon::prelude::*;
 use serde::{Deserialize, Serialize};
 
+use super::{
+    bam::BamFilter, consensus::ConsensusParams, filter::ContigFilter, native::NativeParams,
+};
 use crate::{
-    core::deps::{lastz::LastzMetadata, DepMetadata},
+    core::deps::{
+        exonerate::ExonerateMetadata, lastz::LastzMetadata, minimap::get_minimap_version,
+        DepMetadata,
+    },
     helper::{
         common::UllarConfig,
-        configs::generate_config_output_path,
+        configs::{generate_config_output_path, PreviousStep},
+        fastq::FastqInput,
         files::{FileFinder, FileMetadata},
     },
     types::{
-        map::{Aligner, MappingReferenceType},
+        map::{Aligner, MappingReferenceType, SequenceOutputFormat},
+        reads::FastqReads,
         SupportedFormats,
     },
 };
 
 pub const DEFAULT_REF_MAPPING_CONFIG: &str = "contig_mapping";
+pub const DEFAULT_READ_MAPPING_CONFIG: &str = "read_mapping";
+
+/// Output of the mapped loci sequences
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct SequenceOutput {
+    pub format: SequenceOutputFormat,
+    /// Compress the sequence files using gzip
+    pub gzip: bool,
+}
+
+impl SequenceOutput {
+    pub fn new(format: SequenceOutputFormat, gzip: bool) -> Self {
+        Self { format, gzip }
+    }
+}
 
 pub const CONTIG_REGEX: &str = r"(?i)(contig*)";
-pub const LASTZ_ALIGNER: &str = "aligner";
+/// Dependency key of the aligner used for mapping
+pub const ALIGNER_DEPENDENCY: &str = "aligner";
 
 #[derive(Debug, Default, Serialize, Deserialize, Clone)]
 pub enum SampleNameSource {
@@ -65,7 +89,32 @@ pub struct ContigMappingConfig {
     pub app: UllarConfig,
     pub input: ContigInput,
     pub dependencies: BTreeMap<String, DepMetadata>,
+    /// Pipeline step that produced the input
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub previous_step: Option<PreviousStep>,
     pub sequence_reference: ReferenceFile,
+    /// Filter contigs by length and coverage before mapping
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub contig_filter: Option<ContigFilter>,
+    /// Exclude loci flagged as paralogous in more than
+    /// this percent of samples. Only used for probe references.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_paralog_percent: Option<f64>,
+    /// Write only the region matched by the probes
+    /// plus flanks of this length in bp.
+    /// Write the entire contig if not set.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub flank_length: Option<usize>,
+    /// Also write contig alignments to
+    /// coordinate-sorted BAM files if set.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub bam_output: Option<BamFilter>,
+    /// Parameters of the native aligner
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub native_params: Option<NativeParams>,
+    /// Format of the mapped loci sequences
+    #[serde(default)]
+    pub sequence_output: SequenceOutput,
     pub contigs: Vec<ContigFiles>,
 }
 
@@ -75,8 +124,15 @@ impl ContigMappingConfig {
             app: UllarConfig::init(),
             input: ContigInput::default(),
             dependencies: BTreeMap::new(),
+            previous_step: None,
             contigs: Vec::new(),
             sequence_reference: ReferenceFile::new(reference_regex, reference_type, true),
+            contig_filter: None,
+            max_paralog_percent: None,
+            flank_length: None,
+            bam_output: None,
+            native_params: None,
+            sequence_output: SequenceOutput::default(),
         }
     }
 
@@ -89,8 +145,15 @@ impl ContigMappingConfig {
         Self {
             app: UllarConfig::default(),
             dependencies: BTreeMap::new(),
+            previous_step: None,
             input,
             sequence_reference: ReferenceFile::new(reference_regex, reference_type, single_ref),
+            contig_filter: None,
+            max_paralog_percent: None,
+            flank_length: None,
+            bam_output: None,
+            native_params: None,
+            sequence_output: SequenceOutput::default(),
             contigs: Vec::new(),
         }
     }
@@ -99,7 +162,8 @@ impl ContigMappingConfig {
         let content = std::fs::read_to_string(config_path)?;
         let ext = config_path.extension().unwrap_or_default();
         if ext == "yaml" || ext == "yml" {
-            let config = serde_yaml::from_str(&content)?;
+            let config: Self = serde_yaml::from_str(&content)?;
+            config.validate()?;
             let toml = toml::to_string_pretty(&config)?;
             let config_path = config_path.with_extension("toml");
             std::fs::write(&config_path, toml)?;
@@ -109,10 +173,40 @@ impl ContigMappingConfig {
             );
             return Ok(config);
         }
-        let config = toml::from_str(&content)?;
+        let config: Self = toml::from_str(&content)?;
+        config.validate()?;
         Ok(config)
     }
 
+    /// Check that the aligner supports contig mapping
+    /// and the reference, and that genome references
+    /// have target regions.
+    pub fn validate(&self) -> Result<(), Box<dyn Error>> {
+        if self.input.aligner == Aligner::Minimap {
+            return Err(
+                "Minimap is only supported for read mapping. Use lastz, exonerate, or native"
+                    .into(),
+            );
+        }
+        if self.sequence_reference.reference_type == MappingReferenceType::Genome
+            && self.sequence_reference.target_regions.is_none()
+        {
+            return Err(
+                "Genome reference requires target regions. Use --regions to set a BED or GFF file"
+                    .into(),
+            );
+        }
+        if self.input.aligner == Aligner::Native {
+            if self.sequence_reference.reference_type != MappingReferenceType::Probes {
+                return Err("The native aligner only supports probe references. Use lastz".into());
+            }
+            if let Some(params) = &self.native_params {
+                params.validate()?;
+            }
+        }
+        Ok(())
+    }
+
     pub fn to_toml(
         &mut self,
         file_name: &str,
@@ -188,11 +282,75 @@ impl ContigMappingConfig {
     }
 
     fn get_dependency(&mut self, override_args: Option<&str>) {
-        let dep = LastzMetadata::new().override_args(override_args).get();
-        let lastz = dep.unwrap_or_else(|| {
-            panic!("Lastz dependency not found. Please, install lastz");
-        });
-        self.dependencies.insert(LASTZ_ALIGNER.to_string(), lastz);
+        let dep = match self.input.aligner {
+            // The native aligner does not require external dependencies
+            Aligner::Native => return,
+            Aligner::Exonerate => ExonerateMetadata::new()
+                .override_args(override_args)
+                .get()
+                .unwrap_or_else(|| {
+                    panic!("Exonerate dependency not found. Please, install exonerate");
+                }),
+            _ => LastzMetadata::new()
+                .override_args(override_args)
+                .get()
+                .unwrap_or_else(|| {
+                    panic!("Lastz dependency not found. Please, install lastz");
+                }),
+        };
+        self.dependencies
+            .insert(ALIGNER_DEPENDENCY.to_string(), dep);
+    }
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct ReadMappingConfig {
+    #[serde(flatten)]
+    pub app: UllarConfig,
+    pub input: FastqInput,
+    pub dependencies: BTreeMap<String, DepMetadata>,
+    /// Pipeline step that produced the input
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub previous_step: Option<PreviousStep>,
+    pub sequence_reference: ReferenceFile,
+    /// Consensus calling parameters
+    #[serde(default)]
+    pub consensus: ConsensusParams,
+    pub samples: Vec<FastqReads>,
+}
+
+impl ReadMappingConfig {
+    pub fn new(input: FastqInput, reference: ReferenceFile, samples: Vec<FastqReads>) -> Self {
+        Self {
+            app: UllarConfig::default(),
+            input,
+            dependencies: BTreeMap::new(),
+            previous_step: None,
+            sequence_reference: reference,
+            consensus: ConsensusParams::default(),
+            samples,
+        }
+    }
+
+    pub fn from_toml(config_path: &Path) -> Result<Self, Box<dyn Error>> {
+        let content = std::fs::read_to_string(config_path)?;
+        let config = toml::from_str(&content)?;
+        Ok(config)
+    }
+
+    pub fn to_toml(&mut self, file_name: &str, ref_path: &Path) -> Result<PathBuf, Box<dyn Error>> {
+        self.get_dependency();
+        self.sequence_reference.get(ref_path);
+        let output_path = generate_config_output_path(file_name);
+        let toml = toml::to_string_pretty(&self)?;
+        std::fs::write(&output_path, toml)?;
+        Ok(output_path)
+    }
+
+    // Minimap2 is embedded in ullar
+    fn get_dependency(&mut self) {
+        self.dependencies
+            .insert(ALIGNER_DEPENDENCY.to_string(), get_minimap_version());
     }
 }
 
@@ -225,7 +383,7 @@ impl ContigInput {
     }
 }
 
-#[derive(Debug, Default, Serialize, Deserialize)]
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
 pub struct ContigFiles {
     pub sample_name: String,
     pub metadata: FileMetadata,
@@ -296,6 +454,10 @@ pub struct ReferenceFile {
     #[serde(alias = "type")]
     pub reference_type: MappingReferenceType,
     pub single_ref: bool,
+    /// BED or GFF file of target regions.
+    /// Required for the genome reference type.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub target_regions: Option<FileMetadata>,
 }
 
 impl ReferenceFile {
@@ -305,10 +467,15 @@ impl ReferenceFile {
             metadata: FileMetadata::new(),
             reference_type,
             single_ref,
+            target_regions: None,
         }
     }
 
     pub fn get(&mut self, reference: &Path) {
         self.metadata = FileMetadata::from_path(reference);
     }
+
+    pub fn set_target_regions(&mut self, regions: &Path) {
+        self.target_regions = Some(FileMetadata::from_path(regions));
+    }
 }
@@ -0,0 +1,221 @@
+//! Call consensus sequences from reads mapped to a reference.
+//!
+//! Bases are counted for each reference position
+//! and the majority base is called. Insertions relative
+//! to the reference are ignored.
+use std::collections::BTreeMap;
+
+use noodles::sam::alignment::record::cigar::{op::Kind, Op};
+use serde::{Deserialize, Serialize};
+
+pub const DEFAULT_MIN_CONSENSUS_DEPTH: usize = 3;
+pub const DEFAULT_MIN_MAPPING_QUALITY: u8 = 20;
+
+/// Index of deletions in the base counts
+const DELETION_INDEX: usize = 4;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ConsensusParams {
+    /// Minimum read depth to call a base.
+    /// Positions with lower depth are called as N.
+    pub min_depth: usize,
+    /// Minimum mapping quality of reads used to call bases
+    pub min_mapping_quality: u8,
+}
+
+impl Default for ConsensusParams {
+    fn default() -> Self {
+        Self {
+            min_depth: DEFAULT_MIN_CONSENSUS_DEPTH,
+            min_mapping_quality: DEFAULT_MIN_MAPPING_QUALITY,
+        }
+    }
+}
+
+/// Counts of A, C, G, T, and deletions
+/// at each position of a reference sequence
+#[derive(Debug, Clone, PartialEq)]
+pub struct Pileup {
+    counts: Vec<[u32; 5]>,
+}
+
+impl Pileup {
+    pub fn new(reference_length: usize) -> Self {
+        Self {
+            counts: vec![[0; 5]; reference_length],
+        }
+    }
+
+    /// Add an aligned read.
+    /// `start` is the 0-based reference start and
+    /// `sequence` is the read in the reference orientation.
+    /// Returns false and skips the read if the CIGAR
+    /// does not match the read length.
+    pub fn add(&mut self, start: usize, sequence: &[u8], cigar: &[Op]) -> bool {
+        if query_length(cigar) != sequence.len() {
+            return false;
+        }
+        let mut ref_pos = start;
+        let mut read_pos = 0;
+        for op in cigar {
+            let len = op.len();
+            match op.kind() {
+                Kind::Match | Kind::SequenceMatch | Kind::SequenceMismatch => {
+                    for (i, base) in sequence[read_pos..read_pos + len].iter().enumerate() {
+                        if let (Some(index), Some(counts)) =
+                            (base_index(*base), self.counts.get_mut(ref_pos + i))
+                        {
+                            counts[index] += 1;
+                        }
+                    }
+                    ref_pos += len;
+                    read_pos += len;
+                }
+                Kind::Deletion => {
+                    for counts in self.counts.iter_mut().skip(ref_pos).take(len) {
+                        counts[DELETION_INDEX] += 1;
+                    }
+                    ref_pos += len;
+                }
+                Kind::Skip => ref_pos += len,
+                Kind::Insertion | Kind::SoftClip => read_pos += len,
+                Kind::HardClip | Kind::Pad => (),
+            }
+        }
+        true
+    }
+
+    /// Call the majority base at each position.
+    /// Uncovered ends are trimmed.
+    /// Returns None if no base is called.
+    pub fn consensus(&self, min_depth: usize) -> Option<String> {
+        let min_depth = min_depth.max(1) as u32;
+        let consensus: String = self
+            .counts
+            .iter()
+            .filter_map(|counts| {
+                let depth: u32 = counts.iter().sum();
+                if depth < min_depth {
+                    return Some('N');
+                }
+                let (index, _) = counts
+                    .iter()
+                    .enumerate()
+                    .max_by_key(|(i, count)| (**count, std::cmp::Reverse(*i)))?;
+                match index {
+                    DELETION_INDEX => None,
+                    _ => Some(b"ACGT"[index] as char),
+                }
+            })
+            .collect();
+        let consensus = consensus.trim_matches('N');
+        if consensus.is_empty() {
+            None
+        } else {
+            Some(consensus.to_string())
+        }
+    }
+}
+
+/// Keep the consensus with the most called bases
+/// when multiple references belong to the same locus
+/// (e.g., multiple probes of a UCE locus).
+pub fn select_locus_consensus(
+    consensus: Vec<(String, String)>,
+    refname_regex: &str,
+) -> BTreeMap<String, String> {
+    let re = regex::Regex::new(refname_regex).expect("Failed to create regex");
+    let mut loci: BTreeMap<String, String> = BTreeMap::new();
+    consensus.into_iter().for_each(|(refname, sequence)| {
+        let locus = match re.captures(&refname) {
+            Some(capture) => capture[0].to_string(),
+            None => refname,
+        };
+        match loci.get(&locus) {
+            Some(current) if called_bases(current) >= called_bases(&sequence) => (),
+            _ => {
+                loci.insert(locus, sequence);
+            }
+        }
+    });
+    loci
+}
+
+fn called_bases(sequence: &str) -> usize {
+    sequence.bytes().filter(|b| *b != b'N').count()
+}
+
+fn base_index(base: u8) -> Option<usize> {
+    match base.to_ascii_uppercase() {
+        b'A' => Some(0),
+        b'C' => Some(1),
+        b'G' => Some(2),
+        b'T' => Some(3),
+        _ => None,
+    }
+}
+
+/// Number of read bases covered by the CIGAR
+pub fn query_length(cigar: &[Op]) -> usize {
+    cigar
+        .iter()
+        .filter(|op| op.kind().consumes_read())
+        .map(|op| op.len())
+        .sum()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_pileup_consensus() {
+        let mut pileup = Pileup::new(10);
+        let cigar = [Op::new(Kind::Match, 6)];
+        pileup.add(2, b"ACGTAC", &cigar);
+        pileup.add(2, b"ACGTAC", &cigar);
+        pileup.add(2, b"ACCTAC", &cigar);
+        // Deletion at reference position 5
+        let cigar = [
+            Op::new(Kind::SoftClip, 1),
+            Op::new(Kind::Match, 3),
+            Op::new(Kind::Deletion, 1),
+            Op::new(Kind::Match, 2),
+        ];
+        pileup.add(2, b"TACGAC", &cigar);
+        assert_eq!(pileup.consensus(3), Some(String::from("ACGTAC")));
+        // Deletion becomes the majority
+        let cigar = [
+            Op::new(Kind::Match, 3),
+            Op::new(Kind::Deletion, 1),
+            Op::new(Kind::Match, 2),
+        ];
+        (0..3).for_each(|_| pileup.add(2, b"ACGAC", &cigar));
+        assert_eq!(pileup.consensus(3), Some(String::from("ACGAC")));
+        assert_eq!(Pileup::new(5).consensus(3), None);
+    }
+
+    #[test]
+    fn test_pileup_skips_invalid_cigar() {
+        let mut pileup = Pileup::new(10);
+        // CIGAR covers more bases than the read
+        let cigar = [Op::new(Kind::SoftClip, 2), Op::new(Kind::Match, 6)];
+        assert!(!pileup.add(2, b"ACGTAC", &cigar));
+        assert_eq!(pileup, Pileup::new(10));
+        let cigar = [Op::new(Kind::Match, 6)];
+        assert!(pileup.add(2, b"ACGTAC", &cigar));
+    }
+
+    #[test]
+    fn test_select_locus_consensus() {
+        let consensus = vec![
+            (String::from("uce-1_p1"), String::from("ACGNNN")),
+            (String::from("uce-1_p2"), String::from("ACGTAC")),
+            (String::from("uce-2_p1"), String::from("GGCC")),
+        ];
+        let loci = select_locus_consensus(consensus, "^(uce|locus)-\\d+");
+        assert_eq!(loci.len(), 2);
+        assert_eq!(loci["uce-1"], "ACGTAC");
+        assert_eq!(loci["uce-2"], "GGCC");
+    }
+}
@@ -0,0 +1,335 @@
+//! Runner for Exonerate protein-guided exon extraction.
+//!
+//! Reference sequences are used as queries and contigs as targets.
+//! Protein references are mapped using the protein2genome model
+//! and nucleotide (CDS) references using the coding2genome model.
+//! Only the coding part of the best hit is kept for each locus.
+//!
+//! Documentation for Exonerate can be found [here](https://www.ebi.ac.uk/about/vertebrate-genomics/software/exonerate-manual)
+use std::{
+    collections::{BTreeMap, HashMap},
+    error::Error,
+    fmt::Display,
+    fs,
+    path::{Path, PathBuf},
+    process::{Command, Output},
+    sync::mpsc,
+};
+
+use anyhow::Context;
+use bio::io::fasta;
+use colored::Colorize;
+use rayon::prelude::*;
+
+use crate::{
+    core::deps::{exonerate::EXONERATE_EXE, DepMetadata},
+    helper::common,
+    parse_override_args,
+};
+
+use super::{
+    configs::{ContigFiles, ReferenceFile},
+    reports::ExonMappingReport,
+};
+
+/// Default Exonerate parameters.
+/// Keep the best hit of each reference with
+/// at least 30% of the maximum possible score.
+pub const DEFAULT_EXONERATE_PARAMS: &str = "--bestn 1 --percent 30";
+
+const EXONERATE_RESULT_DIR: &str = "exonerate_results";
+const EXONERATE_RESULT_EXT: &str = "txt";
+/// Prefix of the custom output header line
+const RYO_PREFIX: &str = "#ullar";
+/// Query id, target id, raw score, target strand,
+/// and the coding sequence of the target.
+const RYO_FORMAT: &str = "#ullar\t%qi\t%ti\t%s\t%tS\n%tcs\n";
+/// Minimum fraction of nucleotide characters
+/// to treat a reference as CDS sequences.
+const MIN_NUCLEOTIDE_FRACTION: f64 = 0.9;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExonerateModel {
+    Protein2Genome,
+    Coding2Genome,
+}
+
+impl Display for ExonerateModel {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ExonerateModel::Protein2Genome => write!(f, "protein2genome"),
+            ExonerateModel::Coding2Genome => write!(f, "coding2genome"),
+        }
+    }
+}
+
+impl ExonerateModel {
+    /// Choose the model based on the reference alphabet.
+    pub fn from_reference(reference_path: &Path) -> Result<Self, Box<dyn Error>> {
+        let reader = fasta::Reader::from_file(reference_path)?;
+        let mut total = 0;
+        let mut nucleotides = 0;
+        for record in reader.records() {
+            let record = record?;
+            total += record.seq().len();
+            nucleotides += record
+                .seq()
+                .iter()
+                .filter(|base| b"ACGTUN-".contains(&base.to_ascii_uppercase()))
+                .count();
+        }
+        if total == 0 {
+            return Err("Reference sequences are empty".into());
+        }
+        if nucleotides as f64 / total as f64 >= MIN_NUCLEOTIDE_FRACTION {
+            Ok(ExonerateModel::Coding2Genome)
+        } else {
+            Ok(ExonerateModel::Protein2Genome)
+        }
+    }
+}
+
+/// A hit parsed from the custom Exonerate output
+#[derive(Debug, Clone, PartialEq)]
+pub struct ExonerateHit {
+    pub query_name: String,
+    pub target_name: String,
+    pub score: usize,
+    pub target_strand: char,
+    /// In-frame coding sequence of the target
+    pub coding_sequence: String,
+}
+
+impl ExonerateHit {
+    /// Parse hits written using the custom output format.
+    /// Other Exonerate output lines are ignored.
+    pub fn parse(content: &str) -> Result<Vec<Self>, Box<dyn Error>> {
+        let mut hits: Vec<Self> = Vec::new();
+        let mut in_record = false;
+        for line in content.lines() {
+            if let Some(header) = line.strip_prefix(RYO_PREFIX) {
+                hits.push(Self::parse_header(header)?);
+                in_record = true;
+            } else if in_record && line.chars().all(|c| c.is_ascii_alphabetic()) {
+                if let Some(hit) = hits.last_mut() {
+                    hit.coding_sequence.push_str(line.trim());
+                }
+            } else {
+                in_record = false;
+            }
+        }
+        hits.iter_mut().for_each(|hit| hit.trim_partial_codon());
+        Ok(hits)
+    }
+
+    fn parse_header(header: &str) -> Result<Self, Box<dyn Error>> {
+        let fields: Vec<&str> = header.trim().split('\t').collect();
+        if fields.len() < 4 {
+            return Err(format!("Invalid Exonerate output: {}", header).into());
+        }
+        Ok(Self {
+            query_name: fields[0].to_string(),
+            target_name: fields[1].to_string(),
+            score: fields[2].parse()?,
+            target_strand: fields[3].chars().next().unwrap_or('+'),
+            coding_sequence: String::new(),
+        })
+    }
+
+    // Frameshifts may leave an incomplete codon at the end
+    fn trim_partial_codon(&mut self) {
+        let len = self.coding_sequence.len() / 3 * 3;
+        self.coding_sequence.truncate(len);
+    }
+}
+
+/// Exonerate runner
+/// Handle IO parsing and execution of Exonerate
+pub struct ExonerateMapping<'a> {
+    /// Protein or CDS reference sequences
+    pub reference_data: &'a ReferenceFile,
+    pub output_dir: &'a Path,
+    /// Override arguments for Exonerate
+    pub dependency: &'a DepMetadata,
+}
+
+impl<'a> ExonerateMapping<'a> {
+    pub fn new(
+        reference_data: &'a ReferenceFile,
+        output_dir: &'a Path,
+        dependency: &'a DepMetadata,
+    ) -> Self {
+        Self {
+            reference_data,
+            output_dir,
+            dependency,
+        }
+    }
+
+    /// Map reference sequences to the contigs of each sample.
+    /// Keep the coding sequence of the best hit for each locus.
+    pub fn map_to_exons(
+        &self,
+        contigs: &[ContigFiles],
+    ) -> Result<Vec<ExonMappingReport>, Box<dyn Error>> {
+        let reference_path = self
+            .reference_data
+            .metadata
+            .parent_dir
+            .join(&self.reference_data.metadata.file_name);
+        let model = ExonerateModel::from_reference(&reference_path)?;
+        log::info!("Mapping reference to contigs using {}", model);
+        let progress_bar = common::init_progress_bar(contigs.len() as u64);
+        let msg = "samples";
+        progress_bar.set_message(msg);
+        let (tx, rx) = mpsc::channel();
+        contigs.par_iter().for_each_with(tx, |tx, contig| {
+            match self.run_exonerate(&reference_path, contig, model) {
+                Ok(report) => tx.send(report).expect("Failed to send data"),
+                Err(e) => {
+                    let msg = format!("Failed to map contig {}: {}", contig.sample_name.red(), e);
+                    log::error!("{}", msg);
+                }
+            }
+            progress_bar.inc(1);
+        });
+        let reports = rx.iter().collect::<Vec<ExonMappingReport>>();
+        progress_bar.finish_with_message(format!("{} {}\n", "✔".green(), msg));
+        Ok(reports)
+    }
+
+    fn run_exonerate(
+        &self,
+        reference_path: &Path,
+        contig: &ContigFiles,
+        model: ExonerateModel,
+    ) -> Result<ExonMappingReport, Box<dyn Error>> {
+        let contig_path = contig.metadata.parent_dir.join(&contig.metadata.file_name);
+        let output = self.execute_exonerate(reference_path, &contig_path, model)?;
+        if !output.status.success() {
+            return Err(format!(
+                "Exonerate execution failed: {}",
+                String::from_utf8_lossy(&output.stderr)
+            )
+            .into());
+        }
+        let output_path = self.create_output_path(&contig.sample_name)?;
+        fs::write(&output_path, &output.stdout).with_context(|| {
+            format!(
+                "Failed to write Exonerate output to file: {}",
+                output_path.display()
+            )
+        })?;
+        let hits = ExonerateHit::parse(&String::from_utf8_lossy(&output.stdout))?;
+        let mut report = ExonMappingReport::new(&contig.sample_name, output_path);
+        report.exons = self.select_best_hits(&hits);
+        Ok(report)
+    }
+
+    fn execute_exonerate(
+        &self,
+        reference_path: &Path,
+        contig_path: &Path,
+        model: ExonerateModel,
+    ) -> Result<Output, Box<dyn Error>> {
+        let executable = self.dependency.get_executable(EXONERATE_EXE);
+        let mut cmd = Command::new(executable);
+        cmd.arg("--model").arg(model.to_string());
+        cmd.arg("--query").arg(reference_path);
+        cmd.arg("--target").arg(contig_path);
+        cmd.arg("--showalignment").arg("no");
+        cmd.arg("--showvulgar").arg("no");
+        cmd.arg("--ryo").arg(RYO_FORMAT);
+        match &self.dependency.override_args {
+            Some(params) => parse_override_args!(cmd, params),
+            None => parse_override_args!(cmd, DEFAULT_EXONERATE_PARAMS),
+        };
+        let output = cmd.output().with_context(|| {
+            format!(
+                "Failed to execute Exonerate. Do {} to see exonerate executable exists.",
+                "ullar deps check".yellow()
+            )
+        })?;
+        Ok(output)
+    }
+
+    // Keep the highest scoring hit of each locus
+    fn select_best_hits(&self, hits: &[ExonerateHit]) -> BTreeMap<String, String> {
+        let re =
+            regex::Regex::new(&self.reference_data.name_regex).expect("Failed to create regex");
+        let mut best_hits: HashMap<String, &ExonerateHit> = HashMap::new();
+        hits.iter()
+            .filter(|hit| !hit.coding_sequence.is_empty())
+            .for_each(|hit| {
+                let locus = match re.captures(&hit.query_name) {
+                    Some(capture) => capture[0].to_string(),
+                    None => hit.query_name.to_string(),
+                };
+                match best_hits.get(&locus) {
+                    Some(best) if best.score >= hit.score => (),
+                    _ => {
+                        best_hits.insert(locus, hit);
+                    }
+                }
+            });
+        best_hits
+            .into_iter()
+            .map(|(locus, hit)| (locus, hit.coding_sequence.to_string()))
+            .collect()
+    }
+
+    fn create_output_path(&self, sample_name: &str) -> Result<PathBuf, Box<dyn Error>> {
+        let output_dir = self.output_dir.join(EXONERATE_RESULT_DIR);
+        fs::create_dir_all(&output_dir).with_context(|| {
+            format!(
+                "Failed to create Exonerate output directory: {}",
+                output_dir.display()
+            )
+        })?;
+        Ok(output_dir
+            .join(sample_name)
+            .with_extension(EXONERATE_RESULT_EXT))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_exonerate_output() {
+        let output = "Command line: [exonerate --model protein2genome]\n\
+            Hostname: [localhost]\n\
+            #ullar\tlocus-1_p1\tNODE_1_length_900_cov_10.5\t512\t-\n\
+            ATGGCTAAA\n\
+            GGCTTA\n\
+            #ullar\tlocus-2_p1\tNODE_2_length_500_cov_8.0\t230\t+\n\
+            ATGCCCGGGTT\n\
+            -- completed exonerate analysis\n";
+        let hits = ExonerateHit::parse(output).unwrap();
+        assert_eq!(hits.len(), 2);
+        assert_eq!(hits[0].query_name, "locus-1_p1");
+        assert_eq!(hits[0].score, 512);
+        assert_eq!(hits[0].target_strand, '-');
+        assert_eq!(hits[0].coding_sequence, "ATGGCTAAAGGCTTA");
+        // Incomplete codon is trimmed
+        assert_eq!(hits[1].coding_sequence, "ATGCCCGGG");
+    }
+
+    #[test]
+    fn test_exonerate_model() {
+        let dir = tempdir::TempDir::new("exonerate").unwrap();
+        let protein = dir.path().join("protein.fasta");
+        fs::write(&protein, ">locus-1\nMAKGLLVEWRSTQ\n").unwrap();
+        let cds = dir.path().join("cds.fasta");
+        fs::write(&cds, ">locus-1\nATGGCTAAAGGCTTA\n").unwrap();
+        assert_eq!(
+            ExonerateModel::from_reference(&protein).unwrap(),
+            ExonerateModel::Protein2Genome
+        );
+        assert_eq!(
+            ExonerateModel::from_reference(&cds).unwrap(),
+            ExonerateModel::Coding2Genome
+        );
+    }
+}
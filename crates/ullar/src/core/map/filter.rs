@@ -0,0 +1,358 @@
+//! Filter assembled contigs before mapping.
+//!
+//! Contigs are filtered by length and by k-mer coverage
+//! parsed from the contig headers. Supports SPAdes headers
+//! (e.g., `NODE_1_length_100_cov_5.0`) and MEGAHIT headers
+//! (e.g., `k141_1 flag=1 multi=2.0 len=100`).
+//! Dropped contigs are written to a separate file for auditing.
+
+use std::{
+    error::Error,
+    fmt::Display,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use bio::io::fasta;
+use colored::Colorize;
+use comfy_table::Table;
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::{cli::commands::map::ContigFilterArgs, helper::files::FileMetadata};
+
+use super::configs::ContigFiles;
+
+pub const DEFAULT_FILTERED_CONTIG_DIR: &str = "filtered_contigs";
+pub const DEFAULT_DROPPED_CONTIG_DIR: &str = "dropped_contigs";
+pub const CONTIG_FILTER_SUMMARY: &str = "contig_filter_summary.csv";
+
+const SPADES_COVERAGE_TAG: &str = "_cov_";
+const MEGAHIT_COVERAGE_TAG: &str = "multi=";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DropReason {
+    Length,
+    Coverage,
+    CoverageOutlier,
+}
+
+impl Display for DropReason {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DropReason::Length => write!(f, "length"),
+            DropReason::Coverage => write!(f, "coverage"),
+            DropReason::CoverageOutlier => write!(f, "coverage_outlier"),
+        }
+    }
+}
+
+/// Contig filtering thresholds.
+/// Filters that are not set are skipped.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct ContigFilter {
+    /// Minimum contig length
+    pub min_length: Option<usize>,
+    /// Minimum k-mer coverage
+    pub min_coverage: Option<f64>,
+    /// Maximum fold difference from the sample median coverage.
+    /// Drop contigs with coverage above `median * fold`
+    /// or below `median / fold`.
+    pub max_coverage_fold: Option<f64>,
+}
+
+impl ContigFilter {
+    /// Returns None if no filter is set
+    pub fn from_arg(args: &ContigFilterArgs) -> Option<Self> {
+        let filter = Self {
+            min_length: args.min_contig_length,
+            min_coverage: args.min_contig_coverage,
+            max_coverage_fold: args.max_coverage_fold,
+        };
+        if filter.is_empty() {
+            None
+        } else {
+            Some(filter)
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.min_length.is_none() && self.min_coverage.is_none() && self.max_coverage_fold.is_none()
+    }
+
+    /// Check a contig against the thresholds.
+    /// Returns the reason the contig is dropped.
+    /// Coverage filters are skipped if the coverage is unknown.
+    pub fn check(
+        &self,
+        length: usize,
+        coverage: Option<f64>,
+        median_coverage: Option<f64>,
+    ) -> Option<DropReason> {
+        if let Some(min_length) = self.min_length {
+            if length < min_length {
+                return Some(DropReason::Length);
+            }
+        }
+        let coverage = coverage?;
+        if let Some(min_coverage) = self.min_coverage {
+            if coverage < min_coverage {
+                return Some(DropReason::Coverage);
+            }
+        }
+        if let (Some(fold), Some(median)) = (self.max_coverage_fold, median_coverage) {
+            if coverage > median * fold || coverage < median / fold {
+                return Some(DropReason::CoverageOutlier);
+            }
+        }
+        None
+    }
+
+    /// Filter the contigs of a sample.
+    /// Kept contigs are written to `filtered_contigs/<sample>.fasta`
+    /// and dropped contigs to `dropped_contigs/<sample>.fasta`
+    /// inside the output directory.
+    pub fn filter(
+        &self,
+        contig: &ContigFiles,
+        output_dir: &Path,
+    ) -> Result<(ContigFiles, ContigFilterStats), Box<dyn Error>> {
+        let input_path = contig.metadata.parent_dir.join(&contig.metadata.file_name);
+        let records = fasta::Reader::from_file(&input_path)?
+            .records()
+            .collect::<Result<Vec<fasta::Record>, _>>()?;
+        let coverages: Vec<Option<f64>> = records
+            .iter()
+            .map(|r| parse_coverage(r.id(), r.desc()))
+            .collect();
+        // Use contigs passing the length filter to compute the median.
+        // Short contigs tend to have noisy coverage.
+        let median_coverage = median(
+            records
+                .iter()
+                .zip(coverages.iter())
+                .filter(|(r, _)| self.min_length.is_none_or(|min| r.seq().len() >= min))
+                .filter_map(|(_, cov)| *cov)
+                .collect(),
+        );
+
+        let filtered_dir = output_dir.join(DEFAULT_FILTERED_CONTIG_DIR);
+        let dropped_dir = output_dir.join(DEFAULT_DROPPED_CONTIG_DIR);
+        fs::create_dir_all(&filtered_dir)?;
+        fs::create_dir_all(&dropped_dir)?;
+        let file_name = format!("{}.fasta", contig.sample_name);
+        let filtered_path = filtered_dir.join(&file_name);
+        let dropped_path = dropped_dir.join(&file_name);
+        let mut kept_writer = fasta::Writer::to_file(&filtered_path)?;
+        let mut dropped_writer = fasta::Writer::to_file(&dropped_path)?;
+
+        let mut stats = ContigFilterStats::new(&contig.sample_name, records.len());
+        stats.median_coverage = median_coverage;
+        for (record, coverage) in records.iter().zip(coverages) {
+            match self.check(record.seq().len(), coverage, median_coverage) {
+                Some(reason) => {
+                    // Keep the drop reason in the header for auditing
+                    let desc = match record.desc() {
+                        Some(desc) => format!("{} reason={}", desc, reason),
+                        None => format!("reason={}", reason),
+                    };
+                    dropped_writer.write(record.id(), Some(&desc), record.seq())?;
+                    stats.add_dropped(reason);
+                }
+                None => {
+                    kept_writer.write_record(record)?;
+                    stats.kept_contigs += 1;
+                }
+            }
+        }
+        kept_writer.flush()?;
+        dropped_writer.flush()?;
+
+        let filtered = ContigFiles {
+            sample_name: contig.sample_name.to_string(),
+            metadata: FileMetadata::from_path(&filtered_path),
+        };
+        Ok((filtered, stats))
+    }
+}
+
+/// Parse k-mer coverage from SPAdes or MEGAHIT contig headers
+pub fn parse_coverage(id: &str, desc: Option<&str>) -> Option<f64> {
+    if let Some(pos) = id.rfind(SPADES_COVERAGE_TAG) {
+        let value = &id[pos + SPADES_COVERAGE_TAG.len()..];
+        let value = value.split('_').next().unwrap_or_default();
+        return value.parse().ok();
+    }
+    desc?
+        .split_whitespace()
+        .find_map(|field| field.strip_prefix(MEGAHIT_COVERAGE_TAG))
+        .and_then(|value| value.parse().ok())
+}
+
+fn median(mut values: Vec<f64>) -> Option<f64> {
+    if values.is_empty() {
+        return None;
+    }
+    values.sort_by(|a, b| a.total_cmp(b));
+    let mid = values.len() / 2;
+    if values.len() % 2 == 0 {
+        Some((values[mid - 1] + values[mid]) / 2.0)
+    } else {
+        Some(values[mid])
+    }
+}
+
+/// Contig filtering statistics of a sample
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct ContigFilterStats {
+    pub sample_name: String,
+    pub total_contigs: usize,
+    pub kept_contigs: usize,
+    pub dropped_length: usize,
+    pub dropped_coverage: usize,
+    pub dropped_coverage_outlier: usize,
+    /// Empty if the headers do not contain coverage
+    pub median_coverage: Option<f64>,
+}
+
+impl ContigFilterStats {
+    fn new(sample_name: &str, total_contigs: usize) -> Self {
+        Self {
+            sample_name: sample_name.to_string(),
+            total_contigs,
+            ..Default::default()
+        }
+    }
+
+    fn add_dropped(&mut self, reason: DropReason) {
+        match reason {
+            DropReason::Length => self.dropped_length += 1,
+            DropReason::Coverage => self.dropped_coverage += 1,
+            DropReason::CoverageOutlier => self.dropped_coverage_outlier += 1,
+        }
+    }
+}
+
+/// Cross-sample summary of the contig filtering step
+pub struct ContigFilterSummary<'a> {
+    /// Contig mapping output directory
+    pub output_dir: &'a Path,
+    pub stats: Vec<ContigFilterStats>,
+}
+
+impl<'a> ContigFilterSummary<'a> {
+    /// Filter the contigs of each sample.
+    /// Samples that fail to be filtered use the unfiltered contigs.
+    pub fn filter(
+        output_dir: &'a Path,
+        filter: &ContigFilter,
+        contigs: &[ContigFiles],
+    ) -> (Vec<ContigFiles>, Self) {
+        let results: Vec<(ContigFiles, Option<ContigFilterStats>)> = contigs
+            .par_iter()
+            .map(|contig| match filter.filter(contig, output_dir) {
+                Ok((filtered, stats)) => (filtered, Some(stats)),
+                Err(e) => {
+                    log::warn!(
+                        "{} Failed to filter contigs of {}: {}. Using unfiltered contigs.",
+                        "Warning:".yellow(),
+                        contig.sample_name,
+                        e
+                    );
+                    (contig.clone(), None)
+                }
+            })
+            .collect();
+        let (filtered, stats): (Vec<ContigFiles>, Vec<Option<ContigFilterStats>>) =
+            results.into_iter().unzip();
+        let summary = Self {
+            output_dir,
+            stats: stats.into_iter().flatten().collect(),
+        };
+        (filtered, summary)
+    }
+
+    pub fn write(&self) -> Result<PathBuf, Box<dyn Error>> {
+        fs::create_dir_all(self.output_dir)?;
+        let output_path = self.output_dir.join(CONTIG_FILTER_SUMMARY);
+        let mut writer = csv::Writer::from_path(&output_path)?;
+        for stats in &self.stats {
+            writer.serialize(stats)?;
+        }
+        writer.flush()?;
+        Ok(output_path)
+    }
+
+    pub fn log_table(&self) {
+        if self.stats.is_empty() {
+            return;
+        }
+        let mut table = Table::new();
+        table.set_header(vec![
+            "Sample",
+            "Contigs",
+            "Kept",
+            "Short",
+            "Low coverage",
+            "Coverage outliers",
+            "Median coverage",
+        ]);
+        for stats in &self.stats {
+            table.add_row(vec![
+                stats.sample_name.to_string(),
+                stats.total_contigs.to_string(),
+                stats.kept_contigs.to_string(),
+                stats.dropped_length.to_string(),
+                stats.dropped_coverage.to_string(),
+                stats.dropped_coverage_outlier.to_string(),
+                stats
+                    .median_coverage
+                    .map_or(String::from("-"), |cov| format!("{:.2}", cov)),
+            ]);
+        }
+        log::info!("\n{}", table);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_coverage() {
+        let spades = parse_coverage("NODE_1_length_100_cov_5.5", None);
+        assert_eq!(spades, Some(5.5));
+        let megahit = parse_coverage("k141_1", Some("flag=1 multi=2.0000 len=100"));
+        assert_eq!(megahit, Some(2.0));
+        assert_eq!(parse_coverage("contig_1", None), None);
+    }
+
+    #[test]
+    fn test_contig_filter() {
+        let output_dir = tempdir::TempDir::new("contig_filter").unwrap();
+        let path = Path::new("tests/data/contigs/sample_contigs.fasta");
+        let contig = ContigFiles {
+            sample_name: String::from("sample_1"),
+            metadata: FileMetadata::from_path(path),
+        };
+        let filter = ContigFilter {
+            min_length: Some(4),
+            min_coverage: Some(2.5),
+            max_coverage_fold: Some(1.3),
+        };
+        let (filtered, stats) = filter.filter(&contig, output_dir.path()).unwrap();
+        // Median of the contigs passing the length filter: 3.5
+        assert_eq!(stats.median_coverage, Some(3.5));
+        assert_eq!(stats.total_contigs, 5);
+        assert_eq!(stats.kept_contigs, 2);
+        assert_eq!(stats.dropped_length, 1);
+        assert_eq!(stats.dropped_coverage, 1);
+        assert_eq!(stats.dropped_coverage_outlier, 1);
+        assert_eq!(filtered.sample_name, "sample_1");
+        assert!(output_dir
+            .path()
+            .join(DEFAULT_DROPPED_CONTIG_DIR)
+            .join("sample_1.fasta")
+            .is_file());
+    }
+}
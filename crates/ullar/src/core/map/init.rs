@@ -10,16 +10,32 @@ use colored::Colorize;
 
 use crate::{
     cli::commands::{common::CommonInitArgs, map::MapInitArgs},
-    core::map::ContigMapping,
+    core::{
+        map::{ContigMapping, ReadMapping},
+        project::manifest::{ProjectManifest, TaskStatus},
+    },
     helper::{
         common::{self, PrettyHeader},
         configs::{CONFIG_EXTENSION_TOML, DEFAULT_CONFIG_DIR},
-        files::PathCheck,
+        fastq::{FastqInput, ReadAssignmentStrategy},
+        files::{FileFinder, PathCheck},
+    },
+    types::{
+        map::{Aligner, MappingQueryFormat},
+        reads::{ReadAssignment, SampleNameFormat},
+        SupportedFormats, Task,
     },
-    types::map::MappingQueryFormat,
 };
 
-use super::configs::{ContigInput, ContigMappingConfig, SampleNameSource};
+use super::{
+    bam::BamFilter,
+    configs::{
+        ContigInput, ContigMappingConfig, ReadMappingConfig, ReferenceFile, SampleNameSource,
+        SequenceOutput, DEFAULT_READ_MAPPING_CONFIG, DEFAULT_REF_MAPPING_CONFIG,
+    },
+    filter::ContigFilter,
+    native::NativeParams,
+};
 
 pub struct InitMappingConfig<'a> {
     /// Query directory containing query sequences
@@ -37,12 +53,26 @@ pub struct InitMappingConfig<'a> {
     /// Lastz output format
     /// (default: "general")
     pub reference_type: &'a str,
+    /// Contig aligner
+    pub aligner: Aligner,
+    /// BED or GFF file of target regions
+    pub regions_path: Option<&'a Path>,
     /// Reference regex names
     pub refname_regex: &'a str,
     /// Reference contain multiple references
     pub single_ref: bool,
     /// Sample name regex
     pub sample_name_regex: &'a str,
+    /// Contig filtering thresholds
+    pub contig_filter: Option<ContigFilter>,
+    /// Percent of samples to exclude paralogous loci
+    pub max_paralog_percent: Option<f64>,
+    /// Flank length of the matched region
+    pub flank_length: Option<usize>,
+    /// Filter of contig alignments written to BAM files
+    pub bam_output: Option<BamFilter>,
+    /// Format of the mapped loci sequences
+    pub sequence_output: SequenceOutput,
     pub common: &'a CommonInitArgs,
 }
 
@@ -56,9 +86,23 @@ impl<'a> InitMappingConfig<'a> {
             name_source: &args.name_source,
             config_name: &args.config_name,
             reference_type: &args.reference_type,
+            aligner: args.aligner.parse().expect("Invalid aligner"),
+            regions_path: args.regions.as_deref(),
             refname_regex: &args.re_reference,
             single_ref: args.single_ref,
             sample_name_regex: &args.re_sample,
+            contig_filter: ContigFilter::from_arg(&args.contig_filter),
+            max_paralog_percent: args.max_paralog_percent,
+            flank_length: args.flank,
+            bam_output: args
+                .bam
+                .then(|| BamFilter::new(args.min_identity.unwrap_or_default())),
+            sequence_output: SequenceOutput::new(
+                args.output_format
+                    .parse()
+                    .expect("Invalid sequence output format"),
+                args.gzip,
+            ),
             common: &args.common,
         }
     }
@@ -66,18 +110,19 @@ impl<'a> InitMappingConfig<'a> {
     pub fn init(&self) {
         self.log_input();
         let config_path = Path::new(DEFAULT_CONFIG_DIR)
-            .join(self.config_name)
+            .join(self.get_config_name())
             .with_extension(CONFIG_EXTENSION_TOML);
         PathCheck::new(&config_path).prompt_exists(false);
         let spinner = common::init_spinner();
         spinner.set_message("Writing mapping config");
         let config_path = self.write_config();
         match config_path {
-            Ok((path, config)) => {
+            Ok((path, sample_counts)) => {
                 spinner
                     .finish_with_message(format!("{} Finished writing config file\n", "✔".green()));
                 self.log_output(&path);
-                self.log_contig_output(&config);
+                log::info!("{:18}: {}", "Sample counts", sample_counts);
+                ProjectManifest::record(self.get_task(), TaskStatus::Initialized, Some(&path));
                 if self.common.autorun {
                     let footer = PrettyHeader::new();
                     footer.get_section_footer();
@@ -99,32 +144,104 @@ impl<'a> InitMappingConfig<'a> {
         log::info!("{}", header.cyan());
         log::info!("");
 
-        let runner = ContigMapping::from_config_path(config_path);
-        runner.map();
+        match self.query_format {
+            MappingQueryFormat::Contig => {
+                ContigMapping::from_config_path(config_path)
+                    .map()
+                    .expect("Failed to map contigs");
+            }
+            MappingQueryFormat::Fastq => {
+                ReadMapping::from_config_path(config_path)
+                    .map()
+                    .expect("Failed to map reads");
+            }
+        }
+    }
+
+    fn get_task(&self) -> Task {
+        match self.query_format {
+            MappingQueryFormat::Contig => Task::ContigMapping,
+            MappingQueryFormat::Fastq => Task::ReadMapping,
+        }
+    }
+
+    // Use the read mapping config name
+    // unless users specify a different name.
+    fn get_config_name(&self) -> &str {
+        match self.query_format {
+            MappingQueryFormat::Fastq if self.config_name == DEFAULT_REF_MAPPING_CONFIG => {
+                DEFAULT_READ_MAPPING_CONFIG
+            }
+            _ => self.config_name,
+        }
     }
 
-    fn write_config(&self) -> Result<(PathBuf, ContigMappingConfig), Box<dyn Error>> {
+    /// Write the config file.
+    /// Returns the config path and the sample counts.
+    fn write_config(&self) -> Result<(PathBuf, usize), Box<dyn Error>> {
         match self.query_format {
             MappingQueryFormat::Contig => {
                 let (path, config) = self.write_contig_config()?;
-                Ok((path, config))
+                Ok((path, config.input.file_counts))
             }
-            MappingQueryFormat::Fastq => unimplemented!(),
+            MappingQueryFormat::Fastq => {
+                let (path, config) = self.write_read_config()?;
+                Ok((path, config.samples.len()))
+            }
+        }
+    }
+
+    fn write_read_config(&self) -> Result<(PathBuf, ReadMappingConfig), Box<dyn Error>> {
+        // Cleaned reads are stored in sample directories
+        let files = match self.query_dir {
+            Some(dir) => FileFinder::new(dir, &SupportedFormats::Fastq).find(true)?,
+            None => self.get_query_paths(),
+        };
+        if files.is_empty() {
+            return Err("No reads found in the input. Please, check input is FASTQ".into());
         }
+        let samples = ReadAssignment::new(&files, &SampleNameFormat::default()).assign();
+        let input_dir = match self.query_dir {
+            Some(dir) => dir.to_path_buf(),
+            None => files[0].parent().map(Path::to_path_buf).unwrap_or_default(),
+        };
+        let input = FastqInput::new(
+            &input_dir,
+            samples.len(),
+            files.len(),
+            ReadAssignmentStrategy::default(),
+        );
+        let reference_type = self.reference_type.parse().expect("Invalid reference type");
+        let reference = ReferenceFile::new(self.refname_regex, reference_type, self.single_ref);
+        let mut config = ReadMappingConfig::new(input, reference, samples);
+        let output_path = config.to_toml(self.get_config_name(), self.reference_path)?;
+        Ok((output_path, config))
     }
 
     fn write_contig_config(&self) -> Result<(PathBuf, ContigMappingConfig), Box<dyn Error>> {
         let name_source = self.get_sample_name_source();
-        let input = ContigInput::new(name_source);
+        let mut input = ContigInput::new(name_source);
+        input.aligner = self.aligner.clone();
         let output_format = self
             .reference_type
             .parse()
             .expect("Invalid lastz output format");
         let mut config =
             ContigMappingConfig::init(input, self.refname_regex, output_format, self.single_ref);
+        config.contig_filter = self.contig_filter.clone();
+        config.max_paralog_percent = self.max_paralog_percent;
+        config.flank_length = self.flank_length;
+        config.bam_output = self.bam_output.clone();
+        config.sequence_output = self.sequence_output.clone();
+        if self.aligner == Aligner::Native {
+            config.native_params = Some(NativeParams::default());
+        }
+        if let Some(regions) = self.regions_path {
+            config.sequence_reference.set_target_regions(regions);
+        }
         match self.query_dir {
             Some(dir) => config.from_contig_dir(dir),
-            None => config.from_contig_paths(&self.get_contig_paths()),
+            None => config.from_contig_paths(&self.get_query_paths()),
         }
         if config.contigs.is_empty() {
             return Err(
@@ -150,11 +267,11 @@ impl<'a> InitMappingConfig<'a> {
         source
     }
 
-    fn get_contig_paths(&self) -> Vec<PathBuf> {
+    fn get_query_paths(&self) -> Vec<PathBuf> {
         match self.query_paths {
             Some(paths) => {
                 if paths.is_empty() {
-                    panic!("No contig files found in input");
+                    panic!("No query files found in input");
                 }
                 paths.to_vec()
             }
@@ -207,8 +324,4 @@ impl<'a> InitMappingConfig<'a> {
                 .expect("Failed parsing file")
         );
     }
-
-    fn log_contig_output(&self, config: &ContigMappingConfig) {
-        log::info!("{:18}: {}", "Sample counts", config.input.file_counts);
-    }
 }
@@ -20,8 +20,9 @@ use crate::helper::common;
 use crate::types::map::{LastzNameParse, LastzOutputFormat};
 use crate::{get_file_stem, parse_override_args};
 
+use super::bam::{self, BamFilter, PairwiseAlignment};
 use super::configs::{ContigFiles, ReferenceFile};
-use super::reports::MappingData;
+use super::reports::{BamMappingReport, MappingData};
 
 /// Default lastz parameters. We use the following parameters by default:
 /// 1. --nogfextend to disable gapped extension
@@ -34,6 +35,13 @@ pub const DEFAULT_OUTPUT_EXT: &str = "csv";
 const LASTZ_RESULT_DIR: &str = "lastz_results";
 const LASTZ_RESULT_SUFFIX: &str = "lastz";
 const LASTZ_MAF_EXT: &str = "maf";
+/// Default general output fields plus the aligned texts
+/// used to write BAM files
+const GENERAL_FIELDS_WITH_TEXT: &str = "score,name1,strand1,size1,zstart1,end1,name2,strand2,\
+    size2,zstart2,end2,identity,idPct,coverage,covPct,text1,text2";
+
+/// Reference sequences and filter of BAM output
+type BamOutput<'a> = (&'a [(String, usize)], &'a BamFilter);
 
 pub enum RefNameRegex {
     Default,
@@ -49,6 +57,8 @@ pub struct LastzMapping<'a> {
     pub output_dir: &'a Path,
     /// Override arguments for Lastz
     pub dependency: &'a DepMetadata,
+    /// Also write the alignments to sorted BAM files if set
+    pub bam_output: Option<&'a BamFilter>,
 }
 
 impl<'a> LastzMapping<'a> {
@@ -61,9 +71,15 @@ impl<'a> LastzMapping<'a> {
             reference_data,
             output_dir,
             dependency,
+            bam_output: None,
         }
     }
 
+    pub fn with_bam_output(mut self, filter: Option<&'a BamFilter>) -> Self {
+        self.bam_output = filter;
+        self
+    }
+
     /// Map contig to reference sequence using Lastz.
     /// We use target as the reference sequence
     ///     and query as the contig sequence.
@@ -73,18 +89,28 @@ impl<'a> LastzMapping<'a> {
     ///   multiple reference sequences or vice versa.
     /// It is just the way genomic sequences behave.
     /// We don't want those duplicates. We will only keep the best match.
+    /// BAM files are written from the same Lastz run if set.
     pub fn map_to_probes(
         &self,
         contigs: &[ContigFiles],
         output_format: &LastzOutputFormat,
-    ) -> Result<Vec<MappingData>, Box<dyn Error>> {
+    ) -> Result<(Vec<MappingData>, Vec<BamMappingReport>), Box<dyn Error>> {
         log::info!("Mapping contigs to reference sequence");
+        let references = self.parse_bam_references()?;
+        let bam_output = self.get_bam_output(&references);
+        // BAM records are built from the aligned texts
+        let general_with_text = LastzOutputFormat::General(GENERAL_FIELDS_WITH_TEXT.to_string());
+        let output_format = match bam_output {
+            Some(_) => &general_with_text,
+            None => output_format,
+        };
         let progress_bar = common::init_progress_bar(contigs.len() as u64);
         let msg = "Samples";
         progress_bar.set_message(msg);
         let (tx, rx) = mpsc::channel();
         contigs.par_iter().for_each_with(tx, |tx, contig| {
-            let data = self.run_lastz_general(contig, &contig.sample_name, output_format);
+            let data =
+                self.run_lastz_general(contig, &contig.sample_name, output_format, bam_output);
             match data {
                 Ok(data) => {
                     tx.send(data).expect("Failed to send data");
@@ -96,24 +122,28 @@ impl<'a> LastzMapping<'a> {
             }
             progress_bar.inc(1);
         });
-        let data = rx.iter().collect::<Vec<MappingData>>();
+        let (data, bam_reports): (Vec<MappingData>, Vec<Option<BamMappingReport>>) =
+            rx.iter().unzip();
         progress_bar.finish_with_message(format!("{} {}\n", "✔".green(), msg));
-        Ok(data)
+        Ok((data, sort_bam_reports(bam_reports)))
     }
 
     /// Map contig to reference sequence using Lastz.
     /// Export to MAF format for downstream analysis.
+    /// BAM files are written from the same Lastz run if set.
     pub fn map_to_reference(
         &self,
         contigs: &[ContigFiles],
-    ) -> Result<Vec<PathBuf>, Box<dyn Error>> {
+    ) -> Result<(Vec<PathBuf>, Vec<BamMappingReport>), Box<dyn Error>> {
         log::info!("Mapping contig to reference sequence");
+        let references = self.parse_bam_references()?;
+        let bam_output = self.get_bam_output(&references);
         let progress_bar = common::init_progress_bar(contigs.len() as u64);
         let msg = "samples";
         progress_bar.set_message(msg);
         let (tx, rx) = mpsc::channel();
         contigs.par_iter().for_each_with(tx, |tx, contig| {
-            let maf_path = self.run_lastz_maf(contig, &contig.sample_name);
+            let maf_path = self.run_lastz_maf(contig, &contig.sample_name, bam_output);
             match maf_path {
                 Ok(path) => {
                     tx.send(path).expect("Failed to send data");
@@ -125,9 +155,22 @@ impl<'a> LastzMapping<'a> {
             }
             progress_bar.inc(1);
         });
-        let maf_path = rx.iter().collect::<Vec<PathBuf>>();
+        let (maf_path, bam_reports): (Vec<PathBuf>, Vec<Option<BamMappingReport>>) =
+            rx.iter().unzip();
         progress_bar.finish_with_message(format!("{} {}\n", "✔".green(), msg));
-        Ok(maf_path)
+        Ok((maf_path, sort_bam_reports(bam_reports)))
+    }
+
+    // Reference names and lengths are only parsed if BAM output is set
+    fn parse_bam_references(&self) -> Result<Vec<(String, usize)>, Box<dyn Error>> {
+        match self.bam_output {
+            Some(_) => bam::parse_references(&self.get_target().target_path),
+            None => Ok(Vec::new()),
+        }
+    }
+
+    fn get_bam_output<'b>(&'b self, references: &'b [(String, usize)]) -> Option<BamOutput<'b>> {
+        self.bam_output.map(|filter| (references, filter))
     }
 
     fn run_lastz_general(
@@ -135,7 +178,8 @@ impl<'a> LastzMapping<'a> {
         contig: &ContigFiles,
         sample_name: &str,
         output_format: &LastzOutputFormat,
-    ) -> Result<MappingData, Box<dyn Error>> {
+        bam_output: Option<BamOutput>,
+    ) -> Result<(MappingData, Option<BamMappingReport>), Box<dyn Error>> {
         let target = self.get_target();
         let query = self.get_query(contig);
         let runner = Lastz::new(
@@ -145,7 +189,8 @@ impl<'a> LastzMapping<'a> {
             output_format,
             self.dependency,
             &self.reference_data.name_regex,
-        );
+        )
+        .with_bam_output(bam_output);
         runner.map_general_output(sample_name)
     }
 
@@ -153,7 +198,8 @@ impl<'a> LastzMapping<'a> {
         &self,
         contig: &ContigFiles,
         sample_name: &str,
-    ) -> Result<PathBuf, Box<dyn Error>> {
+        bam_output: Option<BamOutput>,
+    ) -> Result<(PathBuf, Option<BamMappingReport>), Box<dyn Error>> {
         let target = self.get_target();
         let query = self.get_query(contig);
         let runner = Lastz::new(
@@ -163,7 +209,8 @@ impl<'a> LastzMapping<'a> {
             &LastzOutputFormat::Maf,
             self.dependency,
             &self.reference_data.name_regex,
-        );
+        )
+        .with_bam_output(bam_output);
         runner.map_maf_output(sample_name)
     }
 
@@ -216,6 +263,9 @@ pub struct Lastz<'a> {
     pub dependency: &'a DepMetadata,
     /// Reference sequence name regex pattern
     pub refname_regex: &'a str,
+    /// Reference sequences and filter to write the
+    ///     alignments to a BAM file. Skipped if not set.
+    pub bam_output: Option<BamOutput<'a>>,
 }
 
 impl<'a> Lastz<'a> {
@@ -234,16 +284,25 @@ impl<'a> Lastz<'a> {
             output_format,
             dependency,
             refname_regex,
+            bam_output: None,
         }
     }
 
+    pub fn with_bam_output(mut self, bam_output: Option<BamOutput<'a>>) -> Self {
+        self.bam_output = bam_output;
+        self
+    }
+
     /// General output is used for mapping probe sequences.
     /// This approach focuses on identifying which parts of the contig
     /// align with the reference sequence, without requiring the sequences themselves.
     /// It is particularly useful when mapping contigs to probes as references,
     /// where probes are typically short sequences. The goal is to extract
     /// the entire contig sequence that aligns with the probe later in the pipeline.
-    pub fn map_general_output(&self, sample_name: &str) -> Result<MappingData, Box<dyn Error>> {
+    pub fn map_general_output(
+        &self,
+        sample_name: &str,
+    ) -> Result<(MappingData, Option<BamMappingReport>), Box<dyn Error>> {
         let output = self.execute_lastz();
         let parsed_output = self.parse_output(&output);
         if !self.check_success(&output).is_ok() {
@@ -257,6 +316,9 @@ impl<'a> Lastz<'a> {
         match parsed_output {
             Ok(data) => {
                 let output_path = self.write_output(&data, sample_name)?;
+                let alignments: Vec<PairwiseAlignment> =
+                    data.iter().filter_map(|d| d.to_alignment()).collect();
+                let bam_report = self.write_bam(&alignments, sample_name)?;
                 let mut results = MappingData::new(
                     sample_name,
                     &self.query.query_path,
@@ -264,7 +326,7 @@ impl<'a> Lastz<'a> {
                     self.refname_regex,
                 );
                 results.summarize(&data, &self.target.target_path);
-                Ok(results)
+                Ok((results, bam_report))
             }
             Err(e) => Err(format!("Failed to parse Lastz output: {}", e).into()),
         }
@@ -274,7 +336,10 @@ impl<'a> Lastz<'a> {
     /// This function extracts the contig sequence that matches the reference sequence.
     /// Useful when you need the matching part. Outputs to Multi Alignment Format (MAF).
     /// It returns paths to the maf output files.
-    pub fn map_maf_output(&self, sample_name: &str) -> Result<PathBuf, Box<dyn Error>> {
+    pub fn map_maf_output(
+        &self,
+        sample_name: &str,
+    ) -> Result<(PathBuf, Option<BamMappingReport>), Box<dyn Error>> {
         let output = self.execute_lastz();
         if !self.check_success(&output).is_ok() {
             return Err(format!(
@@ -290,7 +355,28 @@ impl<'a> Lastz<'a> {
                 output_path.display()
             )
         })?;
-        Ok(output_path)
+        let bam_report = match self.bam_output {
+            Some(_) => self.write_bam(&parse_maf(&output.stdout)?, sample_name)?,
+            None => None,
+        };
+        Ok((output_path, bam_report))
+    }
+
+    /// Write the alignments to a coordinate-sorted BAM file
+    /// with the sample name as the read group.
+    /// Returns None if BAM output is not set.
+    fn write_bam(
+        &self,
+        alignments: &[PairwiseAlignment],
+        sample_name: &str,
+    ) -> Result<Option<BamMappingReport>, Box<dyn Error>> {
+        let (references, filter) = match self.bam_output {
+            Some(bam_output) => bam_output,
+            None => return Ok(None),
+        };
+        let report =
+            bam::write_sample_bam(self.output_dir, references, alignments, sample_name, filter)?;
+        Ok(Some(report))
     }
 
     fn execute_lastz(&self) -> Output {
@@ -420,6 +506,14 @@ pub struct LastzGeneralOutput {
     ///    the same as coverage but in percentage
     #[serde(rename = "covPct")]
     pub cov_pct: f64,
+    /// Aligned target sequence.
+    /// Only requested when writing BAM files.
+    #[serde(default, skip_serializing)]
+    pub text1: Option<String>,
+    /// Aligned query sequence.
+    /// Only requested when writing BAM files.
+    #[serde(default, skip_serializing)]
+    pub text2: Option<String>,
 }
 
 impl Default for LastzGeneralOutput {
@@ -446,9 +540,28 @@ impl LastzGeneralOutput {
             id_pct: 0.0,
             coverage: String::new(),
             cov_pct: 0.0,
+            text1: None,
+            text2: None,
         }
     }
 
+    /// Pairwise alignment of the record.
+    /// Returns None if the aligned texts were not requested.
+    pub fn to_alignment(&self) -> Option<PairwiseAlignment> {
+        Some(PairwiseAlignment {
+            query_name: self.name2.clone(),
+            reference_name: self.name1.clone(),
+            reference_start: self.zstart1,
+            query_start: self.zstart2,
+            query_end: self.end2,
+            query_size: self.size2,
+            is_reverse: self.strand2 == '-',
+            score: self.score,
+            reference_text: self.text1.clone()?,
+            query_text: self.text2.clone()?,
+        })
+    }
+
     pub fn parse(&self, content: &[u8]) -> Result<Vec<Self>, Box<dyn Error>> {
         if content.is_empty() {
             return Err("No content to parse".into());
@@ -474,6 +587,59 @@ impl LastzGeneralOutput {
     }
 }
 
+/// Parse pairwise alignments from Lastz MAF output.
+/// The first sequence of each block is the target
+///     and the second is the query.
+pub fn parse_maf(content: &[u8]) -> Result<Vec<PairwiseAlignment>, Box<dyn Error>> {
+    let content = String::from_utf8_lossy(content);
+    let mut alignments = Vec::new();
+    let mut score = 0;
+    let mut rows: Vec<Vec<&str>> = Vec::new();
+    for line in content.lines() {
+        if let Some(block) = line.strip_prefix("a ") {
+            score = block
+                .split_whitespace()
+                .find_map(|field| field.strip_prefix("score="))
+                .and_then(|score| score.parse::<f64>().ok())
+                .unwrap_or_default() as usize;
+            rows.clear();
+            continue;
+        }
+        if !line.starts_with("s ") {
+            continue;
+        }
+        let fields: Vec<&str> = line.split_whitespace().collect();
+        if fields.len() != 7 {
+            return Err(format!("Invalid MAF sequence line: {}", line).into());
+        }
+        rows.push(fields);
+        if let [target, query] = rows.as_slice() {
+            let query_start: usize = query[2].parse()?;
+            alignments.push(PairwiseAlignment {
+                query_name: query[1].to_string(),
+                reference_name: target[1].to_string(),
+                reference_start: target[2].parse()?,
+                query_start,
+                query_end: query_start + query[3].parse::<usize>()?,
+                query_size: query[5].parse()?,
+                is_reverse: query[4] == "-",
+                score,
+                reference_text: target[6].to_string(),
+                query_text: query[6].to_string(),
+            });
+            rows.clear();
+        }
+    }
+    Ok(alignments)
+}
+
+// Reports of samples with BAM output sorted by sample name
+fn sort_bam_reports(reports: Vec<Option<BamMappingReport>>) -> Vec<BamMappingReport> {
+    let mut reports: Vec<BamMappingReport> = reports.into_iter().flatten().collect();
+    reports.sort_by(|a, b| a.sample_name.cmp(&b.sample_name));
+    reports
+}
+
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct LastzTarget {
     pub target_path: PathBuf,
@@ -570,3 +736,29 @@ impl LastzQuery {
         get_file_stem!(self, query_path)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_maf() {
+        let maf = "##maf version=1 scoring=lastz.v1.04.15\n\
+            # lastz.v1.04.15\n\
+            \n\
+            a score=1200\n\
+            s uce-1    10 5 + 100 ACG-TA\n\
+            s contig_1  3 6 - 20  ACGGTA\n\
+            \n";
+        let alignments = parse_maf(maf.as_bytes()).unwrap();
+        assert_eq!(alignments.len(), 1);
+        let alignment = &alignments[0];
+        assert_eq!(alignment.reference_name, "uce-1");
+        assert_eq!(alignment.query_name, "contig_1");
+        assert_eq!(alignment.reference_start, 10);
+        assert_eq!(alignment.query_end, 9);
+        assert_eq!(alignment.query_size, 20);
+        assert_eq!(alignment.score, 1200);
+        assert!(alignment.is_reverse);
+    }
+}
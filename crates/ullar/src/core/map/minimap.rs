@@ -1,30 +1,93 @@
 //! Map reads to reference using minimap2.
+//!
+//! Reads of each sample are written to a coordinate-sorted BAM
+//! file with the sample name as the read group. The mapped reads
+//! are then used to call a consensus sequence for each locus.
 
-use crate::helper::common::SystemInfo;
-use minimap2::{Aligner, Built};
-use std::path::Path;
+use std::{
+    collections::{BTreeMap, HashMap},
+    error::Error,
+    fs::{self, File},
+    io::{BufReader, Read},
+    path::Path,
+    sync::mpsc,
+};
+
+use bio::{alphabets::dna, io::fastq};
+use colored::Colorize;
+use flate2::read::MultiGzDecoder;
+use minimap2::{Aligner, Built, Mapping, Strand};
+use noodles::{
+    core::Position,
+    sam::alignment::{
+        record::{
+            cigar::{op::Kind, Op},
+            data::field::Tag,
+            Flags, MappingQuality,
+        },
+        record_buf::{data::field::Value, Cigar, Data, QualityScores, Sequence},
+        RecordBuf,
+    },
+};
+use rayon::prelude::*;
+
+use crate::{
+    helper::common::{self, SystemInfo},
+    types::reads::FastqReads,
+};
+
+use super::{
+    bam::{self, SortedBamWriter, BAM_EXTENSION, DEFAULT_BAM_OUTPUT_DIR},
+    consensus::{self, ConsensusParams, Pileup},
+    reports::ReadMappingReport,
+};
+
+const MINIMAP_THREAD_MEMORY: u64 = 500_000; // in KB (500 MB per thread)
+const BUFFER_MEMORY: u64 = 1_000_000; // in KB (1 GB buffer)
+/// Maximum fragment length of paired-end reads
+const MAX_FRAGMENT_LENGTH: usize = 1000;
+/// Phred quality offset of FASTQ files
+const PHRED_OFFSET: u8 = 33;
+
+type FastqReader = fastq::Reader<BufReader<Box<dyn Read>>>;
+type FastqRecords = fastq::Records<BufReader<Box<dyn Read>>>;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Segment {
+    Single,
+    First,
+    Last,
+}
 
-const MINIMAP_THREAD_MEMORY: u64 = 500_000; // in KB (200 MB per thread)
-const BUFFER_MEMORY: u64 = 1000_000; // in KB (200 MB buffer)
 /// Minimap2 mapper
 /// Requires:
-/// reference_path - path to the reference genome
-/// query_path - path to the reads to be mapped
+/// reference_path - path to the reference sequences
+/// output_dir - output directory for the BAM files
 pub struct MinimapMapping<'a> {
     pub reference_path: &'a Path,
-    pub query_path: &'a Path,
+    pub output_dir: &'a Path,
+    /// Regex to parse locus names from the reference names
+    pub refname_regex: &'a str,
+    pub params: &'a ConsensusParams,
 }
 
 impl<'a> MinimapMapping<'a> {
     /// Create a new MinimapMapping instance
-    pub fn new(reference_path: &'a Path, query_path: &'a Path) -> Self {
+    pub fn new(
+        reference_path: &'a Path,
+        output_dir: &'a Path,
+        refname_regex: &'a str,
+        params: &'a ConsensusParams,
+    ) -> Self {
         Self {
             reference_path,
-            query_path,
+            output_dir,
+            refname_regex,
+            params,
         }
     }
 
-    /// Generate the minimap2 command to map reads to the reference
+    /// Build the minimap2 index of the reference
     pub fn build_aligner(&self) -> Aligner<Built> {
         let available_cpu_threads = self.get_cpu_threads();
         Aligner::builder()
@@ -35,21 +98,476 @@ impl<'a> MinimapMapping<'a> {
             .expect("Failed to build minimap2 aligner")
     }
 
+    /// Map reads of each sample to the reference.
+    /// Samples that fail to map are logged and skipped.
+    pub fn map_reads(&self, samples: &[FastqReads]) -> Vec<ReadMappingReport> {
+        let spinner = common::init_spinner();
+        spinner.set_message("Indexing reference sequences");
+        let aligner = self.build_aligner();
+        let references =
+            bam::parse_references(self.reference_path).expect("Failed to parse reference");
+        spinner.finish_with_message(format!("{} Finished indexing reference\n", "✔".green()));
+        let progress_bar = common::init_progress_bar(samples.len() as u64);
+        let msg = "samples";
+        progress_bar.set_message(msg);
+        let (tx, rx) = mpsc::channel();
+        samples.par_iter().for_each_with(tx, |tx, sample| {
+            match self.map_sample(&aligner, &references, sample) {
+                Ok(report) => tx.send(report).expect("Failed to send report"),
+                Err(e) => log::error!("Failed to map reads of {}: {}", sample.sample_name.red(), e),
+            }
+            progress_bar.inc(1);
+        });
+        progress_bar.finish_with_message(format!("{} {}\n", "✔".green(), msg));
+        let mut reports: Vec<ReadMappingReport> = rx.iter().collect();
+        reports.sort_by(|a, b| a.sample_name.cmp(&b.sample_name));
+        reports
+    }
+
+    fn map_sample(
+        &self,
+        aligner: &Aligner<Built>,
+        references: &[(String, usize)],
+        sample: &FastqReads,
+    ) -> Result<ReadMappingReport, Box<dyn Error>> {
+        let read1 = sample.get_read1();
+        if !read1.is_file() {
+            return Err(format!("Read 1 file not found: {}", read1.display()).into());
+        }
+        let bam_dir = self.output_dir.join(DEFAULT_BAM_OUTPUT_DIR);
+        fs::create_dir_all(&bam_dir)?;
+        let bam_path = bam_dir
+            .join(&sample.sample_name)
+            .with_extension(BAM_EXTENSION);
+        let header = bam::build_header(references, &sample.sample_name)?;
+        let mut mapper = SampleMapper::new(&sample.sample_name, references, self.params);
+        let mut writer = SortedBamWriter::new(header, &bam_path);
+
+        let mut reads1 = open_fastq(&read1)?.records();
+        match sample.get_read2() {
+            Some(read2) => {
+                let mut reads2 = open_fastq(&read2)?.records();
+                loop {
+                    let (r1, r2) = match (reads1.next(), reads2.next()) {
+                        (Some(r1), Some(r2)) => (r1?, r2?),
+                        (None, None) => break,
+                        _ => {
+                            return Err(format!(
+                                "Read 1 and read 2 have different read counts after {} pairs",
+                                mapper.total_reads / 2
+                            )
+                            .into())
+                        }
+                    };
+                    let (m1, m2) = aligner.map_pair(
+                        r1.seq(),
+                        r2.seq(),
+                        false,
+                        false,
+                        Some(MAX_FRAGMENT_LENGTH),
+                        None,
+                        Some(read_name(r1.id()).as_bytes()),
+                    )?;
+                    let (m1, m2) = (primary(&m1), primary(&m2));
+                    mapper.add(&mut writer, &r1, m1, m2, Segment::First)?;
+                    mapper.add(&mut writer, &r2, m2, m1, Segment::Last)?;
+                }
+            }
+            None => self.map_single(aligner, &mut mapper, &mut writer, &mut reads1)?,
+        }
+        if let Some(singleton) = sample.get_singleton() {
+            let mut reads = open_fastq(&singleton)?.records();
+            self.map_single(aligner, &mut mapper, &mut writer, &mut reads)?;
+        }
+        writer.write()?;
+
+        let mut report = ReadMappingReport::new(&sample.sample_name, bam_path);
+        report.total_reads = mapper.total_reads;
+        report.mapped_reads = writer.len();
+        report.consensus = mapper.consensus(self.refname_regex);
+        Ok(report)
+    }
+
+    fn map_single(
+        &self,
+        aligner: &Aligner<Built>,
+        mapper: &mut SampleMapper,
+        writer: &mut SortedBamWriter,
+        reads: &mut FastqRecords,
+    ) -> Result<(), Box<dyn Error>> {
+        for read in reads {
+            let read = read?;
+            if read.seq().is_empty() {
+                continue;
+            }
+            let mappings = aligner.map(read.seq(), false, false, None, None, None)?;
+            mapper.add(writer, &read, primary(&mappings), None, Segment::Single)?;
+        }
+        Ok(())
+    }
+
     // Get CPU counts based on available memory
     fn get_cpu_threads(&self) -> usize {
         let mut sysinfo = SystemInfo::new();
         sysinfo.get();
 
-        let available_memory =
-            if sysinfo.available_memory == 0 && sysinfo.available_memory < MINIMAP_THREAD_MEMORY {
-                sysinfo.total_memory
-            } else {
-                sysinfo.available_memory
-            };
-        let max_threads_by_memory = (available_memory / MINIMAP_THREAD_MEMORY) - BUFFER_MEMORY;
+        let available_memory = if sysinfo.available_memory == 0 {
+            sysinfo.total_memory
+        } else {
+            sysinfo.available_memory
+        };
+        // System memory is in bytes
+        let max_threads_by_memory =
+            (available_memory / 1024).saturating_sub(BUFFER_MEMORY) / MINIMAP_THREAD_MEMORY;
         let sys_threads = sysinfo.threads as u64;
         let threads = std::cmp::min(sys_threads, max_threads_by_memory) as usize;
         // We want at least 1 thread to run minimap2 just in case the calculation gives 0
         std::cmp::max(1, threads)
     }
 }
+
+/// Convert minimap2 mappings of a sample to BAM records
+/// and pile up reads for consensus calling.
+struct SampleMapper<'a> {
+    read_group: &'a str,
+    reference_ids: HashMap<&'a str, usize>,
+    references: &'a [(String, usize)],
+    pileups: Vec<Option<Pileup>>,
+    params: &'a ConsensusParams,
+    total_reads: usize,
+}
+
+impl<'a> SampleMapper<'a> {
+    fn new(
+        read_group: &'a str,
+        references: &'a [(String, usize)],
+        params: &'a ConsensusParams,
+    ) -> Self {
+        Self {
+            read_group,
+            reference_ids: references
+                .iter()
+                .enumerate()
+                .map(|(i, (name, _))| (name.as_str(), i))
+                .collect(),
+            references,
+            pileups: vec![None; references.len()],
+            params,
+            total_reads: 0,
+        }
+    }
+
+    // Unmapped reads are counted but not written.
+    fn add(
+        &mut self,
+        writer: &mut SortedBamWriter,
+        read: &fastq::Record,
+        mapping: Option<&Mapping>,
+        mate: Option<&Mapping>,
+        segment: Segment,
+    ) -> Result<(), Box<dyn Error>> {
+        self.total_reads += 1;
+        let mapping = match mapping {
+            Some(mapping) => mapping,
+            None => return Ok(()),
+        };
+        let reference_id = match self.reference_id(mapping) {
+            Some(id) => id,
+            None => return Ok(()),
+        };
+        let is_reverse = mapping.strand == Strand::Reverse;
+        let (sequence, qualities) = oriented_read(read, is_reverse);
+        let cigar = to_cigar(mapping, sequence.len(), is_reverse);
+        // Skip records whose CIGAR does not cover the read
+        if consensus::query_length(&cigar) != sequence.len() {
+            return Ok(());
+        }
+        let start = mapping.target_start.max(0) as usize;
+        if mapping.mapq >= self.params.min_mapping_quality as u32 {
+            let (_, length) = &self.references[reference_id];
+            self.pileups[reference_id]
+                .get_or_insert_with(|| Pileup::new(*length))
+                .add(start, &sequence, &cigar);
+        }
+
+        let mut builder = RecordBuf::builder()
+            .set_name(read_name(read.id()))
+            .set_flags(self.flags(mapping, mate, segment))
+            .set_reference_sequence_id(reference_id)
+            .set_alignment_start(Position::try_from(start + 1).expect("Invalid position"))
+            .set_cigar(cigar.into_iter().collect::<Cigar>())
+            .set_sequence(Sequence::from(sequence))
+            .set_quality_scores(QualityScores::from(qualities))
+            .set_data(Data::from_iter([(
+                Tag::READ_GROUP,
+                Value::from(self.read_group),
+            )]));
+        if let Some(mapq) = MappingQuality::new(mapping.mapq.min(254) as u8) {
+            builder = builder.set_mapping_quality(mapq);
+        }
+        if let Some(mate) = mate {
+            if let Some(mate_id) = self.reference_id(mate) {
+                let mate_start = mate.target_start.max(0) as usize + 1;
+                builder = builder
+                    .set_mate_reference_sequence_id(mate_id)
+                    .set_mate_alignment_start(
+                        Position::try_from(mate_start).expect("Invalid position"),
+                    );
+                if mate_id == reference_id {
+                    builder = builder.set_template_length(template_length(mapping, mate));
+                }
+            }
+        }
+        writer.push(builder.build())
+    }
+
+    fn flags(&self, mapping: &Mapping, mate: Option<&Mapping>, segment: Segment) -> Flags {
+        let mut flags = Flags::empty();
+        if mapping.strand == Strand::Reverse {
+            flags |= Flags::REVERSE_COMPLEMENTED;
+        }
+        match segment {
+            Segment::Single => return flags,
+            Segment::First => flags |= Flags::SEGMENTED | Flags::FIRST_SEGMENT,
+            Segment::Last => flags |= Flags::SEGMENTED | Flags::LAST_SEGMENT,
+        }
+        match mate {
+            Some(mate) => {
+                if mate.strand == Strand::Reverse {
+                    flags |= Flags::MATE_REVERSE_COMPLEMENTED;
+                }
+                if is_proper_pair(mapping, mate) {
+                    flags |= Flags::PROPERLY_SEGMENTED;
+                }
+            }
+            None => flags |= Flags::MATE_UNMAPPED,
+        }
+        flags
+    }
+
+    fn reference_id(&self, mapping: &Mapping) -> Option<usize> {
+        let name = mapping.target_name.as_ref()?;
+        self.reference_ids.get(name.as_str()).copied()
+    }
+
+    fn consensus(&self, refname_regex: &str) -> BTreeMap<String, String> {
+        let consensus = self
+            .pileups
+            .iter()
+            .zip(self.references.iter())
+            .filter_map(|(pileup, (name, _))| {
+                let sequence = pileup.as_ref()?.consensus(self.params.min_depth)?;
+                Some((name.to_string(), sequence))
+            })
+            .collect();
+        consensus::select_locus_consensus(consensus, refname_regex)
+    }
+}
+
+/// Mates on the same reference facing each other (forward before reverse)
+/// within the maximum fragment length
+fn is_proper_pair(mapping: &Mapping, mate: &Mapping) -> bool {
+    if mapping.target_name != mate.target_name || mapping.strand == mate.strand {
+        return false;
+    }
+    let (forward, reverse) = match mapping.strand {
+        Strand::Forward => (mapping, mate),
+        Strand::Reverse => (mate, mapping),
+    };
+    if forward.target_start > reverse.target_start {
+        return false;
+    }
+    let fragment_length = forward.target_end.max(reverse.target_end) - forward.target_start;
+    fragment_length as usize <= MAX_FRAGMENT_LENGTH
+}
+
+/// Open plain or gzip-compressed FASTQ files
+fn open_fastq(path: &Path) -> Result<FastqReader, Box<dyn Error>> {
+    let file = File::open(path)?;
+    let reader: Box<dyn Read> = match path.extension() {
+        Some(ext) if ext == "gz" => Box::new(MultiGzDecoder::new(file)),
+        _ => Box::new(file),
+    };
+    Ok(fastq::Reader::new(reader))
+}
+
+fn primary(mappings: &[Mapping]) -> Option<&Mapping> {
+    mappings
+        .iter()
+        .find(|m| m.is_primary && !m.is_supplementary)
+}
+
+// Remove the /1 and /2 suffixes so both mates share the same name
+fn read_name(id: &str) -> String {
+    id.strip_suffix("/1")
+        .or_else(|| id.strip_suffix("/2"))
+        .unwrap_or(id)
+        .to_string()
+}
+
+// BAM stores reverse mapped reads as reverse complement
+fn oriented_read(read: &fastq::Record, is_reverse: bool) -> (Vec<u8>, Vec<u8>) {
+    let qualities = read.qual().iter().map(|q| q.saturating_sub(PHRED_OFFSET));
+    if is_reverse {
+        (dna::revcomp(read.seq()), qualities.rev().collect())
+    } else {
+        (read.seq().to_vec(), qualities.collect())
+    }
+}
+
+// Minimap2 CIGAR excludes clipped bases.
+// Add them back as soft clips.
+fn to_cigar(mapping: &Mapping, read_length: usize, is_reverse: bool) -> Vec<Op> {
+    let query_start = mapping.query_start.max(0) as usize;
+    let query_end = (mapping.query_end.max(0) as usize).min(read_length);
+    let (lead_clip, tail_clip) = if is_reverse {
+        (read_length - query_end, query_start)
+    } else {
+        (query_start, read_length - query_end)
+    };
+    let mut cigar = Vec::new();
+    if lead_clip > 0 {
+        cigar.push(Op::new(Kind::SoftClip, lead_clip));
+    }
+    let ops = mapping
+        .alignment
+        .as_ref()
+        .and_then(|aln| aln.cigar.as_ref());
+    match ops {
+        Some(ops) => ops.iter().for_each(|(len, op)| {
+            cigar.push(Op::new(cigar_kind(*op), *len as usize));
+        }),
+        None => cigar.push(Op::new(Kind::Match, query_end - query_start)),
+    }
+    if tail_clip > 0 {
+        cigar.push(Op::new(Kind::SoftClip, tail_clip));
+    }
+    cigar
+}
+
+// Minimap2 uses the BAM CIGAR operation codes
+fn cigar_kind(op: u8) -> Kind {
+    match op {
+        0 => Kind::Match,
+        1 => Kind::Insertion,
+        2 => Kind::Deletion,
+        3 => Kind::Skip,
+        4 => Kind::SoftClip,
+        5 => Kind::HardClip,
+        6 => Kind::Pad,
+        7 => Kind::SequenceMatch,
+        _ => Kind::SequenceMismatch,
+    }
+}
+
+fn template_length(mapping: &Mapping, mate: &Mapping) -> i32 {
+    let start = mapping.target_start.min(mate.target_start);
+    let end = mapping.target_end.max(mate.target_end);
+    let length = end - start;
+    if mapping.target_start <= mate.target_start {
+        length
+    } else {
+        -length
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+
+    use super::*;
+
+    // Deterministic pseudo-random sequence
+    fn random_sequence(length: usize, seed: u64) -> Vec<u8> {
+        let mut state = seed;
+        (0..length)
+            .map(|_| {
+                state = state
+                    .wrapping_mul(6364136223846793005)
+                    .wrapping_add(1442695040888963407);
+                b"ACGT"[(state >> 33) as usize % 4]
+            })
+            .collect()
+    }
+
+    fn write_reads(path: &Path, reads: &[(String, Vec<u8>)]) {
+        let mut file = File::create(path).unwrap();
+        reads.iter().for_each(|(name, seq)| {
+            let qual = "I".repeat(seq.len());
+            writeln!(
+                file,
+                "@{}\n{}\n+\n{}",
+                name,
+                String::from_utf8_lossy(seq),
+                qual
+            )
+            .unwrap();
+        });
+    }
+
+    fn mapping(strand: Strand, start: i32, end: i32) -> Mapping {
+        Mapping {
+            target_name: Some(std::sync::Arc::new(String::from("uce-1_p1"))),
+            strand,
+            target_start: start,
+            target_end: end,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_is_proper_pair() {
+        let forward = mapping(Strand::Forward, 100, 200);
+        let reverse = mapping(Strand::Reverse, 300, 400);
+        assert!(is_proper_pair(&forward, &reverse));
+        assert!(is_proper_pair(&reverse, &forward));
+        // Mates facing away from each other
+        let forward_after = mapping(Strand::Forward, 500, 600);
+        assert!(!is_proper_pair(&forward_after, &reverse));
+        // Fragment longer than the maximum
+        let reverse_far = mapping(Strand::Reverse, 2000, 2100);
+        assert!(!is_proper_pair(&forward, &reverse_far));
+        // Same strand or different references
+        assert!(!is_proper_pair(&forward, &forward));
+        let mut other = reverse.clone();
+        other.target_name = Some(std::sync::Arc::new(String::from("uce-2_p1")));
+        assert!(!is_proper_pair(&forward, &other));
+    }
+
+    #[test]
+    fn test_minimap_mapping() {
+        let output_dir = tempdir::TempDir::new("read_mapping").unwrap();
+        let reference = random_sequence(600, 42);
+        let ref_path = output_dir.path().join("reference.fasta");
+        let mut file = File::create(&ref_path).unwrap();
+        writeln!(file, ">uce-1_p1\n{}", String::from_utf8_lossy(&reference)).unwrap();
+
+        // Pairs from a 300 bp fragment sliding along the reference
+        let (mut reads1, mut reads2) = (Vec::new(), Vec::new());
+        for (i, start) in (0..=300).step_by(10).enumerate() {
+            let fragment = &reference[start..start + 300];
+            reads1.push((format!("pair_{}/1", i), fragment[..100].to_vec()));
+            reads2.push((format!("pair_{}/2", i), dna::revcomp(&fragment[200..])));
+        }
+        let read1 = output_dir.path().join("sample_1_R1.fastq");
+        let read2 = output_dir.path().join("sample_1_R2.fastq");
+        write_reads(&read1, &reads1);
+        write_reads(&read2, &reads2);
+        let mut sample = FastqReads::new();
+        sample.match_define_reads(String::from("sample_1"), &read1, Some(&read2));
+
+        let params = ConsensusParams::default();
+        let mapping =
+            MinimapMapping::new(&ref_path, output_dir.path(), "^(uce|locus)-\\d+", &params);
+        let reports = mapping.map_reads(&[sample]);
+        assert_eq!(reports.len(), 1);
+        let report = &reports[0];
+        assert!(report.bam.is_file());
+        assert_eq!(report.total_reads, reads1.len() * 2);
+        assert_eq!(report.mapped_reads, report.total_reads);
+        // Ends covered by fewer reads than the minimum depth are trimmed
+        let consensus = report.consensus.get("uce-1").unwrap();
+        assert!(consensus.len() > 500);
+        assert!(String::from_utf8_lossy(&reference).contains(consensus.as_str()));
+    }
+}
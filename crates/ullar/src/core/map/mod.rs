@@ -5,12 +5,24 @@ use std::{
 };
 
 use colored::Colorize;
-use configs::{ContigMappingConfig, ReferenceFile, LASTZ_ALIGNER};
+use configs::{ContigFiles, ContigMappingConfig, ReadMappingConfig, ALIGNER_DEPENDENCY};
+use exonerate::{ExonerateMapping, DEFAULT_EXONERATE_PARAMS};
+use filter::{ContigFilter, ContigFilterSummary};
 use lastz::{LastzMapping, DEFAULT_LASTZ_PARAMS};
-use reports::MappingData;
+use native::NativeMapping;
+use paralogs::ParalogSummary;
+use reports::{BamMappingReport, MappingData};
 
+use regions::TargetRegion;
 use summary::FinalMappingSummary;
-use writer::{LocusMappingWriter, ProbeMappingWriter};
+use writer::{
+    ExonMappingWriter, LocusMappingWriter, ProbeMappingWriter, ReadMappingWriter,
+    RegionMappingWriter,
+};
+
+use bam::{BamFilter, DEFAULT_BAM_OUTPUT_DIR};
+#[cfg(target_family = "unix")]
+use minimap::MinimapMapping;
 
 use crate::{
     cli::commands::map::{MapContigArgs, MapReadArgs},
@@ -22,14 +34,25 @@ use crate::{
     },
 };
 
-use super::deps::{lastz::LastzMetadata, DepMetadata};
+use super::{
+    deps::{exonerate::ExonerateMetadata, lastz::LastzMetadata, DepMetadata},
+    project::manifest::{ProjectManifest, TaskStatus},
+};
 
+pub mod bam;
 pub mod configs;
+pub mod consensus;
+pub mod exonerate;
+pub mod filter;
 pub mod init;
 pub mod lastz;
 #[cfg(target_family = "unix")]
 pub mod minimap;
+pub mod native;
+pub mod paralogs;
+pub mod regions;
 pub mod reports;
+pub mod spill;
 pub mod summary;
 pub mod writer;
 
@@ -71,18 +94,85 @@ impl<'a> ReadMapping<'a> {
         }
     }
 
+    /// Map reads to the reference sequence and
+    /// call the consensus sequence of each locus.
+    /// Returns the summary of the matched references.
     #[cfg(target_family = "unix")]
-    pub fn map(&self) {
-        // Implementation for read mapping goes here
-        // Print task info for now
-        log::info!("Running task: {}", self.task);
+    pub fn map(&self) -> Result<FinalMappingSummary, Box<dyn Error>> {
+        // Read mapping keeps no completion ledger to resume from
+        if self.runner.resume {
+            return Err(format!("{} does not support --resume. Use --overwrite", self.task).into());
+        }
+        let spinner = common::init_spinner();
+        spinner.set_message("Parsing read mapping config");
+        let config = ReadMappingConfig::from_toml(self.config_path)?;
+        spinner.finish_with_message(format!("{} Finished parsing config\n", "✔".green()));
+        self.log_input(&config);
+        PathCheck::new(self.output_dir)
+            .is_dir()
+            .with_force_overwrite(self.runner.overwrite)
+            .prompt_exists(self.runner.dry_run);
+        if self.runner.dry_run {
+            log::info!("Dry run. Samples were not mapped.");
+            return Ok(FinalMappingSummary::default());
+        }
+        ProjectManifest::record(self.task, TaskStatus::Running, Some(self.config_path));
+        let reference = &config.sequence_reference;
+        let reference_path = reference
+            .metadata
+            .parent_dir
+            .join(&reference.metadata.file_name);
+        let reports = MinimapMapping::new(
+            &reference_path,
+            self.output_dir,
+            &reference.name_regex,
+            &config.consensus,
+        )
+        .map_reads(&config.samples);
+        let summary = ReadMappingWriter::new(self.output_dir, reference).write(&reports);
+        self.log_output(reports.len(), &summary);
+        let status = TaskStatus::from_success(reports.len() == config.samples.len());
+        ProjectManifest::record(self.task, status, Some(self.config_path));
+        Ok(summary)
     }
 
     #[cfg(not(target_family = "unix"))]
-    pub fn map(&self) {
-        let msg = format!("{} {}", self.task, "is not supported on this OS.");
-        eprintln!("{}\n{}", "Error:".red().bold(), msg.red());
-        return;
+    pub fn map(&self) -> Result<FinalMappingSummary, Box<dyn Error>> {
+        Err(format!("{} is not supported on this OS", self.task).into())
+    }
+
+    #[cfg(target_family = "unix")]
+    fn log_input(&self, config: &ReadMappingConfig) {
+        log::info!("{}", "Input".cyan());
+        log::info!("{:18}: {}", "Config", self.config_path.display());
+        log::info!("{:18}: {}", "Sample counts", config.samples.len());
+        log::info!("{:18}: {}", "Task", self.task);
+        log::info!("{:18}: {}", "Aligner", Aligner::Minimap);
+        log::info!("{:18}: {}", "Min depth", config.consensus.min_depth);
+        log::info!(
+            "{:18}: {}\n",
+            "Min MAPQ",
+            config.consensus.min_mapping_quality
+        );
+    }
+
+    #[cfg(target_family = "unix")]
+    fn log_output(&self, processed: usize, summary: &FinalMappingSummary) {
+        log::info!("{}", "Output".cyan());
+        log::info!("{:18}: {}", "Output dir", self.output_dir.display());
+        log::info!(
+            "{:18}: {}",
+            "BAM dir",
+            self.output_dir.join(DEFAULT_BAM_OUTPUT_DIR).display()
+        );
+        log::info!("{:18}: {}", "Total processed", processed);
+        log::info!("{:18}: {}", "Reference counts", summary.total_references);
+        log::info!("{:18}: {}", "Sample matches", summary.total_matches);
+        log::info!(
+            "{:18}: {:.2}%",
+            "Percent coverage",
+            summary.total_percent_coverage
+        );
     }
 }
 
@@ -121,19 +211,72 @@ impl<'a> ContigMapping<'a> {
         }
     }
 
-    pub fn map(&self) {
+    /// Map contigs to the reference sequence.
+    /// Returns the summary of the matched references.
+    pub fn map(&self) -> Result<FinalMappingSummary, Box<dyn Error>> {
+        // Contig mapping keeps no completion ledger to resume from
+        if self.runner.resume {
+            return Err(format!("{} does not support --resume. Use --overwrite", self.task).into());
+        }
         let spinner = common::init_spinner();
         spinner.set_message("Mapping contigs to reference sequence");
-        let config = self.parse_config().expect("Failed to parse config");
+        let mut config = self.parse_config()?;
         spinner.finish_with_message(format!("{} Finished parsing config\n", "✔".green()));
-        let dep = config.dependencies.get(LASTZ_ALIGNER);
-        let updated_dep = LastzMetadata::new().update(dep);
-        self.log_input(config.contigs.len(), &config.input.aligner, &updated_dep);
+        let dep = config.dependencies.get(ALIGNER_DEPENDENCY);
+        let updated_dep = match config.input.aligner {
+            Aligner::Lastz => LastzMetadata::new().update(dep),
+            Aligner::Exonerate => ExonerateMetadata::new().update(dep),
+            Aligner::Native => DepMetadata::default(),
+            // Rejected when the config is parsed
+            Aligner::Minimap => unreachable!("Minimap is not a contig aligner"),
+        };
+        self.log_input(&config, &updated_dep);
         PathCheck::new(self.output_dir)
             .is_dir()
             .with_force_overwrite(self.runner.overwrite)
             .prompt_exists(self.runner.dry_run);
-        self.run_lastz(&config, &updated_dep);
+        ProjectManifest::record(self.task, TaskStatus::Running, Some(self.config_path));
+        if let Some(filter) = &config.contig_filter {
+            config.contigs = self.filter_contigs(filter, &config.contigs);
+        }
+        let results = self.run_mapping(&config, &updated_dep);
+        let status = match &results {
+            Ok((mapped_samples, _)) => {
+                TaskStatus::from_success(*mapped_samples == config.contigs.len())
+            }
+            Err(_) => TaskStatus::Failed,
+        };
+        ProjectManifest::record(self.task, status, Some(self.config_path));
+        results.map(|(_, summary)| summary)
+    }
+
+    // Each runner returns the number of mapped samples
+    // and the summary of the matched references.
+    fn run_mapping(
+        &self,
+        config: &ContigMappingConfig,
+        dep: &DepMetadata,
+    ) -> Result<(usize, FinalMappingSummary), Box<dyn Error>> {
+        match config.input.aligner {
+            Aligner::Exonerate => self.run_exonerate(config, dep),
+            Aligner::Native => self.run_native(config),
+            _ => self.run_lastz(config, dep),
+        }
+    }
+
+    /// Filter contigs by length and coverage.
+    /// Returns the filtered contig files to map.
+    fn filter_contigs(&self, filter: &ContigFilter, contigs: &[ContigFiles]) -> Vec<ContigFiles> {
+        let spinner = common::init_spinner();
+        spinner.set_message("Filtering contigs");
+        let (filtered, summary) = ContigFilterSummary::filter(self.output_dir, filter, contigs);
+        spinner.finish_with_message(format!("{} Finished filtering contigs\n", "✔".green()));
+        summary.log_table();
+        match summary.write() {
+            Ok(path) => log::info!("{:18}: {}\n", "Filter summary", path.display()),
+            Err(e) => log::error!("Failed to write contig filter summary: {}", e),
+        }
+        filtered
     }
 
     fn parse_config(&self) -> Result<ContigMappingConfig, Box<dyn Error>> {
@@ -141,34 +284,113 @@ impl<'a> ContigMapping<'a> {
         Ok(config)
     }
 
-    fn run_lastz(&self, config: &ContigMappingConfig, dep: &DepMetadata) {
-        let lastz = LastzMapping::new(&config.sequence_reference, self.output_dir, dep);
+    fn run_lastz(
+        &self,
+        config: &ContigMappingConfig,
+        dep: &DepMetadata,
+    ) -> Result<(usize, FinalMappingSummary), Box<dyn Error>> {
+        let lastz = LastzMapping::new(&config.sequence_reference, self.output_dir, dep)
+            .with_bam_output(config.bam_output.as_ref());
 
         match config.sequence_reference.reference_type {
             MappingReferenceType::Probes => {
                 let lastz_output_fmt = LastzOutputFormat::General(String::new());
-                let results = lastz
-                    .map_to_probes(&config.contigs, &lastz_output_fmt)
-                    .expect("Failed to run Lastz");
-                let summary = self.write_probes_matched(&results, &config);
+                let (results, bam_reports) =
+                    lastz.map_to_probes(&config.contigs, &lastz_output_fmt)?;
+                self.log_bam(&bam_reports, config.bam_output.as_ref());
+                let summary = self.write_probes_matched(&results, config);
                 self.log_output(results.len(), &summary);
+                Ok((results.len(), summary))
             }
             MappingReferenceType::Loci => {
-                let results = lastz
-                    .map_to_reference(&config.contigs)
-                    .expect("Failed to run Lastz");
-                let summary = self.write_loci_matched(&results, &config.sequence_reference);
+                let (results, bam_reports) = lastz.map_to_reference(&config.contigs)?;
+                self.log_bam(&bam_reports, config.bam_output.as_ref());
+                let summary = self.write_loci_matched(&results, config);
+                self.log_output(results.len(), &summary);
+                Ok((results.len(), summary))
+            }
+            MappingReferenceType::Genome => {
+                let (results, bam_reports) = lastz.map_to_reference(&config.contigs)?;
+                self.log_bam(&bam_reports, config.bam_output.as_ref());
+                let summary = self.write_regions_matched(&results, config)?;
                 self.log_output(results.len(), &summary);
+                Ok((results.len(), summary))
             }
         }
     }
 
+    fn log_bam(&self, reports: &[BamMappingReport], filter: Option<&BamFilter>) {
+        let filter = match filter {
+            Some(filter) => filter,
+            None => return,
+        };
+        let alignments: usize = reports.iter().map(|r| r.alignments).sum();
+        let filtered: usize = reports.iter().map(|r| r.filtered_alignments).sum();
+        log::info!(
+            "{:18}: {}",
+            "BAM dir",
+            self.output_dir.join(DEFAULT_BAM_OUTPUT_DIR).display()
+        );
+        log::info!("{:18}: {}%", "Min identity", filter.min_identity);
+        log::info!("{:18}: {}", "Alignments", alignments);
+        log::info!("{:18}: {}\n", "Filtered", filtered);
+    }
+
+    // The reference type and parameters are
+    // checked when the config is parsed.
+    fn run_native(
+        &self,
+        config: &ContigMappingConfig,
+    ) -> Result<(usize, FinalMappingSummary), Box<dyn Error>> {
+        let params = config.native_params.clone().unwrap_or_default();
+        let (results, bam_reports) =
+            NativeMapping::new(&config.sequence_reference, self.output_dir, &params)
+                .with_bam_output(config.bam_output.as_ref())
+                .map_to_probes(&config.contigs)?;
+        self.log_bam(&bam_reports, config.bam_output.as_ref());
+        let summary = self.write_probes_matched(&results, config);
+        self.log_output(results.len(), &summary);
+        Ok((results.len(), summary))
+    }
+
+    fn run_exonerate(
+        &self,
+        config: &ContigMappingConfig,
+        dep: &DepMetadata,
+    ) -> Result<(usize, FinalMappingSummary), Box<dyn Error>> {
+        let reports = ExonerateMapping::new(&config.sequence_reference, self.output_dir, dep)
+            .map_to_exons(&config.contigs)?;
+        let summary = ExonMappingWriter::new(self.output_dir, &config.sequence_reference)
+            .with_sequence_output(&config.sequence_output)
+            .write(&reports);
+        self.log_output(reports.len(), &summary);
+        Ok((reports.len(), summary))
+    }
+
     fn write_loci_matched(
         &self,
         maf_files: &[PathBuf],
-        reference: &ReferenceFile,
+        config: &ContigMappingConfig,
     ) -> FinalMappingSummary {
-        LocusMappingWriter::new(self.output_dir, maf_files, reference).write()
+        LocusMappingWriter::new(self.output_dir, maf_files, &config.sequence_reference)
+            .with_sequence_output(&config.sequence_output)
+            .write()
+    }
+
+    fn write_regions_matched(
+        &self,
+        maf_files: &[PathBuf],
+        config: &ContigMappingConfig,
+    ) -> Result<FinalMappingSummary, Box<dyn Error>> {
+        let regions_path = match &config.sequence_reference.target_regions {
+            Some(regions) => regions.parent_dir.join(&regions.file_name),
+            None => return Err("Genome reference requires target regions".into()),
+        };
+        let regions = TargetRegion::from_file(&regions_path)?;
+        let summary = RegionMappingWriter::new(self.output_dir, maf_files, &regions)
+            .with_sequence_output(&config.sequence_output)
+            .write();
+        Ok(summary)
     }
 
     fn write_probes_matched(
@@ -176,15 +398,40 @@ impl<'a> ContigMapping<'a> {
         data: &[MappingData],
         config: &ContigMappingConfig,
     ) -> FinalMappingSummary {
-        ProbeMappingWriter::new(self.output_dir, &config.sequence_reference).write_general(data)
+        let paralogs = ParalogSummary::new(self.output_dir, data, config.max_paralog_percent);
+        paralogs.log_table();
+        match paralogs.write() {
+            Ok((report, summary)) => {
+                log::info!("{:18}: {}", "Paralog report", report.display());
+                log::info!("{:18}: {}\n", "Paralog summary", summary.display());
+            }
+            Err(e) => log::error!("Failed to write paralog report: {}", e),
+        }
+        let excluded_loci = paralogs.excluded_loci();
+        ProbeMappingWriter::new(self.output_dir, &config.sequence_reference)
+            .exclude_loci(&excluded_loci)
+            .flank_length(config.flank_length)
+            .with_sequence_output(&config.sequence_output)
+            .write_general(data)
     }
 
-    fn log_input(&self, file_count: usize, aligner: &Aligner, dep: &DepMetadata) {
+    fn log_input(&self, config: &ContigMappingConfig, dep: &DepMetadata) {
         log::info!("{}", "Input".cyan());
         log::info!("{:18}: {}", "Config", self.config_path.display());
-        log::info!("{:18}: {}", "File count", file_count);
+        log::info!("{:18}: {}", "File count", config.contigs.len());
         log::info!("{:18}: {}", "Task", self.task);
-        self.log_aligner_info(aligner, dep);
+        match &config.input.aligner {
+            Aligner::Native => self.log_native_params(config),
+            aligner => self.log_aligner_info(aligner, dep),
+        }
+    }
+
+    fn log_native_params(&self, config: &ContigMappingConfig) {
+        let params = config.native_params.clone().unwrap_or_default();
+        log::info!("{:18}: {}", "Aligner:", "Native");
+        log::info!("{:18}: {}", "K-mer size", params.kmer_size);
+        log::info!("{:18}: {}", "Band width", params.band_width);
+        log::info!("{:18}: {}\n", "Min score", params.min_score);
     }
 
     fn log_aligner_info(&self, aligner: &Aligner, dep: &DepMetadata) {
@@ -192,10 +439,15 @@ impl<'a> ContigMapping<'a> {
             Aligner::Lastz => log::info!("{:18}: {}", "Aligner:", "Lastz"),
             Aligner::Exonerate => log::info!("{:18}: {}", "Aligner:", "Exonerate"),
             Aligner::Minimap => log::info!("{:18}: {}", "Aligner:", "Minimap"),
+            Aligner::Native => log::info!("{:18}: {}", "Aligner:", "Native"),
         }
+        let default_params = match aligner {
+            Aligner::Exonerate => DEFAULT_EXONERATE_PARAMS,
+            _ => DEFAULT_LASTZ_PARAMS,
+        };
         match &dep.override_args {
             Some(args) => log::info!("{:18}: {}\n", "Override args:", args),
-            None => log::info!("{:18}: {}\n", "Override args:", DEFAULT_LASTZ_PARAMS),
+            None => log::info!("{:18}: {}\n", "Override args:", default_params),
         }
     }
 
@@ -212,3 +464,63 @@ impl<'a> ContigMapping<'a> {
         );
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::{fs::File, io::Write};
+
+    use configs::{ContigInput, SampleNameSource};
+
+    use super::*;
+    use crate::helper::regex::UCE_REGEX;
+
+    // Deterministic pseudo-random sequence
+    fn random_sequence(length: usize, seed: u64) -> String {
+        let mut state = seed;
+        (0..length)
+            .map(|_| {
+                state = state
+                    .wrapping_mul(6364136223846793005)
+                    .wrapping_add(1442695040888963407);
+                b"ACGT"[(state >> 33) as usize % 4] as char
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_contig_mapping_partly_failed() {
+        let dir = tempdir::TempDir::new("contig_mapping").unwrap();
+        let probe = random_sequence(120, 1);
+        let ref_path = dir.path().join("probes.fasta");
+        let mut file = File::create(&ref_path).unwrap();
+        writeln!(file, ">uce-1_p1\n{}", probe).unwrap();
+
+        let mut input = ContigInput::new(SampleNameSource::File);
+        input.aligner = Aligner::Native;
+        let mut config =
+            ContigMappingConfig::init(input, UCE_REGEX, MappingReferenceType::Probes, false);
+        config.sequence_reference.get(&ref_path);
+        let contig = format!("{}{}", random_sequence(100, 2), probe);
+        for sample in ["sample_1", "sample_2"] {
+            let path = dir.path().join(format!("{}.fasta", sample));
+            let mut file = File::create(&path).unwrap();
+            writeln!(file, ">contig_1\n{}", contig).unwrap();
+            let mut files = ContigFiles::new();
+            files.parse(&path, &SampleNameSource::File);
+            config.contigs.push(files);
+        }
+        // The second sample is removed after the config is created
+        std::fs::remove_file(dir.path().join("sample_2.fasta")).unwrap();
+
+        let config_path = dir.path().join("contig_mapping.toml");
+        let output_dir = dir.path().join("out");
+        let mapping = ContigMapping::new(&config_path, &output_dir);
+        let (mapped_samples, summary) = mapping
+            .run_mapping(&config, &DepMetadata::default())
+            .unwrap();
+        assert_eq!(mapped_samples, 1);
+        assert_eq!(summary.total_matches, 1);
+        let status = TaskStatus::from_success(mapped_samples == config.contigs.len());
+        assert_eq!(status, TaskStatus::Failed);
+    }
+}
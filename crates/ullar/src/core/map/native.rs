@@ -0,0 +1,506 @@
+//! Map contigs to probes without external aligners.
+//!
+//! Probe k-mers are indexed once. Contigs that share enough
+//! k-mers with a probe are aligned to the probe on both strands
+//! using banded local alignment. Hits above the score threshold
+//! are reported as Lastz general output records, so the rest of
+//! the probe mapping pipeline is the same as Lastz.
+use std::{
+    collections::HashMap,
+    error::Error,
+    fs,
+    path::{Path, PathBuf},
+    sync::mpsc,
+};
+
+use bio::{
+    alignment::{pairwise::banded, Alignment, AlignmentOperation},
+    alphabets::dna,
+    io::fasta,
+};
+use colored::Colorize;
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::helper::common::{self, percent};
+
+use super::{
+    bam::{self, BamFilter},
+    configs::{ContigFiles, ReferenceFile},
+    lastz::LastzGeneralOutput,
+    reports::{BamMappingReport, MappingData},
+};
+
+pub const DEFAULT_KMER_SIZE: usize = 13;
+pub const DEFAULT_BAND_WIDTH: usize = 16;
+pub const DEFAULT_MIN_SCORE: i32 = 60;
+pub const DEFAULT_MIN_SEEDS: usize = 2;
+
+const NATIVE_RESULT_DIR: &str = "native_results";
+const NATIVE_RESULT_SUFFIX: &str = "native";
+const NATIVE_RESULT_EXT: &str = "csv";
+
+const MATCH_SCORE: i32 = 1;
+const MISMATCH_SCORE: i32 = -2;
+const GAP_OPEN: i32 = -5;
+const GAP_EXTEND: i32 = -1;
+
+type MatchFn = fn(u8, u8) -> i32;
+/// Sequence names and uppercase sequences
+type Sequences = Vec<(String, Vec<u8>)>;
+
+/// Parameters of the native probe mapper
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct NativeParams {
+    /// K-mer size used to find candidate contigs
+    /// and to construct the alignment band
+    pub kmer_size: usize,
+    /// Width of the alignment band around the seeds
+    pub band_width: usize,
+    /// Minimum alignment score to report a hit.
+    /// Matches score 1, mismatches -2,
+    /// gap opens -5, and gap extensions -1.
+    pub min_score: i32,
+    /// Minimum shared k-mers to align a probe to a contig
+    pub min_seeds: usize,
+}
+
+impl NativeParams {
+    /// Check that k-mers can be indexed
+    pub fn validate(&self) -> Result<(), Box<dyn Error>> {
+        if self.kmer_size == 0 {
+            return Err("Native aligner k-mer size must be greater than 0".into());
+        }
+        Ok(())
+    }
+}
+
+impl Default for NativeParams {
+    fn default() -> Self {
+        Self {
+            kmer_size: DEFAULT_KMER_SIZE,
+            band_width: DEFAULT_BAND_WIDTH,
+            min_score: DEFAULT_MIN_SCORE,
+            min_seeds: DEFAULT_MIN_SEEDS,
+        }
+    }
+}
+
+/// Native probe mapper.
+/// Produces the same results as Lastz general output.
+pub struct NativeMapping<'a> {
+    /// Probe sequences to align against
+    pub reference_data: &'a ReferenceFile,
+    pub output_dir: &'a Path,
+    pub params: &'a NativeParams,
+    /// Write the alignments of each sample to a BAM file
+    pub bam_output: Option<&'a BamFilter>,
+}
+
+impl<'a> NativeMapping<'a> {
+    pub fn new(
+        reference_data: &'a ReferenceFile,
+        output_dir: &'a Path,
+        params: &'a NativeParams,
+    ) -> Self {
+        Self {
+            reference_data,
+            output_dir,
+            params,
+            bam_output: None,
+        }
+    }
+
+    pub fn with_bam_output(mut self, filter: Option<&'a BamFilter>) -> Self {
+        self.bam_output = filter;
+        self
+    }
+
+    /// Map contigs of each sample to the probes.
+    /// Samples that fail to map are logged and skipped.
+    /// BAM reports are empty if BAM output is not set.
+    pub fn map_to_probes(
+        &self,
+        contigs: &[ContigFiles],
+    ) -> Result<(Vec<MappingData>, Vec<BamMappingReport>), Box<dyn Error>> {
+        log::info!("Mapping contigs to reference sequence");
+        let reference_path = self
+            .reference_data
+            .metadata
+            .parent_dir
+            .join(&self.reference_data.metadata.file_name);
+        let probes = parse_sequences(&reference_path)?;
+        let index = ProbeIndex::new(&probes, self.params.kmer_size);
+        let references: Vec<(String, usize)> = probes
+            .iter()
+            .map(|(name, sequence)| (name.clone(), sequence.len()))
+            .collect();
+        let progress_bar = common::init_progress_bar(contigs.len() as u64);
+        let msg = "Samples";
+        progress_bar.set_message(msg);
+        let (tx, rx) = mpsc::channel();
+        contigs.par_iter().for_each_with(tx, |tx, contig| {
+            match self.map_sample(contig, &reference_path, &probes, &index, &references) {
+                Ok(data) => tx.send(data).expect("Failed to send data"),
+                Err(e) => {
+                    let msg = format!("Failed to map contig {}: {}", contig.sample_name.red(), e);
+                    log::error!("{}", msg);
+                }
+            }
+            progress_bar.inc(1);
+        });
+        let (data, bam_reports): (Vec<MappingData>, Vec<Option<BamMappingReport>>) =
+            rx.iter().unzip();
+        let mut bam_reports: Vec<BamMappingReport> = bam_reports.into_iter().flatten().collect();
+        bam_reports.sort_by(|a, b| a.sample_name.cmp(&b.sample_name));
+        progress_bar.finish_with_message(format!("{} {}\n", "✔".green(), msg));
+        Ok((data, bam_reports))
+    }
+
+    fn map_sample(
+        &self,
+        contig: &ContigFiles,
+        reference_path: &Path,
+        probes: &[(String, Vec<u8>)],
+        index: &ProbeIndex,
+        references: &[(String, usize)],
+    ) -> Result<(MappingData, Option<BamMappingReport>), Box<dyn Error>> {
+        let contig_path = contig.metadata.parent_dir.join(&contig.metadata.file_name);
+        let contigs = parse_sequences(&contig_path)?;
+        let mut aligner = banded::Aligner::new(
+            GAP_OPEN,
+            GAP_EXTEND,
+            match_score as MatchFn,
+            self.params.kmer_size,
+            self.params.band_width,
+        );
+        let hits: Vec<LastzGeneralOutput> = contigs
+            .iter()
+            .flat_map(|(name, sequence)| {
+                self.align_contig(&mut aligner, probes, index, name, sequence)
+            })
+            .collect();
+        let output_path = self.write_output(&hits, &contig.sample_name)?;
+        let bam_report = match self.bam_output {
+            Some(filter) => {
+                let alignments: Vec<_> = hits.iter().filter_map(|h| h.to_alignment()).collect();
+                Some(bam::write_sample_bam(
+                    self.output_dir,
+                    references,
+                    &alignments,
+                    &contig.sample_name,
+                    filter,
+                )?)
+            }
+            None => None,
+        };
+        let mut data = MappingData::new(
+            &contig.sample_name,
+            &contig_path,
+            output_path,
+            &self.reference_data.name_regex,
+        );
+        data.summarize(&hits, reference_path);
+        Ok((data, bam_report))
+    }
+
+    // Query coordinates of the reverse strand are counted
+    // from the start of the reverse complement, as in Lastz.
+    fn align_contig(
+        &self,
+        aligner: &mut banded::Aligner<MatchFn>,
+        probes: &[(String, Vec<u8>)],
+        index: &ProbeIndex,
+        contig_name: &str,
+        sequence: &[u8],
+    ) -> Vec<LastzGeneralOutput> {
+        let mut hits = Vec::new();
+        let revcomp = dna::revcomp(sequence);
+        for (strand, sequence) in [('+', sequence), ('-', revcomp.as_slice())] {
+            for probe in index.candidates(sequence, self.params.min_seeds) {
+                let (probe_name, probe_seq) = &probes[probe];
+                let alignment = aligner.local(probe_seq, sequence);
+                if alignment.score >= self.params.min_score {
+                    let mut hit = to_general_output(&alignment, probe_name, contig_name, strand);
+                    if self.bam_output.is_some() {
+                        let (text1, text2) = aligned_texts(&alignment, probe_seq, sequence);
+                        hit.text1 = Some(text1);
+                        hit.text2 = Some(text2);
+                    }
+                    hits.push(hit);
+                }
+            }
+        }
+        hits
+    }
+
+    fn write_output(
+        &self,
+        hits: &[LastzGeneralOutput],
+        sample_name: &str,
+    ) -> Result<PathBuf, Box<dyn Error>> {
+        let output_dir = self.output_dir.join(NATIVE_RESULT_DIR);
+        fs::create_dir_all(&output_dir)?;
+        let output_path = output_dir
+            .join(format!("{}_{}", sample_name, NATIVE_RESULT_SUFFIX))
+            .with_extension(NATIVE_RESULT_EXT);
+        let mut writer = csv::Writer::from_path(&output_path)?;
+        for hit in hits {
+            writer.serialize(hit)?;
+        }
+        writer.flush()?;
+        Ok(output_path)
+    }
+}
+
+/// Index of probe k-mers.
+/// The value is the index of the probes containing the k-mer.
+struct ProbeIndex<'a> {
+    kmer_size: usize,
+    kmers: HashMap<&'a [u8], Vec<usize>>,
+}
+
+impl<'a> ProbeIndex<'a> {
+    fn new(probes: &'a [(String, Vec<u8>)], kmer_size: usize) -> Self {
+        let mut kmers: HashMap<&[u8], Vec<usize>> = HashMap::new();
+        probes.iter().enumerate().for_each(|(i, (_, sequence))| {
+            sequence.windows(kmer_size).for_each(|kmer| {
+                let probes = kmers.entry(kmer).or_default();
+                if probes.last() != Some(&i) {
+                    probes.push(i);
+                }
+            });
+        });
+        Self { kmer_size, kmers }
+    }
+
+    /// Probes sharing at least `min_seeds` k-mers with the sequence
+    fn candidates(&self, sequence: &[u8], min_seeds: usize) -> Vec<usize> {
+        let mut counts: HashMap<usize, usize> = HashMap::new();
+        sequence.windows(self.kmer_size).for_each(|kmer| {
+            if let Some(probes) = self.kmers.get(kmer) {
+                probes
+                    .iter()
+                    .for_each(|probe| *counts.entry(*probe).or_default() += 1);
+            }
+        });
+        let mut candidates: Vec<usize> = counts
+            .into_iter()
+            .filter(|(_, count)| *count >= min_seeds.max(1))
+            .map(|(probe, _)| probe)
+            .collect();
+        candidates.sort_unstable();
+        candidates
+    }
+}
+
+// Sequences are converted to uppercase
+// so soft-masked bases still match.
+fn parse_sequences(path: &Path) -> Result<Sequences, Box<dyn Error>> {
+    let reader = fasta::Reader::from_file(path)?;
+    let mut sequences = Vec::new();
+    for record in reader.records() {
+        let record = record?;
+        sequences.push((record.id().to_string(), record.seq().to_ascii_uppercase()));
+    }
+    Ok(sequences)
+}
+
+fn match_score(a: u8, b: u8) -> i32 {
+    if a == b && a != b'N' {
+        MATCH_SCORE
+    } else {
+        MISMATCH_SCORE
+    }
+}
+
+// Probe is the target (1) and contig is the query (2).
+// Coverage is relative to the shorter sequence, as in Lastz.
+fn to_general_output(
+    alignment: &Alignment,
+    probe_name: &str,
+    contig_name: &str,
+    strand: char,
+) -> LastzGeneralOutput {
+    let matches = count_operations(alignment, AlignmentOperation::Match);
+    let aligned = matches + count_operations(alignment, AlignmentOperation::Subst);
+    let (covered, size) = if alignment.xlen <= alignment.ylen {
+        (alignment.xend - alignment.xstart, alignment.xlen)
+    } else {
+        (alignment.yend - alignment.ystart, alignment.ylen)
+    };
+    LastzGeneralOutput {
+        score: alignment.score.max(0) as usize,
+        name1: probe_name.to_string(),
+        name2: contig_name.to_string(),
+        strand1: '+',
+        strand2: strand,
+        size1: alignment.xlen,
+        size2: alignment.ylen,
+        zstart1: alignment.xstart,
+        end1: alignment.xend,
+        zstart2: alignment.ystart,
+        end2: alignment.yend,
+        identity: format!("{}/{}", matches, aligned),
+        id_pct: percent(matches, aligned),
+        coverage: format!("{}/{}", covered, size),
+        cov_pct: percent(covered, size),
+        text1: None,
+        text2: None,
+    }
+}
+
+// Aligned probe (1) and contig (2) sequences with gaps.
+// Insertions consume the probe and deletions consume the contig.
+fn aligned_texts(alignment: &Alignment, probe: &[u8], contig: &[u8]) -> (String, String) {
+    let mut text1 = String::new();
+    let mut text2 = String::new();
+    let (mut x, mut y) = (alignment.xstart, alignment.ystart);
+    for operation in &alignment.operations {
+        match operation {
+            AlignmentOperation::Match | AlignmentOperation::Subst => {
+                text1.push(probe[x] as char);
+                text2.push(contig[y] as char);
+                x += 1;
+                y += 1;
+            }
+            AlignmentOperation::Ins => {
+                text1.push(probe[x] as char);
+                text2.push('-');
+                x += 1;
+            }
+            AlignmentOperation::Del => {
+                text1.push('-');
+                text2.push(contig[y] as char);
+                y += 1;
+            }
+            AlignmentOperation::Xclip(_) | AlignmentOperation::Yclip(_) => (),
+        }
+    }
+    (text1, text2)
+}
+
+fn count_operations(alignment: &Alignment, operation: AlignmentOperation) -> usize {
+    alignment
+        .operations
+        .iter()
+        .filter(|op| **op == operation)
+        .count()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs::File, io::Write};
+
+    use crate::{helper::regex::UCE_REGEX, types::map::MappingReferenceType};
+
+    use super::super::configs::SampleNameSource;
+    use super::*;
+
+    // Deterministic pseudo-random sequence
+    fn random_sequence(length: usize, seed: u64) -> Vec<u8> {
+        let mut state = seed;
+        (0..length)
+            .map(|_| {
+                state = state
+                    .wrapping_mul(6364136223846793005)
+                    .wrapping_add(1442695040888963407);
+                b"ACGT"[(state >> 33) as usize % 4]
+            })
+            .collect()
+    }
+
+    fn write_fasta(path: &Path, sequences: &[(&str, &[u8])]) {
+        let mut file = File::create(path).unwrap();
+        sequences.iter().for_each(|(name, seq)| {
+            writeln!(file, ">{}\n{}", name, String::from_utf8_lossy(seq)).unwrap();
+        });
+    }
+
+    #[test]
+    fn test_native_mapping() {
+        let output_dir = tempdir::TempDir::new("native_mapping").unwrap();
+        let probe_1 = random_sequence(120, 1);
+        let probe_2 = random_sequence(120, 2);
+        let ref_path = output_dir.path().join("probes.fasta");
+        write_fasta(&ref_path, &[("uce-1_p1", &probe_1), ("uce-2_p1", &probe_2)]);
+
+        // Probe 1 on the forward strand with a mismatch,
+        // probe 2 on the reverse strand, and an unrelated contig.
+        let mut contig_1 = random_sequence(200, 3);
+        let mut probe_copy = probe_1.clone();
+        probe_copy[60] = if probe_copy[60] == b'A' { b'C' } else { b'A' };
+        contig_1.splice(50..50, probe_copy);
+        let mut contig_2 = random_sequence(100, 4);
+        contig_2.extend(dna::revcomp(&probe_2));
+        let contig_3 = random_sequence(300, 5);
+        let contig_path = output_dir.path().join("sample_1.fasta");
+        write_fasta(
+            &contig_path,
+            &[
+                ("contig_1", &contig_1),
+                ("contig_2", &contig_2),
+                ("contig_3", &contig_3),
+            ],
+        );
+
+        let mut reference = ReferenceFile::new(UCE_REGEX, MappingReferenceType::Probes, false);
+        reference.get(&ref_path);
+        let mut contig = ContigFiles::new();
+        contig.parse(&contig_path, &SampleNameSource::File);
+        let params = NativeParams::default();
+        let mapping = NativeMapping::new(&reference, output_dir.path(), &params);
+        let (data, bam_reports) = mapping.map_to_probes(&[contig]).unwrap();
+        assert!(bam_reports.is_empty());
+        assert_eq!(data.len(), 1);
+        assert!(data[0].output_path.is_file());
+        let best = &data[0].data;
+        assert_eq!(best.len(), 2);
+        let uce_1 = &best["uce-1"];
+        assert_eq!(uce_1.contig_name, "contig_1");
+        assert_eq!(uce_1.strand, '+');
+        assert_eq!((uce_1.start, uce_1.end), (50, 170));
+        assert!(uce_1.percent_identity < 100.0);
+        let uce_2 = &best["uce-2"];
+        assert_eq!(uce_2.contig_name, "contig_2");
+        assert_eq!(uce_2.strand, '-');
+        assert_eq!((uce_2.start, uce_2.end), (100, 220));
+        assert_eq!(uce_2.percent_coverage, 100.0);
+    }
+
+    #[test]
+    fn test_native_params_validate() {
+        assert!(NativeParams::default().validate().is_ok());
+        let params = NativeParams {
+            kmer_size: 0,
+            ..Default::default()
+        };
+        assert!(params.validate().is_err());
+    }
+
+    #[test]
+    fn test_aligned_texts() {
+        let probe = random_sequence(80, 6);
+        let mut contig = probe.clone();
+        contig.remove(40);
+        let mut aligner = banded::Aligner::new(
+            GAP_OPEN,
+            GAP_EXTEND,
+            match_score as MatchFn,
+            DEFAULT_KMER_SIZE,
+            DEFAULT_BAND_WIDTH,
+        );
+        let alignment = aligner.local(&probe, &contig);
+        let (text1, text2) = aligned_texts(&alignment, &probe, &contig);
+        assert_eq!(text1.len(), text2.len());
+        assert_eq!(
+            text1.replace('-', "").as_bytes(),
+            &probe[alignment.xstart..alignment.xend]
+        );
+        assert_eq!(
+            text2.replace('-', "").as_bytes(),
+            &contig[alignment.ystart..alignment.yend]
+        );
+        assert_eq!(text2.matches('-').count(), 1);
+    }
+}
@@ -0,0 +1,217 @@
+//! Report putative paralogs in probe mapping.
+//!
+//! A locus is flagged in a sample if it matches multiple contigs
+//! or its contigs match other loci. Only the best contig is kept
+//! for each locus, but the other hits are reported here.
+use std::{
+    collections::{BTreeMap, BTreeSet, HashSet},
+    error::Error,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use comfy_table::Table;
+use serde::Serialize;
+
+use super::reports::MappingData;
+
+pub const PARALOG_REPORT: &str = "paralog_report.csv";
+pub const PARALOG_SUMMARY: &str = "paralog_summary.csv";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MultiHitType {
+    /// A locus matched by more than one contig
+    MultipleContigs,
+    /// A contig matched to more than one locus
+    MultipleLoci,
+}
+
+/// A locus and contig pair of a multi-hit
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ParalogHit {
+    pub sample_name: String,
+    pub locus: String,
+    pub contig_name: String,
+    pub score: usize,
+    pub hit_type: MultiHitType,
+    /// The contig is the best match of the locus
+    pub selected: bool,
+}
+
+impl ParalogHit {
+    pub fn new(
+        sample_name: &str,
+        locus: &str,
+        contig_name: &str,
+        score: usize,
+        hit_type: MultiHitType,
+        selected: bool,
+    ) -> Self {
+        Self {
+            sample_name: sample_name.to_string(),
+            locus: locus.to_string(),
+            contig_name: contig_name.to_string(),
+            score,
+            hit_type,
+            selected,
+        }
+    }
+}
+
+/// Paralog flags of a locus across samples
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct LocusParalogs {
+    pub locus: String,
+    /// Samples with a contig mapped to the locus
+    pub mapped_samples: usize,
+    /// Samples with multi-hits at the locus
+    pub flagged_samples: usize,
+    /// Flagged samples / mapped samples * 100
+    pub percent_flagged: f64,
+    pub excluded: bool,
+}
+
+pub struct ParalogSummary<'a> {
+    pub output_dir: &'a Path,
+    pub hits: Vec<&'a ParalogHit>,
+    pub loci: Vec<LocusParalogs>,
+}
+
+impl<'a> ParalogSummary<'a> {
+    /// Summarize multi-hits of all samples.
+    /// Loci flagged in more than `max_percent` of the mapped samples
+    /// are marked as excluded.
+    pub fn new(output_dir: &'a Path, data: &'a [MappingData], max_percent: Option<f64>) -> Self {
+        let mut hits: Vec<&ParalogHit> = data.iter().flat_map(|d| d.multi_hits.iter()).collect();
+        hits.sort_by(|a, b| {
+            (&a.sample_name, &a.locus, &a.contig_name).cmp(&(
+                &b.sample_name,
+                &b.locus,
+                &b.contig_name,
+            ))
+        });
+        let flagged: HashSet<(&str, &str)> = hits
+            .iter()
+            .map(|hit| (hit.sample_name.as_str(), hit.locus.as_str()))
+            .collect();
+        let mut mapped: BTreeMap<&str, BTreeSet<&str>> = BTreeMap::new();
+        data.iter().for_each(|d| {
+            d.data.keys().for_each(|locus| {
+                mapped.entry(locus).or_default().insert(&d.sample_name);
+            });
+        });
+        let loci = mapped
+            .into_iter()
+            .filter_map(|(locus, samples)| {
+                let flagged_samples = samples
+                    .iter()
+                    .filter(|sample| flagged.contains(&(**sample, locus)))
+                    .count();
+                if flagged_samples == 0 {
+                    return None;
+                }
+                let percent_flagged = flagged_samples as f64 / samples.len() as f64 * 100.0;
+                Some(LocusParalogs {
+                    locus: locus.to_string(),
+                    mapped_samples: samples.len(),
+                    flagged_samples,
+                    percent_flagged,
+                    excluded: max_percent.is_some_and(|max| percent_flagged > max),
+                })
+            })
+            .collect();
+        Self {
+            output_dir,
+            hits,
+            loci,
+        }
+    }
+
+    pub fn excluded_loci(&self) -> HashSet<String> {
+        self.loci
+            .iter()
+            .filter(|locus| locus.excluded)
+            .map(|locus| locus.locus.to_string())
+            .collect()
+    }
+
+    /// Write the per-sample multi-hits and the per-locus summary.
+    /// Returns the paths of the report and the summary.
+    pub fn write(&self) -> Result<(PathBuf, PathBuf), Box<dyn Error>> {
+        fs::create_dir_all(self.output_dir)?;
+        let report_path = self.output_dir.join(PARALOG_REPORT);
+        let mut writer = csv::Writer::from_path(&report_path)?;
+        for hit in &self.hits {
+            writer.serialize(hit)?;
+        }
+        writer.flush()?;
+        let summary_path = self.output_dir.join(PARALOG_SUMMARY);
+        let mut writer = csv::Writer::from_path(&summary_path)?;
+        for locus in &self.loci {
+            writer.serialize(locus)?;
+        }
+        writer.flush()?;
+        Ok((report_path, summary_path))
+    }
+
+    pub fn log_table(&self) {
+        let excluded = self.loci.iter().filter(|locus| locus.excluded).count();
+        let mut table = Table::new();
+        table.set_header(vec!["Multi-hits", "Flagged loci", "Excluded loci"]);
+        table.add_row(vec![
+            self.hits.len().to_string(),
+            self.loci.len().to_string(),
+            excluded.to_string(),
+        ]);
+        log::info!("\n{}", table);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::core::map::reports::BestContig;
+
+    use super::*;
+
+    fn mapping_data(sample_name: &str, loci: &[&str], flagged: &[&str]) -> MappingData {
+        let mut data = MappingData::new(sample_name, Path::new("test"), PathBuf::new(), "");
+        loci.iter().for_each(|locus| {
+            data.data.insert(locus.to_string(), BestContig::new());
+        });
+        data.multi_hits = flagged
+            .iter()
+            .map(|locus| {
+                ParalogHit::new(
+                    sample_name,
+                    locus,
+                    "contig1",
+                    100,
+                    MultiHitType::MultipleContigs,
+                    true,
+                )
+            })
+            .collect();
+        data
+    }
+
+    #[test]
+    fn test_paralog_summary() {
+        let data = vec![
+            mapping_data("sample_1", &["uce-1", "uce-2"], &["uce-1", "uce-2"]),
+            mapping_data("sample_2", &["uce-1", "uce-2"], &["uce-1"]),
+            mapping_data("sample_3", &["uce-1", "uce-2", "uce-3"], &[]),
+        ];
+        let summary = ParalogSummary::new(Path::new("test"), &data, Some(50.0));
+        assert_eq!(summary.hits.len(), 3);
+        assert_eq!(summary.loci.len(), 2);
+        assert_eq!(summary.loci[0].flagged_samples, 2);
+        assert_eq!(summary.loci[0].mapped_samples, 3);
+        assert_eq!(
+            summary.excluded_loci(),
+            HashSet::from([String::from("uce-1")])
+        );
+        let summary = ParalogSummary::new(Path::new("test"), &data, None);
+        assert!(summary.excluded_loci().is_empty());
+    }
+}
@@ -0,0 +1,392 @@
+//! Extract target regions from contigs mapped to a genome reference.
+//!
+//! Target regions are read from BED or GFF files. Contigs are mapped
+//! to the genome using Lastz MAF output. The part of a contig aligned
+//! to a region is extracted in the reference orientation.
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    error::Error,
+    fs::{self, File},
+    io::BufReader,
+    path::{Path, PathBuf},
+};
+
+use segul::parser::maf::{MafParagraph, MafReader, MafSequence};
+use serde::Serialize;
+
+pub const REGION_COVERAGE_SUMMARY: &str = "region_coverage.csv";
+
+const GAP: u8 = b'-';
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RegionFormat {
+    /// 0-based, end-exclusive coordinates
+    Bed,
+    /// 1-based, end-inclusive coordinates.
+    /// Also used for GTF files.
+    Gff,
+}
+
+impl RegionFormat {
+    pub fn from_path(path: &Path) -> Result<Self, Box<dyn Error>> {
+        let ext = path
+            .extension()
+            .and_then(|ext| ext.to_str())
+            .unwrap_or_default()
+            .to_lowercase();
+        match ext.as_str() {
+            "bed" => Ok(RegionFormat::Bed),
+            "gff" | "gff3" | "gtf" => Ok(RegionFormat::Gff),
+            _ => Err(format!(
+                "Unknown target region format: {}. Use BED or GFF files",
+                path.display()
+            )
+            .into()),
+        }
+    }
+}
+
+/// Target region in the genome reference.
+/// Coordinates are 0-based and end-exclusive.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TargetRegion {
+    pub name: String,
+    pub chrom: String,
+    pub start: usize,
+    pub end: usize,
+}
+
+impl TargetRegion {
+    pub fn len(&self) -> usize {
+        self.end - self.start
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.end <= self.start
+    }
+
+    /// Parse target regions from a BED or GFF file.
+    /// Regions without names are named using their coordinates.
+    pub fn from_file(path: &Path) -> Result<Vec<Self>, Box<dyn Error>> {
+        let format = RegionFormat::from_path(path)?;
+        let content = fs::read_to_string(path)?;
+        Self::parse(&content, format)
+    }
+
+    pub fn parse(content: &str, format: RegionFormat) -> Result<Vec<Self>, Box<dyn Error>> {
+        let mut regions = Vec::new();
+        let mut names = HashSet::new();
+        for line in content.lines() {
+            let line = line.trim_end();
+            // GFF3 may contain sequences at the end of the file
+            if line.starts_with("##FASTA") {
+                break;
+            }
+            if line.is_empty()
+                || line.starts_with('#')
+                || line.starts_with("track")
+                || line.starts_with("browser")
+            {
+                continue;
+            }
+            let mut region = match format {
+                RegionFormat::Bed => Self::parse_bed(line)?,
+                RegionFormat::Gff => Self::parse_gff(line)?,
+            };
+            if region.is_empty() {
+                continue;
+            }
+            // Keep file names unique
+            if !names.insert(region.name.to_string()) {
+                region.name = format!("{}_{}", region.name, region.coordinates());
+                names.insert(region.name.to_string());
+            }
+            regions.push(region);
+        }
+        Ok(regions)
+    }
+
+    fn parse_bed(line: &str) -> Result<Self, Box<dyn Error>> {
+        let fields: Vec<&str> = line.split('\t').collect();
+        if fields.len() < 3 {
+            return Err(format!("Invalid BED line: {}", line).into());
+        }
+        let mut region = Self {
+            name: String::new(),
+            chrom: fields[0].to_string(),
+            start: fields[1].parse()?,
+            end: fields[2].parse()?,
+        };
+        region.name = match fields.get(3) {
+            Some(name) if !name.is_empty() => name.to_string(),
+            _ => region.coordinates(),
+        };
+        Ok(region)
+    }
+
+    fn parse_gff(line: &str) -> Result<Self, Box<dyn Error>> {
+        let fields: Vec<&str> = line.split('\t').collect();
+        if fields.len() < 9 {
+            return Err(format!("Invalid GFF line: {}", line).into());
+        }
+        let start: usize = fields[3].parse()?;
+        let mut region = Self {
+            name: String::new(),
+            chrom: fields[0].to_string(),
+            start: start.saturating_sub(1),
+            end: fields[4].parse()?,
+        };
+        region.name = Self::parse_gff_name(fields[8]).unwrap_or_else(|| region.coordinates());
+        Ok(region)
+    }
+
+    // Support GFF3 (ID=name) and GTF (gene_id "name") attributes
+    fn parse_gff_name(attributes: &str) -> Option<String> {
+        let attributes: Vec<(&str, &str)> = attributes
+            .split(';')
+            .filter_map(|attr| {
+                let attr = attr.trim();
+                attr.split_once('=')
+                    .or_else(|| attr.split_once(' '))
+                    .map(|(key, value)| (key.trim(), value.trim().trim_matches('"')))
+            })
+            .collect();
+        ["ID", "Name", "gene_id"].iter().find_map(|key| {
+            attributes
+                .iter()
+                .find(|(k, v)| k == key && !v.is_empty())
+                .map(|(_, v)| v.to_string())
+        })
+    }
+
+    fn coordinates(&self) -> String {
+        format!("{}_{}-{}", self.chrom, self.start, self.end)
+    }
+}
+
+/// Contig sequence extracted for a target region
+#[derive(Debug, Clone, PartialEq)]
+pub struct RegionHit {
+    pub sequence: String,
+    /// Number of region bases aligned to the contig
+    pub aligned_bases: usize,
+    pub score: f64,
+}
+
+/// Extract target regions from Lastz MAF alignments.
+/// The reference is the first sequence of each alignment block.
+pub struct RegionExtractor<'a> {
+    pub regions: &'a [TargetRegion],
+    chroms: HashMap<&'a str, Vec<&'a TargetRegion>>,
+}
+
+impl<'a> RegionExtractor<'a> {
+    pub fn new(regions: &'a [TargetRegion]) -> Self {
+        let mut chroms: HashMap<&str, Vec<&TargetRegion>> = HashMap::new();
+        regions.iter().for_each(|region| {
+            chroms
+                .entry(region.chrom.as_str())
+                .or_default()
+                .push(region);
+        });
+        Self { regions, chroms }
+    }
+
+    /// Extract the best hit of each region.
+    /// The key is the region name.
+    pub fn extract_maf(
+        &self,
+        maf_path: &Path,
+    ) -> Result<BTreeMap<String, RegionHit>, Box<dyn Error>> {
+        let file = File::open(maf_path)?;
+        let maf = MafReader::new(BufReader::new(file));
+        let mut hits: BTreeMap<String, RegionHit> = BTreeMap::new();
+        maf.into_iter().for_each(|paragraph| {
+            if let MafParagraph::Alignment(aln) = paragraph {
+                if aln.sequences.len() < 2 {
+                    return;
+                }
+                let score = aln.score.unwrap_or(0.0);
+                let reference = &aln.sequences[0];
+                aln.sequences.iter().skip(1).for_each(|query| {
+                    self.extract_block(reference, query, score, &mut hits);
+                });
+            }
+        });
+        Ok(hits)
+    }
+
+    fn extract_block(
+        &self,
+        reference: &MafSequence,
+        query: &MafSequence,
+        score: f64,
+        hits: &mut BTreeMap<String, RegionHit>,
+    ) {
+        let regions = match self.chroms.get(reference.source.as_str()) {
+            Some(regions) => regions,
+            None => return,
+        };
+        let block_end = reference.start + reference.size;
+        regions
+            .iter()
+            .filter(|region| region.start < block_end && region.end > reference.start)
+            .for_each(|region| {
+                let hit = match extract_region(region, reference, query, score) {
+                    Some(hit) => hit,
+                    None => return,
+                };
+                match hits.get(&region.name) {
+                    Some(best) if best.score >= hit.score => (),
+                    _ => {
+                        hits.insert(region.name.to_string(), hit);
+                    }
+                }
+            });
+    }
+}
+
+/// Extract the query bases aligned to a region.
+/// Insertions inside the region are kept.
+/// Lastz reports the reference on the forward strand,
+/// so the extracted bases follow the reference orientation.
+pub fn extract_region(
+    region: &TargetRegion,
+    reference: &MafSequence,
+    query: &MafSequence,
+    score: f64,
+) -> Option<RegionHit> {
+    let mut position = reference.start;
+    let mut sequence = String::new();
+    let mut aligned_bases = 0;
+    for (ref_base, query_base) in reference.text.iter().zip(query.text.iter()) {
+        let in_region = if *ref_base == GAP {
+            position > region.start && position < region.end
+        } else {
+            position += 1;
+            position > region.start && position <= region.end
+        };
+        if in_region && *query_base != GAP {
+            sequence.push(*query_base as char);
+            if *ref_base != GAP {
+                aligned_bases += 1;
+            }
+        }
+    }
+    if sequence.is_empty() {
+        return None;
+    }
+    Some(RegionHit {
+        sequence,
+        aligned_bases,
+        score,
+    })
+}
+
+/// Coverage of a target region in a sample
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct RegionCoverage {
+    pub region: String,
+    pub chrom: String,
+    pub start: usize,
+    pub end: usize,
+    pub sample_name: String,
+    pub aligned_bases: usize,
+    /// Percent of the region aligned to the sample contig
+    pub percent_coverage: f64,
+}
+
+impl RegionCoverage {
+    pub fn new(region: &TargetRegion, sample_name: &str, hit: Option<&RegionHit>) -> Self {
+        let aligned_bases = hit.map(|hit| hit.aligned_bases).unwrap_or(0);
+        Self {
+            region: region.name.to_string(),
+            chrom: region.chrom.to_string(),
+            start: region.start,
+            end: region.end,
+            sample_name: sample_name.to_string(),
+            aligned_bases,
+            percent_coverage: aligned_bases as f64 / region.len() as f64 * 100.0,
+        }
+    }
+}
+
+pub fn write_region_coverage(
+    output_dir: &Path,
+    coverage: &[RegionCoverage],
+) -> Result<PathBuf, Box<dyn Error>> {
+    fs::create_dir_all(output_dir)?;
+    let output_path = output_dir.join(REGION_COVERAGE_SUMMARY);
+    let mut writer = csv::Writer::from_path(&output_path)?;
+    for record in coverage {
+        writer.serialize(record)?;
+    }
+    writer.flush()?;
+    Ok(output_path)
+}
+
+#[cfg(test)]
+mod tests {
+    use segul::helper::types::DnaStrand;
+
+    use super::*;
+
+    fn maf_sequence(source: &str, start: usize, text: &str) -> MafSequence {
+        MafSequence {
+            source: source.to_string(),
+            start,
+            size: text.bytes().filter(|b| *b != GAP).count(),
+            strand: DnaStrand::Forward,
+            src_size: 1000,
+            text: text.as_bytes().to_vec(),
+        }
+    }
+
+    #[test]
+    fn test_parse_regions() {
+        let bed = "track name=targets\nchr1\t10\t20\tgene1\nchr1\t30\t40\n";
+        let regions = TargetRegion::parse(bed, RegionFormat::Bed).unwrap();
+        assert_eq!(regions.len(), 2);
+        assert_eq!(regions[0].name, "gene1");
+        assert_eq!((regions[0].start, regions[0].end), (10, 20));
+        assert_eq!(regions[1].name, "chr1_30-40");
+        let gff = "##gff-version 3\n\
+            chr2\tsrc\tgene\t11\t20\t.\t-\t.\tID=gene2;Name=abc\n\
+            chr2\tsrc\tgene\t31\t40\t.\t+\t.\tgene_id \"gene3\"; transcript_id \"t1\"\n";
+        let regions = TargetRegion::parse(gff, RegionFormat::Gff).unwrap();
+        assert_eq!(regions[0].name, "gene2");
+        assert_eq!((regions[0].start, regions[0].end), (10, 20));
+        assert_eq!(regions[1].name, "gene3");
+    }
+
+    #[test]
+    fn test_extract_region() {
+        // Reference positions 100..112 with an insertion
+        // after 105 and a deletion at 108
+        let reference = maf_sequence("chr1", 100, "ACGTAC--GTAC-GTA");
+        let query = maf_sequence("contig", 0, "ACGTACTTGT-CAGTA");
+        let region = TargetRegion {
+            name: String::from("gene1"),
+            chrom: String::from("chr1"),
+            start: 104,
+            end: 110,
+        };
+        let hit = extract_region(&region, &reference, &query, 10.0).unwrap();
+        assert_eq!(hit.sequence, "ACTTGTC");
+        assert_eq!(hit.aligned_bases, 5);
+    }
+
+    #[test]
+    fn test_extract_maf() {
+        let maf = Path::new("tests/data/maf/mitogenomes.maf");
+        let regions = vec![TargetRegion {
+            name: String::from("nd6"),
+            chrom: String::from("Rattus_rattus_NC_012374_mtDNA-ND6"),
+            start: 0,
+            end: 12,
+        }];
+        let hits = RegionExtractor::new(&regions).extract_maf(maf).unwrap();
+        assert_eq!(hits["nd6"].sequence, "ATGATAAATTAT");
+        assert_eq!(hits["nd6"].aligned_bases, 12);
+    }
+}
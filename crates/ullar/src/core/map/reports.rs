@@ -1,5 +1,5 @@
 use std::{
-    collections::{btree_map::Entry, BTreeMap, HashMap},
+    collections::{btree_map::Entry, BTreeMap, HashMap, HashSet},
     path::{Path, PathBuf},
 };
 
@@ -10,10 +10,79 @@ use segul::helper::{
 
 use crate::types::map::LastzOutputFormat;
 
-use super::lastz::LastzGeneralOutput;
+use super::{
+    lastz::LastzGeneralOutput,
+    paralogs::{MultiHitType, ParalogHit},
+};
 
 type ContigMapping = BTreeMap<String, BestContig>;
 
+/// Read mapping results of a sample
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ReadMappingReport {
+    pub sample_name: String,
+    /// Coordinate-sorted BAM file
+    pub bam: PathBuf,
+    pub total_reads: usize,
+    pub mapped_reads: usize,
+    /// Consensus sequence of each locus.
+    /// The key is the locus name.
+    pub consensus: BTreeMap<String, String>,
+}
+
+impl ReadMappingReport {
+    pub fn new(sample_name: &str, bam: PathBuf) -> Self {
+        Self {
+            sample_name: sample_name.to_string(),
+            bam,
+            ..Default::default()
+        }
+    }
+}
+
+/// Contig alignments of a sample written to a BAM file
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct BamMappingReport {
+    pub sample_name: String,
+    /// Coordinate-sorted BAM file
+    pub bam: PathBuf,
+    /// Alignments written to the BAM file
+    pub alignments: usize,
+    /// Alignments removed by the mapping quality filter
+    pub filtered_alignments: usize,
+}
+
+impl BamMappingReport {
+    pub fn new(sample_name: &str, bam: PathBuf) -> Self {
+        Self {
+            sample_name: sample_name.to_string(),
+            bam,
+            ..Default::default()
+        }
+    }
+}
+
+/// Exon mapping results of a sample
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ExonMappingReport {
+    pub sample_name: String,
+    /// Raw Exonerate output
+    pub output_path: PathBuf,
+    /// Coding sequence of the best hit of each locus.
+    /// The key is the locus name.
+    pub exons: BTreeMap<String, String>,
+}
+
+impl ExonMappingReport {
+    pub fn new(sample_name: &str, output_path: PathBuf) -> Self {
+        Self {
+            sample_name: sample_name.to_string(),
+            output_path,
+            ..Default::default()
+        }
+    }
+}
+
 pub struct MappingReport {
     pub output_dir: PathBuf,
     pub output_format: LastzOutputFormat,
@@ -43,6 +112,9 @@ pub struct MappingData {
     /// Number of references that the contig mapped to
     /// This could be a probe sequence
     pub ref_count: usize,
+    /// Loci hit by multiple contigs and
+    /// contigs hitting multiple loci
+    pub multi_hits: Vec<ParalogHit>,
 }
 
 impl MappingData {
@@ -59,11 +131,15 @@ impl MappingData {
             refname_regex: refname_regex.to_string(),
             ref_count: 0,
             data: BTreeMap::new(),
+            multi_hits: Vec::new(),
         }
     }
 
     pub fn summarize(&mut self, lastz_output: &[LastzGeneralOutput], target_path: &Path) {
         self.data = self.find_best_contigs(lastz_output);
+        self.collapse_probes(lastz_output);
+        self.flag_shared_contigs();
+        self.multi_hits = self.find_multi_hits(lastz_output);
         let (seq, _) = SeqParser::new(target_path, &DataType::Dna).parse(&InputFmt::Auto);
         self.ref_count = seq.len();
     }
@@ -93,6 +169,107 @@ impl MappingData {
         best_contigs
     }
 
+    /// Collapse probes of the same locus into a single match.
+    /// All probes of a locus must resolve to the same contig:
+    /// the contig matched by the most probes wins, ties are broken
+    /// by the total score. The matched region spans all probe hits
+    /// of the winning contig.
+    fn collapse_probes(&mut self, lastz_output: &[LastzGeneralOutput]) {
+        let mut loci: BTreeMap<String, BTreeMap<&str, ProbeHits>> = BTreeMap::new();
+        lastz_output.iter().for_each(|output| {
+            let locus = self.clean_reference_name(&output.name1);
+            loci.entry(locus)
+                .or_default()
+                .entry(output.name2.as_str())
+                .or_default()
+                .add(output);
+        });
+        loci.iter().for_each(|(locus, contigs)| {
+            let winner = contigs
+                .iter()
+                .max_by_key(|(_, hits)| (hits.probes.len(), hits.total_score));
+            let (contig_name, hits) = match winner {
+                Some(winner) => winner,
+                None => return,
+            };
+            let probes: HashSet<&str> = contigs
+                .values()
+                .flat_map(|hits| hits.probes.keys().map(|probe| probe.as_str()))
+                .collect();
+            if let Some(best) = self.data.get_mut(locus) {
+                best.collapse(contig_name, hits, probes.len());
+            }
+        });
+    }
+
+    /// Flag contigs selected for more than one locus.
+    /// Collapsing probes can move a locus to a contig
+    /// that is already the best match of another locus.
+    /// Probes of the same locus count as one locus.
+    fn flag_shared_contigs(&mut self) {
+        let mut loci_per_contig: HashMap<String, usize> = HashMap::new();
+        self.data.values().for_each(|best| {
+            *loci_per_contig
+                .entry(best.contig_name.to_string())
+                .or_default() += 1;
+        });
+        self.data.values_mut().for_each(|best| {
+            best.duplicate_refs = loci_per_contig[&best.contig_name] - 1;
+        });
+    }
+
+    /// Find loci matched by more than one contig and contigs
+    /// matched to more than one locus. Probes of the same locus
+    /// count as one locus. Only the best score of each
+    /// locus and contig pair is kept.
+    fn find_multi_hits(&self, lastz_output: &[LastzGeneralOutput]) -> Vec<ParalogHit> {
+        let mut pairs: BTreeMap<(String, String), usize> = BTreeMap::new();
+        lastz_output.iter().for_each(|output| {
+            let key = (
+                self.clean_reference_name(&output.name1),
+                output.name2.to_string(),
+            );
+            let score = pairs.entry(key).or_insert(output.score);
+            if output.score > *score {
+                *score = output.score;
+            }
+        });
+        let mut contigs_per_locus: HashMap<&str, usize> = HashMap::new();
+        let mut loci_per_contig: HashMap<&str, usize> = HashMap::new();
+        pairs.keys().for_each(|(locus, contig)| {
+            *contigs_per_locus.entry(locus).or_default() += 1;
+            *loci_per_contig.entry(contig).or_default() += 1;
+        });
+        let mut hits = Vec::new();
+        pairs.iter().for_each(|((locus, contig), score)| {
+            let selected = self
+                .data
+                .get(locus)
+                .is_some_and(|best| &best.contig_name == contig);
+            if contigs_per_locus[locus.as_str()] > 1 {
+                hits.push(ParalogHit::new(
+                    &self.sample_name,
+                    locus,
+                    contig,
+                    *score,
+                    MultiHitType::MultipleContigs,
+                    selected,
+                ));
+            }
+            if loci_per_contig[contig.as_str()] > 1 {
+                hits.push(ParalogHit::new(
+                    &self.sample_name,
+                    locus,
+                    contig,
+                    *score,
+                    MultiHitType::MultipleLoci,
+                    selected,
+                ));
+            }
+        });
+        hits
+    }
+
     // Check if the contig has already been mapped to a reference
     fn update_matching_refs(
         &self,
@@ -141,6 +318,56 @@ impl MappingData {
     }
 }
 
+/// Probe hits of a locus on a contig
+#[derive(Debug, Default)]
+struct ProbeHits<'a> {
+    /// Best score of each probe
+    probes: HashMap<String, usize>,
+    total_score: usize,
+    /// Best scoring hit
+    best: Option<&'a LastzGeneralOutput>,
+    /// Matched region on the forward strand of the contig
+    start: usize,
+    end: usize,
+}
+
+impl<'a> ProbeHits<'a> {
+    fn add(&mut self, output: &'a LastzGeneralOutput) {
+        let score = self.probes.entry(output.name1.to_string()).or_default();
+        if output.score > *score {
+            self.total_score = self.total_score - *score + output.score;
+            *score = output.score;
+        }
+        let (start, end) = forward_coordinates(output);
+        match self.best {
+            Some(best) => {
+                self.start = self.start.min(start);
+                self.end = self.end.max(end);
+                if output.score > best.score {
+                    self.best = Some(output);
+                }
+            }
+            None => {
+                self.start = start;
+                self.end = end;
+                self.best = Some(output);
+            }
+        }
+    }
+}
+
+/// Lastz counts query positions along the matched strand.
+/// Convert them to the forward strand.
+fn forward_coordinates(output: &LastzGeneralOutput) -> (usize, usize) {
+    match output.strand2 {
+        '-' => (
+            output.size2.saturating_sub(output.end2),
+            output.size2.saturating_sub(output.zstart2),
+        ),
+        _ => (output.zstart2, output.end2),
+    }
+}
+
 /// Data structure to store the mapped contigs
 /// and their mapping information. Only the
 /// best mapping information is stored.
@@ -159,6 +386,15 @@ pub struct BestContig {
     pub duplicate_refs: usize,
     /// Number of contigs that mapped to the same reference
     pub duplicate_contigs: usize,
+    /// Start of the matched region on the forward strand of the contig
+    pub start: usize,
+    /// End of the matched region on the forward strand of the contig
+    pub end: usize,
+    /// Number of probes of the locus matched to the contig
+    pub probe_count: usize,
+    /// Number of probes of the locus
+    /// that matched other contigs only
+    pub conflicting_probes: usize,
 }
 
 impl BestContig {
@@ -173,6 +409,10 @@ impl BestContig {
             size: 0,
             duplicate_refs: 0,
             duplicate_contigs: 0,
+            start: 0,
+            end: 0,
+            probe_count: 0,
+            conflicting_probes: 0,
         }
     }
 
@@ -187,7 +427,22 @@ impl BestContig {
             size: output.size2,
             duplicate_refs: 0,
             duplicate_contigs: 0,
+            start: forward_coordinates(output).0,
+            end: forward_coordinates(output).1,
+            probe_count: 1,
+            conflicting_probes: 0,
+        }
+    }
+
+    fn collapse(&mut self, contig_name: &str, hits: &ProbeHits, total_probes: usize) {
+        if let Some(best) = hits.best {
+            self.update_best(best);
         }
+        self.contig_name = contig_name.to_string();
+        self.start = hits.start;
+        self.end = hits.end;
+        self.probe_count = hits.probes.len();
+        self.conflicting_probes = total_probes - hits.probes.len();
     }
 
     fn update_duplicates(&mut self, with_refs: bool) {
@@ -202,6 +457,11 @@ impl BestContig {
     }
 
     fn update(&mut self, output: &LastzGeneralOutput) {
+        self.update_best(output);
+        self.duplicate_contigs += 1;
+    }
+
+    fn update_best(&mut self, output: &LastzGeneralOutput) {
         self.contig_name = String::from(&output.name2);
         self.ref_name = String::from(&output.name1);
         self.strand = output.strand2;
@@ -209,7 +469,7 @@ impl BestContig {
         self.percent_identity = output.id_pct;
         self.percent_coverage = output.cov_pct;
         self.size = output.size2;
-        self.duplicate_contigs += 1;
+        (self.start, self.end) = forward_coordinates(output);
     }
 }
 
@@ -246,6 +506,8 @@ mod tests {
             coverage: String::from("100/100"),
             id_pct: 100.0,
             cov_pct: 100.0,
+            text1: None,
+            text2: None,
         };
         let lastz_output2 = LastzGeneralOutput {
             name1: String::from("uce-2_p1"),
@@ -263,6 +525,8 @@ mod tests {
             coverage: String::from("100/100"),
             id_pct: 100.0,
             cov_pct: 100.0,
+            text1: None,
+            text2: None,
         };
         let lastz_output3 = LastzGeneralOutput {
             name1: String::from("uce-2_p1"),
@@ -280,6 +544,8 @@ mod tests {
             coverage: String::from("100/100"),
             id_pct: 8.0,
             cov_pct: 8.0,
+            text1: None,
+            text2: None,
         };
         let lastz_output4 = LastzGeneralOutput {
             name1: String::from("uce-1_p1"),
@@ -297,6 +563,8 @@ mod tests {
             coverage: String::from("100/100"),
             id_pct: 8.0,
             cov_pct: 8.0,
+            text1: None,
+            text2: None,
         };
         let lastz_output = vec![lastz_output, lastz_output2, lastz_output3, lastz_output4];
         let report = MappingData::new(
@@ -314,4 +582,114 @@ mod tests {
         assert_eq!(best_contigs.get("uce-1").unwrap().duplicate_refs, 1);
         assert_eq!(best_contigs.get("uce-2").unwrap().duplicate_contigs, 1);
     }
+
+    #[test]
+    fn test_collapse_probes() {
+        let output = |name1: &str, name2: &str, score: usize, strand2: char, start: usize| {
+            LastzGeneralOutput {
+                score,
+                name1: String::from(name1),
+                name2: String::from(name2),
+                strand2,
+                size2: 1000,
+                zstart2: start,
+                end2: start + 100,
+                ..LastzGeneralOutput::new()
+            }
+        };
+        let lastz_output = vec![
+            output("uce-1_p1", "contig1", 500, '-', 100),
+            output("uce-1_p2", "contig1", 600, '-', 300),
+            // A single probe with a higher score
+            output("uce-1_p3", "contig2", 2000, '+', 0),
+        ];
+        let mut report = MappingData::new(
+            "test_contig",
+            Path::new("test"),
+            PathBuf::from("test"),
+            UCE_REGEX,
+        );
+        report.data = report.find_best_contigs(&lastz_output);
+        assert_eq!(report.data["uce-1"].contig_name, "contig2");
+        report.collapse_probes(&lastz_output);
+        let best = &report.data["uce-1"];
+        assert_eq!(best.contig_name, "contig1");
+        assert_eq!(best.strand, '-');
+        assert_eq!(best.score, 600);
+        assert_eq!((best.start, best.end), (600, 900));
+        assert_eq!(best.probe_count, 2);
+        assert_eq!(best.conflicting_probes, 1);
+    }
+
+    #[test]
+    fn test_collapsed_contig_wins_two_loci() {
+        let output = |name1: &str, name2: &str, score: usize| LastzGeneralOutput {
+            score,
+            name1: String::from(name1),
+            name2: String::from(name2),
+            size2: 1000,
+            end2: 100,
+            ..LastzGeneralOutput::new()
+        };
+        let lastz_output = vec![
+            output("uce-2_p1", "contig1", 3000),
+            output("uce-1_p3", "contig2", 2000),
+            output("uce-1_p1", "contig1", 500),
+            output("uce-1_p2", "contig1", 600),
+        ];
+        let mut report = MappingData::new(
+            "test_contig",
+            Path::new("test"),
+            PathBuf::from("test"),
+            UCE_REGEX,
+        );
+        report.data = report.find_best_contigs(&lastz_output);
+        assert_eq!(report.data["uce-1"].contig_name, "contig2");
+        assert_eq!(report.data["uce-2"].duplicate_refs, 0);
+        report.collapse_probes(&lastz_output);
+        report.flag_shared_contigs();
+        // Both loci keep contig1 and each is flagged once,
+        // although two probes of uce-1 matched contig1.
+        assert_eq!(report.data["uce-1"].contig_name, "contig1");
+        assert_eq!(report.data["uce-1"].probe_count, 2);
+        assert_eq!(report.data["uce-2"].contig_name, "contig1");
+        assert_eq!(report.data["uce-1"].duplicate_refs, 1);
+        assert_eq!(report.data["uce-2"].duplicate_refs, 1);
+    }
+
+    #[test]
+    fn test_multi_hits() {
+        let output = |name1: &str, name2: &str, score: usize| LastzGeneralOutput {
+            score,
+            name1: String::from(name1),
+            name2: String::from(name2),
+            ..LastzGeneralOutput::new()
+        };
+        let lastz_output = vec![
+            output("uce-1_p1", "contig1", 10000),
+            // Probes of the same locus are not multi-hits
+            output("uce-1_p2", "contig1", 9000),
+            output("uce-2_p1", "contig2", 6000),
+            output("uce-2_p1", "contig3", 4000),
+            output("uce-3_p1", "contig3", 5000),
+        ];
+        let mut report = MappingData::new(
+            "test_contig",
+            Path::new("test"),
+            PathBuf::from("test"),
+            UCE_REGEX,
+        );
+        report.data = report.find_best_contigs(&lastz_output);
+        let hits = report.find_multi_hits(&lastz_output);
+        assert_eq!(hits.len(), 4);
+        assert!(hits.iter().all(|hit| hit.locus != "uce-1"));
+        let multiple_contigs: Vec<&ParalogHit> = hits
+            .iter()
+            .filter(|hit| hit.hit_type == MultiHitType::MultipleContigs)
+            .collect();
+        assert_eq!(multiple_contigs.len(), 2);
+        assert!(multiple_contigs[0].selected);
+        assert_eq!(multiple_contigs[0].contig_name, "contig2");
+        assert!(!multiple_contigs[1].selected);
+    }
 }
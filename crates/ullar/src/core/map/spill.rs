@@ -0,0 +1,196 @@
+//! Spill mapped sequences to disk.
+//!
+//! Sequences of each sample are appended to bucket files
+//! grouped by locus name. Buckets are merged one at a time,
+//! so only the loci of a single bucket are held in memory.
+use std::{
+    collections::hash_map::DefaultHasher,
+    error::Error,
+    fs::{self, File},
+    hash::{Hash, Hasher},
+    io::{BufRead, BufReader, BufWriter, Write},
+    mem,
+    path::{Path, PathBuf},
+    sync::Mutex,
+};
+
+use segul::helper::types::SeqMatrix;
+
+use super::writer::MappedMatrix;
+
+pub const SPILL_DIR: &str = ".spill";
+
+/// Number of bucket files.
+/// Each bucket holds about 1/128 of the loci.
+const SPILL_BUCKETS: usize = 128;
+const SPILL_EXT: &str = "tsv";
+
+pub struct LocusSpill {
+    pub spill_dir: PathBuf,
+    buckets: Vec<Mutex<BufWriter<File>>>,
+}
+
+impl LocusSpill {
+    /// Create bucket files in the output directory.
+    /// Existing spill files are overwritten.
+    pub fn new(output_dir: &Path) -> Result<Self, Box<dyn Error>> {
+        let spill_dir = output_dir.join(SPILL_DIR);
+        fs::create_dir_all(&spill_dir)?;
+        let mut buckets = Vec::with_capacity(SPILL_BUCKETS);
+        for i in 0..SPILL_BUCKETS {
+            let file = File::create(Self::bucket_path(&spill_dir, i))?;
+            buckets.push(Mutex::new(BufWriter::new(file)));
+        }
+        Ok(Self { spill_dir, buckets })
+    }
+
+    /// Append the sequences of a sample.
+    /// Safe to call from multiple threads.
+    pub fn append(&self, matrix: &MappedMatrix) -> Result<(), Box<dyn Error>> {
+        let mut records: Vec<Vec<(&str, &str, &str)>> = vec![Vec::new(); SPILL_BUCKETS];
+        matrix.iter().for_each(|(locus, samples)| {
+            let bucket = &mut records[Self::bucket_index(locus)];
+            samples.iter().for_each(|(sample_name, sequence)| {
+                bucket.push((locus, sample_name, sequence));
+            });
+        });
+        for (i, records) in records.iter().enumerate() {
+            if records.is_empty() {
+                continue;
+            }
+            let mut writer = self.buckets[i].lock().expect("Failed to lock spill bucket");
+            for (locus, sample_name, sequence) in records {
+                writeln!(writer, "{}\t{}\t{}", locus, sample_name, sequence)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Merge each bucket into a matrix of its loci.
+    /// Samples of each locus are sorted by name.
+    /// The spill files are removed after merging.
+    pub fn merge<F>(mut self, mut write: F) -> Result<(), Box<dyn Error>>
+    where
+        F: FnMut(MappedMatrix),
+    {
+        for bucket in mem::take(&mut self.buckets) {
+            bucket
+                .into_inner()
+                .expect("Failed to unlock spill bucket")
+                .flush()?;
+        }
+        for i in 0..SPILL_BUCKETS {
+            let matrix = Self::read_bucket(&Self::bucket_path(&self.spill_dir, i))?;
+            if !matrix.is_empty() {
+                write(matrix);
+            }
+        }
+        fs::remove_dir_all(&self.spill_dir)?;
+        Ok(())
+    }
+
+    fn read_bucket(path: &Path) -> Result<MappedMatrix, Box<dyn Error>> {
+        let reader = BufReader::new(File::open(path)?);
+        let mut matrix = MappedMatrix::new();
+        for line in reader.lines() {
+            let line = line?;
+            let mut fields = line.splitn(3, '\t');
+            match (fields.next(), fields.next(), fields.next()) {
+                (Some(locus), Some(sample_name), Some(sequence)) => {
+                    matrix
+                        .entry(locus.to_string())
+                        .or_default()
+                        .insert(sample_name.to_string(), sequence.to_string());
+                }
+                _ => return Err(format!("Invalid spill record in {}", path.display()).into()),
+            }
+        }
+        matrix
+            .values_mut()
+            .for_each(|samples: &mut SeqMatrix| samples.sort_keys());
+        Ok(matrix)
+    }
+
+    fn bucket_index(locus: &str) -> usize {
+        let mut hasher = DefaultHasher::new();
+        locus.hash(&mut hasher);
+        (hasher.finish() % SPILL_BUCKETS as u64) as usize
+    }
+
+    fn bucket_path(spill_dir: &Path, index: usize) -> PathBuf {
+        spill_dir
+            .join(format!("bucket_{}", index))
+            .with_extension(SPILL_EXT)
+    }
+}
+
+// Remove the spill files if merging did not finish,
+// such as after an error or a panic while writing.
+impl Drop for LocusSpill {
+    fn drop(&mut self) {
+        // Close the bucket files first,
+        // so they can be removed on Windows.
+        self.buckets.clear();
+        if self.spill_dir.exists() {
+            let _ = fs::remove_dir_all(&self.spill_dir);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use indexmap::IndexMap;
+
+    use super::*;
+
+    fn sample_matrix(sample_name: &str, loci: &[(&str, &str)]) -> MappedMatrix {
+        loci.iter()
+            .map(|(locus, sequence)| {
+                let mut samples = IndexMap::new();
+                samples.insert(sample_name.to_string(), sequence.to_string());
+                (locus.to_string(), samples)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_locus_spill() {
+        let output_dir = tempdir::TempDir::new("spill").unwrap();
+        let spill = LocusSpill::new(output_dir.path()).unwrap();
+        spill
+            .append(&sample_matrix(
+                "sample_2",
+                &[("uce-1", "ACGT"), ("uce-2", "GG")],
+            ))
+            .unwrap();
+        spill
+            .append(&sample_matrix("sample_1", &[("uce-1", "AC-T")]))
+            .unwrap();
+        let mut merged = MappedMatrix::new();
+        spill.merge(|matrix| merged.extend(matrix)).unwrap();
+        assert!(!output_dir.path().join(SPILL_DIR).exists());
+        assert_eq!(merged.len(), 2);
+        let samples: Vec<(&String, &String)> = merged["uce-1"].iter().collect();
+        assert_eq!(
+            samples[0],
+            (&String::from("sample_1"), &String::from("AC-T"))
+        );
+        assert_eq!(
+            samples[1],
+            (&String::from("sample_2"), &String::from("ACGT"))
+        );
+        assert_eq!(merged["uce-2"]["sample_2"], "GG");
+    }
+
+    #[test]
+    fn test_locus_spill_dropped() {
+        let output_dir = tempdir::TempDir::new("spill").unwrap();
+        let spill = LocusSpill::new(output_dir.path()).unwrap();
+        spill
+            .append(&sample_matrix("sample_1", &[("uce-1", "ACGT")]))
+            .unwrap();
+        assert!(output_dir.path().join(SPILL_DIR).exists());
+        drop(spill);
+        assert!(!output_dir.path().join(SPILL_DIR).exists());
+    }
+}
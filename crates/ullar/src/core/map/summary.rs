@@ -1,7 +1,52 @@
-use segul::helper::types::SeqMatrix;
+use std::collections::HashMap;
+
+use indexmap::IndexMap;
 use serde::{Deserialize, Serialize};
 
-use super::writer::MappedMatrix;
+use super::{
+    reports::{BestContig, MappingData},
+    writer::MappedMatrix,
+};
+
+/// Sequence lengths of the mapped loci.
+/// Summaries only need the lengths, so sequences
+/// can be written to disk before summarizing.
+/// The key is the locus name and the value
+/// is the sequence length of each sample.
+pub type LengthMatrix = HashMap<String, IndexMap<String, SequenceLength>>;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct SequenceLength {
+    /// Length including gaps
+    pub length: usize,
+    /// Length excluding gaps and missing data
+    pub bases: usize,
+}
+
+impl SequenceLength {
+    pub fn new(sequence: &str) -> Self {
+        Self {
+            length: sequence.len(),
+            bases: sequence_length(sequence),
+        }
+    }
+}
+
+/// Get the sequence lengths of the mapped loci
+pub fn length_matrix(matrix: &MappedMatrix) -> LengthMatrix {
+    matrix
+        .iter()
+        .map(|(locus, samples)| {
+            let lengths = samples
+                .iter()
+                .map(|(sample_name, sequence)| {
+                    (sample_name.to_string(), SequenceLength::new(sequence))
+                })
+                .collect();
+            (locus.to_string(), lengths)
+        })
+        .collect()
+}
 
 #[derive(Debug, Default, Deserialize, Serialize)]
 pub struct FinalMappingSummary {
@@ -30,7 +75,7 @@ impl FinalMappingSummary {
 
     /// Summarize the matches for each reference sequence.
     /// Returns true if the reference sequence is found in the data.
-    pub fn summarize(&mut self, data: &MappedMatrix) {
+    pub fn summarize(&mut self, data: &LengthMatrix) {
         self.total_matches = data.len();
         self.total_percent_coverage = self.calculate_percent_matches();
     }
@@ -40,6 +85,82 @@ impl FinalMappingSummary {
     }
 }
 
+/// Mapping statistics of a sample across all loci.
+/// Used to spot samples with poor recovery.
+#[derive(Debug, Default, Deserialize, Serialize)]
+pub struct SampleMappingSummary {
+    pub sample_name: String,
+    /// Total number of loci recovered
+    pub loci_recovered: usize,
+    /// Percentage of reference loci recovered
+    pub percent_reference: f64,
+    /// Total length of the recovered sequences
+    /// excluding gaps
+    pub total_length: usize,
+    /// Mean percent identity of the best alignment
+    /// of each recovered locus. Only available for
+    /// Lastz general output.
+    pub mean_identity: Option<f64>,
+    /// Mean percent coverage of the best alignment
+    /// of each recovered locus. Only available for
+    /// Lastz general output.
+    pub mean_coverage: Option<f64>,
+}
+
+impl SampleMappingSummary {
+    pub fn new(sample_name: &str) -> Self {
+        Self {
+            sample_name: sample_name.to_string(),
+            ..Default::default()
+        }
+    }
+
+    /// Count the loci recovered by the sample.
+    /// `total_references` is the number of loci in the reference.
+    pub fn summarize(&mut self, data: &LengthMatrix, total_references: usize) {
+        data.values().for_each(|matrix| {
+            if let Some(length) = matrix.get(&self.sample_name) {
+                self.loci_recovered += 1;
+                self.total_length += length.bases;
+            }
+        });
+        if total_references > 0 {
+            self.percent_reference = self.loci_recovered as f64 / total_references as f64 * 100.0;
+        }
+    }
+
+    /// Mean identity and coverage of the best contigs
+    /// of the loci written to the matrix for this sample.
+    pub fn summarize_alignments(&mut self, data: &MappingData, matrix: &LengthMatrix) {
+        let contigs: Vec<&BestContig> = data
+            .data
+            .iter()
+            .filter(|(locus, _)| {
+                matrix
+                    .get(*locus)
+                    .is_some_and(|m| m.contains_key(&data.sample_name))
+            })
+            .map(|(_, contig)| contig)
+            .collect();
+        if contigs.is_empty() {
+            return;
+        }
+        let count = contigs.len() as f64;
+        let identity = contigs.iter().map(|c| c.percent_identity).sum::<f64>();
+        let coverage = contigs.iter().map(|c| c.percent_coverage).sum::<f64>();
+        self.mean_identity = Some(identity / count);
+        self.mean_coverage = Some(coverage / count);
+    }
+}
+
+/// Sequence length excluding gaps and missing data
+pub fn sequence_length(sequence: &str) -> usize {
+    sequence
+        .bytes()
+        .filter(|b| !matches!(b, b'-' | b'?' | b'.'))
+        .count()
+}
+
 /// Summary of the contigs mapped to the reference sequence.
 #[derive(Debug, Default, Deserialize, Serialize)]
 pub struct FinalContigSummary {
@@ -79,7 +200,7 @@ impl FinalContigSummary {
 
     /// Summarize the matches for each reference sequence.
     /// Returns true if the reference sequence is found in the data.
-    pub fn summarize_matches(&mut self, data: &MappedMatrix) -> bool {
+    pub fn summarize_matches(&mut self, data: &LengthMatrix) -> bool {
         if data.get(&self.reference_name).is_none() {
             return false;
         }
@@ -102,8 +223,8 @@ impl FinalContigSummary {
         (self.total_sample_matches as f64 / self.total_samples as f64) * 100.0
     }
 
-    fn count_total_sequence_length(&self, matrix: &SeqMatrix) -> Vec<f64> {
-        matrix.iter().map(|(_, seq)| seq.len() as f64).collect()
+    fn count_total_sequence_length(&self, matrix: &IndexMap<String, SequenceLength>) -> Vec<f64> {
+        matrix.iter().map(|(_, seq)| seq.length as f64).collect()
     }
 
     fn calculate_mean_sequence_length(&self, total_sequence_length: f64) -> f64 {
@@ -120,19 +241,73 @@ impl FinalContigSummary {
         }
     }
 
-    fn calculate_min_sequence_length(&self, matrix: &SeqMatrix) -> usize {
+    fn calculate_min_sequence_length(&self, matrix: &IndexMap<String, SequenceLength>) -> usize {
         matrix
             .iter()
-            .map(|(_, seq)| seq.len())
+            .map(|(_, seq)| seq.length)
             .min_by(|a, b| a.partial_cmp(b).expect("Failed to compare"))
             .expect("Failed to get min sequence length")
     }
 
-    fn calculate_max_sequence_length(&self, matrix: &SeqMatrix) -> usize {
+    fn calculate_max_sequence_length(&self, matrix: &IndexMap<String, SequenceLength>) -> usize {
         matrix
             .iter()
-            .map(|(_, seq)| seq.len())
+            .map(|(_, seq)| seq.length)
             .max_by(|a, b| a.partial_cmp(b).expect("Failed to compare"))
             .expect("Failed to get max sequence length")
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::path::{Path, PathBuf};
+
+    use crate::helper::regex::UCE_REGEX;
+
+    use super::*;
+
+    fn best_contig(identity: f64, coverage: f64) -> BestContig {
+        let mut contig = BestContig::new();
+        contig.percent_identity = identity;
+        contig.percent_coverage = coverage;
+        contig
+    }
+
+    #[test]
+    fn test_summarize_alignments_kept_loci() {
+        let mut sample_1 = MappingData::new("sample_1", Path::new("."), PathBuf::new(), UCE_REGEX);
+        sample_1
+            .data
+            .insert("uce-1".to_string(), best_contig(90.0, 80.0));
+        sample_1
+            .data
+            .insert("uce-2".to_string(), best_contig(100.0, 100.0));
+        let mut sample_2 = MappingData::new("sample_2", Path::new("."), PathBuf::new(), UCE_REGEX);
+        sample_2
+            .data
+            .insert("uce-1".to_string(), best_contig(50.0, 40.0));
+        sample_2
+            .data
+            .insert("uce-2".to_string(), best_contig(70.0, 60.0));
+
+        // Both samples keep uce-2, only sample_1 keeps uce-1
+        let mut matrix = LengthMatrix::new();
+        let length = SequenceLength::new("ACGT");
+        matrix
+            .entry("uce-1".to_string())
+            .or_default()
+            .insert("sample_1".to_string(), length);
+        let uce_2 = matrix.entry("uce-2".to_string()).or_default();
+        uce_2.insert("sample_1".to_string(), length);
+        uce_2.insert("sample_2".to_string(), length);
+
+        let mut summary_1 = SampleMappingSummary::new("sample_1");
+        summary_1.summarize_alignments(&sample_1, &matrix);
+        assert_eq!(summary_1.mean_identity, Some(95.0));
+        assert_eq!(summary_1.mean_coverage, Some(90.0));
+        let mut summary_2 = SampleMappingSummary::new("sample_2");
+        summary_2.summarize_alignments(&sample_2, &matrix);
+        assert_eq!(summary_2.mean_identity, Some(70.0));
+        assert_eq!(summary_2.mean_coverage, Some(60.0));
+    }
+}
@@ -1,22 +1,25 @@
 /// Write results
 use core::str;
 use std::{
-    collections::{hash_map::Entry, HashMap},
+    collections::{hash_map::Entry, BTreeMap, BTreeSet, HashMap, HashSet},
+    error::Error,
     fs::{self, File},
-    io::BufReader,
+    io::{self, BufReader},
+    mem,
     path::{Path, PathBuf},
     sync::mpsc,
 };
 
 use bio::alphabets::dna;
 use colored::Colorize;
+use flate2::{write::GzEncoder, Compression};
 use indexmap::IndexMap;
 use rayon::prelude::*;
 use segul::{
     helper::{
         finder::IDs,
         sequence::SeqParser,
-        types::{self, DataType, Header, OutputFmt, SeqMatrix},
+        types::{self, DataType, Header, SeqMatrix},
     },
     parser::maf::{MafAlignment, MafParagraph, MafReader},
     writer::sequences::SeqWriter,
@@ -25,14 +28,18 @@ use segul::{
 use crate::helper::common;
 
 use super::{
-    configs::ReferenceFile,
-    reports::MappingData,
-    summary::{FinalContigSummary, FinalMappingSummary},
+    configs::{ReferenceFile, SequenceOutput},
+    regions::{write_region_coverage, RegionCoverage, RegionExtractor, RegionHit, TargetRegion},
+    reports::{BestContig, ExonMappingReport, MappingData, ReadMappingReport},
+    spill::LocusSpill,
+    summary::{self, FinalContigSummary, FinalMappingSummary, LengthMatrix, SampleMappingSummary},
 };
 
 pub const DEFAULT_UNALIGN_SEQUENCE_OUTPUT_DIR: &str = "sequences";
 pub const SUMMARY_FILE_STEM: &str = "mapping_summary";
 pub const SUMMARY_EXT: &str = "csv";
+pub const OCCUPANCY_FILE_STEM: &str = "occupancy_matrix";
+pub const SAMPLE_SUMMARY_FILE_STEM: &str = "sample_summary";
 
 pub type MappedMatrix = HashMap<String, SeqMatrix>;
 
@@ -47,73 +54,164 @@ trait MappingWriter {
         }
     }
 
-    fn write_sequences(&self, final_matrix: &MappedMatrix, output_dir: &Path) {
-        let progress_bar = common::init_progress_bar(final_matrix.len() as u64);
-        progress_bar.set_message("matched loci");
+    /// Format of the sequence files.
+    /// Interleaved FASTA by default.
+    fn get_sequence_output(&self) -> SequenceOutput {
+        SequenceOutput::default()
+    }
+
+    /// Write loci spilled to disk one bucket at a time.
+    /// Returns the sequence lengths of the loci for the summary.
+    fn write_spilled_sequences(&self, spill: LocusSpill, output_dir: &Path) -> LengthMatrix {
+        let spinner = common::init_spinner();
+        spinner.set_message("Writing matched loci");
         let output_dir = output_dir.join(DEFAULT_UNALIGN_SEQUENCE_OUTPUT_DIR);
         fs::create_dir_all(&output_dir).expect("Failed to create output directory");
-        let output_fmt = OutputFmt::FastaInt;
-        final_matrix.par_iter().for_each(|(refname, contigs)| {
-            let file_name = format!("{}.fas", refname);
-            let output_path = output_dir.join(file_name);
-            let mut header = Header::default();
-            header.from_seq_matrix(&contigs, false);
-            let mut writer = SeqWriter::new(&output_path, contigs, &header);
-            writer
-                .write_sequence(&output_fmt)
-                .expect("Failed to write sequences");
-            progress_bar.inc(1);
-        });
-        progress_bar.finish_with_message(format!("{} {}\n", "✔".green(), "matched loci"));
+        let output = self.get_sequence_output();
+        let mut lengths = LengthMatrix::new();
+        spill
+            .merge(|matrix| {
+                matrix.par_iter().for_each(|(refname, contigs)| {
+                    write_locus(&output_dir, refname, contigs, &output);
+                });
+                lengths.extend(summary::length_matrix(&matrix));
+            })
+            .expect("Failed to merge mapped loci");
+        spinner.finish_with_message(format!(
+            "{} Finished writing {} matched loci\n",
+            "✔".green(),
+            lengths.len()
+        ));
+        lengths
+    }
+}
+
+/// Loci of a single sample.
+/// Used to spill per-sample results to disk.
+fn sample_matrix<'s, I>(sample_name: &str, sequences: I) -> MappedMatrix
+where
+    I: IntoIterator<Item = (&'s String, &'s String)>,
+{
+    sequences
+        .into_iter()
+        .map(|(locus, sequence)| {
+            let mut samples = SeqMatrix::new();
+            samples.insert(sample_name.to_string(), sequence.to_string());
+            (locus.to_string(), samples)
+        })
+        .collect()
+}
+
+fn write_locus(output_dir: &Path, refname: &str, contigs: &SeqMatrix, output: &SequenceOutput) {
+    let file_name = format!("{}.{}", refname, output.format.extension());
+    let output_path = output_dir.join(file_name);
+    let mut header = Header::default();
+    header.from_seq_matrix(contigs, false);
+    let mut writer = SeqWriter::new(&output_path, contigs, &header);
+    writer
+        .write_sequence(&output.format.to_output_fmt())
+        .expect("Failed to write sequences");
+    if output.gzip {
+        compress_file(&output_path).expect("Failed to compress sequences");
     }
 }
 
+// Replace the file with its gzip-compressed copy
+fn compress_file(path: &Path) -> Result<PathBuf, Box<dyn Error>> {
+    let mut file_name = path.file_name().unwrap_or_default().to_os_string();
+    file_name.push(".gz");
+    let output_path = path.with_file_name(file_name);
+    let mut input = File::open(path)?;
+    let mut encoder = GzEncoder::new(File::create(&output_path)?, Compression::default());
+    io::copy(&mut input, &mut encoder)?;
+    encoder.finish()?;
+    fs::remove_file(path)?;
+    Ok(output_path)
+}
+
 pub struct ProbeMappingWriter<'a> {
     pub output_dir: &'a Path,
     pub reference_data: &'a ReferenceFile,
+    /// Loci excluded from the written sequences
+    pub excluded_loci: Option<&'a HashSet<String>>,
+    /// Write only the matched region plus flanks of this length.
+    /// Write the entire contig if None.
+    pub flank_length: Option<usize>,
+    /// Format of the written sequences
+    pub sequence_output: SequenceOutput,
 }
 
-impl<'a> MappingWriter for ProbeMappingWriter<'a> {}
+impl<'a> MappingWriter for ProbeMappingWriter<'a> {
+    fn get_sequence_output(&self) -> SequenceOutput {
+        self.sequence_output.clone()
+    }
+}
 
 impl<'a> ProbeMappingWriter<'a> {
     pub fn new(output_dir: &'a Path, reference_data: &'a ReferenceFile) -> Self {
         Self {
             output_dir,
             reference_data,
+            excluded_loci: None,
+            flank_length: None,
+            sequence_output: SequenceOutput::default(),
         }
     }
 
+    pub fn with_sequence_output(mut self, output: &SequenceOutput) -> Self {
+        self.sequence_output = output.clone();
+        self
+    }
+
+    pub fn exclude_loci(mut self, loci: &'a HashSet<String>) -> Self {
+        self.excluded_loci = Some(loci);
+        self
+    }
+
+    pub fn flank_length(mut self, flank_length: Option<usize>) -> Self {
+        self.flank_length = flank_length;
+        self
+    }
+
     /// Writer for general lastz output.
     /// Matches contigs to probes.
     /// Pulls entire sequences that match the reference sequence.
     pub fn write_general(&self, mapping_data: &[MappingData]) -> FinalMappingSummary {
         log::info!("{}", "Mapping and filtering duplicate matches...");
-        let final_matrix = self.map_contig_to_probe(mapping_data);
+        let spill = LocusSpill::new(self.output_dir).expect("Failed to create spill files");
+        self.map_contig_to_probe(mapping_data, &spill);
         log::info!("{}", "Writing contigs to file...");
-        self.write_sequences(&final_matrix, self.output_dir);
+        let lengths = self.write_spilled_sequences(spill, self.output_dir);
         log::info!("{}", "Writing summary to file...");
         let total_samples = mapping_data.len();
-        let mut summary_writer = SummaryWriter::new(self.output_dir, &final_matrix, total_samples);
+        let mut summary_writer = SummaryWriter::new(self.output_dir, &lengths, total_samples)
+            .alignment_stats(mapping_data);
         summary_writer.write(self.reference_data)
     }
 
-    // All contigs mapped to reference sequence. Key is the reference sequence name
-    // and value is a map of sample name and contig sequence.
-    // This allow us to keep track of the contig name and the sample name.
-    fn map_contig_to_probe(&self, mapping_data: &[MappingData]) -> HashMap<String, SeqMatrix> {
+    // Contigs of each sample mapped to reference sequence. Key is the reference
+    // sequence name and value is a map of sample name and contig sequence.
+    // Each sample is spilled to disk, so only one sample is kept in memory per thread.
+    fn map_contig_to_probe(&self, mapping_data: &[MappingData], spill: &LocusSpill) {
         let progress_bar = common::init_progress_bar(mapping_data.len() as u64);
-        let mut final_matrix: MappedMatrix = HashMap::new();
         let msg = "samples";
         progress_bar.set_message(msg);
-        let (tx, rx) = mpsc::channel();
-        mapping_data.par_iter().for_each_with(tx, |tx, data| {
+        mapping_data.par_iter().for_each(|data| {
             let mut matrix: MappedMatrix = HashMap::new();
             let (mut seq, _) =
                 SeqParser::new(&data.contig_path, &DataType::Dna).parse(&types::InputFmt::Fasta);
-            data.data.iter().for_each(|(refname, contig)| {
+            let included = data.data.iter().filter(|(refname, _)| {
+                self.excluded_loci
+                    .is_none_or(|excluded| !excluded.contains(*refname))
+            });
+            included.for_each(|(refname, contig)| {
                 let sequence = seq
                     .get(&contig.contig_name)
                     .expect("Failed to get contig names. Check if contig names inside the FASTA file are correct.");
+                let sequence = match self.flank_length {
+                    Some(flank) => self.get_matched_region(sequence, contig, flank),
+                    None => sequence,
+                };
                 let sequence = self.get_sequence(sequence, contig.strand);
                 if matrix.contains_key(refname) {
                     let seq_matrix = matrix.get_mut(refname).unwrap();
@@ -125,28 +223,22 @@ impl<'a> ProbeMappingWriter<'a> {
                 }
             });
             seq.clear();
-            tx.send((data.sample_name.to_string(), matrix))
-                .expect("Failed to send data");
+            spill.append(&matrix).expect("Failed to spill mapped contigs");
             progress_bar.inc(1);
         });
-
-        rx.iter().for_each(|(_, matrix)| {
-            self.create_mapped_matrix(&mut final_matrix, matrix);
-        });
-
         progress_bar.finish_with_message(format!("{} {}\n", "✔".green(), msg));
-        final_matrix
     }
 
-    fn create_mapped_matrix(&self, final_matrix: &mut MappedMatrix, matrix: MappedMatrix) {
-        matrix.iter().for_each(|(refname, contigs)| {
-            if final_matrix.contains_key(refname) {
-                let seq_matrix = final_matrix.get_mut(refname).unwrap();
-                seq_matrix.extend(contigs.to_owned());
-            } else {
-                final_matrix.insert(refname.to_string(), contigs.to_owned());
-            }
-        });
+    // Matched region coordinates are on the forward strand
+    fn get_matched_region<'s>(
+        &self,
+        sequence: &'s str,
+        contig: &BestContig,
+        flank: usize,
+    ) -> &'s str {
+        let start = contig.start.saturating_sub(flank);
+        let end = contig.end.saturating_add(flank).min(sequence.len());
+        sequence.get(start..end).unwrap_or(sequence)
     }
 }
 
@@ -154,9 +246,15 @@ pub struct LocusMappingWriter<'a> {
     pub output_dir: &'a Path,
     pub reference: &'a ReferenceFile,
     pub maf_files: &'a [PathBuf],
+    /// Format of the written sequences
+    pub sequence_output: SequenceOutput,
 }
 
-impl<'a> MappingWriter for LocusMappingWriter<'a> {}
+impl<'a> MappingWriter for LocusMappingWriter<'a> {
+    fn get_sequence_output(&self) -> SequenceOutput {
+        self.sequence_output.clone()
+    }
+}
 
 impl<'a> LocusMappingWriter<'a> {
     pub fn new(
@@ -168,45 +266,37 @@ impl<'a> LocusMappingWriter<'a> {
             output_dir,
             maf_files,
             reference,
+            sequence_output: SequenceOutput::default(),
         }
     }
 
+    pub fn with_sequence_output(mut self, output: &SequenceOutput) -> Self {
+        self.sequence_output = output.clone();
+        self
+    }
+
     pub fn write(&self) -> FinalMappingSummary {
         log::info!("{}", "Parsing and filtering duplicate matches...");
-        let final_matrix = self.parse_samples();
+        let spill = LocusSpill::new(self.output_dir).expect("Failed to create spill files");
+        self.parse_samples(&spill);
         log::info!("{}", "Writing contigs to file...");
-        self.write_sequences(&final_matrix, self.output_dir);
+        let lengths = self.write_spilled_sequences(spill, self.output_dir);
         log::info!("{}", "Writing summary to file...");
         let total_samples = self.maf_files.len();
-        let mut summary_writer = SummaryWriter::new(self.output_dir, &final_matrix, total_samples);
-        let summary = summary_writer.write(self.reference);
-        summary
+        let mut summary_writer = SummaryWriter::new(self.output_dir, &lengths, total_samples);
+        summary_writer.write(self.reference)
     }
 
-    fn parse_samples(&self) -> MappedMatrix {
+    // Each sample is spilled to disk after parsing
+    fn parse_samples(&self, spill: &LocusSpill) {
         let progress_bar = common::init_progress_bar(self.maf_files.len() as u64);
         progress_bar.set_message("samples");
-        let mut final_matrix = MappedMatrix::new();
-        let (tx, rx) = mpsc::channel();
-        self.maf_files.par_iter().for_each_with(tx, |tx, path| {
+        self.maf_files.par_iter().for_each(|path| {
             let matrix = self.parse_maf(path);
-            tx.send(matrix).expect("Failed to send data");
+            spill.append(&matrix).expect("Failed to spill mapped loci");
             progress_bar.inc(1);
         });
-        rx.iter().for_each(|matrix| {
-            matrix.iter().for_each(|(refname, contigs)| {
-                if final_matrix.contains_key(refname) {
-                    let seq_matrix = final_matrix
-                        .get_mut(refname)
-                        .expect("Failed to get final matrix");
-                    seq_matrix.extend(contigs.to_owned());
-                } else {
-                    final_matrix.insert(refname.to_string(), contigs.to_owned());
-                }
-            });
-        });
         progress_bar.finish_with_message(format!("{} samples\n", "✔".green()));
-        final_matrix
     }
 
     fn parse_maf(&self, maf_path: &Path) -> MappedMatrix {
@@ -298,35 +388,227 @@ impl<'a> LocusMappingWriter<'a> {
     }
 }
 
+pub struct ReadMappingWriter<'a> {
+    pub output_dir: &'a Path,
+    pub reference_data: &'a ReferenceFile,
+}
+
+impl<'a> MappingWriter for ReadMappingWriter<'a> {}
+
+impl<'a> ReadMappingWriter<'a> {
+    pub fn new(output_dir: &'a Path, reference_data: &'a ReferenceFile) -> Self {
+        Self {
+            output_dir,
+            reference_data,
+        }
+    }
+
+    /// Writer for consensus sequences of mapped reads.
+    /// Each locus is written to the same layout as mapped contigs.
+    pub fn write(&self, reports: &[ReadMappingReport]) -> FinalMappingSummary {
+        let spill = LocusSpill::new(self.output_dir).expect("Failed to create spill files");
+        reports.par_iter().for_each(|report| {
+            let matrix = sample_matrix(&report.sample_name, &report.consensus);
+            spill
+                .append(&matrix)
+                .expect("Failed to spill consensus sequences");
+        });
+        log::info!("{}", "Writing consensus sequences to file...");
+        let lengths = self.write_spilled_sequences(spill, self.output_dir);
+        log::info!("{}", "Writing summary to file...");
+        let mut summary_writer = SummaryWriter::new(self.output_dir, &lengths, reports.len());
+        summary_writer.write(self.reference_data)
+    }
+}
+
+pub struct RegionMappingWriter<'a> {
+    pub output_dir: &'a Path,
+    pub maf_files: &'a [PathBuf],
+    pub regions: &'a [TargetRegion],
+    /// Format of the written sequences
+    pub sequence_output: SequenceOutput,
+}
+
+impl<'a> MappingWriter for RegionMappingWriter<'a> {
+    fn get_sequence_output(&self) -> SequenceOutput {
+        self.sequence_output.clone()
+    }
+}
+
+impl<'a> RegionMappingWriter<'a> {
+    pub fn new(
+        output_dir: &'a Path,
+        maf_files: &'a [PathBuf],
+        regions: &'a [TargetRegion],
+    ) -> Self {
+        Self {
+            output_dir,
+            maf_files,
+            regions,
+            sequence_output: SequenceOutput::default(),
+        }
+    }
+
+    pub fn with_sequence_output(mut self, output: &SequenceOutput) -> Self {
+        self.sequence_output = output.clone();
+        self
+    }
+
+    /// Writer for target regions of contigs mapped to a genome.
+    /// Each region is written as a locus.
+    pub fn write(&self) -> FinalMappingSummary {
+        log::info!("{}", "Extracting target regions...");
+        let spill = LocusSpill::new(self.output_dir).expect("Failed to create spill files");
+        let samples = self.parse_samples(&spill);
+        log::info!("{}", "Writing regions to file...");
+        let lengths = self.write_spilled_sequences(spill, self.output_dir);
+        self.write_coverage(&samples);
+        log::info!("{}", "Writing summary to file...");
+        let region_names: Vec<String> = self.regions.iter().map(|r| r.name.to_string()).collect();
+        let mut summary_writer =
+            SummaryWriter::new(self.output_dir, &lengths, self.maf_files.len());
+        summary_writer.write_references(&region_names)
+    }
+
+    // Sample name and the best hit of each region.
+    // Region sequences are spilled to disk,
+    // so only their coverage is kept in memory.
+    fn parse_samples(&self, spill: &LocusSpill) -> Vec<(String, BTreeMap<String, RegionHit>)> {
+        let progress_bar = common::init_progress_bar(self.maf_files.len() as u64);
+        progress_bar.set_message("samples");
+        let extractor = RegionExtractor::new(self.regions);
+        let (tx, rx) = mpsc::channel();
+        self.maf_files.par_iter().for_each_with(tx, |tx, path| {
+            let sample_name = path
+                .file_stem()
+                .expect("Failed to get file name")
+                .to_string_lossy()
+                .to_string();
+            match extractor.extract_maf(path) {
+                Ok(mut hits) => {
+                    let mut matrix = MappedMatrix::new();
+                    hits.iter_mut().for_each(|(region, hit)| {
+                        let mut samples = SeqMatrix::new();
+                        samples.insert(sample_name.to_string(), mem::take(&mut hit.sequence));
+                        matrix.insert(region.to_string(), samples);
+                    });
+                    spill
+                        .append(&matrix)
+                        .expect("Failed to spill target regions");
+                    tx.send((sample_name, hits)).expect("Failed to send data");
+                }
+                Err(e) => log::error!("Failed to parse {}: {}", path.display(), e),
+            }
+            progress_bar.inc(1);
+        });
+        let mut samples: Vec<(String, BTreeMap<String, RegionHit>)> = rx.iter().collect();
+        samples.sort_by(|a, b| a.0.cmp(&b.0));
+        progress_bar.finish_with_message(format!("{} samples\n", "✔".green()));
+        samples
+    }
+
+    fn write_coverage(&self, samples: &[(String, BTreeMap<String, RegionHit>)]) {
+        let coverage: Vec<RegionCoverage> = self
+            .regions
+            .iter()
+            .flat_map(|region| {
+                samples.iter().map(move |(sample_name, hits)| {
+                    RegionCoverage::new(region, sample_name, hits.get(&region.name))
+                })
+            })
+            .collect();
+        match write_region_coverage(self.output_dir, &coverage) {
+            Ok(path) => log::info!("{:18}: {}", "Region coverage", path.display()),
+            Err(e) => log::error!("Failed to write region coverage: {}", e),
+        }
+    }
+}
+
+pub struct ExonMappingWriter<'a> {
+    pub output_dir: &'a Path,
+    pub reference_data: &'a ReferenceFile,
+    /// Format of the written sequences
+    pub sequence_output: SequenceOutput,
+}
+
+impl<'a> MappingWriter for ExonMappingWriter<'a> {
+    fn get_sequence_output(&self) -> SequenceOutput {
+        self.sequence_output.clone()
+    }
+}
+
+impl<'a> ExonMappingWriter<'a> {
+    pub fn new(output_dir: &'a Path, reference_data: &'a ReferenceFile) -> Self {
+        Self {
+            output_dir,
+            reference_data,
+            sequence_output: SequenceOutput::default(),
+        }
+    }
+
+    pub fn with_sequence_output(mut self, output: &SequenceOutput) -> Self {
+        self.sequence_output = output.clone();
+        self
+    }
+
+    /// Writer for coding sequences extracted by Exonerate.
+    /// Sequences are already in the reference orientation.
+    pub fn write(&self, reports: &[ExonMappingReport]) -> FinalMappingSummary {
+        let spill = LocusSpill::new(self.output_dir).expect("Failed to create spill files");
+        reports.par_iter().for_each(|report| {
+            let matrix = sample_matrix(&report.sample_name, &report.exons);
+            spill.append(&matrix).expect("Failed to spill exons");
+        });
+        log::info!("{}", "Writing exons to file...");
+        let lengths = self.write_spilled_sequences(spill, self.output_dir);
+        log::info!("{}", "Writing summary to file...");
+        let mut summary_writer = SummaryWriter::new(self.output_dir, &lengths, reports.len());
+        summary_writer.write(self.reference_data)
+    }
+}
+
 pub struct SummaryWriter<'a> {
     pub output_dir: &'a Path,
     /// Total number of reference sequences
     /// or loci in the reference sequence.
     pub reference_counts: usize,
-    pub mapped_matrix: &'a MappedMatrix,
+    /// Sequence lengths of the mapped loci
+    pub lengths: &'a LengthMatrix,
     /// Total number of samples
     pub total_samples: usize,
+    /// Lastz mapping data used to summarize
+    /// alignment identity and coverage of each sample
+    pub mapping_data: Option<&'a [MappingData]>,
 }
 
 impl<'a> SummaryWriter<'a> {
-    pub fn new(
-        output_dir: &'a Path,
-        mapped_matrix: &'a MappedMatrix,
-        total_samples: usize,
-    ) -> Self {
+    pub fn new(output_dir: &'a Path, lengths: &'a LengthMatrix, total_samples: usize) -> Self {
         Self {
             output_dir,
             reference_counts: 0,
-            mapped_matrix,
+            lengths,
             total_samples,
+            mapping_data: None,
         }
     }
 
+    pub fn alignment_stats(mut self, mapping_data: &'a [MappingData]) -> Self {
+        self.mapping_data = Some(mapping_data);
+        self
+    }
+
     pub fn write(&mut self, reference_data: &ReferenceFile) -> FinalMappingSummary {
         let ref_names = self.count_references(reference_data);
+        self.write_references(&ref_names)
+    }
+
+    /// Write the summary of the given reference names.
+    /// Used when loci are not the reference sequences,
+    /// such as target regions of a genome.
+    pub fn write_references(&mut self, ref_names: &[String]) -> FinalMappingSummary {
         self.reference_counts = ref_names.len();
         let mut summary = FinalMappingSummary::new(self.reference_counts);
-        summary.summarize(self.mapped_matrix);
+        summary.summarize(self.lengths);
         let spinner = common::init_spinner();
         let msg = "Writing summary to file...";
         spinner.set_message(msg);
@@ -338,15 +620,93 @@ impl<'a> SummaryWriter<'a> {
                 .serialize(summary)
                 .expect("Failed to write summary to file");
         });
+        writer.flush().expect("Failed to write summary to file");
+        if let Err(e) = self.write_occupancy(ref_names) {
+            log::error!("Failed to write occupancy matrix: {}", e);
+        }
+        if let Err(e) = self.write_sample_summary() {
+            log::error!("Failed to write sample summary: {}", e);
+        }
         spinner.finish_with_message(format!("{} Finished writing summary\n", "✔".green()));
         summary
     }
 
+    /// Write a sample by locus matrix of recovered sequence lengths.
+    /// Loci that are not recovered have a length of 0.
+    pub fn write_occupancy(&self, ref_names: &[String]) -> Result<PathBuf, Box<dyn Error>> {
+        let output_path = self.create_output_file(OCCUPANCY_FILE_STEM);
+        let mut writer = csv::Writer::from_path(&output_path)?;
+        let mut header = vec!["sample_name"];
+        header.extend(ref_names.iter().map(|name| name.as_str()));
+        writer.write_record(&header)?;
+        for sample_name in self.sample_names() {
+            let mut record = vec![sample_name.to_string()];
+            ref_names.iter().for_each(|name| {
+                let length = self
+                    .lengths
+                    .get(name)
+                    .and_then(|matrix| matrix.get(&sample_name))
+                    .map(|length| length.bases)
+                    .unwrap_or(0);
+                record.push(length.to_string());
+            });
+            writer.write_record(&record)?;
+        }
+        writer.flush()?;
+        Ok(output_path)
+    }
+
+    /// Write the loci recovered, percent of reference,
+    /// and alignment statistics of each sample.
+    pub fn write_sample_summary(&self) -> Result<PathBuf, Box<dyn Error>> {
+        let output_path = self.create_output_file(SAMPLE_SUMMARY_FILE_STEM);
+        let mut writer = csv::Writer::from_path(&output_path)?;
+        for summary in self.summarize_samples() {
+            writer.serialize(summary)?;
+        }
+        writer.flush()?;
+        Ok(output_path)
+    }
+
+    fn summarize_samples(&self) -> Vec<SampleMappingSummary> {
+        let mapping_data: HashMap<&str, &MappingData> = self
+            .mapping_data
+            .unwrap_or_default()
+            .iter()
+            .map(|data| (data.sample_name.as_str(), data))
+            .collect();
+        self.sample_names()
+            .iter()
+            .map(|sample_name| {
+                let mut summary = SampleMappingSummary::new(sample_name);
+                summary.summarize(self.lengths, self.reference_counts);
+                if let Some(data) = mapping_data.get(sample_name.as_str()) {
+                    summary.summarize_alignments(data, self.lengths);
+                }
+                summary
+            })
+            .collect()
+    }
+
+    // Samples with mapping data are included
+    // even if no locus is recovered.
+    fn sample_names(&self) -> BTreeSet<String> {
+        let mut sample_names: BTreeSet<String> = self
+            .lengths
+            .values()
+            .flat_map(|matrix| matrix.keys().cloned())
+            .collect();
+        if let Some(data) = self.mapping_data {
+            sample_names.extend(data.iter().map(|d| d.sample_name.to_string()));
+        }
+        sample_names
+    }
+
     fn summarize_matches(&self, ref_name: &str) -> FinalContigSummary {
-        match self.mapped_matrix.get(ref_name) {
+        match self.lengths.get(ref_name) {
             Some(_) => {
                 let mut summary = FinalContigSummary::new(ref_name.to_string(), self.total_samples);
-                summary.summarize_matches(self.mapped_matrix);
+                summary.summarize_matches(self.lengths);
                 summary
             }
             None => FinalContigSummary::new(ref_name.to_string(), self.total_samples),
@@ -354,7 +714,8 @@ impl<'a> SummaryWriter<'a> {
     }
 
     fn count_references(&mut self, reference_data: &ReferenceFile) -> Vec<String> {
-        let input_fmt = types::InputFmt::Auto;
+        // References are always FASTA, including protein (e.g., .faa) files
+        let input_fmt = types::InputFmt::Fasta;
         let datatype = DataType::Dna;
         let ref_path = reference_data
             .metadata
@@ -380,10 +741,12 @@ impl<'a> SummaryWriter<'a> {
     }
 
     fn create_output_path(&self) -> PathBuf {
+        self.create_output_file(SUMMARY_FILE_STEM)
+    }
+
+    fn create_output_file(&self, file_stem: &str) -> PathBuf {
         fs::create_dir_all(self.output_dir).expect("Failed to create output directory");
-        self.output_dir
-            .join(SUMMARY_FILE_STEM)
-            .with_extension(SUMMARY_EXT)
+        self.output_dir.join(file_stem).with_extension(SUMMARY_EXT)
     }
 }
 
@@ -391,7 +754,7 @@ impl<'a> SummaryWriter<'a> {
 mod tests {
     use tempdir::TempDir;
 
-    use crate::types::map::MappingReferenceType;
+    use crate::types::map::{MappingReferenceType, SequenceOutputFormat};
 
     use super::*;
 
@@ -406,6 +769,46 @@ mod tests {
         assert_eq!(id, "mtDNA-COX1");
     }
 
+    #[test]
+    fn test_occupancy_matrix() {
+        let tempdir = TempDir::new("occupancy_test").unwrap();
+        let mut matrix = MappedMatrix::new();
+        let mut uce_1 = IndexMap::new();
+        uce_1.insert(String::from("sample_1"), String::from("ACGT--A"));
+        uce_1.insert(String::from("sample_2"), String::from("ACG"));
+        matrix.insert(String::from("uce-1"), uce_1);
+        let mut uce_2 = IndexMap::new();
+        uce_2.insert(String::from("sample_1"), String::from("AC"));
+        matrix.insert(String::from("uce-2"), uce_2);
+        let ref_names = vec![
+            String::from("uce-1"),
+            String::from("uce-2"),
+            String::from("uce-3"),
+        ];
+        let lengths = summary::length_matrix(&matrix);
+        let mut writer = SummaryWriter::new(tempdir.path(), &lengths, 2);
+        writer.write_references(&ref_names);
+
+        let occupancy = fs::read_to_string(
+            tempdir
+                .path()
+                .join(OCCUPANCY_FILE_STEM)
+                .with_extension(SUMMARY_EXT),
+        )
+        .unwrap();
+        let rows: Vec<&str> = occupancy.lines().collect();
+        assert_eq!(rows[0], "sample_name,uce-1,uce-2,uce-3");
+        assert_eq!(rows[1], "sample_1,5,2,0");
+        assert_eq!(rows[2], "sample_2,3,0,0");
+
+        let samples = writer.summarize_samples();
+        assert_eq!(samples.len(), 2);
+        assert_eq!(samples[0].loci_recovered, 2);
+        assert_eq!(samples[0].total_length, 7);
+        assert!((samples[1].percent_reference - 100.0 / 3.0).abs() < 1e-9);
+        assert_eq!(samples[1].mean_identity, None);
+    }
+
     #[test]
     fn test_mapping_maf() {
         let maf_path = vec![PathBuf::from("tests/data/maf/mitogenomes.maf")];
@@ -457,4 +860,60 @@ mod tests {
             "Sequence is not correct"
         );
     }
+
+    #[test]
+    fn test_write_locus_gzip() {
+        let tempdir = TempDir::new("write_locus_test").unwrap();
+        let mut contigs = IndexMap::new();
+        contigs.insert(String::from("sample_1"), String::from("ACGT"));
+        contigs.insert(String::from("sample_2"), String::from("AC-T"));
+        let output = SequenceOutput::new(SequenceOutputFormat::Fasta, true);
+        write_locus(tempdir.path(), "uce-1", &contigs, &output);
+
+        let output_path = tempdir.path().join("uce-1.fas.gz");
+        assert!(output_path.is_file());
+        assert!(!tempdir.path().join("uce-1.fas").exists());
+        let mut decoder = flate2::read::GzDecoder::new(File::open(&output_path).unwrap());
+        let mut fasta = String::new();
+        io::Read::read_to_string(&mut decoder, &mut fasta).unwrap();
+        assert!(fasta.starts_with(">sample_1"));
+        assert!(fasta.contains("sample_2"));
+    }
+
+    #[test]
+    fn test_write_locus_unequal_lengths() {
+        let tempdir = TempDir::new("write_locus_unequal_test").unwrap();
+        let mut contigs = IndexMap::new();
+        contigs.insert(String::from("sample_1"), String::from("ACGTACGTAC"));
+        contigs.insert(String::from("sample_2"), String::from("ACG"));
+        let output = SequenceOutput::new(SequenceOutputFormat::Fasta, false);
+        write_locus(tempdir.path(), "uce-1", &contigs, &output);
+
+        let output_path = tempdir.path().join("uce-1.fas");
+        let sequences: Vec<(String, usize)> = bio::io::fasta::Reader::from_file(&output_path)
+            .unwrap()
+            .records()
+            .map(|r| {
+                let record = r.unwrap();
+                (record.id().to_string(), record.seq().len())
+            })
+            .collect();
+        assert_eq!(
+            sequences,
+            vec![
+                (String::from("sample_1"), 10),
+                (String::from("sample_2"), 3)
+            ]
+        );
+    }
+
+    #[test]
+    fn test_unaligned_output_format() {
+        assert!("nexus".parse::<SequenceOutputFormat>().is_err());
+        assert!("phylip-int".parse::<SequenceOutputFormat>().is_err());
+        assert_eq!(
+            "fasta".parse::<SequenceOutputFormat>(),
+            Ok(SequenceOutputFormat::Fasta)
+        );
+    }
 }
@@ -5,5 +5,7 @@ pub mod assembly;
 pub mod clean;
 pub mod deps;
 pub mod map;
+pub mod project;
 pub mod tree;
 pub mod utils;
+pub mod workflow;
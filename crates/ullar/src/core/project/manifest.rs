@@ -0,0 +1,237 @@
+//! Project manifest to track samples and task status.
+use std::{
+    error::Error,
+    fmt::Display,
+    path::{Path, PathBuf},
+};
+
+use colored::Colorize;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    core::{
+        alignment::{qc::DEFAULT_ALIGNMENT_QC_OUTPUT_DIR, DEFAULT_ALIGNMENT_OUTPUT_DIR},
+        assembly::DEFAULT_ASSEMBLY_OUTPUT_DIR,
+        clean::DEFAULT_CLEAN_READ_OUTPUT_DIR,
+        map::{DEFAULT_CONTIG_MAPPING_OUTPUT_DIR, DEFAULT_READ_MAPPING_OUTPUT_DIR},
+        tree::DEFAULT_PHYLO_OUTPUT_DIR,
+    },
+    helper::common::{self, UllarConfig},
+    types::{reads::FastqReads, Task},
+};
+
+pub const DEFAULT_PROJECT_MANIFEST: &str = "ullar_project.toml";
+
+/// Pipeline tasks tracked in the project manifest.
+/// Ordered as they are run in the pipeline.
+pub const PROJECT_TASKS: [Task; 7] = [
+    Task::CleanReads,
+    Task::Assembly,
+    Task::ReadMapping,
+    Task::ContigMapping,
+    Task::SequenceAlignment,
+    Task::AlignmentQc,
+    Task::TreeInference,
+];
+
+/// Get the default output directory of a task
+pub fn get_task_output_dir(task: &Task) -> Option<&'static str> {
+    match task {
+        Task::CleanReads => Some(DEFAULT_CLEAN_READ_OUTPUT_DIR),
+        Task::Assembly => Some(DEFAULT_ASSEMBLY_OUTPUT_DIR),
+        Task::ReadMapping => Some(DEFAULT_READ_MAPPING_OUTPUT_DIR),
+        Task::ContigMapping => Some(DEFAULT_CONTIG_MAPPING_OUTPUT_DIR),
+        Task::SequenceAlignment => Some(DEFAULT_ALIGNMENT_OUTPUT_DIR),
+        Task::AlignmentQc => Some(DEFAULT_ALIGNMENT_QC_OUTPUT_DIR),
+        Task::TreeInference => Some(DEFAULT_PHYLO_OUTPUT_DIR),
+        _ => None,
+    }
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct ProjectManifest {
+    #[serde(flatten)]
+    pub app: UllarConfig,
+    pub project: ProjectInfo,
+    pub tasks: Vec<ProjectTask>,
+    pub samples: Vec<FastqReads>,
+}
+
+impl ProjectManifest {
+    pub fn new(project: ProjectInfo, samples: Vec<FastqReads>) -> Self {
+        let tasks = PROJECT_TASKS.iter().map(ProjectTask::new).collect();
+        Self {
+            app: UllarConfig::init(),
+            project,
+            tasks,
+            samples,
+        }
+    }
+
+    pub fn from_toml(manifest_path: &Path) -> Result<Self, Box<dyn Error>> {
+        let content = std::fs::read_to_string(manifest_path)?;
+        let manifest = toml::from_str(&content)?;
+        Ok(manifest)
+    }
+
+    pub fn to_toml(&self, manifest_path: &Path) -> Result<(), Box<dyn Error>> {
+        let toml = toml::to_string_pretty(self)?;
+        std::fs::write(manifest_path, toml)?;
+        Ok(())
+    }
+
+    /// Update the task status in the project manifest
+    /// located in the current working directory.
+    /// Does nothing if the command is not run inside a project.
+    pub fn record(task: Task, status: TaskStatus, config_path: Option<&Path>) {
+        let manifest_path = Path::new(DEFAULT_PROJECT_MANIFEST);
+        if !manifest_path.is_file() {
+            return;
+        }
+        let result = Self::from_toml(manifest_path).and_then(|mut manifest| {
+            manifest.update_task(task, status, config_path);
+            manifest.to_toml(manifest_path)
+        });
+        if let Err(e) = result {
+            log::warn!(
+                "{} Failed to update the project manifest: {}",
+                "Warning:".yellow(),
+                e
+            );
+        }
+    }
+
+    pub fn update_task(&mut self, task: Task, status: TaskStatus, config_path: Option<&Path>) {
+        match self.tasks.iter_mut().find(|t| t.task == task) {
+            Some(t) => t.update(status, config_path),
+            None => {
+                let mut project_task = ProjectTask::new(&task);
+                project_task.update(status, config_path);
+                self.tasks.push(project_task);
+            }
+        }
+    }
+
+    pub fn get_task(&self, task: &Task) -> Option<&ProjectTask> {
+        self.tasks.iter().find(|t| t.task == *task)
+    }
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct ProjectInfo {
+    pub name: String,
+    pub raw_read_dir: PathBuf,
+    pub config_dir: PathBuf,
+    pub sample_counts: usize,
+    pub file_counts: usize,
+}
+
+impl ProjectInfo {
+    pub fn new(
+        name: &str,
+        raw_read_dir: &Path,
+        config_dir: &Path,
+        sample_counts: usize,
+        file_counts: usize,
+    ) -> Self {
+        Self {
+            name: name.to_string(),
+            raw_read_dir: raw_read_dir.to_path_buf(),
+            config_dir: config_dir.to_path_buf(),
+            sample_counts,
+            file_counts,
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ProjectTask {
+    pub task: Task,
+    pub status: TaskStatus,
+    pub output_dir: Option<PathBuf>,
+    pub config: Option<PathBuf>,
+    pub last_updated: Option<String>,
+}
+
+impl ProjectTask {
+    pub fn new(task: &Task) -> Self {
+        Self {
+            task: *task,
+            status: TaskStatus::NotStarted,
+            output_dir: get_task_output_dir(task).map(PathBuf::from),
+            config: None,
+            last_updated: None,
+        }
+    }
+
+    pub fn update(&mut self, status: TaskStatus, config_path: Option<&Path>) {
+        self.status = status;
+        if let Some(path) = config_path {
+            self.config = Some(path.to_path_buf());
+        }
+        self.last_updated = Some(common::get_timestamp());
+    }
+}
+
+/// Status of a task in the project manifest
+#[derive(Debug, Default, PartialEq, Clone, Copy, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TaskStatus {
+    /// No config has been created for the task
+    #[default]
+    NotStarted,
+    /// Config file is created, but the task is not run yet
+    Initialized,
+    /// Task is running or was interrupted
+    Running,
+    /// Task finished successfully
+    Completed,
+    /// Task finished with errors
+    Failed,
+}
+
+impl TaskStatus {
+    pub fn from_success(is_success: bool) -> Self {
+        if is_success {
+            TaskStatus::Completed
+        } else {
+            TaskStatus::Failed
+        }
+    }
+}
+
+impl Display for TaskStatus {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TaskStatus::NotStarted => write!(f, "not_started"),
+            TaskStatus::Initialized => write!(f, "initialized"),
+            TaskStatus::Running => write!(f, "running"),
+            TaskStatus::Completed => write!(f, "completed"),
+            TaskStatus::Failed => write!(f, "failed"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_manifest_round_trip() {
+        let info = ProjectInfo::new("test", Path::new("raw_reads"), Path::new("configs"), 0, 0);
+        let mut manifest = ProjectManifest::new(info, Vec::new());
+        manifest.update_task(
+            Task::Assembly,
+            TaskStatus::Initialized,
+            Some(Path::new("configs/assembly.toml")),
+        );
+        let toml = toml::to_string_pretty(&manifest).unwrap();
+        let parsed: ProjectManifest = toml::from_str(&toml).unwrap();
+        assert_eq!(parsed.tasks.len(), PROJECT_TASKS.len());
+        let task = parsed.get_task(&Task::Assembly).unwrap();
+        assert_eq!(task.status, TaskStatus::Initialized);
+        assert_eq!(task.config, Some(PathBuf::from("configs/assembly.toml")));
+        let task = parsed.get_task(&Task::CleanReads).unwrap();
+        assert_eq!(task.status, TaskStatus::NotStarted);
+    }
+}
@@ -0,0 +1,183 @@
+//! Create a new project with a standard directory layout
+//! and a manifest to track samples and task status.
+pub mod manifest;
+
+use std::{
+    error::Error,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use colored::Colorize;
+use manifest::{
+    get_task_output_dir, ProjectInfo, ProjectManifest, DEFAULT_PROJECT_MANIFEST, PROJECT_TASKS,
+};
+
+use crate::{
+    cli::commands::{common::GenomicReadsInitArgs, new::NewArgs},
+    helper::{common, configs::DEFAULT_CONFIG_DIR, files::FileFinder},
+    types::{
+        reads::{FastqReads, ReadAssignment, SampleNameFormat},
+        SupportedFormats,
+    },
+};
+
+pub struct NewProject<'a> {
+    /// Project directory
+    pub project_dir: &'a Path,
+    /// Raw read directory relative to the project directory
+    pub raw_read_dir: &'a Path,
+    reads: &'a GenomicReadsInitArgs,
+    sample_name_format: SampleNameFormat,
+}
+
+impl<'a> NewProject<'a> {
+    /// Initialize a new NewProject instance
+    /// from command line arguments
+    pub fn from_arg(args: &'a NewArgs) -> Self {
+        let sample_name_format = match &args.common.re_sample {
+            Some(regex) => SampleNameFormat::Custom(regex.to_string()),
+            None => args
+                .common
+                .sample_name
+                .parse::<SampleNameFormat>()
+                .expect("Invalid sample name format"),
+        };
+        Self {
+            project_dir: &args.name,
+            raw_read_dir: &args.dir,
+            reads: &args.common,
+            sample_name_format,
+        }
+    }
+
+    pub fn create(&self) {
+        self.log_input();
+        let spin = common::init_spinner();
+        spin.set_message("Creating project directories...");
+        if let Err(e) = self.create_layout() {
+            spin.finish_with_message(format!("{} Failed to create project\n", "✖".red()));
+            log::error!("{}", e);
+            return;
+        }
+        spin.set_message("Finding raw reads...");
+        let files = self.find_reads();
+        let file_counts = files.len();
+        let mut samples = ReadAssignment::new(&files, &self.sample_name_format).assign();
+        self.relative_to_project(&mut samples);
+        spin.set_message("Writing project manifest...");
+        match self.write_manifest(samples, file_counts) {
+            Ok((path, manifest)) => {
+                spin.finish_with_message(format!("{} Finished creating a project\n", "✔".green()));
+                self.log_output(&path, &manifest);
+            }
+            Err(e) => {
+                spin.finish_with_message(format!(
+                    "{} Failed to write project manifest\n",
+                    "✖".red()
+                ));
+                log::error!("{}", e);
+            }
+        }
+    }
+
+    fn create_layout(&self) -> Result<(), Box<dyn Error>> {
+        fs::create_dir_all(self.project_dir.join(self.raw_read_dir))?;
+        fs::create_dir_all(self.project_dir.join(DEFAULT_CONFIG_DIR))?;
+        for task in PROJECT_TASKS.iter() {
+            if let Some(dir) = get_task_output_dir(task) {
+                fs::create_dir_all(self.project_dir.join(dir))?;
+            }
+        }
+        Ok(())
+    }
+
+    fn find_reads(&self) -> Vec<PathBuf> {
+        let read_dir = self.project_dir.join(self.raw_read_dir);
+        FileFinder::new(&read_dir, &SupportedFormats::Fastq)
+            .find(self.reads.recursive)
+            .expect(
+                "Failed to find files. \
+            Check if the directory exists and you have permission to access it.",
+            )
+    }
+
+    // Later commands are run inside the project directory.
+    // We store the read locations relative to it.
+    fn relative_to_project(&self, samples: &mut [FastqReads]) {
+        samples.iter_mut().for_each(|sample| {
+            [
+                &mut sample.read_1,
+                &mut sample.read_2,
+                &mut sample.singletons,
+            ]
+            .into_iter()
+            .flatten()
+            .for_each(|meta| {
+                if let Ok(path) = meta.parent_dir.strip_prefix(self.project_dir) {
+                    meta.parent_dir = path.to_path_buf();
+                }
+            });
+        });
+    }
+
+    // If the project already exists, we keep the task status
+    // and only update the samples found in the raw read directory.
+    fn write_manifest(
+        &self,
+        samples: Vec<FastqReads>,
+        file_counts: usize,
+    ) -> Result<(PathBuf, ProjectManifest), Box<dyn Error>> {
+        let manifest_path = self.project_dir.join(DEFAULT_PROJECT_MANIFEST);
+        let info = ProjectInfo::new(
+            &self.get_project_name(),
+            self.raw_read_dir,
+            Path::new(DEFAULT_CONFIG_DIR),
+            samples.len(),
+            file_counts,
+        );
+        let manifest = if manifest_path.is_file() {
+            let mut manifest = ProjectManifest::from_toml(&manifest_path)?;
+            manifest.project = info;
+            manifest.samples = samples;
+            manifest
+        } else {
+            ProjectManifest::new(info, samples)
+        };
+        manifest.to_toml(&manifest_path)?;
+        Ok((manifest_path, manifest))
+    }
+
+    fn get_project_name(&self) -> String {
+        let project_dir = self
+            .project_dir
+            .canonicalize()
+            .unwrap_or_else(|_| self.project_dir.to_path_buf());
+        project_dir
+            .file_name()
+            .map(|n| n.to_string_lossy().to_string())
+            .unwrap_or_default()
+    }
+
+    fn log_input(&self) {
+        log::info!("{}", "Input".cyan());
+        log::info!("{:18}: {}", "Project", self.project_dir.display());
+        log::info!("{:18}: {}", "Raw reads", self.raw_read_dir.display());
+        log::info!("{:18}: {}\n", "Sample name format", self.sample_name_format);
+    }
+
+    fn log_output(&self, manifest_path: &Path, manifest: &ProjectManifest) {
+        log::info!("{}", "\nOutput".cyan());
+        log::info!("{:18}: {}", "Directory", self.project_dir.display());
+        log::info!("{:18}: {}", "Manifest", manifest_path.display());
+        log::info!("{:18}: {}", "Sample counts", manifest.project.sample_counts);
+        log::info!("{:18}: {}", "File counts", manifest.project.file_counts);
+        if manifest.samples.is_empty() {
+            log::warn!(
+                "\n{} No raw reads found. Add reads to {} and rerun `ullar new` to update the samples.",
+                "Warning:".yellow(),
+                self.project_dir.join(self.raw_read_dir).display()
+            );
+        }
+    }
+}
@@ -9,6 +9,7 @@ use serde::{Deserialize, Serialize};
 
 use crate::{
     cli::commands::tree::{AsterSettingArgs, IqTreeSettingArgs},
+    core::alignment::codon::CODON_PARTITION_FILE,
     core::deps::{
         aster::AsterParams,
         iqtree::IqTreeParams,
@@ -18,7 +19,8 @@ use crate::{
     types::alignments::AlignmentFiles,
 };
 use crate::{
-    core::deps::DepMetadata, helper::configs::generate_config_output_path,
+    core::deps::DepMetadata,
+    helper::configs::{generate_config_output_path, PreviousStep},
     types::trees::TreeInferenceMethod,
 };
 
@@ -64,6 +66,9 @@ pub struct TreeInferenceConfig {
     pub app: UllarConfig,
     pub input: TreeInferenceInput,
     pub data_preparation: DepMetadata,
+    /// Pipeline step that produced the input
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub previous_step: Option<PreviousStep>,
     // We use an IndexMap instead of BTreeMap
     // or HashMap to maintain the order of the analyses.
     pub analyses: IndexMap<String, TreeInferenceAnalyses>,
@@ -84,6 +89,7 @@ impl TreeInferenceConfig {
             app: UllarConfig::init(),
             input: TreeInferenceInput::new(input_dir, methods.to_vec()),
             data_preparation: get_segul_metadata(),
+            previous_step: None,
             analyses: IndexMap::new(),
             alignments,
         }
@@ -196,13 +202,20 @@ impl TreeInferenceConfig {
 pub struct TreeInferenceInput {
     pub input_dir: PathBuf,
     pub analyses: Vec<TreeInferenceMethod>,
+    /// Codon partition written by codon alignment.
+    /// Used to partition the species tree alignment
+    /// by codon position.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub codon_partition: Option<PathBuf>,
 }
 
 impl TreeInferenceInput {
     pub fn new(input_dir: &Path, analyses: Vec<TreeInferenceMethod>) -> Self {
+        let codon_partition = input_dir.join(CODON_PARTITION_FILE);
         Self {
             input_dir: input_dir.to_path_buf(),
             analyses,
+            codon_partition: codon_partition.is_file().then_some(codon_partition),
         }
     }
 }
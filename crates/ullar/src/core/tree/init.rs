@@ -8,12 +8,15 @@ use segul::helper::types::{DataType, InputFmt};
 
 use crate::cli::commands::common::CommonInitArgs;
 use crate::cli::commands::tree::{AsterSettingArgs, IqTreeSettingArgs, TreeInferenceInitArgs};
+use crate::core::alignment::filter::remove_dropped_loci;
 use crate::core::deps::aster::AsterMetadata;
 use crate::core::deps::iqtree::IqtreeMetadata;
+use crate::core::project::manifest::{ProjectManifest, TaskStatus};
 use crate::core::tree::DEFAULT_PHYLO_OUTPUT_DIR;
 use crate::helper::common::{self, PrettyHeader};
 use crate::types::alignments::AlignmentFiles;
 use crate::types::trees::{MscInferenceMethod, TreeInferenceMethod};
+use crate::types::Task;
 
 use super::configs::{reorder_analyses, TreeInferenceConfig};
 use super::TreeEstimation;
@@ -128,6 +131,7 @@ impl<'a> TreeInferenceInit<'a> {
                     "✔".green()
                 ));
                 self.log_final_output(&path, &config);
+                ProjectManifest::record(Task::TreeInference, TaskStatus::Initialized, Some(&path));
                 if self.common.autorun {
                     let footer = PrettyHeader::new();
                     footer.get_section_footer();
@@ -147,7 +151,7 @@ impl<'a> TreeInferenceInit<'a> {
         log::info!("");
         let output_dir = Path::new(DEFAULT_PHYLO_OUTPUT_DIR);
         let runner = TreeEstimation::from_config_path(&config_path, output_dir);
-        runner.infer();
+        runner.infer().expect("Failed to run tree inference");
     }
 
     fn check_all_dependencies(&self) -> Result<(), Box<dyn Error>> {
@@ -195,7 +199,8 @@ impl<'a> TreeInferenceInit<'a> {
     }
 
     fn find_alignments(&self) -> AlignmentFiles {
-        let files = SeqFileFinder::new(self.input_dir).find(&self.input_format);
+        let mut files = SeqFileFinder::new(self.input_dir).find(&self.input_format);
+        remove_dropped_loci(self.input_dir, &mut files);
         AlignmentFiles::from_sequence_files(&files, &self.input_format, &self.datatype)
     }
 
@@ -241,5 +246,8 @@ impl<'a> TreeInferenceInit<'a> {
             config.alignments.sample_counts
         );
         log::info!("{:18}: {}", "File counts", config.alignments.file_counts);
+        if let Some(path) = &config.input.codon_partition {
+            log::info!("{:18}: {}", "Codon partition", path.display());
+        }
     }
 }
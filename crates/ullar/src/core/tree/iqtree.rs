@@ -12,17 +12,23 @@ use segul::{
     helper::{
         concat::Concat,
         files,
-        types::{DataType, InputFmt, OutputFmt, PartitionFmt},
+        types::{DataType, InputFmt, OutputFmt, Partition, PartitionFmt},
     },
     writer::{partition::PartWriter, sequences::SeqWriter},
 };
 
 use crate::{
-    core::deps::{
-        iqtree::{IqTreeParams, IQTREE3_EXE, IQTREE_EXE},
-        DepMetadata,
+    core::{
+        alignment::codon::CodonPartition,
+        deps::{
+            iqtree::{IqTreeParams, IQTREE3_EXE, IQTREE_EXE},
+            DepMetadata,
+        },
+    },
+    helper::{
+        common,
+        ledger::{self, LedgerEntry, LedgerStatus, StepLedger},
     },
-    helper::common,
     parse_override_args,
     types::{alignments::AlignmentFiles, trees::IQTreePartitions},
 };
@@ -37,6 +43,10 @@ pub struct MlSpeciesTree<'a> {
     pub iqtree_configs: &'a IqTreeParams,
     pub output_dir: &'a Path,
     pub codon_model: bool,
+    /// Codon partition from codon alignment.
+    /// If not set, loci are assumed to start
+    /// at the first codon position.
+    pub codon_partition: Option<&'a Path>,
 }
 
 impl<'a> MlSpeciesTree<'a> {
@@ -52,9 +62,15 @@ impl<'a> MlSpeciesTree<'a> {
             iqtree_configs,
             output_dir,
             codon_model,
+            codon_partition: None,
         }
     }
 
+    pub fn codon_partition(mut self, codon_partition: Option<&'a Path>) -> Self {
+        self.codon_partition = codon_partition;
+        self
+    }
+
     pub fn infer_species_tree(
         &self,
         iqtree_result: &mut IQTreeResults,
@@ -62,7 +78,7 @@ impl<'a> MlSpeciesTree<'a> {
     ) -> Result<(), Box<dyn std::error::Error>> {
         let spinner = common::init_spinner();
         spinner.set_message("Concatenating alignments");
-        let (alignment_path, partition_path) = self.concat_alignments(&self.output_dir, prefix);
+        let (alignment_path, partition_path) = self.concat_alignments(&self.output_dir, prefix)?;
         spinner.set_message("Running IQ-TREE for species tree");
         let output_dir = self.output_dir.join(prefix);
         let meta = match &self.iqtree_configs.dependency {
@@ -95,7 +111,11 @@ impl<'a> MlSpeciesTree<'a> {
         Ok(())
     }
 
-    fn concat_alignments(&self, output_dir: &Path, prefix: &str) -> (PathBuf, PathBuf) {
+    fn concat_alignments(
+        &self,
+        output_dir: &Path,
+        prefix: &str,
+    ) -> Result<(PathBuf, PathBuf), Box<dyn std::error::Error>> {
         let output_pre = Path::new(prefix);
         let input_fmt = InputFmt::Auto;
         let output_fmt = OutputFmt::Phylip;
@@ -119,21 +139,61 @@ impl<'a> MlSpeciesTree<'a> {
             .write_sequence(&output_fmt)
             .expect("Failed writing the output file");
         let partition_path = output_dir.join("partition").with_extension("txt");
-        let part_writer = PartWriter::new(
-            &partition_path,
-            &concat.partition,
-            &partition_fmt,
-            &datatype,
-        );
-        part_writer.write_partition();
-        (output_path, partition_path)
+        match self.codon_partition.filter(|_| self.codon_model) {
+            Some(codon_path) => {
+                let codons = CodonPartition::from_csv(codon_path)?;
+                write_codon_partition(&partition_path, &concat.partition, &codons)?;
+            }
+            None => {
+                let part_writer = PartWriter::new(
+                    &partition_path,
+                    &concat.partition,
+                    &partition_fmt,
+                    &datatype,
+                );
+                part_writer.write_partition();
+            }
+        }
+        Ok((output_path, partition_path))
+    }
+}
+
+/// Write a RAxML partition of the concatenated alignment
+/// by codon position. Codon loci start at the first codon
+/// position. Loci without a codon partition are written
+/// as a single partition.
+fn write_codon_partition(
+    output_path: &Path,
+    partitions: &[Partition],
+    codons: &[CodonPartition],
+) -> std::io::Result<()> {
+    let file = File::create(output_path)?;
+    let mut writer = BufWriter::new(file);
+    for part in partitions {
+        if !codons.iter().any(|c| c.locus == part.gene) {
+            writeln!(writer, "DNA, {} = {}-{}", part.gene, part.start, part.end)?;
+            continue;
+        }
+        for position in 1..=3 {
+            let start = part.start + position - 1;
+            if start <= part.end {
+                writeln!(
+                    writer,
+                    "DNA, {}_pos{} = {}-{}\\3",
+                    part.gene, position, start, part.end
+                )?;
+            }
+        }
     }
+    writer.flush()
 }
 
 pub struct MlGeneTree<'a> {
     pub alignments: &'a AlignmentFiles,
     pub iqtree_configs: &'a IqTreeParams,
     pub output_dir: &'a Path,
+    /// Skip gene trees completed in the previous runs
+    pub resume: bool,
 }
 
 impl<'a> MlGeneTree<'a> {
@@ -146,19 +206,36 @@ impl<'a> MlGeneTree<'a> {
             alignments,
             iqtree_configs,
             output_dir,
+            resume: false,
         }
     }
 
+    pub fn resume(mut self, resume: bool) -> Self {
+        self.resume = resume;
+        self
+    }
+
     pub fn infer_gene_trees(&self, iqtree_result: &mut IQTreeResults) {
         let progress_bar = common::init_progress_bar(self.alignments.file_counts as u64);
         log::info!("Running IQ-TREE for gene trees");
         progress_bar.set_message("gene trees");
+        let ledger = StepLedger::new(self.output_dir);
+        let params_hash = ledger::hash_params(self.iqtree_configs);
         self.alignments.files.par_iter().for_each(|f| {
             let alignment_path = f.parent_dir.join(&f.file_name);
             let file_stem = alignment_path.file_stem().expect("Failed to get file stem");
             let output_dir = self.output_dir.join(file_stem);
             create_dir_all(&output_dir).expect("Failed to create output directory");
             let full_path = output_dir.join(file_stem);
+            let input_hash = ledger::hash_files(&[f]);
+            let treefile = gene_treefile(&full_path);
+            if self.resume
+                && treefile.is_file()
+                && ledger.is_completed(&f.file_name, &input_hash, &params_hash)
+            {
+                progress_bar.inc(1);
+                return;
+            }
             let meta = match &self.iqtree_configs.dependency {
                 Some(m) => m,
                 None => {
@@ -171,6 +248,13 @@ impl<'a> MlGeneTree<'a> {
             };
             let iqtree = IqTree::new(self.iqtree_configs, &meta);
             let out = iqtree.infer_gene_trees(&alignment_path, &full_path);
+            let status = LedgerStatus::from_success(out.status.success());
+            ledger.record(&LedgerEntry::new(
+                &f.file_name,
+                &input_hash,
+                &params_hash,
+                status,
+            ));
             if !out.status.success() {
                 let error = str::from_utf8(&out.stderr).expect("Failed to read error message");
                 let message = format!(
@@ -184,18 +268,25 @@ impl<'a> MlGeneTree<'a> {
             progress_bar.inc(1);
         });
         progress_bar.finish_with_message("gene trees\n");
-        let gene_trees = self.find_gene_trees(&self.output_dir);
+        let gene_trees = self.find_gene_trees();
         let gene_tree_path = self.combine_gene_trees(&gene_trees);
         iqtree_result.add_gene_trees(gene_tree_path);
     }
 
-    fn find_gene_trees(&self, output_dir: &Path) -> Vec<PathBuf> {
-        let pattern = format!("{}/*/*.treefile", output_dir.display());
-        let gene_trees = glob::glob(&pattern)
-            .expect("Failed to find gene trees")
-            .filter_map(|f| f.ok())
-            .collect::<Vec<PathBuf>>();
-        gene_trees
+    /// Gene trees of the alignments in the config.
+    /// Trees of loci from previous runs that are
+    /// no longer in the config are ignored.
+    fn find_gene_trees(&self) -> Vec<PathBuf> {
+        self.alignments
+            .files
+            .iter()
+            .map(|f| {
+                let alignment_path = f.parent_dir.join(&f.file_name);
+                let file_stem = alignment_path.file_stem().expect("Failed to get file stem");
+                gene_treefile(&self.output_dir.join(file_stem).join(file_stem))
+            })
+            .filter(|treefile| treefile.is_file())
+            .collect()
     }
 
     fn combine_gene_trees(&self, gene_trees: &[PathBuf]) -> PathBuf {
@@ -214,6 +305,11 @@ impl<'a> MlGeneTree<'a> {
     }
 }
 
+// IQ-TREE appends the extension to the output prefix
+fn gene_treefile(prefix: &Path) -> PathBuf {
+    PathBuf::from(format!("{}.{}", prefix.display(), TREE_FILE_EXTENSION))
+}
+
 pub struct GeneSiteConcordance<'a> {
     pub iqtree_configs: &'a IqTreeParams,
     pub output_dir: &'a Path,
@@ -436,3 +532,36 @@ impl<'a> IqTree<'a> {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_write_codon_partition() {
+        let dir = tempdir::TempDir::new("codon_partition").unwrap();
+        let path = dir.path().join("partition.txt");
+        let partitions = vec![
+            Partition {
+                gene: "exon-1".to_string(),
+                start: 1,
+                end: 9,
+            },
+            Partition {
+                gene: "exon-2".to_string(),
+                start: 10,
+                end: 15,
+            },
+        ];
+        let codons = vec![CodonPartition {
+            locus: "exon-1".to_string(),
+            length: 9,
+        }];
+        write_codon_partition(&path, &partitions, &codons).unwrap();
+        let content = std::fs::read_to_string(&path).unwrap();
+        let lines: Vec<&str> = content.lines().collect();
+        assert_eq!(lines[0], "DNA, exon-1_pos1 = 1-9\\3");
+        assert_eq!(lines[2], "DNA, exon-1_pos3 = 3-9\\3");
+        assert_eq!(lines[3], "DNA, exon-2 = 10-15");
+    }
+}
@@ -19,7 +19,7 @@ use crate::{
         configs::{CONFIG_EXTENSION_TOML, DEFAULT_CONFIG_DIR},
         files::PathCheck,
     },
-    types::{runner::RunnerOptions, trees::TreeInferenceMethod},
+    types::{runner::RunnerOptions, trees::TreeInferenceMethod, Task},
 };
 
 use super::deps::{
@@ -28,6 +28,7 @@ use super::deps::{
     segul::SegulMethods,
     DepMetadata,
 };
+use super::project::manifest::{ProjectManifest, TaskStatus};
 
 pub mod aster;
 pub mod configs;
@@ -88,24 +89,29 @@ impl<'a> TreeEstimation<'a> {
         }
     }
 
-    pub fn infer(&self) {
+    /// Run the tree inference analyses in the config.
+    /// Returns an error if any of the analyses failed.
+    pub fn infer(&self) -> Result<(), Box<dyn Error>> {
         let spinner = common::init_spinner();
         spinner.set_message("Parsing the config file\n");
-        let config = self.parse_config().expect("Failed to parse config");
+        let config = self.parse_config()?;
         self.log_input(&config);
-        self.check_dependencies(&config)
-            .expect("Failed finding dependencies");
+        self.check_dependencies(&config)?;
         if config.input.analyses.is_empty() {
             log::error!(
                 "{} No tree inference method specified in the config files.",
                 "Warning:".red()
             );
-            return;
+            return Ok(());
         }
         spinner.finish_with_message("Skipping config data check\n");
         self.check_path_exists(&config.input.analyses);
-        self.run_tree_inference(&config)
-            .expect("Failed to run tree inference");
+        let task = Task::TreeInference;
+        ProjectManifest::record(task, TaskStatus::Running, Some(&self.config_path));
+        let results = self.run_tree_inference(&config);
+        let status = TaskStatus::from_success(results.is_ok());
+        ProjectManifest::record(task, status, Some(&self.config_path));
+        results
     }
 
     fn run_tree_inference(&self, config: &TreeInferenceConfig) -> Result<(), Box<dyn Error>> {
@@ -154,7 +160,10 @@ impl<'a> TreeEstimation<'a> {
                 a.to_string().cyan()
             );
             let output_dir = self.generate_output_path(a);
-            PathCheck::new(&output_dir).is_dir().prompt_exists(false);
+            PathCheck::new(&output_dir)
+                .is_dir()
+                .with_resume(self.runner.resume)
+                .prompt_exists(false);
         });
     }
 
@@ -176,7 +185,8 @@ impl<'a> TreeEstimation<'a> {
                 self.log_iqtree(params);
                 let codon_model = self.use_codon_model(config);
                 let ml_analyses =
-                    MlSpeciesTree::new(&config.alignments, &params, &output_dir, codon_model);
+                    MlSpeciesTree::new(&config.alignments, &params, &output_dir, codon_model)
+                        .codon_partition(config.input.codon_partition.as_deref());
                 ml_analyses.infer_species_tree(iqtree_result, prefix)?;
                 self.log_output(&output_dir);
                 log::info!(
@@ -222,7 +232,8 @@ impl<'a> TreeEstimation<'a> {
                     .as_ref()
                     .with_context(|| "Gene tree parameters not found")?;
                 self.log_iqtree(params);
-                let ml_analyses = MlGeneTree::new(&config.alignments, &params, &output_dir);
+                let ml_analyses = MlGeneTree::new(&config.alignments, &params, &output_dir)
+                    .resume(self.runner.resume);
                 ml_analyses.infer_gene_trees(iqtree_result);
                 self.log_output(&output_dir);
                 log::info!("{:18}: {}", "Treefile", iqtree_result.gene_trees.display());
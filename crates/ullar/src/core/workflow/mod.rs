@@ -0,0 +1,541 @@
+//! Run the whole pipeline from raw reads to phylogenetic trees.
+//!
+//! Each step config is generated from the output of the previous step.
+//! The workflow stops at the first step that fails.
+use std::{
+    error::Error,
+    path::{Path, PathBuf},
+};
+
+use colored::Colorize;
+use enum_iterator::all;
+use segul::helper::{finder::SeqFileFinder, types::InputFmt};
+
+use crate::{
+    cli::commands::{
+        alignment::LocusFilterArgs,
+        clean::ReadQcArgs,
+        common::{CommonRunnerArgs, GenomicReadsInitArgs},
+        map::ContigFilterArgs,
+        run::WorkflowArgs,
+        tree::{AsterSettingArgs, IqTreeSettingArgs},
+    },
+    core::{
+        alignment::{
+            configs::AlignmentConfig,
+            filter::{remove_dropped_loci, LocusFilter},
+            qc::{AlignmentQc, DEFAULT_ALIGNMENT_QC_OUTPUT_DIR},
+            trim::TrimParams,
+            SequenceAlignment, DEFAULT_ALIGNMENT_OUTPUT_DIR,
+        },
+        assembly::{configs::AssemblyConfig, reports::AssemblyReports, Assembly},
+        clean::{
+            configs::{CleanReadConfig, QcThresholds},
+            reports::CleanReadReport,
+            summary::{ReadCleaningSummary, READ_CLEANING_SUMMARY},
+            ReadCleaning, DEFAULT_CLEAN_READ_OUTPUT_DIR,
+        },
+        deps::{
+            aster::AsterMetadata, clustalo::ClustalOmegaMetadata, exonerate::ExonerateMetadata,
+            fastp::FastpMetadata, iqtree::IqtreeMetadata, lastz::LastzMetadata,
+            mafft::MafftMetadata, megahit::MegahitMetadata, muscle::MuscleMetadata,
+            spades::SpadesMetadata, DepMetadata,
+        },
+        map::{
+            configs::{
+                ContigInput, ContigMappingConfig, SampleNameSource, DEFAULT_REF_MAPPING_CONFIG,
+            },
+            filter::ContigFilter,
+            native::NativeParams,
+            writer::DEFAULT_UNALIGN_SEQUENCE_OUTPUT_DIR,
+            ContigMapping, DEFAULT_CONTIG_MAPPING_OUTPUT_DIR,
+        },
+        project::manifest::{ProjectManifest, TaskStatus},
+        tree::{
+            configs::{reorder_analyses, TreeInferenceConfig},
+            init::DependencyError,
+            TreeEstimation, DEFAULT_PHYLO_OUTPUT_DIR,
+        },
+    },
+    helper::{
+        common::PrettyHeader,
+        configs::PreviousStep,
+        fastq::{FastqInput, ReadAssignmentStrategy},
+        files::FileFinder,
+    },
+    types::{
+        alignments::{Aligner, AlignmentFiles},
+        assembly::Assembler,
+        map::Aligner as ContigAligner,
+        reads::{ReadAssignment, SampleNameFormat},
+        runner::RunnerOptions,
+        trees::{MscInferenceMethod, TreeInferenceMethod},
+        SupportedFormats, Task,
+    },
+};
+
+/// Error returned when a workflow step fails.
+/// Holds the failed task to report where the workflow stopped.
+struct StepError {
+    task: Task,
+    message: String,
+}
+
+impl StepError {
+    fn new<E: ToString>(task: Task, error: E) -> Self {
+        Self {
+            task,
+            message: error.to_string(),
+        }
+    }
+}
+
+pub struct Workflow<'a> {
+    /// Directory containing the raw reads
+    pub raw_read_dir: &'a Path,
+    /// Path to the reference sequence for contig mapping
+    pub reference: &'a Path,
+    /// Reference name regex
+    pub refname_regex: &'a str,
+    /// Reference type: probes or loci
+    pub reference_type: &'a str,
+    /// Reference contains a single reference
+    pub single_ref: bool,
+    /// Tree inference analyses to run
+    pub analyses: Vec<TreeInferenceMethod>,
+    /// De novo assembler
+    pub assembler: Assembler,
+    /// Aligner to map contigs to the reference
+    pub contig_aligner: ContigAligner,
+    /// Multiple sequence aligner
+    pub aligner: Aligner,
+    /// Keep samples that failed read cleaning QC
+    pub keep_failed_qc: bool,
+    reads: &'a GenomicReadsInitArgs,
+    qc: &'a ReadQcArgs,
+    contig_filter: &'a ContigFilterArgs,
+    /// Percent of samples to exclude paralogous loci
+    max_paralog_percent: Option<f64>,
+    /// Flank length of the matched region
+    flank_length: Option<usize>,
+    /// Trim the alignments before filtering
+    trim_alignments: bool,
+    locus_filter: &'a LocusFilterArgs,
+    iqtree: &'a IqTreeSettingArgs,
+    aster: &'a AsterSettingArgs,
+    common: &'a CommonRunnerArgs,
+    sample_name_format: SampleNameFormat,
+}
+
+impl<'a> Workflow<'a> {
+    /// Initialize a new Workflow instance
+    /// from command line arguments
+    pub fn from_arg(args: &'a WorkflowArgs) -> Self {
+        let sample_name_format = match &args.reads.re_sample {
+            Some(regex) => SampleNameFormat::Custom(regex.to_string()),
+            None => args
+                .reads
+                .sample_name
+                .parse::<SampleNameFormat>()
+                .expect("Invalid sample name format"),
+        };
+        let mut analyses = match &args.specify_analyses {
+            Some(analyses) => analyses
+                .iter()
+                .map(|m| {
+                    m.parse().expect(
+                        "Failed parsing tree inference methods. \
+                Check the help message for valid options",
+                    )
+                })
+                .collect(),
+            None => all::<TreeInferenceMethod>().collect(),
+        };
+        reorder_analyses(&mut analyses);
+        Self {
+            raw_read_dir: &args.dir,
+            reference: &args.reference,
+            refname_regex: &args.re_reference,
+            reference_type: &args.reference_type,
+            single_ref: args.single_ref,
+            analyses,
+            assembler: args
+                .assembler
+                .parse::<Assembler>()
+                .expect("Invalid assembler"),
+            contig_aligner: args
+                .contig_aligner
+                .parse::<ContigAligner>()
+                .expect("Invalid contig aligner"),
+            aligner: args.aligner.parse::<Aligner>().expect("Invalid aligner"),
+            keep_failed_qc: args.keep_failed_qc,
+            reads: &args.reads,
+            qc: &args.qc,
+            contig_filter: &args.contig_filter,
+            max_paralog_percent: args.max_paralog_percent,
+            flank_length: args.flank,
+            trim_alignments: args.trim_alignments,
+            locus_filter: &args.locus_filter,
+            iqtree: &args.iqtree,
+            aster: &args.aster,
+            common: &args.common,
+            sample_name_format,
+        }
+    }
+
+    pub fn run(&self) {
+        self.log_input();
+        if let Err(e) = self.check_dependencies() {
+            log::error!("{}", e);
+            return;
+        }
+        match self.run_steps() {
+            Ok(_) => {
+                let mut decorator = PrettyHeader::new();
+                let header = decorator.get_section_header("Workflow");
+                log::info!("{}", header);
+                log::info!("{} Finished running all steps", "✔".green());
+            }
+            Err(e) => {
+                ProjectManifest::record(e.task, TaskStatus::Failed, None);
+                log::error!(
+                    "\n{} Workflow stopped at {}: {}",
+                    "✖".red(),
+                    e.task.to_string().red(),
+                    e.message
+                );
+                log::error!(
+                    "Fix the error and continue from the generated config in the configs directory."
+                );
+            }
+        }
+    }
+
+    fn run_steps(&self) -> Result<(), StepError> {
+        let (clean_deps, clean_reports) = self.clean_reads()?;
+        if self.common.dry_run {
+            log::info!("Dry run. Skipping the remaining steps.");
+            return Ok(());
+        }
+        let (assembly_deps, assemblies) = self.assemble(clean_deps, &clean_reports)?;
+        let mapping_deps = self.map_contigs(assembly_deps, &assemblies)?;
+        let alignment_deps = self.align(mapping_deps)?;
+        let alignments = self.qc_alignments()?;
+        self.infer_trees(alignment_deps, alignments)
+    }
+
+    fn clean_reads(&self) -> Result<(Vec<DepMetadata>, Vec<CleanReadReport>), StepError> {
+        let task = Task::CleanReads;
+        self.print_header(&task);
+        let files = FileFinder::new(self.raw_read_dir, &SupportedFormats::Fastq)
+            .find(self.reads.recursive)
+            .map_err(|e| StepError::new(task, e))?;
+        if files.is_empty() {
+            return Err(StepError::new(
+                task,
+                format!("No raw reads found in {}", self.raw_read_dir.display()),
+            ));
+        }
+        let samples = ReadAssignment::new(&files, &self.sample_name_format).assign();
+        let strategy = ReadAssignmentStrategy::from_arg(self.reads);
+        let input = FastqInput::new(self.raw_read_dir, samples.len(), files.len(), strategy);
+        let mut config = CleanReadConfig::new(input, samples);
+        config.qc_thresholds = QcThresholds::from_arg(self.qc);
+        let config_path = config.to_toml(None).map_err(|e| StepError::new(task, e))?;
+        ProjectManifest::record(task, TaskStatus::Initialized, Some(&config_path));
+        let mut runner = ReadCleaning::from_config_path(&config_path);
+        runner.runner = RunnerOptions::from_arg(self.common);
+        let reports = runner.clean().map_err(|e| StepError::new(task, e))?;
+        if reports.is_empty() && !self.common.dry_run {
+            return Err(StepError::new(task, "No samples were cleaned"));
+        }
+        Ok((config.dependencies.into_values().collect(), reports))
+    }
+
+    fn assemble(
+        &self,
+        dependencies: Vec<DepMetadata>,
+        clean_reports: &[CleanReadReport],
+    ) -> Result<(Vec<DepMetadata>, Vec<AssemblyReports>), StepError> {
+        let task = Task::Assembly;
+        self.print_header(&task);
+        let strategy = ReadAssignmentStrategy::from_arg(self.reads);
+        let input = FastqInput::new(Path::new(DEFAULT_CLEAN_READ_OUTPUT_DIR), 0, 0, strategy);
+        let mut config = AssemblyConfig::new(input, Vec::new());
+        config.previous_step = Some(PreviousStep::with_dependencies(
+            Task::CleanReads,
+            dependencies,
+        ));
+        let summary_path = Path::new(DEFAULT_CLEAN_READ_OUTPUT_DIR).join(READ_CLEANING_SUMMARY);
+        let qc_summaries = ReadCleaningSummary::from_csv(&summary_path).unwrap_or_default();
+        let config_path = config
+            .from_clean_read_config(
+                clean_reports,
+                &qc_summaries,
+                self.keep_failed_qc,
+                self.assembler,
+            )
+            .map_err(|e| StepError::new(task, e))?;
+        if config.samples.is_empty() {
+            return Err(StepError::new(task, "No samples passed read cleaning QC"));
+        }
+        ProjectManifest::record(task, TaskStatus::Initialized, Some(&config_path));
+        let mut runner = Assembly::from_config_path(&config_path);
+        runner.runner = RunnerOptions::from_arg(self.common);
+        let reports = runner.assemble().map_err(|e| StepError::new(task, e))?;
+        if reports.is_empty() {
+            return Err(StepError::new(task, "No samples were assembled"));
+        }
+        Ok((config.dependencies.into_values().collect(), reports))
+    }
+
+    fn map_contigs(
+        &self,
+        dependencies: Vec<DepMetadata>,
+        assemblies: &[AssemblyReports],
+    ) -> Result<Vec<DepMetadata>, StepError> {
+        let task = Task::ContigMapping;
+        self.print_header(&task);
+        let contigs: Vec<PathBuf> = assemblies
+            .iter()
+            .filter(|r| r.contigs.is_file())
+            .map(|r| r.contigs.to_path_buf())
+            .collect();
+        if contigs.is_empty() {
+            return Err(StepError::new(
+                task,
+                "No contig files found in the assemblies",
+            ));
+        }
+        let reference_type = self
+            .reference_type
+            .parse()
+            .map_err(|e| StepError::new(task, e))?;
+        let mut input = ContigInput::new(SampleNameSource::Directory);
+        input.aligner = self.contig_aligner.clone();
+        let mut config =
+            ContigMappingConfig::init(input, self.refname_regex, reference_type, self.single_ref);
+        if self.contig_aligner == ContigAligner::Native {
+            config.native_params = Some(NativeParams::default());
+        }
+        config.contig_filter = ContigFilter::from_arg(self.contig_filter);
+        config.max_paralog_percent = self.max_paralog_percent;
+        config.flank_length = self.flank_length;
+        config.previous_step = Some(PreviousStep::with_dependencies(
+            Task::Assembly,
+            dependencies,
+        ));
+        config.from_contig_paths(&contigs);
+        let config_path = config
+            .to_toml(DEFAULT_REF_MAPPING_CONFIG, self.reference, None)
+            .map_err(|e| StepError::new(task, e))?;
+        ProjectManifest::record(task, TaskStatus::Initialized, Some(&config_path));
+        let mut runner = ContigMapping::from_config_path(&config_path);
+        runner.runner = RunnerOptions::from_arg(self.common);
+        if runner.runner.resume {
+            // Contig mapping has no ledger, so rerun it from the assemblies
+            log::info!("Contig mapping does not support resume. Rerunning all samples.");
+            runner.runner.resume = false;
+            runner.runner.overwrite = true;
+        }
+        let summary = runner.map().map_err(|e| StepError::new(task, e))?;
+        if summary.total_matches == 0 {
+            return Err(StepError::new(
+                task,
+                "No contigs matched the reference sequence",
+            ));
+        }
+        Ok(config.dependencies.into_values().collect())
+    }
+
+    fn align(&self, dependencies: Vec<DepMetadata>) -> Result<Vec<DepMetadata>, StepError> {
+        let task = Task::SequenceAlignment;
+        self.print_header(&task);
+        let input_dir =
+            Path::new(DEFAULT_CONTIG_MAPPING_OUTPUT_DIR).join(DEFAULT_UNALIGN_SEQUENCE_OUTPUT_DIR);
+        let mut config = AlignmentConfig::default();
+        config.init(&input_dir, &InputFmt::Fasta);
+        if config.sequences.is_empty() {
+            return Err(StepError::new(
+                task,
+                format!("No sequences to align found in {}", input_dir.display()),
+            ));
+        }
+        config.previous_step = Some(PreviousStep::with_dependencies(
+            Task::ContigMapping,
+            dependencies,
+        ));
+        let config_path = config
+            .to_toml(self.aligner, None)
+            .map_err(|e| StepError::new(task, e))?;
+        ProjectManifest::record(task, TaskStatus::Initialized, Some(&config_path));
+        let mut runner = SequenceAlignment::from_config_path(&config_path);
+        runner.runner = RunnerOptions::from_arg(self.common);
+        let report = runner.align().map_err(|e| StepError::new(task, e))?;
+        if report.alignments.file_counts == 0 {
+            return Err(StepError::new(task, "No sequences were aligned"));
+        }
+        Ok(config.dependencies.into_values().collect())
+    }
+
+    // Filter the alignments. They are only trimmed,
+    // using the default parameters, if requested.
+    // Only loci that pass are used for tree inference.
+    fn qc_alignments(&self) -> Result<AlignmentFiles, StepError> {
+        let task = Task::AlignmentQc;
+        self.print_header(&task);
+        let output_dir = Path::new(DEFAULT_ALIGNMENT_QC_OUTPUT_DIR);
+        let params = if self.trim_alignments {
+            TrimParams::default()
+        } else {
+            TrimParams::disabled()
+        };
+        let mut runner = AlignmentQc::new(
+            Path::new(DEFAULT_ALIGNMENT_OUTPUT_DIR),
+            output_dir,
+            params,
+            LocusFilter::from_arg(self.locus_filter),
+        );
+        runner.input_format = InputFmt::Fasta;
+        runner.runner = RunnerOptions::from_arg(self.common);
+        let summary = runner.trim();
+        if !summary.iter().any(|s| s.kept) {
+            return Err(StepError::new(task, "No alignments passed the filters"));
+        }
+        let mut files = SeqFileFinder::new(output_dir).find(&InputFmt::Fasta);
+        remove_dropped_loci(output_dir, &mut files);
+        Ok(AlignmentFiles::from_sequence_files(
+            &files,
+            &InputFmt::Fasta,
+            &runner.datatype,
+        ))
+    }
+
+    fn infer_trees(
+        &self,
+        dependencies: Vec<DepMetadata>,
+        alignments: AlignmentFiles,
+    ) -> Result<(), StepError> {
+        let task = Task::TreeInference;
+        self.print_header(&task);
+        let input_dir = Path::new(DEFAULT_ALIGNMENT_QC_OUTPUT_DIR);
+        let mut config = TreeInferenceConfig::init(input_dir, &self.analyses, alignments);
+        config.update_analyses(&self.analyses, self.iqtree, self.aster);
+        if config.has_msc() && !config.has_ml_gene_tree() {
+            return Err(StepError::new(
+                task,
+                format!(
+                    "Cannot infer MSC inference without a gene tree inference method. Use {}",
+                    "--specify-analyses ml-genes msc".yellow()
+                ),
+            ));
+        }
+        config.previous_step = Some(PreviousStep::with_dependencies(
+            Task::AlignmentQc,
+            dependencies,
+        ));
+        let config_path = config
+            .to_toml(self.iqtree.codon)
+            .map_err(|e| StepError::new(task, e))?;
+        ProjectManifest::record(task, TaskStatus::Initialized, Some(&config_path));
+        let mut runner =
+            TreeEstimation::from_config_path(&config_path, Path::new(DEFAULT_PHYLO_OUTPUT_DIR));
+        runner.runner = RunnerOptions::from_arg(self.common);
+        runner.infer().map_err(|e| StepError::new(task, e))
+    }
+
+    // Check all dependencies before running
+    // to avoid failing after hours of processing.
+    fn check_dependencies(&self) -> Result<(), Box<dyn Error>> {
+        let mut missing = Vec::new();
+        if FastpMetadata::new().get().is_none() {
+            missing.push("fastp");
+        }
+        match self.assembler {
+            Assembler::Spades if SpadesMetadata::new().get().is_none() => missing.push("SPAdes"),
+            Assembler::Megahit if MegahitMetadata::new().get().is_none() => missing.push("MEGAHIT"),
+            _ => (),
+        }
+        match self.contig_aligner {
+            ContigAligner::Lastz if LastzMetadata::new().get().is_none() => missing.push("LASTZ"),
+            ContigAligner::Exonerate if ExonerateMetadata::new().get().is_none() => {
+                missing.push("Exonerate")
+            }
+            _ => (),
+        }
+        match self.aligner {
+            Aligner::Mafft if MafftMetadata::new().get().is_none() => missing.push("MAFFT"),
+            Aligner::Muscle if MuscleMetadata::new().get().is_none() => missing.push("MUSCLE"),
+            Aligner::ClustalOmega if ClustalOmegaMetadata::new().get().is_none() => {
+                missing.push("Clustal Omega")
+            }
+            _ => (),
+        }
+        let use_iqtree = self
+            .analyses
+            .iter()
+            .any(|a| a != &TreeInferenceMethod::MscSpeciesTree);
+        if use_iqtree && IqtreeMetadata::new().get().is_none() {
+            missing.push("IQ-TREE");
+        }
+        if !missing.is_empty() {
+            return Err(format!(
+                "{} Missing dependencies: {}. \
+                Please ensure they are installed and accessible in your PATH",
+                "Error:".red(),
+                missing.join(", ")
+            )
+            .into());
+        }
+        if self.analyses.contains(&TreeInferenceMethod::MscSpeciesTree) {
+            self.check_msc_dependencies()?;
+        }
+        Ok(())
+    }
+
+    fn check_msc_dependencies(&self) -> Result<(), Box<dyn Error>> {
+        let methods = match &self.aster.specify_msc_methods {
+            Some(methods) => methods
+                .iter()
+                .map(|m| m.parse::<MscInferenceMethod>())
+                .collect::<Result<Vec<_>, _>>()?,
+            None => vec![MscInferenceMethod::Astral],
+        };
+        for method in methods {
+            if AsterMetadata::new().get_matching(&method).is_none() {
+                return Err(match method {
+                    MscInferenceMethod::Astral => DependencyError::MissingAstral,
+                    MscInferenceMethod::AstralPro => DependencyError::MissingAstralPro,
+                    MscInferenceMethod::WeightedAstral => DependencyError::MissingWeightedAstral,
+                }
+                .to_string()
+                .into());
+            }
+        }
+        Ok(())
+    }
+
+    fn print_header(&self, task: &Task) {
+        let mut decorator = PrettyHeader::new();
+        let header = decorator.get_section_header(&task.to_string());
+        log::info!("{}", header);
+    }
+
+    fn log_input(&self) {
+        log::info!("{}", "Input".cyan());
+        log::info!("{:18}: {}", "Raw reads", self.raw_read_dir.display());
+        log::info!("{:18}: {}", "Reference", self.reference.display());
+        log::info!("{:18}: {}", "Reference type", self.reference_type);
+        log::info!("{:18}: {}", "Sample name format", self.sample_name_format);
+        log::info!("{:18}: {}", "Assembler", self.assembler);
+        log::info!("{:18}: {}", "Contig aligner", self.contig_aligner);
+        log::info!("{:18}: {}", "Aligner", self.aligner);
+        let analyses: Vec<String> = self.analyses.iter().map(|a| a.to_string()).collect();
+        log::info!("{:18}: {}", "Tree inference", analyses.join(", "));
+        log::info!(
+            "{:18}: {}\n",
+            "Task",
+            "Clean → Assemble → Map → Align → QC → Tree"
+        );
+    }
+}
@@ -8,6 +8,28 @@ use segul::helper::{
 };
 use serde::{Deserialize, Serialize};
 
+/// Unambiguous nucleotides
+pub const NUCLEOTIDES: &[u8] = b"ACGT";
+/// Unambiguous amino acids
+pub const AMINO_ACIDS: &[u8] = b"ACDEFGHIKLMNPQRSTVWY";
+
+/// Characters counted as gaps or missing data.
+/// N is an amino acid, so only X is missing in proteins.
+pub fn missing_chars(datatype: &DataType) -> &'static [u8] {
+    match datatype {
+        DataType::Aa => b"-?Xx",
+        _ => b"-?Nn",
+    }
+}
+
+/// Unambiguous characters compared between sequences
+pub fn residues(datatype: &DataType) -> &'static [u8] {
+    match datatype {
+        DataType::Aa => AMINO_ACIDS,
+        _ => NUCLEOTIDES,
+    }
+}
+
 /// Data structure to filter
 ///     problematic contigs.
 #[derive(Debug, Default, Serialize, Deserialize)]
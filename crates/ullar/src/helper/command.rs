@@ -6,3 +6,13 @@ macro_rules! parse_override_args {
         })
     };
 }
+
+/// Check if the override arguments contain any of the flags.
+/// Support both `--flag value` and `--flag=value` styles.
+pub fn has_override_arg(override_args: &str, flags: &[&str]) -> bool {
+    override_args.split_whitespace().any(|arg| {
+        flags
+            .iter()
+            .any(|flag| arg == *flag || arg.starts_with(&format!("{}=", flag)))
+    })
+}
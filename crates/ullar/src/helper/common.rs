@@ -49,6 +49,14 @@ pub fn init_progress_bar(len: u64) -> ProgressBar {
     progress_bar
 }
 
+/// Percent of count in total. Returns 0 if total is 0.
+pub fn percent(count: usize, total: usize) -> f64 {
+    if total == 0 {
+        return 0.0;
+    }
+    count as f64 / total as f64 * 100.0
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct UllarConfig {
     #[serde(skip_serializing_if = "Option::is_none")]
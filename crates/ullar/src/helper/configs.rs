@@ -19,7 +19,9 @@ pub fn generate_config_output_path(config_path: &str) -> PathBuf {
     output_path
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+/// Task that generated the input of a config.
+/// Used to track the lineage of pipeline steps.
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct PreviousStep {
     pub task: Task,
     pub dependencies: Vec<DepMetadata>,
@@ -46,6 +46,8 @@ pub struct PathCheck<'a> {
     is_dir: bool,
     /// Force overwrite of existing files
     force: bool,
+    /// Keep existing files to resume a previous run
+    resume: bool,
 }
 
 impl<'a> PathCheck<'a> {
@@ -55,6 +57,7 @@ impl<'a> PathCheck<'a> {
             path,
             is_dir: false,
             force: false,
+            resume: false,
         }
     }
 
@@ -63,6 +66,11 @@ impl<'a> PathCheck<'a> {
         self
     }
 
+    pub fn with_resume(mut self, resume: bool) -> Self {
+        self.resume = resume;
+        self
+    }
+
     pub fn is_dir(mut self) -> Self {
         self.is_dir = true;
         self
@@ -75,6 +83,14 @@ impl<'a> PathCheck<'a> {
             self.path.display().to_string().red()
         );
 
+        if self.resume && self.path.exists() {
+            log::info!(
+                "\nPath {} already exists. Resuming the previous run...\n",
+                self.path.display().to_string().yellow()
+            );
+            return;
+        }
+
         if dry_run && self.path.exists() {
             log::warn!(
                 "\nPath {} already exists. \
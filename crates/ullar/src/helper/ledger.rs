@@ -0,0 +1,240 @@
+//! Per-step completion ledger to resume interrupted runs.
+//!
+//! Each processed item (a sample or a locus) is appended
+//! to a CSV file in the step output directory together with
+//! the hash of its inputs and the hash of its parameters.
+//! When resuming, items completed with the same inputs
+//! and parameters are skipped.
+use std::{
+    collections::HashMap,
+    error::Error,
+    fmt::Display,
+    fs::{self, OpenOptions},
+    path::{Path, PathBuf},
+    str::FromStr,
+    sync::Mutex,
+};
+
+use colored::Colorize;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::types::reads::FastqReads;
+
+use super::files::FileMetadata;
+
+pub const DEFAULT_LEDGER_FILENAME: &str = "ullar_ledger.csv";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LedgerStatus {
+    Completed,
+    Failed,
+}
+
+impl LedgerStatus {
+    pub fn from_success(is_success: bool) -> Self {
+        if is_success {
+            LedgerStatus::Completed
+        } else {
+            LedgerStatus::Failed
+        }
+    }
+}
+
+impl Display for LedgerStatus {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            LedgerStatus::Completed => write!(f, "completed"),
+            LedgerStatus::Failed => write!(f, "failed"),
+        }
+    }
+}
+
+impl FromStr for LedgerStatus {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "completed" => Ok(LedgerStatus::Completed),
+            "failed" => Ok(LedgerStatus::Failed),
+            _ => Err(format!("Unknown ledger status: {}", s)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LedgerEntry {
+    /// Sample or locus name
+    pub item: String,
+    /// Hash of the item input files
+    pub input_hash: String,
+    /// Hash of the parameters used to process the item
+    pub params_hash: String,
+    pub status: LedgerStatus,
+}
+
+impl LedgerEntry {
+    pub fn new(item: &str, input_hash: &str, params_hash: &str, status: LedgerStatus) -> Self {
+        Self {
+            item: item.to_string(),
+            input_hash: input_hash.to_string(),
+            params_hash: params_hash.to_string(),
+            status,
+        }
+    }
+}
+
+/// Completion ledger of a pipeline step.
+/// Entries are appended as soon as an item finishes,
+/// so the ledger survives a crash in the middle of a run.
+pub struct StepLedger {
+    /// Path to the ledger file
+    pub path: PathBuf,
+    /// Latest entry of each item from previous runs
+    entries: HashMap<String, LedgerEntry>,
+    // Allow recording entries from parallel loops
+    lock: Mutex<()>,
+}
+
+impl StepLedger {
+    /// Load the ledger in the step output directory.
+    /// Start with an empty ledger if none exists.
+    pub fn new(output_dir: &Path) -> Self {
+        let path = output_dir.join(DEFAULT_LEDGER_FILENAME);
+        let entries = match Self::read_entries(&path) {
+            Ok(entries) => entries,
+            Err(e) => {
+                log::warn!(
+                    "{} Failed to read ledger {}: {}",
+                    "Warning:".yellow(),
+                    path.display(),
+                    e
+                );
+                HashMap::new()
+            }
+        };
+        Self {
+            path,
+            entries,
+            lock: Mutex::new(()),
+        }
+    }
+
+    /// Check if the item was completed in a previous run
+    /// using the same inputs and parameters.
+    pub fn is_completed(&self, item: &str, input_hash: &str, params_hash: &str) -> bool {
+        match self.entries.get(item) {
+            Some(entry) => {
+                entry.status == LedgerStatus::Completed
+                    && entry.input_hash == input_hash
+                    && entry.params_hash == params_hash
+            }
+            None => false,
+        }
+    }
+
+    /// Append an entry to the ledger file.
+    /// Only warns on failure to avoid stopping the step.
+    pub fn record(&self, entry: &LedgerEntry) {
+        let _guard = self.lock.lock().expect("Failed to lock the ledger");
+        if let Err(e) = self.append(entry) {
+            log::warn!(
+                "{} Failed to update ledger {}: {}",
+                "Warning:".yellow(),
+                self.path.display(),
+                e
+            );
+        }
+    }
+
+    fn append(&self, entry: &LedgerEntry) -> Result<(), Box<dyn Error>> {
+        if let Some(parent) = self.path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let is_new = !self.path.is_file();
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)?;
+        let mut writer = csv::WriterBuilder::new()
+            .has_headers(is_new)
+            .from_writer(file);
+        writer.serialize(entry)?;
+        writer.flush()?;
+        Ok(())
+    }
+
+    // Later entries override earlier ones
+    // because the file is append only.
+    fn read_entries(path: &Path) -> Result<HashMap<String, LedgerEntry>, Box<dyn Error>> {
+        let mut entries = HashMap::new();
+        if !path.is_file() {
+            return Ok(entries);
+        }
+        let mut reader = csv::Reader::from_path(path)?;
+        for record in reader.deserialize() {
+            let entry: LedgerEntry = record?;
+            entries.insert(entry.item.clone(), entry);
+        }
+        Ok(entries)
+    }
+}
+
+/// Generate SHA256 hash of a string
+pub fn hash_string(value: &str) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(value.as_bytes());
+    format!("{:x}", hasher.finalize())
+}
+
+/// Hash the serialized parameters of a step
+pub fn hash_params<T: Serialize>(params: &T) -> String {
+    let value = toml::to_string(params).unwrap_or_default();
+    hash_string(&value)
+}
+
+/// Hash input files using their names, sizes, and checksums
+pub fn hash_files(files: &[&FileMetadata]) -> String {
+    let value = files
+        .iter()
+        .map(|f| format!("{}:{}:{}", f.file_name, f.file_size, f.sha256))
+        .collect::<Vec<String>>()
+        .join(";");
+    hash_string(&value)
+}
+
+/// Hash the read files of a sample
+pub fn hash_fastq(sample: &FastqReads) -> String {
+    let files: Vec<&FileMetadata> = [&sample.read_1, &sample.read_2, &sample.singletons]
+        .into_iter()
+        .flatten()
+        .collect();
+    hash_files(&files)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ledger_resume() {
+        let output_dir = tempdir::TempDir::new("ledger").unwrap();
+        let ledger = StepLedger::new(output_dir.path());
+        let failed = LedgerEntry::new("sample_1", "abc", "def", LedgerStatus::Failed);
+        let completed = LedgerEntry::new("sample_1", "abc", "def", LedgerStatus::Completed);
+        ledger.record(&failed);
+        ledger.record(&completed);
+        ledger.record(&LedgerEntry::new(
+            "sample_2",
+            "abc",
+            "def",
+            LedgerStatus::Failed,
+        ));
+        let ledger = StepLedger::new(output_dir.path());
+        assert!(ledger.is_completed("sample_1", "abc", "def"));
+        assert!(!ledger.is_completed("sample_1", "abc", "xyz"));
+        assert!(!ledger.is_completed("sample_2", "abc", "def"));
+        assert!(!ledger.is_completed("sample_3", "abc", "def"));
+    }
+}
@@ -6,6 +6,8 @@ pub mod common;
 pub mod configs;
 pub mod fastq;
 pub mod files;
+pub mod ledger;
 pub mod logs;
 pub mod regex;
+pub mod scheduler;
 pub mod tracker;
@@ -0,0 +1,128 @@
+//! Allocate CPU and memory for concurrent jobs.
+//!
+//! Decide how many external jobs (e.g., fastp or SPAdes)
+//! run at the same time and how many threads each job gets
+//! based on the available cores and memory.
+use rayon::{ThreadPool, ThreadPoolBuilder};
+use size::Size;
+
+use super::common::SystemInfo;
+
+const GIB: u64 = 1024 * 1024 * 1024;
+
+/// Resource requirement of a single job
+pub struct JobRequirement {
+    /// Minimum threads for a job to run efficiently
+    pub min_threads: usize,
+    /// Maximum threads a job can use effectively
+    pub max_threads: usize,
+    /// Estimated memory usage of a job in bytes
+    pub memory: u64,
+}
+
+impl JobRequirement {
+    pub const fn new(min_threads: usize, max_threads: usize, memory_gib: u64) -> Self {
+        Self {
+            min_threads,
+            max_threads,
+            memory: memory_gib * GIB,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct JobScheduler {
+    /// Number of jobs to run at the same time
+    pub concurrent_jobs: usize,
+    /// Threads allocated for each job
+    pub threads_per_job: usize,
+    /// Memory allocated for each job in bytes
+    pub memory_per_job: u64,
+}
+
+impl JobScheduler {
+    /// Allocate resources based on the current system
+    pub fn from_system(requirement: &JobRequirement, job_counts: usize) -> Self {
+        let mut sysinfo = SystemInfo::new();
+        sysinfo.get();
+        let memory = if sysinfo.available_memory > 0 {
+            sysinfo.available_memory
+        } else {
+            sysinfo.total_memory
+        };
+        Self::new(requirement, sysinfo.threads, memory, job_counts)
+    }
+
+    pub fn new(
+        requirement: &JobRequirement,
+        total_threads: usize,
+        available_memory: u64,
+        job_counts: usize,
+    ) -> Self {
+        let total_threads = total_threads.max(1);
+        let by_cpu = total_threads / requirement.min_threads.max(1);
+        let by_memory = match requirement.memory {
+            0 => by_cpu,
+            memory => (available_memory / memory) as usize,
+        };
+        let concurrent_jobs = by_cpu.min(by_memory).min(job_counts).max(1);
+        let threads_per_job = (total_threads / concurrent_jobs)
+            .min(requirement.max_threads.max(1))
+            .max(1);
+        Self {
+            concurrent_jobs,
+            threads_per_job,
+            memory_per_job: available_memory / concurrent_jobs as u64,
+        }
+    }
+
+    /// Memory per job in GiB. At least 1 GiB.
+    pub fn memory_per_job_gib(&self) -> u64 {
+        (self.memory_per_job / GIB).max(1)
+    }
+
+    /// Thread pool to run the jobs concurrently.
+    /// Each pool thread runs one job at a time.
+    pub fn build_pool(&self) -> ThreadPool {
+        ThreadPoolBuilder::new()
+            .num_threads(self.concurrent_jobs)
+            .build()
+            .expect("Failed to build job thread pool")
+    }
+
+    pub fn log_summary(&self) {
+        log::info!("{:18}: {}", "Concurrent jobs", self.concurrent_jobs);
+        log::info!("{:18}: {}", "Threads per job", self.threads_per_job);
+        log::info!(
+            "{:18}: {}",
+            "Memory per job",
+            Size::from_bytes(self.memory_per_job)
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_job_scheduler() {
+        let requirement = JobRequirement::new(4, 16, 8);
+        // Limited by CPU
+        let scheduler = JobScheduler::new(&requirement, 64, 512 * GIB, 100);
+        assert_eq!(scheduler.concurrent_jobs, 16);
+        assert_eq!(scheduler.threads_per_job, 4);
+        // Limited by memory
+        let scheduler = JobScheduler::new(&requirement, 64, 32 * GIB, 100);
+        assert_eq!(scheduler.concurrent_jobs, 4);
+        assert_eq!(scheduler.threads_per_job, 16);
+        // Limited by samples
+        let scheduler = JobScheduler::new(&requirement, 64, 512 * GIB, 2);
+        assert_eq!(scheduler.concurrent_jobs, 2);
+        assert_eq!(scheduler.threads_per_job, 16);
+        // Small machine still runs one job
+        let scheduler = JobScheduler::new(&requirement, 2, 4 * GIB, 10);
+        assert_eq!(scheduler.concurrent_jobs, 1);
+        assert_eq!(scheduler.threads_per_job, 2);
+    }
+}
@@ -1,3 +1,5 @@
+use std::time::Instant;
+
 use colored::Colorize;
 use comfy_table::Table;
 
@@ -5,6 +7,8 @@ pub struct ProcessingTracker {
     pub sample_counts: usize,
     pub success_counts: usize,
     pub failure_counts: usize,
+    /// Samples skipped because they were completed in a previous run
+    pub skipped_counts: usize,
     /// Streaming mean of the runtime for each sample
     pub mean_runtime: f64,
     /// Total runtime for all samples
@@ -12,6 +16,11 @@ pub struct ProcessingTracker {
     pub total_runtime: f64,
     pub wait_time: f64,
     pub total_processed: usize,
+    /// Number of samples processed at the same time
+    pub concurrent_jobs: usize,
+    /// Number of samples currently processed
+    pub running_counts: usize,
+    start_time: Instant,
 }
 
 impl ProcessingTracker {
@@ -20,18 +29,48 @@ impl ProcessingTracker {
             sample_counts,
             success_counts: 0,
             failure_counts: 0,
+            skipped_counts: 0,
             mean_runtime: 0.0,
             total_runtime: 0.0,
             wait_time: 0.0,
             total_processed: 0,
+            concurrent_jobs: 1,
+            running_counts: 0,
+            start_time: Instant::now(),
         }
     }
 
+    pub fn with_concurrent_jobs(mut self, concurrent_jobs: usize) -> Self {
+        self.concurrent_jobs = concurrent_jobs.max(1);
+        self
+    }
+
+    /// Mark a sample as started
+    pub fn start(&mut self) {
+        self.running_counts += 1;
+    }
+
+    /// Update the tracker with the runtime of a finished sample
     pub fn update(&mut self, runtime: f64) {
+        self.running_counts = self.running_counts.saturating_sub(1);
         self.total_runtime += runtime;
         self.total_processed += 1;
-        self.mean_runtime = self.total_runtime / self.total_processed as f64;
-        self.wait_time = self.mean_runtime * (self.sample_counts - self.total_processed) as f64;
+        let processed = self.total_processed - self.skipped_counts;
+        self.mean_runtime = self.total_runtime / processed as f64;
+        let remaining = self.sample_counts - self.total_processed;
+        self.wait_time = self.mean_runtime * remaining as f64 / self.concurrent_jobs as f64;
+    }
+
+    /// Mark a sample as skipped.
+    /// Skipped samples are excluded from the mean runtime.
+    pub fn skip(&mut self) {
+        self.skipped_counts += 1;
+        self.success_counts += 1;
+        self.total_processed += 1;
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.total_processed >= self.sample_counts
     }
 
     pub fn finalize(&self) {
@@ -40,6 +79,9 @@ impl ProcessingTracker {
         table.add_row(vec!["Success rate", &format!("{:.2}%", success_rate)]);
         self.add_mean_runtime(&mut table);
         self.add_total_runtime(&mut table);
+        if self.concurrent_jobs > 1 {
+            table.add_row(vec!["Concurrent jobs", &self.concurrent_jobs.to_string()]);
+        }
         log::info!("\n{}", "Final Summary".cyan());
         log::info!("{}\n", table);
     }
@@ -52,6 +94,12 @@ impl ProcessingTracker {
             self.sample_counts
         );
         self.add_mean_runtime(&mut table);
+        if self.concurrent_jobs > 1 {
+            table.add_row(vec![
+                "Running",
+                &format!("{} / {}", self.running_counts, self.concurrent_jobs),
+            ]);
+        }
         table.add_row(vec![
             "Estimate wait time",
             &self.parse_duration(self.wait_time),
@@ -85,6 +133,9 @@ impl ProcessingTracker {
             .add_row(vec!["Total processed", &self.total_processed.to_string()])
             .add_row(vec!["Success", &self.success_counts.to_string()])
             .add_row(vec!["Failure", &self.failure_counts.to_string()]);
+        if self.skipped_counts > 0 {
+            table.add_row(vec!["Skipped", &self.skipped_counts.to_string()]);
+        }
 
         table
     }
@@ -96,10 +147,12 @@ impl ProcessingTracker {
         ]);
     }
 
+    // Use the wall clock time because samples
+    // may be processed concurrently.
     fn add_total_runtime(&self, table: &mut Table) {
         table.add_row(vec![
             "Total runtime",
-            &self.parse_duration(self.total_runtime),
+            &self.parse_duration(self.start_time.elapsed().as_secs_f64()),
         ]);
     }
 }
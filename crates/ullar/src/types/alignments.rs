@@ -1,4 +1,4 @@
-use std::path::PathBuf;
+use std::{fmt::Display, path::PathBuf, str::FromStr};
 
 use rayon::prelude::*;
 use segul::helper::{
@@ -7,7 +7,56 @@ use segul::helper::{
 };
 use serde::{Deserialize, Serialize};
 
-use crate::helper::files::FileMetadata;
+use crate::{
+    core::deps::{clustalo::CLUSTALO_NAME, muscle::MUSCLE_NAME, DepMetadata},
+    helper::files::FileMetadata,
+};
+
+/// Supported multiple sequence aligners
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Aligner {
+    #[default]
+    Mafft,
+    /// MUSCLE 5
+    Muscle,
+    ClustalOmega,
+}
+
+impl Aligner {
+    /// Match the aligner from the config dependency.
+    /// Default to MAFFT for configs created
+    /// before other aligners were supported.
+    pub fn from_dependency(dep: Option<&DepMetadata>) -> Self {
+        match dep {
+            Some(dep) if dep.app_name == MUSCLE_NAME => Aligner::Muscle,
+            Some(dep) if dep.app_name == CLUSTALO_NAME => Aligner::ClustalOmega,
+            _ => Aligner::Mafft,
+        }
+    }
+}
+
+impl Display for Aligner {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Aligner::Mafft => write!(f, "mafft"),
+            Aligner::Muscle => write!(f, "muscle"),
+            Aligner::ClustalOmega => write!(f, "clustalo"),
+        }
+    }
+}
+
+impl FromStr for Aligner {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "mafft" => Ok(Aligner::Mafft),
+            "muscle" => Ok(Aligner::Muscle),
+            "clustalo" => Ok(Aligner::ClustalOmega),
+            _ => Err(format!("Unknown aligner: {}", s)),
+        }
+    }
+}
 
 #[derive(Debug, Default, Serialize, Deserialize)]
 pub struct AlignmentFiles {
@@ -0,0 +1,46 @@
+use std::{fmt::Display, str::FromStr};
+
+use serde::{Deserialize, Serialize};
+
+use crate::core::deps::{megahit::MEGAHIT_NAME, DepMetadata};
+
+/// Supported de novo assemblers
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Assembler {
+    #[default]
+    Spades,
+    Megahit,
+}
+
+impl Assembler {
+    /// Match the assembler from the config dependency.
+    /// Default to SPAdes for configs created
+    /// before MEGAHIT was supported.
+    pub fn from_dependency(dep: Option<&DepMetadata>) -> Self {
+        match dep {
+            Some(dep) if dep.app_name == MEGAHIT_NAME => Assembler::Megahit,
+            _ => Assembler::Spades,
+        }
+    }
+}
+
+impl Display for Assembler {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Assembler::Spades => write!(f, "spades"),
+            Assembler::Megahit => write!(f, "megahit"),
+        }
+    }
+}
+
+impl FromStr for Assembler {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "spades" => Ok(Assembler::Spades),
+            "megahit" => Ok(Assembler::Megahit),
+            _ => Err(format!("Unknown assembler: {}", s)),
+        }
+    }
+}
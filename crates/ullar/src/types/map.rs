@@ -1,5 +1,6 @@
 use std::{default, fmt::Display, str::FromStr};
 
+use segul::helper::types::OutputFmt;
 use serde::{Deserialize, Serialize};
 
 #[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
@@ -8,6 +9,9 @@ pub enum Aligner {
     #[default]
     Lastz,
     Minimap,
+    /// Built-in k-mer seeded banded aligner.
+    /// Maps contigs to probes without Lastz.
+    Native,
 }
 
 impl Display for Aligner {
@@ -16,6 +20,7 @@ impl Display for Aligner {
             Aligner::Lastz => write!(f, "Lastz"),
             Aligner::Exonerate => write!(f, "Exonerate"),
             Aligner::Minimap => write!(f, "Minimap"),
+            Aligner::Native => write!(f, "Native"),
         }
     }
 }
@@ -28,6 +33,7 @@ impl FromStr for Aligner {
             "lastz" => Ok(Aligner::Lastz),
             "exonerate" => Ok(Aligner::Exonerate),
             "minimap" => Ok(Aligner::Minimap),
+            "native" => Ok(Aligner::Native),
             _ => Err(format!("Invalid aligner: {}", s)),
         }
     }
@@ -55,12 +61,61 @@ impl FromStr for MappingQueryFormat {
     fn from_str(s: &str) -> Result<Self, Self::Err> {
         match s {
             "contig" => Ok(MappingQueryFormat::Contig),
-            "fastq" => Ok(MappingQueryFormat::Fastq),
+            "fastq" | "read" => Ok(MappingQueryFormat::Fastq),
             _ => Err(format!("Unknown mapping query format: {}", s)),
         }
     }
 }
 
+/// Output format of the mapped loci.
+/// Maps to segul output formats. Mapped loci are unaligned,
+/// so only FASTA is supported. NEXUS and PHYLIP
+/// require sequences of the same length.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum SequenceOutputFormat {
+    Fasta,
+    /// Interleaved FASTA
+    #[default]
+    FastaInt,
+}
+
+impl SequenceOutputFormat {
+    pub fn to_output_fmt(&self) -> OutputFmt {
+        match self {
+            SequenceOutputFormat::Fasta => OutputFmt::Fasta,
+            SequenceOutputFormat::FastaInt => OutputFmt::FastaInt,
+        }
+    }
+
+    /// File extension used by segul for the format
+    pub fn extension(&self) -> &str {
+        match self {
+            SequenceOutputFormat::Fasta | SequenceOutputFormat::FastaInt => "fas",
+        }
+    }
+}
+
+impl Display for SequenceOutputFormat {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SequenceOutputFormat::Fasta => write!(f, "fasta"),
+            SequenceOutputFormat::FastaInt => write!(f, "fasta-int"),
+        }
+    }
+}
+
+impl FromStr for SequenceOutputFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "fasta" => Ok(SequenceOutputFormat::Fasta),
+            "fasta-int" => Ok(SequenceOutputFormat::FastaInt),
+            _ => Err(format!("Unknown sequence output format: {}", s)),
+        }
+    }
+}
+
 /// Lastz support many output formats.
 /// We only support the most commonly used formats.
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
@@ -128,6 +183,9 @@ pub enum MappingReferenceType {
     Probes,
     /// Loci reference type
     Loci,
+    /// Whole genome reference type.
+    /// Target regions are extracted using a BED or GFF file.
+    Genome,
 }
 
 impl Display for MappingReferenceType {
@@ -135,6 +193,7 @@ impl Display for MappingReferenceType {
         match self {
             MappingReferenceType::Probes => write!(f, "probes"),
             MappingReferenceType::Loci => write!(f, "loci"),
+            MappingReferenceType::Genome => write!(f, "genome"),
         }
     }
 }
@@ -146,6 +205,7 @@ impl FromStr for MappingReferenceType {
         match s {
             "probes" => Ok(MappingReferenceType::Probes),
             "loci" => Ok(MappingReferenceType::Loci),
+            "genome" => Ok(MappingReferenceType::Genome),
             _ => Err(format!("Unknown lastz reference type: {}", s)),
         }
     }
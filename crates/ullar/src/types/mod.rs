@@ -1,6 +1,7 @@
 //! Global data and feature type definitions
 
 pub mod alignments;
+pub mod assembly;
 pub mod map;
 pub mod reads;
 pub mod runner;
@@ -40,9 +41,9 @@ pub enum Task {
     /// Current implementation uses MAFFT
     SequenceAlignment,
     /// Alignment quality control task
-    /// Filters and cleans multiple sequence alignment
-    /// Also generates summary statistics for the alignment
-    /// Current implementation uses SEGUL
+    /// Trims ragged edges, gappy columns,
+    /// and high-entropy columns of the alignments
+    /// Also generates a before and after summary for each locus
     AlignmentQc,
     /// Tree inference task
     /// Infers phylogenetic tree from cleaned alignment
@@ -77,7 +78,8 @@ impl FromStr for Task {
         match s {
             "CleanReads" => Ok(Task::CleanReads),
             "Assembly" => Ok(Task::Assembly),
-            "ReadMapping" => Ok(Task::ContigMapping),
+            "ReadMapping" => Ok(Task::ReadMapping),
+            "ContigMapping" => Ok(Task::ContigMapping),
             "AligningSequences" => Ok(Task::SequenceAlignment),
             "AlignmentQc" => Ok(Task::AlignmentQc),
             "TreeInference" => Ok(Task::TreeInference),
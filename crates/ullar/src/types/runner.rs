@@ -10,6 +10,8 @@ pub struct RunnerOptions {
     pub skip_config_check: bool,
     /// Overwrite existing files
     pub overwrite: bool,
+    /// Skip items completed in the previous runs
+    pub resume: bool,
 }
 
 impl Default for RunnerOptions {
@@ -19,6 +21,7 @@ impl Default for RunnerOptions {
             dry_run: false,
             skip_config_check: true,
             overwrite: false,
+            resume: false,
         }
     }
 }
@@ -48,12 +51,18 @@ impl RunnerOptions {
         self
     }
 
+    pub fn resume(mut self) -> Self {
+        self.resume = true;
+        self
+    }
+
     pub fn from_arg(args: &CommonRunnerArgs) -> Self {
         Self {
             ignore_checksum: args.ignore_checksum,
             dry_run: args.dry_run,
             skip_config_check: args.skip_config_check,
             overwrite: args.overwrite,
+            resume: args.resume,
         }
     }
 }